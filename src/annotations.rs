@@ -0,0 +1,267 @@
+//! Highlight and margin note management
+//!
+//! Highlights cover a passage between two content anchors, so they survive
+//! re-wrapping just like bookmarks do. This module creates them from a range
+//! of rendered lines, keeps them sorted by position and works out which part
+//! of each rendered line a highlight covers.
+
+use crate::types::{Chapter, ContentAnchor, Highlight, HighlightColor, RenderedLine};
+use std::ops::RangeInclusive;
+
+const MAX_HIGHLIGHTS: usize = 5000;
+const MAX_NOTE_LENGTH: usize = 1000;
+
+/// Utilities for managing highlights and their notes
+pub struct AnnotationManager;
+
+impl AnnotationManager {
+    /// Build a highlight covering a range of rendered lines
+    ///
+    /// # Arguments
+    /// * `chapter` - Chapter the lines belong to
+    /// * `chapter_idx` - Index of that chapter in the book
+    /// * `lines` - Rendered lines to cover
+    /// * `color` - Background color of the highlight
+    ///
+    /// # Returns
+    /// * `Some(Highlight)` - Highlight without a note
+    /// * `None` - The lines hold no text (only blank lines or images)
+    pub fn highlight_lines(
+        chapter: &Chapter,
+        chapter_idx: usize,
+        lines: RangeInclusive<usize>,
+        color: HighlightColor,
+    ) -> Option<Highlight> {
+        let rows = chapter.content_lines.get(lines.clone())?;
+        let anchored: Vec<(&RenderedLine, &ContentAnchor)> = rows
+            .iter()
+            .filter(|row| !row.text.is_empty())
+            .filter_map(|row| row.anchor.as_ref().map(|anchor| (row, anchor)))
+            .collect();
+
+        let (_, start) = anchored.first()?;
+        let (last_row, last_anchor) = anchored.last()?;
+        let end = ContentAnchor {
+            element_path: last_anchor.element_path.clone(),
+            char_offset: last_anchor.char_offset + last_row.text.len(),
+        };
+
+        let text = crate::clipboard::selection_text(chapter, lines);
+        Some(Highlight {
+            chapter_idx,
+            start: (*start).clone(),
+            end,
+            text,
+            color,
+            note: None,
+            created: chrono::Utc::now(),
+        })
+    }
+
+    /// Add a highlight, keeping the collection sorted by position
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Index of the new highlight
+    /// * `Err(String)` - Note too long or maximum highlights reached
+    pub fn add(highlights: &mut Vec<Highlight>, highlight: Highlight) -> Result<usize, String> {
+        Self::validate_note(highlight.note.as_deref())?;
+        if highlights.len() >= MAX_HIGHLIGHTS {
+            log::warn!(
+                "Highlight creation failed: max highlights ({}) reached",
+                MAX_HIGHLIGHTS
+            );
+            return Err(format!("Maximum highlights ({}) reached", MAX_HIGHLIGHTS));
+        }
+
+        let idx = highlights.partition_point(|h| {
+            (h.chapter_idx, &h.start) <= (highlight.chapter_idx, &highlight.start)
+        });
+        log::info!(
+            "Highlight added at chapter {} ({} total highlights)",
+            highlight.chapter_idx,
+            highlights.len() + 1
+        );
+        highlights.insert(idx, highlight);
+        Ok(idx)
+    }
+
+    /// Check a note against the length limit
+    pub fn validate_note(note: Option<&str>) -> Result<(), String> {
+        match note {
+            Some(note) if note.len() > MAX_NOTE_LENGTH => Err(format!(
+                "Note too long (max {} characters)",
+                MAX_NOTE_LENGTH
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Byte ranges of a rendered line covered by highlights
+    ///
+    /// A line spans from its anchor to the anchor plus its text length, so
+    /// highlights are clipped to that part of their source element.
+    ///
+    /// # Returns
+    /// Ranges `(start, end, color)` as byte offsets into `line.text`
+    pub fn spans_for_line(
+        highlights: &[Highlight],
+        chapter_idx: usize,
+        line: &RenderedLine,
+    ) -> Vec<(usize, usize, HighlightColor)> {
+        let Some(anchor) = &line.anchor else {
+            return Vec::new();
+        };
+        let len = line.text.len();
+
+        highlights
+            .iter()
+            .filter(|h| h.chapter_idx == chapter_idx)
+            .filter_map(|h| {
+                let start = if h.start.element_path == anchor.element_path {
+                    h.start.char_offset.saturating_sub(anchor.char_offset)
+                } else if h.start < *anchor {
+                    0
+                } else {
+                    return None;
+                };
+                let end = if h.end.element_path == anchor.element_path {
+                    h.end
+                        .char_offset
+                        .saturating_sub(anchor.char_offset)
+                        .min(len)
+                } else if h.end > *anchor {
+                    len
+                } else {
+                    return None;
+                };
+                (start < end).then_some((start, end, h.color))
+            })
+            .collect()
+    }
+
+    /// Navigate to the next highlight in the list
+    pub fn next(highlights: &[Highlight], selected_idx: Option<usize>) -> Option<usize> {
+        if highlights.is_empty() {
+            return None;
+        }
+        Some((selected_idx.unwrap_or(0) + 1) % highlights.len())
+    }
+
+    /// Navigate to the previous highlight in the list
+    pub fn previous(highlights: &[Highlight], selected_idx: Option<usize>) -> Option<usize> {
+        if highlights.is_empty() {
+            return None;
+        }
+        match selected_idx.unwrap_or(0) {
+            0 => Some(highlights.len() - 1),
+            idx => Some(idx - 1),
+        }
+    }
+
+    /// Delete a highlight and return the new selected index
+    ///
+    /// # Returns
+    /// * `Some(usize)` - New selected index
+    /// * `None` - No highlights remaining
+    pub fn delete(highlights: &mut Vec<Highlight>, selected_idx: Option<usize>) -> Option<usize> {
+        let idx = selected_idx.filter(|&idx| idx < highlights.len())?;
+        highlights.remove(idx);
+        if highlights.is_empty() {
+            None
+        } else {
+            Some(idx.min(highlights.len() - 1))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+
+    fn chapter() -> Chapter {
+        let html = "<p>First paragraph that is long enough to wrap onto more rows.</p>\
+                    <p>Second paragraph.</p>";
+        let mut chapter = Chapter {
+            title: "Test".to_string(),
+            sections: Vec::new(),
+            content_lines: Vec::new(),
+            file_path: html.to_string(),
+            href: "test.xhtml".to_string(),
+            document: crate::epub::document::build_document(html),
+        };
+        render_chapter(&mut chapter, Some(24), 100, false);
+        chapter
+    }
+
+    #[test]
+    fn test_highlight_survives_rewrap() {
+        let mut chapter = chapter();
+        // Rows 1 and 2 of the first paragraph
+        let highlight =
+            AnnotationManager::highlight_lines(&chapter, 0, 1..=2, HighlightColor::Green).unwrap();
+        let covered: String = chapter.content_lines[1..=2]
+            .iter()
+            .map(|l| l.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(highlight.text, covered);
+
+        // At another width the same text is covered, whatever the row split
+        render_chapter(&mut chapter, Some(40), 100, false);
+        let highlights = [highlight];
+        let mut text = Vec::new();
+        for line in &chapter.content_lines {
+            for (start, end, color) in AnnotationManager::spans_for_line(&highlights, 0, line) {
+                assert_eq!(color, HighlightColor::Green);
+                text.push(&line.text[start..end]);
+            }
+        }
+        assert_eq!(text.join(" "), covered);
+        assert!(
+            AnnotationManager::spans_for_line(&highlights, 1, &chapter.content_lines[0]).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_blank_lines_cannot_be_highlighted() {
+        let chapter = chapter();
+        let blank = chapter
+            .content_lines
+            .iter()
+            .position(|l| l.text.is_empty())
+            .unwrap();
+        assert!(
+            AnnotationManager::highlight_lines(&chapter, 0, blank..=blank, HighlightColor::Yellow)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_add_keeps_order_and_delete() {
+        let chapter = chapter();
+        let last = chapter
+            .content_lines
+            .iter()
+            .rposition(|l| !l.text.is_empty())
+            .unwrap();
+        let second =
+            AnnotationManager::highlight_lines(&chapter, 0, last..=last, HighlightColor::Blue)
+                .unwrap();
+        let first =
+            AnnotationManager::highlight_lines(&chapter, 0, 0..=0, HighlightColor::Pink).unwrap();
+
+        let mut highlights = Vec::new();
+        assert_eq!(AnnotationManager::add(&mut highlights, second), Ok(0));
+        assert_eq!(AnnotationManager::add(&mut highlights, first), Ok(0));
+        assert_eq!(highlights[1].text, "Second paragraph.");
+
+        let mut long_note = highlights[0].clone();
+        long_note.note = Some("x".repeat(MAX_NOTE_LENGTH + 1));
+        assert!(AnnotationManager::add(&mut highlights, long_note).is_err());
+
+        assert_eq!(AnnotationManager::delete(&mut highlights, Some(1)), Some(0));
+        assert_eq!(AnnotationManager::delete(&mut highlights, Some(0)), None);
+        assert_eq!(AnnotationManager::previous(&highlights, None), None);
+    }
+}
@@ -3,15 +3,27 @@
 //! This module contains the main application state (`AppState`) and all
 //! the methods for managing UI state, navigation, and user interactions.
 
+use crate::annotations::AnnotationManager;
 use crate::async_tasks::TaskMessage;
+use crate::collections::{BookCollections, CollectionManager, ReadingStatus};
 use crate::constants::{
     DEFAULT_TERMINAL_HEIGHT, DEFAULT_TERMINAL_WIDTH, WIDTH_PRESET_1, WIDTH_PRESET_2, WIDTH_PRESET_3,
 };
-use crate::persistence::{PersistenceManager, ReadingProgress};
+use crate::export::ExportFormat;
+use crate::images::ImageCache;
+use crate::images::protocol::{GraphicsProtocol, GraphicsRenderer};
+use crate::images::viewer::{ImageViewer, book_images};
+use crate::jump_list::JumpList;
+use crate::library::{LibraryEntry, LibraryManager};
+use crate::links::LinkNavigator;
+use crate::persistence::{
+    BookIdentity, PersistenceManager, ReadingProgress, StateFile, StateFileError,
+};
 use crate::toc::TocManager;
 use crate::types::{
-    Book, Bookmark, Config, FocusTarget, LoadingState, SearchMatch, TocState, UiMode, Viewport,
-    ZenModeState,
+    Book, Bookmark, CollectionsEdit, Config, ContentAnchor, FocusTarget, Footnote, Highlight,
+    HighlightColor, InlineStyle, LinkHint, LinkTarget, LoadingState, Location, SearchMatch,
+    StartPosition, StateRecovery, TocState, UiMode, Viewport, ZenModeState,
 };
 use std::collections::{HashMap, HashSet};
 use tokio::sync::mpsc;
@@ -29,6 +41,9 @@ pub struct AppState {
     // Max width can be temporarily overridden by CLI (not persisted)
     pub cli_max_width_override: Option<usize>,
 
+    // Position to open the next loaded book at, given on the command line (not persisted)
+    pub start_position: Option<StartPosition>,
+
     // UI Mode
     pub ui_mode: UiMode,
     pub previous_focus: Option<FocusTarget>,
@@ -39,6 +54,8 @@ pub struct AppState {
     pub toc_expanded_chapters: HashSet<String>,
     pub bookmarks_panel_visible: bool,
     pub selected_bookmark_idx: Option<usize>,
+    pub annotations_panel_visible: bool,
+    pub selected_highlight_idx: Option<usize>,
     pub titlebar_visible: bool,
     pub statusbar_visible: bool,
 
@@ -52,15 +69,67 @@ pub struct AppState {
     pub current_search_idx: usize,
     pub input_buffer: String,
 
+    // Links
+    pub link_hints: Vec<LinkHint>,
+    pub footnote: Option<Footnote>,
+    pub footnote_scroll: u16,
+
+    // Navigation history
+    pub jump_list: JumpList,
+
+    // Images
+    pub graphics: GraphicsRenderer,
+    pub image_cache: ImageCache,
+    pub image_viewer: Option<ImageViewer>,
+
+    // Code blocks: horizontal scroll of the block starting at (chapter, line)
+    pub code_scroll: usize,
+    pub code_scroll_block: Option<(usize, usize)>,
+
+    // Visual selection: (chapter, line) where it started
+    pub visual_anchor: Option<(usize, usize)>,
+
+    // Clipboard: yanked text waiting to be written to the terminal
+    pub pending_clipboard: Option<String>,
+
+    // One-off message shown in the status bar until the next key press
+    pub status_message: Option<String>,
+
     // Bookmarks
     pub bookmarks: Vec<Bookmark>,
 
+    // Highlights, and the one being created or edited in the highlight prompt
+    pub highlights: Vec<Highlight>,
+    pub pending_highlight: Option<Highlight>,
+    pub editing_highlight_idx: Option<usize>,
+
+    // Reading status and tags of every book, and the book being edited
+    pub collections: HashMap<String, BookCollections>,
+    pub collections_edit: Option<CollectionsEdit>,
+    /// Chapter the reading status was last checked at
+    status_chapter: Option<usize>,
+
     // Persistence
     pub persistence: PersistenceManager,
     pub reading_progress: HashMap<String, ReadingProgress>,
     pub recent_books: Vec<String>,
+    /// Books opened by this process, most recent first; only these are
+    /// moved in the shared recent books list
+    pub opened_books: Vec<String>,
+    /// Configuration as last loaded or saved, to detect changes to write
+    pub saved_config: Config,
     pub current_book_path: Option<String>,
-    pub book_picker_selected_idx: Option<usize>,
+    /// Identity the current book's state is saved under
+    pub current_book_id: Option<String>,
+    /// Books in the library view, most recently read first
+    pub library: Vec<LibraryEntry>,
+    pub library_selected_idx: Option<usize>,
+    /// A library scan is running in the background
+    pub library_scanning: bool,
+    /// State files that failed to load, offered for recovery one at a time
+    pub state_problems: Vec<StateFileError>,
+    /// Damaged state files the user chose to keep; they are never saved over
+    pub protected_state_files: Vec<StateFile>,
 
     // Async task state
     pub loading_state: LoadingState,
@@ -79,19 +148,27 @@ impl AppState {
     /// # Returns
     /// A new AppState initialized with default values and loaded persistent data
     pub fn new(config: Config, persistence: PersistenceManager) -> Self {
+        let mut load_errors = Vec::new();
         let reading_progress = persistence.load_reading_progress().unwrap_or_else(|e| {
-            log::warn!("Failed to load reading progress: {}. Starting fresh.", e);
+            load_errors.push(e);
             HashMap::new()
         });
         let recent_books = persistence.load_recent_books().unwrap_or_else(|e| {
-            log::warn!(
-                "Failed to load recent books: {}. Starting with empty list.",
-                e
-            );
+            load_errors.push(e);
             Vec::new()
         });
+        let collections = persistence.load_collections().unwrap_or_else(|e| {
+            load_errors.push(e);
+            HashMap::new()
+        });
 
-        AppState {
+        // Recent books are listed until a scan has indexed them
+        let mut library = persistence.load_library();
+        LibraryManager::add_unscanned(&mut library, &recent_books);
+        LibraryManager::apply_progress(&mut library, &reading_progress);
+
+        let saved_config = config.clone();
+        let mut app = AppState {
             book: None,
             viewport: Viewport {
                 width: DEFAULT_TERMINAL_WIDTH,
@@ -104,6 +181,7 @@ impl AppState {
             config,
             should_quit: false,
             cli_max_width_override: None,
+            start_position: None,
             ui_mode: UiMode::Normal,
             previous_focus: None,
             toc_panel_visible: false,
@@ -111,6 +189,8 @@ impl AppState {
             toc_expanded_chapters: HashSet::new(),
             bookmarks_panel_visible: false,
             selected_bookmark_idx: None,
+            annotations_panel_visible: false,
+            selected_highlight_idx: None,
             titlebar_visible: true,
             statusbar_visible: true,
             zen_mode_active: false,
@@ -119,15 +199,45 @@ impl AppState {
             search_results: Vec::new(),
             current_search_idx: 0,
             input_buffer: String::new(),
+            link_hints: Vec::new(),
+            footnote: None,
+            footnote_scroll: 0,
+            jump_list: JumpList::default(),
+            graphics: GraphicsRenderer::new(GraphicsProtocol::detect()),
+            image_cache: ImageCache::default(),
+            image_viewer: None,
+            code_scroll: 0,
+            code_scroll_block: None,
+            visual_anchor: None,
+            pending_clipboard: None,
+            status_message: None,
             bookmarks: Vec::new(),
+            highlights: Vec::new(),
+            pending_highlight: None,
+            editing_highlight_idx: None,
+            collections,
+            collections_edit: None,
+            status_chapter: None,
             persistence,
             reading_progress,
             recent_books,
+            opened_books: Vec::new(),
+            saved_config,
             current_book_path: None,
-            book_picker_selected_idx: None,
+            current_book_id: None,
+            library,
+            library_selected_idx: None,
+            library_scanning: false,
+            state_problems: Vec::new(),
+            protected_state_files: Vec::new(),
             loading_state: LoadingState::Idle,
             task_tx: None,
+        };
+
+        for error in load_errors {
+            app.report_state_error(error);
         }
+        app
     }
 
     /// Set the task channel for triggering async operations
@@ -147,8 +257,13 @@ impl AppState {
 
             // Create task runner and spawn loading task
             let task_runner = crate::async_tasks::AsyncTaskRunner::new(tx.clone());
-            let (_handle, _join_handle) =
-                task_runner.spawn_load_epub(file_path.clone(), effective_width, viewport_width);
+            let (_handle, _join_handle) = task_runner.spawn_load_epub(
+                self.persistence.clone(),
+                file_path.clone(),
+                effective_width,
+                viewport_width,
+                self.config.wrap_code,
+            );
 
             // Set loading state
             self.loading_state = LoadingState::LoadingBook { file_path };
@@ -194,6 +309,7 @@ impl AppState {
     ///
     /// Re-renders chapters to account for changed available width.
     pub fn toggle_bookmarks(&mut self) {
+        let was_visible = self.right_panel_visible();
         self.bookmarks_panel_visible = !self.bookmarks_panel_visible;
 
         // If opening bookmarks panel and there are bookmarks, initialize selection
@@ -210,7 +326,38 @@ impl AppState {
         }
 
         // Re-render chapters to account for changed available width
-        self.rerender_chapters();
+        if was_visible != self.right_panel_visible() {
+            self.rerender_chapters();
+        }
+    }
+
+    /// Toggle the annotations (highlights) panel visibility
+    ///
+    /// Shares the right-hand column with the bookmarks panel. Re-renders
+    /// chapters when the column appears or disappears.
+    pub fn toggle_annotations(&mut self) {
+        let was_visible = self.right_panel_visible();
+        self.annotations_panel_visible = !self.annotations_panel_visible;
+
+        if self.annotations_panel_visible
+            && !self.highlights.is_empty()
+            && self.selected_highlight_idx.is_none()
+        {
+            self.selected_highlight_idx = Some(0);
+        }
+
+        if !self.annotations_panel_visible && self.focus == FocusTarget::Annotations {
+            self.focus = FocusTarget::Content;
+        }
+
+        if was_visible != self.right_panel_visible() {
+            self.rerender_chapters();
+        }
+    }
+
+    /// Whether the right-hand column (bookmarks and/or annotations) is shown
+    pub fn right_panel_visible(&self) -> bool {
+        self.bookmarks_panel_visible || self.annotations_panel_visible
     }
 
     fn update_viewport_from_terminal(&mut self) {
@@ -236,6 +383,7 @@ impl AppState {
             if let Some(state) = &self.pre_zen_state {
                 self.toc_panel_visible = state.toc_visible;
                 self.bookmarks_panel_visible = state.bookmarks_visible;
+                self.annotations_panel_visible = state.annotations_visible;
                 self.statusbar_visible = state.statusbar_visible;
                 self.titlebar_visible = state.titlebar_visible;
             }
@@ -246,6 +394,7 @@ impl AppState {
             self.pre_zen_state = Some(ZenModeState {
                 toc_visible: self.toc_panel_visible,
                 bookmarks_visible: self.bookmarks_panel_visible,
+                annotations_visible: self.annotations_panel_visible,
                 statusbar_visible: self.statusbar_visible,
                 titlebar_visible: self.titlebar_visible,
             });
@@ -253,6 +402,7 @@ impl AppState {
             // Hide everything
             self.toc_panel_visible = false;
             self.bookmarks_panel_visible = false;
+            self.annotations_panel_visible = false;
             self.statusbar_visible = false;
             self.titlebar_visible = false;
             self.zen_mode_active = true;
@@ -297,6 +447,8 @@ impl AppState {
                     FocusTarget::Toc
                 } else if self.bookmarks_panel_visible {
                     FocusTarget::Bookmarks
+                } else if self.annotations_panel_visible {
+                    FocusTarget::Annotations
                 } else {
                     FocusTarget::Content
                 }
@@ -304,11 +456,20 @@ impl AppState {
             FocusTarget::Toc => {
                 if self.bookmarks_panel_visible {
                     FocusTarget::Bookmarks
+                } else if self.annotations_panel_visible {
+                    FocusTarget::Annotations
+                } else {
+                    FocusTarget::Content
+                }
+            }
+            FocusTarget::Bookmarks => {
+                if self.annotations_panel_visible {
+                    FocusTarget::Annotations
                 } else {
                     FocusTarget::Content
                 }
             }
-            FocusTarget::Bookmarks => FocusTarget::Content,
+            FocusTarget::Annotations => FocusTarget::Content,
         };
     }
 
@@ -328,6 +489,12 @@ impl AppState {
         }
     }
 
+    pub fn focus_annotations(&mut self) {
+        if self.annotations_panel_visible {
+            self.focus = FocusTarget::Annotations;
+        }
+    }
+
     pub fn toc_next(&mut self) {
         self.toc_state.tree_state.key_down();
     }
@@ -410,6 +577,7 @@ impl AppState {
             return;
         }
 
+        let origin = self.current_location();
         self.current_chapter = chapter_idx;
 
         if let Some(sec_idx) = section_idx {
@@ -445,6 +613,8 @@ impl AppState {
             self.cursor_line = 0;
             self.viewport.scroll_offset = 0;
         }
+
+        self.record_jump(origin);
     }
 
     /// Get the currently displayed chapter
@@ -515,11 +685,14 @@ impl AppState {
     }
 
     pub fn move_cursor_to_chapter_start(&mut self) {
+        let origin = self.current_location();
         self.cursor_line = 0;
         self.viewport.scroll_offset = 0;
+        self.record_jump(origin);
     }
 
     pub fn move_cursor_to_chapter_end(&mut self) {
+        let origin = self.current_location();
         let max_line = self.current_chapter_lines().saturating_sub(1);
         self.cursor_line = max_line;
 
@@ -528,6 +701,7 @@ impl AppState {
         if max_line >= viewport_height {
             self.viewport.scroll_offset = max_line.saturating_sub(viewport_height - 1);
         }
+        self.record_jump(origin);
     }
 
     /// Navigate to the next chapter (wraps to first chapter)
@@ -537,6 +711,7 @@ impl AppState {
             return;
         }
 
+        let origin = self.current_location();
         let old_chapter = self.current_chapter;
         self.current_chapter = (self.current_chapter + 1) % total;
         self.cursor_line = 0;
@@ -550,6 +725,7 @@ impl AppState {
 
         // Sync TOC to new position
         self.sync_toc_to_cursor();
+        self.record_jump(origin);
     }
 
     /// Navigate to the previous chapter (wraps to last chapter)
@@ -559,6 +735,7 @@ impl AppState {
             return;
         }
 
+        let origin = self.current_location();
         let old_chapter = self.current_chapter;
         if self.current_chapter == 0 {
             self.current_chapter = total - 1;
@@ -577,9 +754,11 @@ impl AppState {
 
         // Sync TOC to new position
         self.sync_toc_to_cursor();
+        self.record_jump(origin);
     }
 
     pub fn next_section(&mut self) {
+        let origin = self.current_location();
         if let Some(chapter) = self.get_current_chapter() {
             if chapter.sections.is_empty() {
                 // No sections, jump to next chapter
@@ -608,9 +787,11 @@ impl AppState {
 
         // Sync TOC to new position
         self.sync_toc_to_cursor();
+        self.record_jump(origin);
     }
 
     pub fn previous_section(&mut self) {
+        let origin = self.current_location();
         if let Some(chapter) = self.get_current_chapter() {
             if chapter.sections.is_empty() {
                 // No sections, jump to previous chapter
@@ -656,6 +837,7 @@ impl AppState {
 
         // Sync TOC to new position
         self.sync_toc_to_cursor();
+        self.record_jump(origin);
     }
 
     pub fn page_down(&mut self) {
@@ -735,6 +917,129 @@ impl AppState {
         self.rerender_chapters();
     }
 
+    /// Toggle soft-wrapping of long code block lines
+    ///
+    /// Re-renders all chapters, wrapped code has nothing left to scroll.
+    pub fn toggle_code_wrap(&mut self) {
+        self.config.wrap_code = !self.config.wrap_code;
+        self.rerender_chapters();
+    }
+
+    /// Get the horizontal scroll offset of the code block starting at `block_start`
+    ///
+    /// Only the block that was scrolled last keeps its offset, every other
+    /// code block is shown from its first column.
+    pub fn code_scroll_for(&self, block_start: usize) -> usize {
+        if self.code_scroll_block == Some((self.current_chapter, block_start)) {
+            self.code_scroll
+        } else {
+            0
+        }
+    }
+
+    /// Scroll the code block under the cursor horizontally
+    ///
+    /// Does nothing unless the cursor is on a code block line. The offset is
+    /// clamped so the end of the longest line stays in view.
+    ///
+    /// # Arguments
+    /// * `columns` - Columns to scroll, negative values scroll left
+    pub fn scroll_code(&mut self, columns: isize) {
+        let Some(chapter) = self.get_current_chapter() else {
+            return;
+        };
+        let Some(block) = chapter.code_block_at(self.cursor_line) else {
+            return;
+        };
+
+        let longest = chapter.content_lines[block.clone()]
+            .iter()
+            .map(|line| textwrap::core::display_width(&line.text))
+            .max()
+            .unwrap_or(0);
+        let max_scroll = longest.saturating_sub(self.content_width());
+
+        self.code_scroll = self
+            .code_scroll_for(block.start)
+            .saturating_add_signed(columns)
+            .min(max_scroll);
+        self.code_scroll_block = Some((self.current_chapter, block.start));
+    }
+
+    /// Scroll the code block under the cursor back to its first column
+    pub fn reset_code_scroll(&mut self) {
+        self.code_scroll = 0;
+        self.code_scroll_block = None;
+    }
+
+    /// Start a line selection at the cursor, or cancel the current one
+    pub fn toggle_visual_selection(&mut self) {
+        if self.visual_selection().is_some() {
+            self.visual_anchor = None;
+        } else if self.book.is_some() {
+            self.visual_anchor = Some((self.current_chapter, self.cursor_line));
+        }
+    }
+
+    /// Lines of the current chapter between the selection start and the cursor
+    ///
+    /// # Returns
+    /// * `Some(range)` - Selected lines, in document order
+    /// * `None` - No selection in the current chapter
+    pub fn visual_selection(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        let (chapter, line) = self.visual_anchor?;
+        (chapter == self.current_chapter)
+            .then(|| line.min(self.cursor_line)..=line.max(self.cursor_line))
+    }
+
+    /// Copy the selected lines to the clipboard and end the selection
+    pub fn yank_selection(&mut self) {
+        let Some(range) = self.visual_selection() else {
+            self.status_message = Some("Nothing selected (v to start a selection)".to_string());
+            return;
+        };
+        let text = self
+            .get_current_chapter()
+            .map(|chapter| crate::clipboard::selection_text(chapter, range.clone()))
+            .unwrap_or_default();
+        self.visual_anchor = None;
+        self.yank(text, format!("{} lines", range.count()));
+    }
+
+    /// Copy the code block under the cursor to the clipboard
+    pub fn yank_code_block(&mut self) {
+        let text = self
+            .get_current_chapter()
+            .and_then(|chapter| crate::clipboard::code_block_text(chapter, self.cursor_line));
+        match text {
+            Some(text) => {
+                let what = format!("code block ({} lines)", text.lines().count());
+                self.yank(text, what);
+            }
+            None => self.status_message = Some("Cursor is not in a code block".to_string()),
+        }
+    }
+
+    /// Queue text for the clipboard; the event loop writes it to the terminal
+    fn yank(&mut self, text: String, what: String) {
+        log::debug!("Yanked {} ({} bytes)", what, text.len());
+        self.status_message = Some(format!("Yanked {}", what));
+        self.pending_clipboard = Some(text);
+    }
+
+    /// Width of the text column in cells, as laid out by the renderer
+    fn content_width(&self) -> usize {
+        let available = (self.calculate_available_width() as usize)
+            .saturating_sub(crate::constants::UI_MARGIN_WIDTH);
+        match self.effective_max_width() {
+            Some(max_width) => max_width
+                .min(self.viewport.width as usize)
+                .saturating_sub(crate::constants::UI_MARGIN_WIDTH)
+                .min(available),
+            None => available,
+        }
+    }
+
     /// Calculate available width for content rendering accounting for visible panels
     fn calculate_available_width(&self) -> u16 {
         let mut available_width = self.viewport.width;
@@ -744,8 +1049,8 @@ impl AppState {
             available_width = available_width.saturating_sub(self.config.toc_panel_width + 1);
         }
 
-        // Subtract bookmarks panel width and margin if visible
-        if self.bookmarks_panel_visible {
+        // Subtract bookmarks/annotations column width and margin if visible
+        if self.right_panel_visible() {
             available_width = available_width.saturating_sub(self.config.bookmarks_panel_width + 1);
         }
 
@@ -754,23 +1059,228 @@ impl AppState {
         available_width.saturating_add(crate::constants::UI_MARGIN_WIDTH as u16)
     }
 
+    /// Get the content anchor at the cursor position
+    pub fn current_anchor(&self) -> Option<ContentAnchor> {
+        self.get_current_chapter()
+            .and_then(|ch| ch.anchor_at_line(self.cursor_line))
+    }
+
+    /// Move the cursor to a content anchor in the current chapter
+    ///
+    /// The viewport is scrolled so the cursor ends up `row` lines below its top,
+    /// which keeps the reading position visually stable across re-renders.
+    fn move_cursor_to_anchor(&mut self, anchor: &ContentAnchor, row: usize) {
+        let chapter = match self.get_current_chapter() {
+            Some(ch) => ch,
+            None => return,
+        };
+
+        let line = chapter.line_for_anchor(anchor);
+        let max_scroll = chapter
+            .content_lines
+            .len()
+            .saturating_sub(self.viewport.height as usize);
+
+        self.cursor_line = line;
+        self.viewport.scroll_offset = line.saturating_sub(row).min(max_scroll);
+    }
+
+    /// Get the EPUB CFI of the cursor position
+    pub fn current_cfi(&self) -> Option<String> {
+        let book = self.book.as_ref()?;
+        let anchor = self.current_anchor()?;
+        crate::epub::cfi::generate(book, self.current_chapter, &anchor)
+    }
+
+    /// Jump to a position given as an EPUB CFI
+    ///
+    /// # Errors
+    /// Returns error if no book is loaded or the CFI doesn't resolve
+    pub fn goto_cfi(&mut self, cfi: &str) -> Result<(), String> {
+        let book = self.book.as_ref().ok_or("No book loaded")?;
+        let (chapter_idx, anchor) = crate::epub::cfi::resolve(book, cfi)?;
+        log::info!("Jumping to CFI {} (chapter {})", cfi, chapter_idx);
+
+        let origin = self.current_location();
+        self.jump_to_location(chapter_idx, Some(&anchor), 0);
+        self.record_jump(origin);
+        Ok(())
+    }
+
+    /// Move to a position given on the command line
+    ///
+    /// Chapters and sections are shown from their first line; other positions
+    /// are centered. A search also highlights its matches for `n`/`N`.
+    ///
+    /// # Errors
+    /// Returns error if no book is loaded or the position isn't in the book
+    pub fn go_to_start_position(&mut self, position: &StartPosition) -> Result<(), String> {
+        let book = self.book.as_ref().ok_or("No book loaded")?;
+        let origin = self.current_location();
+        match position {
+            StartPosition::Cfi(cfi) => return self.goto_cfi(cfi),
+            StartPosition::Chapter(number) => {
+                if *number == 0 || *number > book.chapters.len() {
+                    return Err(format!("the book has {} chapters", book.chapters.len()));
+                }
+                self.current_chapter = number - 1;
+                self.cursor_line = 0;
+                self.viewport.scroll_offset = 0;
+                self.sync_toc_to_cursor();
+            }
+            StartPosition::Section(title) => {
+                let (chapter_idx, section_idx) = TocManager::find_by_title(book, title)
+                    .ok_or("no chapter or section has that title")?;
+                let line = section_idx
+                    .and_then(|idx| book.chapters[chapter_idx].sections.get(idx))
+                    .map_or(0, |section| section.start_line);
+                self.current_chapter = chapter_idx;
+                self.cursor_line = line;
+                self.viewport.scroll_offset = line;
+                self.sync_toc_to_cursor();
+            }
+            StartPosition::Percent(percent) => {
+                let (chapter_idx, line) = book
+                    .line_at_percent(*percent)
+                    .ok_or("the book has no text")?;
+                self.jump_to_location(chapter_idx, None, line);
+            }
+            StartPosition::Search(query) => {
+                let results = crate::search::SearchEngine::search(book, query)?;
+                let first = results.first().ok_or("no matches")?;
+                let (chapter_idx, anchor, line) =
+                    (first.chapter_idx, first.anchor.clone(), first.line);
+                if let Some(book) = &mut self.book {
+                    crate::search::SearchEngine::apply_highlights(book, &results);
+                }
+                self.search_query = query.clone();
+                self.search_results = results;
+                self.current_search_idx = 0;
+                self.jump_to_location(chapter_idx, anchor.as_ref(), line);
+            }
+            StartPosition::Bookmark(label) => {
+                let idx = crate::bookmarks::BookmarkManager::find_by_label(&self.bookmarks, label)
+                    .ok_or("no bookmark has that label")?;
+                let bookmark = self.bookmarks[idx].clone();
+                self.selected_bookmark_idx = Some(idx);
+                self.jump_to_location(
+                    bookmark.chapter_idx,
+                    bookmark.anchor.as_ref(),
+                    bookmark.line,
+                );
+            }
+        }
+        log::info!("Opened at {}", position);
+        self.record_jump(origin);
+        Ok(())
+    }
+
+    /// Add a bookmark at the cursor position
+    ///
+    /// # Returns
+    /// * `Ok(())` - Bookmark added
+    /// * `Err(String)` - Invalid label or bookmark limit reached
+    pub fn add_bookmark(&mut self, label: String) -> Result<(), String> {
+        let anchor = self.current_anchor();
+        crate::bookmarks::BookmarkManager::add_bookmark(
+            &mut self.bookmarks,
+            self.current_chapter,
+            self.cursor_line,
+            anchor,
+            label,
+        )?;
+        self.anchor_bookmarks();
+        Ok(())
+    }
+
+    /// Record content anchors and CFIs for bookmarks that don't have them yet
+    fn anchor_bookmarks(&mut self) {
+        let book = match &self.book {
+            Some(b) => b,
+            None => return,
+        };
+
+        for bookmark in &mut self.bookmarks {
+            if bookmark.anchor.is_none() {
+                bookmark.anchor = book
+                    .chapters
+                    .get(bookmark.chapter_idx)
+                    .and_then(|ch| ch.anchor_at_line(bookmark.line));
+            }
+            if bookmark.cfi.is_none()
+                && let Some(anchor) = &bookmark.anchor
+            {
+                bookmark.cfi = crate::epub::cfi::generate(book, bookmark.chapter_idx, anchor);
+            }
+        }
+    }
+
+    /// Recompute bookmark line numbers from their anchors
+    fn relocate_bookmarks(&mut self) {
+        let book = match &self.book {
+            Some(b) => b,
+            None => return,
+        };
+
+        for bookmark in &mut self.bookmarks {
+            if let Some(anchor) = &bookmark.anchor
+                && let Some(chapter) = book.chapters.get(bookmark.chapter_idx)
+            {
+                bookmark.line = chapter.line_for_anchor(anchor);
+            }
+        }
+    }
+
     /// Re-render all chapters with current effective width
-    /// Call this when max-width changes or panel visibility changes
-    fn rerender_chapters(&mut self) {
+    /// Call this when max-width changes, panel visibility changes or the terminal is resized
+    ///
+    /// Line numbers are only valid for one wrap width, so the cursor, bookmarks
+    /// and current search match are captured as content anchors beforehand and
+    /// mapped back onto the new lines afterwards.
+    pub fn rerender_chapters(&mut self) {
         let effective_width = self.effective_max_width();
         let available_width = self.calculate_available_width();
         let has_search_results = !self.search_results.is_empty();
         let search_query = self.search_query.clone();
 
+        // Capture width-independent positions before the line numbers change
+        let cursor_anchor = self.current_anchor();
+        let cursor_row = self.cursor_line.saturating_sub(self.viewport.scroll_offset);
+        let current_match_anchor = self
+            .search_results
+            .get(self.current_search_idx)
+            .and_then(|m| m.anchor.clone());
+        self.anchor_bookmarks();
+
+        // Code block and selection line numbers are about to change
+        self.reset_code_scroll();
+        self.visual_anchor = None;
+
         // Re-render all chapters with available width if we have a book
         if let Some(book) = &mut self.book {
             for chapter in &mut book.chapters {
-                crate::epub::render_chapter(chapter, effective_width, available_width);
+                crate::epub::render_chapter(
+                    chapter,
+                    effective_width,
+                    available_width,
+                    self.config.wrap_code,
+                );
             }
 
             // Re-apply search highlights if there are active results
             if has_search_results {
                 if let Ok(new_results) = crate::search::SearchEngine::search(book, &search_query) {
+                    // Keep pointing at the same match even though its line number changed
+                    self.current_search_idx = current_match_anchor
+                        .and_then(|anchor| {
+                            new_results
+                                .iter()
+                                .position(|m| m.anchor.as_ref() == Some(&anchor))
+                        })
+                        .unwrap_or_else(|| {
+                            self.current_search_idx
+                                .min(new_results.len().saturating_sub(1))
+                        });
                     self.search_results = new_results;
                     crate::search::SearchEngine::apply_highlights(book, &self.search_results);
                 } else {
@@ -781,6 +1291,12 @@ impl AppState {
                 }
             }
         }
+
+        // Map captured positions back onto the re-rendered lines
+        if let Some(anchor) = cursor_anchor {
+            self.move_cursor_to_anchor(&anchor, cursor_row);
+        }
+        self.relocate_bookmarks();
     }
 
     // Bookmark methods
@@ -804,12 +1320,14 @@ impl AppState {
                 &self.viewport,
             )
         {
+            let origin = self.current_location();
             self.current_chapter = chapter_idx;
             self.cursor_line = line;
             self.viewport.scroll_offset = scroll_offset;
 
             // Sync TOC
             self.sync_toc_to_cursor();
+            self.record_jump(origin);
         }
     }
 
@@ -820,7 +1338,461 @@ impl AppState {
         );
     }
 
+    /// Write all bookmarks of the open book to a file in the current directory
+    ///
+    /// The outcome (file name or error) is reported in the status bar.
+    pub fn export_bookmarks(&mut self, format: ExportFormat) {
+        let Some(book) = &self.book else {
+            return;
+        };
+        if self.bookmarks.is_empty() {
+            self.status_message = Some("No bookmarks to export".to_string());
+            return;
+        }
+
+        let path = crate::export::default_file_name(book, format);
+        let result = crate::export::export_bookmarks(book, &self.bookmarks, format)
+            .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+        self.status_message = Some(match result {
+            Ok(()) => {
+                log::info!("Exported {} bookmarks to {}", self.bookmarks.len(), path);
+                format!("Exported {} bookmarks to {}", self.bookmarks.len(), path)
+            }
+            Err(e) => {
+                log::warn!("Bookmark export failed: {}", e);
+                format!("Export failed: {}", e)
+            }
+        });
+    }
+
+    // Highlight methods
+
+    /// Open the highlight prompt for the selection, or the cursor line without one
+    pub fn start_highlight(&mut self) {
+        let lines = self
+            .visual_selection()
+            .unwrap_or(self.cursor_line..=self.cursor_line);
+        let highlight = self.get_current_chapter().and_then(|chapter| {
+            AnnotationManager::highlight_lines(
+                chapter,
+                self.current_chapter,
+                lines,
+                HighlightColor::default(),
+            )
+        });
+
+        match highlight {
+            Some(highlight) => {
+                self.visual_anchor = None;
+                self.pending_highlight = Some(highlight);
+                self.editing_highlight_idx = None;
+                self.input_buffer.clear();
+                self.ui_mode = UiMode::HighlightPrompt;
+            }
+            None => self.status_message = Some("No text to highlight here".to_string()),
+        }
+    }
+
+    /// Open the highlight prompt to change the color and note of the selected highlight
+    pub fn edit_selected_highlight(&mut self) {
+        if let Some(idx) = self.selected_highlight_idx
+            && let Some(highlight) = self.highlights.get(idx)
+        {
+            self.input_buffer = highlight.note.clone().unwrap_or_default();
+            self.pending_highlight = Some(highlight.clone());
+            self.editing_highlight_idx = Some(idx);
+            self.ui_mode = UiMode::HighlightPrompt;
+        }
+    }
+
+    /// Switch the highlight in the prompt to the next color
+    pub fn cycle_highlight_color(&mut self) {
+        if let Some(highlight) = &mut self.pending_highlight {
+            highlight.color = highlight.color.next();
+        }
+    }
+
+    /// Store the highlight in the prompt with the note typed into the input buffer
+    ///
+    /// # Returns
+    /// * `Ok(())` - Highlight added or updated, prompt closed
+    /// * `Err(String)` - Note too long or highlight limit reached
+    pub fn save_pending_highlight(&mut self) -> Result<(), String> {
+        let Some(mut highlight) = self.pending_highlight.take() else {
+            return Ok(());
+        };
+        let note = self.input_buffer.trim();
+        highlight.note = (!note.is_empty()).then(|| note.to_string());
+
+        let result = match self.editing_highlight_idx {
+            Some(idx) if idx < self.highlights.len() => {
+                AnnotationManager::validate_note(highlight.note.as_deref())
+                    .map(|_| self.highlights[idx] = highlight.clone())
+            }
+            _ => AnnotationManager::add(&mut self.highlights, highlight.clone())
+                .map(|idx| self.selected_highlight_idx = Some(idx)),
+        };
+        if result.is_err() {
+            // Keep the prompt open so the note can be fixed
+            self.pending_highlight = Some(highlight);
+            return result;
+        }
+
+        self.cancel_highlight_prompt();
+        Ok(())
+    }
+
+    /// Close the highlight prompt without storing anything
+    pub fn cancel_highlight_prompt(&mut self) {
+        self.pending_highlight = None;
+        self.editing_highlight_idx = None;
+        self.input_buffer.clear();
+        self.ui_mode = UiMode::Normal;
+    }
+
+    pub fn highlight_next(&mut self) {
+        self.selected_highlight_idx =
+            AnnotationManager::next(&self.highlights, self.selected_highlight_idx);
+    }
+
+    pub fn highlight_previous(&mut self) {
+        self.selected_highlight_idx =
+            AnnotationManager::previous(&self.highlights, self.selected_highlight_idx);
+    }
+
+    pub fn jump_to_selected_highlight(&mut self) {
+        let Some(highlight) = self
+            .selected_highlight_idx
+            .and_then(|idx| self.highlights.get(idx))
+        else {
+            return;
+        };
+        let Some(line) = self.book.as_ref().and_then(|book| {
+            book.chapters
+                .get(highlight.chapter_idx)
+                .map(|chapter| chapter.line_for_anchor(&highlight.start))
+        }) else {
+            return;
+        };
+
+        let origin = self.current_location();
+        self.current_chapter = highlight.chapter_idx;
+        self.cursor_line = line;
+        self.viewport.scroll_offset = line.saturating_sub(self.viewport.height as usize / 2);
+        self.sync_toc_to_cursor();
+        self.record_jump(origin);
+    }
+
+    pub fn delete_selected_highlight(&mut self) {
+        self.selected_highlight_idx =
+            AnnotationManager::delete(&mut self.highlights, self.selected_highlight_idx);
+    }
+
+    // Link methods
+
+    /// Enter link-hint mode, labelling every link visible in the content view
+    pub fn start_link_hints(&mut self) {
+        let hints = match self.get_current_chapter() {
+            Some(chapter) => {
+                let start = self.viewport.scroll_offset;
+                let end = start + self.viewport.height as usize;
+                LinkNavigator::visible_hints(chapter, start, end)
+            }
+            None => return,
+        };
+
+        if hints.is_empty() {
+            log::debug!("No visible links to hint");
+            return;
+        }
+
+        log::debug!("Showing {} link hints", hints.len());
+        self.link_hints = hints;
+        self.input_buffer.clear();
+        self.ui_mode = UiMode::LinkHints;
+    }
+
+    /// Handle a typed character in link-hint mode
+    ///
+    /// Follows the link once its full label has been typed and leaves
+    /// hint mode when the input no longer matches any label.
+    pub fn link_hint_input(&mut self, c: char) {
+        self.input_buffer.push(c.to_ascii_lowercase());
+
+        let matching: Vec<&LinkHint> = self
+            .link_hints
+            .iter()
+            .filter(|hint| hint.label.starts_with(&self.input_buffer))
+            .collect();
+
+        match matching.as_slice() {
+            [] => self.cancel_link_hints(),
+            [hint] if hint.label == self.input_buffer => {
+                let (href, note) = (hint.href.clone(), hint.note);
+                self.cancel_link_hints();
+                self.open_link(&href, note);
+            }
+            _ => {}
+        }
+    }
+
+    /// Leave link-hint mode without following a link
+    pub fn cancel_link_hints(&mut self) {
+        self.link_hints.clear();
+        self.input_buffer.clear();
+        self.ui_mode = UiMode::Normal;
+    }
+
+    /// Activate a link: show the note it references, or follow it
+    ///
+    /// # Arguments
+    /// * `href` - Raw link target
+    /// * `is_noteref` - Whether the link is marked as a note reference
+    pub fn open_link(&mut self, href: &str, is_noteref: bool) {
+        let note = self.book.as_ref().and_then(|book| {
+            LinkNavigator::find_note(book, self.current_chapter, href, is_noteref)
+        });
+
+        match note {
+            Some(note) => self.show_footnote(note),
+            None => self.follow_link(href),
+        }
+    }
+
+    /// Open the first note referenced on the cursor line
+    fn open_note_at_cursor(&mut self) {
+        let href = self
+            .get_current_chapter()
+            .and_then(|chapter| chapter.content_lines.get(self.cursor_line))
+            .and_then(|line| {
+                line.links.iter().find_map(|(start, end, href)| {
+                    let is_noteref = line.inline_styles.iter().any(|(s, e, style)| {
+                        *style == InlineStyle::NoteRef && s < end && e > start
+                    });
+                    is_noteref.then(|| href.clone())
+                })
+            });
+
+        match href {
+            Some(href) => self.open_link(&href, true),
+            None => log::debug!("No note reference on line {}", self.cursor_line),
+        }
+    }
+
+    /// Show a note in the footnote popup without moving the cursor
+    fn show_footnote(&mut self, note: Footnote) {
+        log::info!("Showing note from '{}'", note.chapter_title);
+        self.footnote = Some(note);
+        self.footnote_scroll = 0;
+        self.previous_focus = Some(self.focus);
+        self.ui_mode = UiMode::FootnotePopup;
+    }
+
+    /// Close the footnote popup
+    pub fn close_footnote(&mut self) {
+        self.footnote = None;
+        self.footnote_scroll = 0;
+        self.ui_mode = UiMode::Normal;
+        if let Some(prev_focus) = self.previous_focus.take() {
+            self.focus = prev_focus;
+        }
+    }
+
+    /// Open what the cursor line refers to (Enter)
+    ///
+    /// Images open in the image viewer, note references in the footnote popup.
+    pub fn open_at_cursor(&mut self) {
+        if !self.open_image_at_cursor() {
+            self.open_note_at_cursor();
+        }
+    }
+
+    /// Open the image viewer on the image at the cursor
+    ///
+    /// Works on the rows of an inline image as well as on its placeholder.
+    ///
+    /// # Returns
+    /// `true` if the cursor line belongs to an image and the viewer opened
+    fn open_image_at_cursor(&mut self) -> bool {
+        let Some(book) = &self.book else {
+            return false;
+        };
+        let Some(anchor) = self
+            .get_current_chapter()
+            .and_then(|chapter| chapter.content_lines.get(self.cursor_line))
+            .and_then(|line| line.anchor.as_ref())
+        else {
+            return false;
+        };
+
+        let images = book_images(book);
+        let index = images.iter().position(|image| {
+            image.chapter_idx == self.current_chapter
+                && image.anchor.element_path == anchor.element_path
+        });
+        let Some(viewer) = index.and_then(|index| ImageViewer::new(images, index)) else {
+            return false;
+        };
+
+        log::info!("Opening image viewer on '{}'", viewer.current().path);
+        self.image_viewer = Some(viewer);
+        self.previous_focus = Some(self.focus);
+        self.ui_mode = UiMode::ImageViewer;
+        true
+    }
+
+    /// Close the image viewer
+    pub fn close_image_viewer(&mut self) {
+        self.image_viewer = None;
+        self.ui_mode = UiMode::Normal;
+        if let Some(prev_focus) = self.previous_focus.take() {
+            self.focus = prev_focus;
+        }
+    }
+
+    /// Close the image viewer and move the cursor to the image it shows
+    pub fn goto_viewer_image(&mut self) {
+        let Some(image) = self.image_viewer.as_ref().map(|v| v.current().clone()) else {
+            return;
+        };
+        self.close_image_viewer();
+
+        let origin = self.current_location();
+        self.jump_to_location(image.chapter_idx, Some(&image.anchor), 0);
+        self.record_jump(origin);
+    }
+
+    /// Follow a link from the current chapter, remembering where we came from
+    pub fn follow_link(&mut self, href: &str) {
+        let book = match &self.book {
+            Some(b) => b,
+            None => return,
+        };
+
+        match LinkNavigator::resolve(book, self.current_chapter, href) {
+            Ok(LinkTarget::Internal {
+                chapter_idx,
+                anchor,
+            }) => {
+                log::info!("Following link '{}' to chapter {}", href, chapter_idx);
+                let origin = self.current_location();
+                self.jump_to_location(chapter_idx, anchor.as_ref(), 0);
+                self.record_jump(origin);
+            }
+            Ok(LinkTarget::External(url)) => {
+                log::info!("Not following external link: {}", url);
+                self.ui_mode = UiMode::ErrorPopup(format!("External link: {}", url));
+            }
+            Err(e) => {
+                log::warn!("Failed to follow link '{}': {}", href, e);
+                self.ui_mode = UiMode::ErrorPopup(e);
+            }
+        }
+    }
+
+    // Jump list methods
+
+    /// Get the cursor position as a location that can be returned to
+    pub fn current_location(&self) -> Location {
+        Location {
+            chapter_idx: self.current_chapter,
+            line: self.cursor_line,
+            anchor: self.current_anchor(),
+        }
+    }
+
+    /// Record `origin` in the jump list if the cursor has moved away from it
+    fn record_jump(&mut self, origin: Location) {
+        if origin.chapter_idx != self.current_chapter || origin.line != self.cursor_line {
+            log::debug!(
+                "Recording jump from chapter {} line {}",
+                origin.chapter_idx,
+                origin.line
+            );
+            self.jump_list.push(origin);
+        }
+    }
+
+    /// Go back to the position before the last jump (Ctrl-o)
+    pub fn jump_back(&mut self) {
+        let current = self.current_location();
+        if let Some(location) = self.jump_list.back(current) {
+            log::debug!("Jumping back to chapter {}", location.chapter_idx);
+            self.jump_to_location(
+                location.chapter_idx,
+                location.anchor.as_ref(),
+                location.line,
+            );
+        }
+    }
+
+    /// Redo a jump undone with `jump_back` (Ctrl-i)
+    pub fn jump_forward(&mut self) {
+        if let Some(location) = self.jump_list.forward() {
+            log::debug!("Jumping forward to chapter {}", location.chapter_idx);
+            self.jump_to_location(
+                location.chapter_idx,
+                location.anchor.as_ref(),
+                location.line,
+            );
+        }
+    }
+
+    /// Move the cursor to a position, centering it in the viewport
+    ///
+    /// # Arguments
+    /// * `chapter_idx` - Chapter to show
+    /// * `anchor` - Position within the chapter, if known
+    /// * `line` - Line to use when there is no anchor
+    fn jump_to_location(
+        &mut self,
+        chapter_idx: usize,
+        anchor: Option<&ContentAnchor>,
+        line: usize,
+    ) {
+        if chapter_idx >= self.total_chapters() {
+            return;
+        }
+
+        self.current_chapter = chapter_idx;
+        let half_viewport = self.viewport.height as usize / 2;
+        match anchor {
+            Some(anchor) => self.move_cursor_to_anchor(anchor, half_viewport),
+            None => {
+                let last_line = self.current_chapter_lines().saturating_sub(1);
+                self.cursor_line = line.min(last_line);
+                self.viewport.scroll_offset = self.cursor_line.saturating_sub(half_viewport);
+            }
+        }
+        self.sync_toc_to_cursor();
+    }
+
     // Search methods
+    /// Search the book, highlight the matches and jump to the first one
+    ///
+    /// # Returns
+    /// * `Ok(count)` - Number of matches found
+    /// * `Err(String)` - No book loaded, invalid regex or search timeout
+    pub fn search(&mut self, query: &str) -> Result<usize, String> {
+        log::info!("Executing search: query='{}'", query);
+        let book = self.book.as_mut().ok_or("No book loaded")?;
+        let results = crate::search::SearchEngine::search(book, query)?;
+        log::info!("Search completed: {} results found", results.len());
+        self.search_query = query.to_string();
+        self.search_results = results;
+        self.current_search_idx = 0;
+
+        // Apply highlights
+        crate::search::SearchEngine::apply_highlights(book, &self.search_results);
+
+        // Jump to first result if any
+        if !self.search_results.is_empty() {
+            log::debug!("Jumping to first search result");
+            self.next_search_result();
+        }
+        Ok(self.search_results.len())
+    }
+
     pub fn next_search_result(&mut self) {
         if let Some((new_idx, chapter_idx, line, scroll_offset)) =
             crate::search::SearchEngine::next_result(
@@ -829,6 +1801,7 @@ impl AppState {
                 &self.viewport,
             )
         {
+            let origin = self.current_location();
             self.current_search_idx = new_idx;
             self.current_chapter = chapter_idx;
             self.cursor_line = line;
@@ -836,6 +1809,7 @@ impl AppState {
 
             // Sync TOC
             self.sync_toc_to_cursor();
+            self.record_jump(origin);
         }
     }
 
@@ -847,6 +1821,7 @@ impl AppState {
                 &self.viewport,
             )
         {
+            let origin = self.current_location();
             self.current_search_idx = new_idx;
             self.current_chapter = chapter_idx;
             self.cursor_line = line;
@@ -854,6 +1829,7 @@ impl AppState {
 
             // Sync TOC
             self.sync_toc_to_cursor();
+            self.record_jump(origin);
         }
     }
 
@@ -867,39 +1843,492 @@ impl AppState {
     /// - Application configuration
     /// - Recent books list
     ///
+    /// Other reef processes may share the state files, so only the entries
+    /// this process owns are written: the current book's progress, the books
+    /// it opened, and the configuration if it was changed here.
+    ///
+    /// Files that failed to load and were not recovered are skipped, so the
+    /// damaged original is never replaced by what this session knows.
+    ///
     /// # Errors
     /// Returns error if file I/O fails
     pub fn save_state(&mut self) -> anyhow::Result<()> {
         // Save current book progress if we have one
-        if let Some(book_path) = &self.current_book_path {
+        if let Some(book_id) = &self.current_book_id {
             let progress = ReadingProgress {
                 chapter_idx: self.current_chapter,
                 line: self.cursor_line,
                 scroll_offset: self.viewport.scroll_offset,
                 last_read: chrono::Utc::now(),
                 toc_expansion_state: self.get_toc_expansion_state(),
+                anchor: self.current_anchor(),
+                jump_list: self.jump_list.clone(),
+                percent: self
+                    .book
+                    .as_ref()
+                    .map(|book| book.percent_read(self.current_chapter, self.cursor_line)),
             };
 
-            self.reading_progress.insert(book_path.clone(), progress);
+            self.reading_progress.insert(book_id.clone(), progress);
 
-            // Save bookmarks for current book
-            self.persistence
-                .save_bookmarks(book_path, &self.bookmarks)?;
+            // Save bookmarks and highlights for current book
+            if self.may_save(&StateFile::Bookmarks(book_id.clone())) {
+                self.persistence.save_bookmarks(book_id, &self.bookmarks)?;
+            }
+            if self.may_save(&StateFile::Highlights(book_id.clone())) {
+                self.persistence
+                    .save_highlights(book_id, &self.highlights)?;
+            }
         }
 
-        // Save reading progress
-        self.persistence
-            .save_reading_progress(&self.reading_progress)?;
+        // Save reading progress, keeping what other processes wrote
+        if self.may_save(&StateFile::ReadingProgress) {
+            let own_progress = self.current_book_id.as_ref().and_then(|book_id| {
+                self.reading_progress
+                    .get(book_id)
+                    .map(|progress| (book_id.clone(), progress.clone()))
+            });
+            self.reading_progress = self.persistence.update_reading_progress(|progress| {
+                if let Some((book_id, own)) = own_progress {
+                    progress.insert(book_id, own);
+                }
+            })?;
+        }
 
         // Save recent books
-        self.persistence.save_recent_books(&self.recent_books)?;
+        if self.may_save(&StateFile::RecentBooks) && !self.opened_books.is_empty() {
+            self.recent_books = self.persistence.update_recent_books(&self.opened_books)?;
+        }
 
         // Save config
-        self.persistence.save_config(&self.config)?;
+        if self.may_save(&StateFile::Config) && self.config != self.saved_config {
+            self.persistence.save_config(&self.config)?;
+            self.saved_config = self.config.clone();
+        }
 
         Ok(())
     }
 
+    /// Whether a state file can be written without losing data that failed to load
+    fn may_save(&self, file: &StateFile) -> bool {
+        !self.protected_state_files.contains(file)
+            && !self
+                .state_problems
+                .iter()
+                .any(|problem| &problem.file == file)
+    }
+
+    /// Handle a failure to load saved state
+    ///
+    /// Damaged state files are queued for the recovery popup; other errors
+    /// (such as I/O failures) are only logged.
+    pub fn report_state_error(&mut self, error: anyhow::Error) {
+        match error.downcast::<StateFileError>() {
+            Ok(problem) => {
+                log::error!("{}", problem);
+                if !self.state_problems.iter().any(|p| p.file == problem.file) {
+                    self.state_problems.push(problem);
+                }
+                self.ui_mode = UiMode::StateFilePopup;
+            }
+            Err(error) => log::warn!("Failed to load saved state: {}", error),
+        }
+    }
+
+    /// Recover from the first damaged state file in the queue
+    ///
+    /// # Arguments
+    /// * `recovery` - Restore the backup, start afresh or keep the file untouched
+    pub fn recover_state_file(&mut self, recovery: StateRecovery) {
+        if self.state_problems.is_empty() {
+            self.ui_mode = UiMode::Normal;
+            return;
+        }
+        let problem = self.state_problems.remove(0);
+        let file = problem.file;
+
+        match recovery {
+            StateRecovery::RestoreBackup => match self.persistence.restore_backup(&file) {
+                Ok(()) => {
+                    self.status_message =
+                        Some(format!("Restored {} from the backup", file.description()));
+                    self.reload_state_file(&file);
+                }
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    self.status_message = Some(format!("{:#}", e));
+                    self.protected_state_files.push(file);
+                }
+            },
+            StateRecovery::StartFresh => match self.persistence.set_aside(&file) {
+                Ok(path) => {
+                    self.status_message = Some(format!(
+                        "Started new {}; the damaged file is kept as {}",
+                        file.description(),
+                        path.display()
+                    ));
+                }
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    self.status_message = Some(format!("{:#}", e));
+                    self.protected_state_files.push(file);
+                }
+            },
+            StateRecovery::KeepFile => {
+                self.status_message = Some(format!(
+                    "Your {} will not be saved this session",
+                    file.description()
+                ));
+                self.protected_state_files.push(file);
+            }
+        }
+
+        self.ui_mode = if !self.state_problems.is_empty() {
+            UiMode::StateFilePopup
+        } else if self.book.is_none()
+            && matches!(self.loading_state, LoadingState::Idle)
+            && self.has_library()
+        {
+            self.open_library();
+            UiMode::Library
+        } else {
+            UiMode::Normal
+        };
+    }
+
+    /// Whether there is anything to show in the library view
+    pub fn has_library(&self) -> bool {
+        !self.library.is_empty() || !self.config.library_dirs.is_empty()
+    }
+
+    /// Show the library and rescan it in the background
+    ///
+    /// The current book is selected; progress shown is that of this session.
+    pub fn open_library(&mut self) {
+        self.ui_mode = UiMode::Library;
+        self.input_buffer.clear();
+        LibraryManager::apply_progress(&mut self.library, &self.reading_progress);
+        self.select_library_entry(self.current_book_path.clone());
+        self.scan_library();
+    }
+
+    /// Indices into `library` of the entries shown, in display order
+    ///
+    /// The input buffer holds the library filter.
+    pub fn library_view(&self) -> Vec<usize> {
+        LibraryManager::view(
+            &self.library,
+            &self.collections,
+            &self.input_buffer,
+            self.config.library_sort,
+        )
+    }
+
+    /// The library entry under the selection
+    pub fn selected_library_entry(&self) -> Option<&LibraryEntry> {
+        let idx = self.library_selected_idx?;
+        self.library_view()
+            .get(idx)
+            .and_then(|&entry| self.library.get(entry))
+    }
+
+    /// Select the entry for a book, or the first entry shown
+    fn select_library_entry(&mut self, path: Option<String>) {
+        let view = self.library_view();
+        self.library_selected_idx = path
+            .and_then(|path| view.iter().position(|&idx| self.library[idx].path == path))
+            .or(Some(0));
+    }
+
+    pub fn library_next(&mut self) {
+        let last = self.library_view().len().saturating_sub(1);
+        self.library_selected_idx = Some(
+            self.library_selected_idx
+                .map_or(0, |idx| (idx + 1).min(last)),
+        );
+    }
+
+    pub fn library_previous(&mut self) {
+        self.library_selected_idx = Some(
+            self.library_selected_idx
+                .map_or(0, |idx| idx.saturating_sub(1)),
+        );
+    }
+
+    /// Apply an edited library filter; the best match is selected
+    pub fn library_filter_changed(&mut self) {
+        self.library_selected_idx = Some(0);
+    }
+
+    /// Sort the library by the next order, keeping the selected book selected
+    pub fn cycle_library_sort(&mut self) {
+        let selected = self.selected_library_entry().map(|e| e.path.clone());
+        self.config.library_sort = self.config.library_sort.next();
+        self.select_library_entry(selected);
+        self.status_message = Some(format!("Sorted by {}", self.config.library_sort.name()));
+    }
+
+    /// Open the selected library entry
+    pub fn open_selected_library_entry(&mut self) {
+        if let Some(entry) = self.selected_library_entry() {
+            let book_path = entry.path.clone();
+            log::info!("Loading book from library: {}", book_path);
+            self.load_book_async(book_path);
+        }
+    }
+
+    /// Edit the reading status and tags of the current book
+    pub fn open_collections_prompt(&mut self) {
+        let Some(book_id) = self.current_book_id.clone() else {
+            return;
+        };
+        let title = self
+            .book
+            .as_ref()
+            .map(|book| book.metadata.title.trim().to_string())
+            .unwrap_or_default();
+        self.start_collections_edit(book_id, title, None);
+    }
+
+    /// Edit the reading status and tags of the selected library entry
+    pub fn edit_selected_library_entry(&mut self) {
+        let Some(entry) = self.selected_library_entry() else {
+            return;
+        };
+        if entry.book_id.is_empty() {
+            self.status_message = Some("Wait for the library scan to finish".to_string());
+            return;
+        }
+        let book_id = entry.book_id.clone();
+        let title = entry.metadata.title.trim().to_string();
+        let filter = std::mem::take(&mut self.input_buffer);
+        self.start_collections_edit(book_id, title, Some(filter));
+    }
+
+    fn start_collections_edit(
+        &mut self,
+        book_id: String,
+        title: String,
+        library_filter: Option<String>,
+    ) {
+        let current = self.collections.get(&book_id).cloned().unwrap_or_default();
+        self.input_buffer = CollectionManager::format_tags(&current.tags);
+        self.collections_edit = Some(CollectionsEdit {
+            book_id,
+            title,
+            status: current.status,
+            library_filter,
+        });
+        self.ui_mode = UiMode::CollectionsPrompt;
+    }
+
+    /// Move the book being edited to the next reading status
+    pub fn cycle_edited_status(&mut self) {
+        if let Some(edit) = &mut self.collections_edit {
+            edit.status = ReadingStatus::cycle(edit.status);
+        }
+    }
+
+    /// Store the status and the tags typed in the collections prompt
+    pub fn save_collections_edit(&mut self) {
+        if let Some(edit) = &self.collections_edit {
+            let book = BookCollections {
+                status: edit.status,
+                tags: CollectionManager::parse_tags(&self.input_buffer),
+            };
+            let book_id = edit.book_id.clone();
+            self.set_collections(book_id, book);
+        }
+        self.close_collections_prompt();
+    }
+
+    /// Leave the collections prompt, back to the library if it came from there
+    pub fn close_collections_prompt(&mut self) {
+        self.input_buffer.clear();
+        match self.collections_edit.take().and_then(|e| e.library_filter) {
+            Some(filter) => {
+                self.input_buffer = filter;
+                self.ui_mode = UiMode::Library;
+            }
+            None => self.ui_mode = UiMode::Normal,
+        }
+    }
+
+    fn set_collections(&mut self, book_id: String, book: BookCollections) {
+        if !self.may_save(&StateFile::Collections) {
+            self.status_message = Some("Reading status and tags cannot be saved".to_string());
+            return;
+        }
+        let result = self.persistence.update_collections(|collections| {
+            collections.insert(book_id, book);
+        });
+        match result {
+            Ok(collections) => self.collections = collections,
+            Err(e) => {
+                log::error!("Failed to save reading status and tags: {:#}", e);
+                self.status_message = Some(format!("Failed to save reading status: {:#}", e));
+            }
+        }
+    }
+
+    /// Reload the reading status and tags other processes may have changed
+    fn refresh_collections(&mut self) {
+        if !self.may_save(&StateFile::Collections) {
+            return;
+        }
+        match self.persistence.load_collections() {
+            Ok(collections) => self.collections = collections,
+            Err(e) => self.report_state_error(e),
+        }
+    }
+
+    /// Mark the book finished when the reader moves into its last chapter
+    ///
+    /// Only a move into the last chapter counts, so a book opened there or
+    /// set back to another status by hand is left alone.
+    pub fn update_reading_status(&mut self) {
+        let Some(book_id) = self.current_book_id.clone() else {
+            return;
+        };
+        if self.status_chapter == Some(self.current_chapter) {
+            return;
+        }
+        self.status_chapter = Some(self.current_chapter);
+
+        let last_chapter = self.total_chapters().saturating_sub(1);
+        let mut book = self.collections.get(&book_id).cloned().unwrap_or_default();
+        if self.current_chapter == last_chapter
+            && last_chapter > 0
+            && CollectionManager::finishes(book.status)
+        {
+            log::info!("Reached the last chapter, marking the book finished");
+            book.status = Some(ReadingStatus::Finished);
+            self.set_collections(book_id, book);
+            if self.status_message.is_none() {
+                self.status_message = Some("Marked as finished".to_string());
+            }
+        }
+    }
+
+    /// Remove the selected book from the recent books list
+    ///
+    /// Books outside the library directories disappear from the library too.
+    pub fn remove_selected_from_recent(&mut self) {
+        let Some(entry) = self.selected_library_entry() else {
+            return;
+        };
+        let path = entry.path.clone();
+        let title = entry.metadata.title.trim().to_string();
+        if !self.recent_books.contains(&path) {
+            self.status_message = Some(format!("{} is not a recent book", title));
+            return;
+        }
+        if !self.may_save(&StateFile::RecentBooks) {
+            self.status_message = Some("The recent books list cannot be changed".to_string());
+            return;
+        }
+
+        match self.persistence.remove_recent_book(&path) {
+            Ok(books) => {
+                self.recent_books = books;
+                self.opened_books.retain(|p| p != &path);
+                let dirs = LibraryManager::library_dirs(&self.config.library_dirs);
+                if !LibraryManager::in_library_dirs(&path, &dirs) {
+                    self.library.retain(|e| e.path != path);
+                }
+                let last = self.library_view().len().saturating_sub(1);
+                self.library_selected_idx = self.library_selected_idx.map(|idx| idx.min(last));
+                self.status_message = Some(format!("Removed {} from recent books", title));
+            }
+            Err(e) => {
+                log::error!("Failed to remove recent book: {:#}", e);
+                self.status_message = Some(format!("Failed to remove recent book: {:#}", e));
+            }
+        }
+    }
+
+    /// Start a background rescan of the library directories
+    ///
+    /// Does nothing while a scan is already running.
+    pub fn scan_library(&mut self) {
+        if self.library_scanning {
+            return;
+        }
+        let Some(tx) = &self.task_tx else {
+            log::error!("Cannot scan library: task channel not initialized");
+            return;
+        };
+
+        let task_runner = crate::async_tasks::AsyncTaskRunner::new(tx.clone());
+        let _join_handle = task_runner.spawn_library_scan(
+            self.persistence.clone(),
+            LibraryManager::library_dirs(&self.config.library_dirs),
+            self.recent_books.clone(),
+            self.library.clone(),
+        );
+        self.library_scanning = true;
+    }
+
+    /// Show the result of a library scan, keeping the selected book selected
+    pub fn finish_library_scan(&mut self, mut entries: Vec<LibraryEntry>) {
+        self.library_scanning = false;
+        LibraryManager::apply_progress(&mut entries, &self.reading_progress);
+
+        let selected = self.selected_library_entry().map(|e| e.path.clone());
+        self.library = entries;
+        // The scan may have moved the status and tags of changed books
+        self.refresh_collections();
+        self.select_library_entry(selected);
+        log::info!("Library updated: {} books", self.library.len());
+    }
+
+    /// Load a state file again after its backup was restored
+    fn reload_state_file(&mut self, file: &StateFile) {
+        let result = match file {
+            StateFile::Config => self.persistence.load_config().map(|config| {
+                self.saved_config = config.clone();
+                self.config = config;
+                self.rerender_chapters();
+            }),
+            StateFile::ReadingProgress => self
+                .persistence
+                .load_reading_progress()
+                .map(|progress| self.reading_progress = progress),
+            StateFile::Collections => self
+                .persistence
+                .load_collections()
+                .map(|collections| self.collections = collections),
+            StateFile::RecentBooks => self.persistence.load_recent_books().map(|books| {
+                // Keep the book opened this session at the top
+                let current = self.current_book_path.clone();
+                self.recent_books = books;
+                if let Some(path) = current {
+                    self.recent_books.retain(|p| p != &path);
+                    self.recent_books.insert(0, path);
+                }
+            }),
+            StateFile::Bookmarks(book_id) if self.current_book_id.as_ref() == Some(book_id) => {
+                self.persistence.load_bookmarks(book_id).map(|bookmarks| {
+                    self.bookmarks = bookmarks;
+                    self.selected_bookmark_idx = None;
+                    self.relocate_bookmarks();
+                    self.anchor_bookmarks();
+                })
+            }
+            StateFile::Highlights(book_id) if self.current_book_id.as_ref() == Some(book_id) => {
+                self.persistence.load_highlights(book_id).map(|highlights| {
+                    self.highlights = highlights;
+                    self.selected_highlight_idx = None;
+                })
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            self.report_state_error(e);
+        }
+    }
+
     /// Finalize book loading after async task completes
     ///
     /// Takes ownership of the fully-rendered book and sets up metadata,
@@ -908,24 +2337,47 @@ impl AppState {
     /// # Arguments
     /// * `book` - Fully parsed and rendered book (moved, not cloned)
     /// * `file_path` - Path to EPUB file (will be canonicalized)
+    /// * `identity` - Identity the book's state is stored under, found by the
+    ///   load task (which also migrated state saved under an older key)
     ///
     /// # Side Effects
     /// - Clears search state
     /// - Adds book to recent books list
     /// - Loads bookmarks for this book
     /// - Restores reading position and TOC expansion state if available
-    /// - Closes book picker if open
+    /// - Closes the library if open
     /// - Sets UI mode to Normal
-    pub fn finalize_book_load(&mut self, book: Book, file_path: String) -> anyhow::Result<()> {
-        use crate::persistence::canonicalize_path;
+    pub fn finalize_book_load(
+        &mut self,
+        book: Book,
+        file_path: String,
+        identity: BookIdentity,
+    ) -> anyhow::Result<()> {
+        use crate::persistence::{adopt_progress, canonicalize_path};
 
         log::info!("Finalizing book load: {}", file_path);
 
+        // The previous book's position and bookmarks exist only in memory
+        if self.current_book_id.is_some()
+            && let Err(e) = self.save_state()
+        {
+            log::error!("Failed to save state of the previous book: {}", e);
+        }
+
         // Clear search state when switching books
         self.search_query.clear();
         self.search_results.clear();
         self.current_search_idx = 0;
 
+        // Decoded images belong to the previous book
+        self.image_cache.clear();
+        self.image_viewer = None;
+        self.reset_code_scroll();
+        self.visual_anchor = None;
+        if !book.images.is_empty() {
+            log::debug!("Book has {} images", book.images.len());
+        }
+
         // Canonicalize the path
         let canonical_path = canonicalize_path(&file_path)?;
         log::debug!("Canonical path: {}", canonical_path);
@@ -939,33 +2391,67 @@ impl AppState {
             self.recent_books.remove(pos);
         }
         self.recent_books.insert(0, canonical_path.clone());
+        self.opened_books.retain(|p| p != &canonical_path);
+        self.opened_books.insert(0, canonical_path.clone());
+
+        // Damaged files are reported once the book is shown
+        let mut load_errors = Vec::new();
+
+        // Another reef process may have read this book since startup
+        if self.may_save(&StateFile::ReadingProgress) {
+            match self.persistence.load_reading_progress() {
+                Ok(progress) => self.reading_progress = progress,
+                Err(e) => load_errors.push(e),
+            }
+        }
+
+        adopt_progress(&mut self.reading_progress, &identity);
+        let book_id = identity.id;
+
+        // Identification may have moved the status and tags of this book
+        if self.may_save(&StateFile::Collections) {
+            match self.persistence.load_collections() {
+                Ok(collections) => self.collections = collections,
+                Err(e) => load_errors.push(e),
+            }
+        }
 
         // Load bookmarks for this book
         let bookmarks = self
             .persistence
-            .load_bookmarks(&canonical_path)
+            .load_bookmarks(&book_id)
             .unwrap_or_else(|e| {
-                log::warn!(
-                    "Failed to load bookmarks for '{}': {}. Starting fresh.",
-                    canonical_path,
-                    e
-                );
+                load_errors.push(e);
                 Vec::new()
             });
         log::debug!("Loaded {} bookmarks for this book", bookmarks.len());
         self.bookmarks = bookmarks;
+        self.selected_bookmark_idx = None;
+
+        // Load highlights for this book
+        self.highlights = self
+            .persistence
+            .load_highlights(&book_id)
+            .unwrap_or_else(|e| {
+                load_errors.push(e);
+                Vec::new()
+            });
+        self.selected_highlight_idx = None;
+        log::debug!("Loaded {} highlights for this book", self.highlights.len());
 
         // Load and clone reading progress to avoid borrow issues
-        let progress = self.reading_progress.get(&canonical_path).cloned();
+        let progress = self.reading_progress.get(&book_id).cloned();
 
-        // Store current book path
+        // Store current book path and identity
         self.current_book_path = Some(canonical_path);
+        self.current_book_id = Some(book_id);
 
         // Build TOC tree before storing the book
         self.build_toc_tree(&book);
         log::debug!("TOC tree built: {} items", self.toc_state.items.len());
 
         // Restore position if we have progress
+        let mut restore_anchor = None;
         if let Some(progress) = progress {
             log::info!(
                 "Restoring reading progress: chapter {}, line {}",
@@ -977,18 +2463,34 @@ impl AppState {
                 .min(book.chapters.len().saturating_sub(1));
             self.cursor_line = progress.line;
             self.viewport.scroll_offset = progress.scroll_offset;
+            restore_anchor = progress
+                .anchor
+                .map(|anchor| (anchor, progress.line.saturating_sub(progress.scroll_offset)));
 
-            // Restore TOC expansion state
+            // Restore TOC expansion state and navigation history
             self.restore_toc_expansion_state(&progress.toc_expansion_state);
+            if !progress.jump_list.is_empty() {
+                log::debug!("Restored {} jump list entries", progress.jump_list.len());
+            }
+            self.jump_list = progress.jump_list;
         } else {
             log::debug!("No reading progress found, starting at beginning");
             self.current_chapter = 0;
             self.cursor_line = 0;
             self.viewport.scroll_offset = 0;
+            self.jump_list = JumpList::default();
         }
 
         // Store the book
         self.book = Some(book);
+        self.status_chapter = Some(self.current_chapter);
+
+        // Saved line numbers may come from a different wrap width, so prefer anchors
+        if let Some((anchor, cursor_row)) = restore_anchor {
+            self.move_cursor_to_anchor(&anchor, cursor_row);
+        }
+        self.relocate_bookmarks();
+        self.anchor_bookmarks();
 
         // Sync TOC to restored position
         self.sync_toc_to_cursor();
@@ -997,6 +2499,22 @@ impl AppState {
         self.ui_mode = UiMode::Normal;
         self.loading_state = LoadingState::Idle;
 
+        // A position handed over on the command line overrides the saved one
+        if let Some(position) = self.start_position.take()
+            && let Err(e) = self.go_to_start_position(&position)
+        {
+            log::warn!("Failed to open at {}: {}", position, e);
+            self.ui_mode = UiMode::ErrorPopup(format!("Cannot go to {}: {}", position, e));
+        }
+
+        // Recovering saved data takes priority over everything else
+        for error in load_errors {
+            self.report_state_error(error);
+        }
+        if !self.state_problems.is_empty() {
+            self.ui_mode = UiMode::StateFilePopup;
+        }
+
         Ok(())
     }
 
@@ -3,9 +3,12 @@
 //! This module handles all background tasks including:
 //! - EPUB loading and parsing
 //! - Chapter rendering
+//! - Library scanning
 //! - Resize debouncing
 
 use crate::epub::{parse_epub, render_chapter};
+use crate::library::{LibraryEntry, LibraryManager};
+use crate::persistence::{BookIdentity, PersistenceManager, canonicalize_path};
 use crate::types::Book;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -21,14 +24,25 @@ pub enum TaskMessage {
     /// Progress update during chapter rendering (lightweight - just numbers)
     RenderProgress { rendered: usize, total: usize },
 
-    /// EPUB loading completed - all chapters rendered
-    BookLoadingComplete { book: Book, file_path: String },
+    /// EPUB loading completed - all chapters rendered (boxed, books are large)
+    BookLoadingComplete {
+        book: Box<Book>,
+        file_path: String,
+        identity: BookIdentity,
+    },
 
     /// EPUB loading failed
     BookLoadError { error: String },
 
+    /// Library scan finished; the index has been saved
+    LibraryScanComplete { entries: Vec<LibraryEntry> },
+
     /// Resize event after debounce timeout
     ResizeComplete { width: u16, height: u16 },
+
+    /// Command from `reef remote`, waiting for its response
+    #[cfg(unix)]
+    RemoteRequest(crate::remote::RemoteCall),
 }
 
 /// Handle for cancelling a background task
@@ -52,19 +66,31 @@ impl AsyncTaskRunner {
     /// This will:
     /// 1. Parse the EPUB file
     /// 2. Render the first chapter immediately
-    /// 3. Send the book with first chapter rendered
-    /// 4. Render remaining chapters in background
+    /// 3. Identify the book (hashing it if it is new or changed) while
+    ///    the remaining chapters are rendered
+    /// 4. Send the rendered book with its identity
     pub fn spawn_load_epub(
         &self,
+        persistence: PersistenceManager,
         file_path: String,
         effective_width: Option<usize>,
         viewport_width: u16,
+        wrap_code: bool,
     ) -> (TaskHandle, JoinHandle<()>) {
         let tx = self.tx.clone();
         let (cancel_tx, cancel_rx) = watch::channel(false);
 
         let handle = tokio::spawn(async move {
-            load_epub_task(file_path, effective_width, viewport_width, tx, cancel_rx).await
+            load_epub_task(
+                persistence,
+                file_path,
+                effective_width,
+                viewport_width,
+                wrap_code,
+                tx,
+                cancel_rx,
+            )
+            .await
         });
 
         (
@@ -75,6 +101,44 @@ impl AsyncTaskRunner {
         )
     }
 
+    /// Spawn a scan of the library directories
+    ///
+    /// Books unchanged since `previous` was built are not opened again. The
+    /// new index is saved before it is sent back.
+    pub fn spawn_library_scan(
+        &self,
+        persistence: PersistenceManager,
+        dirs: Vec<PathBuf>,
+        extra_paths: Vec<String>,
+        previous: Vec<LibraryEntry>,
+    ) -> JoinHandle<()> {
+        let tx = self.tx.clone();
+        let fallback = previous.clone();
+
+        tokio::spawn(async move {
+            // Opening books is blocking file I/O
+            let scan = tokio::task::spawn_blocking(move || {
+                let mut entries =
+                    LibraryManager::scan(&persistence, &dirs, &extra_paths, &previous);
+                match persistence.load_reading_progress() {
+                    Ok(progress) => LibraryManager::apply_progress(&mut entries, &progress),
+                    Err(e) => log::warn!("Library progress not refreshed: {}", e),
+                }
+                if let Err(e) = persistence.save_library(&entries) {
+                    log::error!("Failed to save library index: {}", e);
+                }
+                entries
+            })
+            .await;
+
+            let entries = scan.unwrap_or_else(|e| {
+                log::error!("Task join error during library scan: {}", e);
+                fallback
+            });
+            let _ = tx.send(TaskMessage::LibraryScanComplete { entries });
+        })
+    }
+
     /// Spawn a resize debouncer
     ///
     /// Collects resize events and sends a single resize message after debounce timeout
@@ -90,9 +154,11 @@ impl AsyncTaskRunner {
 
 /// Background task for loading and rendering an EPUB
 async fn load_epub_task(
+    persistence: PersistenceManager,
     file_path: String,
     effective_width: Option<usize>,
     viewport_width: u16,
+    wrap_code: bool,
     tx: mpsc::UnboundedSender<TaskMessage>,
     cancel_rx: watch::Receiver<bool>,
 ) {
@@ -135,12 +201,21 @@ async fn load_epub_task(
         }
     };
 
+    // Identify the book for its saved state; hashing a large file takes a
+    // while, so it runs alongside rendering
+    let identity_path = file_path.clone();
+    let identifier = book.metadata.identifier.clone();
+    let identity_task = tokio::task::spawn_blocking(move || {
+        let canonical_path = canonicalize_path(&identity_path)?;
+        persistence.book_identity(&canonical_path, identifier.as_deref())
+    });
+
     let total_chapters = book.chapters.len();
     log::debug!("Rendering {} chapters", total_chapters);
 
     // Render first chapter immediately (for quick initial display)
     if let Some(first_chapter) = book.chapters.first_mut() {
-        render_chapter(first_chapter, effective_width, viewport_width);
+        render_chapter(first_chapter, effective_width, viewport_width, wrap_code);
         log::debug!(
             "First chapter rendered: '{}' ({} lines)",
             first_chapter.title,
@@ -167,7 +242,7 @@ async fn load_epub_task(
         }
 
         // Render chapter
-        render_chapter(chapter, effective_width, viewport_width);
+        render_chapter(chapter, effective_width, viewport_width, wrap_code);
         log::debug!(
             "Rendered chapter {}/{}: '{}' ({} lines)",
             idx + 1,
@@ -192,9 +267,32 @@ async fn load_epub_task(
         return;
     }
 
+    let identity = match identity_task.await {
+        Ok(Ok(identity)) => identity,
+        Ok(Err(e)) => {
+            log::error!("Failed to identify book: {}", e);
+            let _ = tx.send(TaskMessage::BookLoadError {
+                error: e.to_string(),
+            });
+            return;
+        }
+        Err(e) => {
+            log::error!("Task join error during book identification: {}", e);
+            let _ = tx.send(TaskMessage::BookLoadError {
+                error: format!("Task join error: {}", e),
+            });
+            return;
+        }
+    };
+    log::debug!("Book identity: {}", identity.id);
+
     // All chapters rendered - send complete book (move ownership, no clone)
     log::info!("All chapters rendered successfully, sending book");
-    let _ = tx.send(TaskMessage::BookLoadingComplete { book, file_path });
+    let _ = tx.send(TaskMessage::BookLoadingComplete {
+        book: Box::new(book),
+        file_path,
+        identity,
+    });
 }
 
 /// Background task for debouncing resize events
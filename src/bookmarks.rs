@@ -4,7 +4,7 @@
 //! creation, navigation, and deletion. Bookmarks are automatically sorted
 //! by position (chapter index, then line number).
 
-use crate::types::{Bookmark, Viewport};
+use crate::types::{Bookmark, ContentAnchor, Viewport};
 
 const MAX_BOOKMARKS: usize = 1000;
 const MAX_LABEL_LENGTH: usize = 100;
@@ -21,6 +21,7 @@ impl BookmarkManager {
     /// * `bookmarks` - Mutable bookmark collection to add to
     /// * `chapter_idx` - Chapter index where bookmark should be placed
     /// * `line` - Line number within chapter
+    /// * `anchor` - Width-independent position of the line, if known
     /// * `label` - User-provided label (will be trimmed)
     ///
     /// # Returns
@@ -30,6 +31,7 @@ impl BookmarkManager {
         bookmarks: &mut Vec<Bookmark>,
         chapter_idx: usize,
         line: usize,
+        anchor: Option<ContentAnchor>,
         label: String,
     ) -> Result<(), String> {
         log::debug!(
@@ -72,6 +74,8 @@ impl BookmarkManager {
             chapter_idx,
             line,
             label: trimmed_label.to_string(),
+            anchor,
+            cfi: None,
         };
 
         bookmarks.push(bookmark);
@@ -154,6 +158,22 @@ impl BookmarkManager {
         }
     }
 
+    /// Find a bookmark by its label
+    ///
+    /// Labels are compared ignoring case. A label that equals `label` wins
+    /// over the first one that merely contains it.
+    pub fn find_by_label(bookmarks: &[Bookmark], label: &str) -> Option<usize> {
+        let label = label.trim().to_lowercase();
+        bookmarks
+            .iter()
+            .position(|b| b.label.to_lowercase() == label)
+            .or_else(|| {
+                bookmarks
+                    .iter()
+                    .position(|b| b.label.to_lowercase().contains(&label))
+            })
+    }
+
     /// Get the position to jump to for a selected bookmark
     ///
     /// # Returns
@@ -208,7 +228,7 @@ mod tests {
     fn test_add_bookmark() {
         let mut bookmarks = Vec::new();
         let result =
-            BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, "Test bookmark".to_string());
+            BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, None, "Test bookmark".to_string());
         assert!(result.is_ok());
         assert_eq!(bookmarks.len(), 1);
         assert_eq!(bookmarks[0].label, "Test bookmark");
@@ -217,7 +237,7 @@ mod tests {
     #[test]
     fn test_empty_label_rejected() {
         let mut bookmarks = Vec::new();
-        let result = BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, "   ".to_string());
+        let result = BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, None, "   ".to_string());
         assert!(result.is_err());
         assert_eq!(bookmarks.len(), 0);
     }
@@ -225,15 +245,27 @@ mod tests {
     #[test]
     fn test_bookmark_sorting() {
         let mut bookmarks = Vec::new();
-        BookmarkManager::add_bookmark(&mut bookmarks, 1, 20, "Second".to_string()).unwrap();
-        BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, "First".to_string()).unwrap();
-        BookmarkManager::add_bookmark(&mut bookmarks, 1, 5, "Third".to_string()).unwrap();
+        BookmarkManager::add_bookmark(&mut bookmarks, 1, 20, None, "Second".to_string()).unwrap();
+        BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, None, "First".to_string()).unwrap();
+        BookmarkManager::add_bookmark(&mut bookmarks, 1, 5, None, "Third".to_string()).unwrap();
 
         assert_eq!(bookmarks[0].label, "First");
         assert_eq!(bookmarks[1].label, "Third");
         assert_eq!(bookmarks[2].label, "Second");
     }
 
+    #[test]
+    fn test_find_by_label() {
+        let mut bookmarks = Vec::new();
+        BookmarkManager::add_bookmark(&mut bookmarks, 0, 10, None, "Intro notes".to_string())
+            .unwrap();
+        BookmarkManager::add_bookmark(&mut bookmarks, 1, 5, None, "Intro".to_string()).unwrap();
+
+        assert_eq!(BookmarkManager::find_by_label(&bookmarks, "intro"), Some(1));
+        assert_eq!(BookmarkManager::find_by_label(&bookmarks, "NOTES"), Some(0));
+        assert_eq!(BookmarkManager::find_by_label(&bookmarks, "missing"), None);
+    }
+
     #[test]
     fn test_label_truncation() {
         let long_text = "a".repeat(100);
@@ -268,16 +300,22 @@ mod tests {
                 chapter_idx: 0,
                 line: 10,
                 label: "First".to_string(),
+                anchor: None,
+                cfi: None,
             },
             Bookmark {
                 chapter_idx: 1,
                 line: 20,
                 label: "Second".to_string(),
+                anchor: None,
+                cfi: None,
             },
             Bookmark {
                 chapter_idx: 2,
                 line: 30,
                 label: "Third".to_string(),
+                anchor: None,
+                cfi: None,
             },
         ];
 
@@ -297,11 +335,15 @@ mod tests {
                 chapter_idx: 0,
                 line: 10,
                 label: "First".to_string(),
+                anchor: None,
+                cfi: None,
             },
             Bookmark {
                 chapter_idx: 1,
                 line: 20,
                 label: "Second".to_string(),
+                anchor: None,
+                cfi: None,
             },
         ];
 
@@ -4,13 +4,25 @@
 //! user inputs for correctness.
 
 use crate::constants::{MAX_MAX_WIDTH, MIN_MAX_WIDTH};
-use clap::Parser;
+use crate::dump::{ChapterRange, DumpFormat};
+use crate::epub::cfi::Cfi;
+use crate::export::ExportFormat;
+use crate::images::protocol::GraphicsProtocol;
+use crate::search::SearchEngine;
+use crate::types::StartPosition;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(name = "reef")]
 #[command(version = "0.0.1")]
 #[command(about = "Dive into your books from the comfort of your terminal", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
+#[command(mut_group("PositionArgs", |group| group.requires("file")))]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to EPUB file to open
     pub file: Option<String>,
 
@@ -21,20 +33,279 @@ pub struct Cli {
     /// Enable logging to specified file
     #[arg(short = 'l', long, value_name = "PATH")]
     pub log_file: Option<String>,
+
+    /// How to draw images: auto, kitty, iterm2, sixel or halfblocks
+    #[arg(short = 'i', long, value_name = "PROTOCOL")]
+    pub images: Option<String>,
+
+    /// Where to open the book instead of its saved position
+    #[command(flatten)]
+    pub position: PositionArgs,
+}
+
+/// Commands that run without opening the reader
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the text of a book to stdout
+    Dump {
+        /// Path to the EPUB file to print
+        file: String,
+
+        /// Output format: text (wrapped), paragraphs (one line each) or markdown
+        #[arg(short = 'f', long, value_name = "FORMAT", default_value = "text")]
+        format: String,
+
+        /// Line width of the text format (40-200)
+        #[arg(short = 'w', long, value_name = "COLS", default_value_t = 80)]
+        width: usize,
+
+        /// Chapters to print, e.g. 3, 2-5 or 4- (numbered from 1)
+        #[arg(short = 'c', long, value_name = "RANGE")]
+        chapters: Option<String>,
+    },
+
+    /// Export the bookmarks of a book, grouped by chapter
+    Export {
+        /// Path to the EPUB file whose bookmarks are exported
+        file: String,
+
+        /// Output format: markdown, json or org
+        #[arg(short = 'f', long, value_name = "FORMAT", default_value = "markdown")]
+        format: String,
+
+        /// File to write ('-' for stdout) [default: <title>-bookmarks.<ext>]
+        #[arg(short = 'o', long, value_name = "PATH")]
+        output: Option<String>,
+    },
+
+    /// Search the text of books, printing matching lines grep style
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+
+        /// EPUB files, or directories searched recursively for them
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Lines of context to print around each match
+        #[arg(short = 'C', long, value_name = "N", default_value_t = 0)]
+        context: usize,
+
+        /// Match regardless of case
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+
+        /// Match the pattern as literal text instead of a regex
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+    },
+
+    /// Print the metadata of a book
+    Info {
+        /// Path to the EPUB file to describe
+        file: String,
+
+        /// Print a JSON object instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the table of contents of a book
+    Toc {
+        /// Path to the EPUB file whose chapters are listed
+        file: String,
+
+        /// Print a JSON array instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Import highlights, bookmarks and the reading position from another reader
+    Import {
+        /// Path to the EPUB file the annotations belong to
+        file: String,
+
+        /// KOReader sidecar (metadata.epub.lua) or Calibre annotation export (.json)
+        annotations: String,
+    },
+
+    /// Control a running reader (needs remote_control in the config)
+    Remote {
+        /// Socket of the reader [default: $REEF_SOCKET or the newest reader]
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        #[command(subcommand)]
+        command: RemoteCommand,
+    },
+}
+
+/// Commands sent to a running reader by `reef remote`
+#[derive(Subcommand, Debug)]
+pub enum RemoteCommand {
+    /// Open a book, at its saved position unless another is given
+    Open {
+        /// Path to the EPUB file to open
+        file: String,
+
+        #[command(flatten)]
+        position: PositionArgs,
+    },
+    /// Move to a position in the open book
+    Goto {
+        #[command(flatten)]
+        position: PositionArgs,
+    },
+    /// Search the open book and jump to the first match
+    Search {
+        /// Regular expression to search for
+        query: String,
+    },
+    /// Go to the next chapter
+    NextChapter,
+    /// Go to the previous chapter
+    PrevChapter,
+    /// Print the book, chapter, section and line of the cursor as JSON
+    GetPosition,
+    /// Print the text of the lines selected with v
+    GetSelection,
+    /// Save the reading position and quit
+    Quit,
+}
+
+/// A position to open a book at, given to `reef` or `reef remote`
+#[derive(Args, Debug)]
+#[group(multiple = false)]
+pub struct PositionArgs {
+    /// Go to an EPUB CFI position (e.g. copied from another reader)
+    #[arg(short = 'g', long, value_name = "CFI", visible_alias = "goto")]
+    pub cfi: Option<String>,
+
+    /// Go to a chapter (numbered from 1)
+    #[arg(short = 'c', long, value_name = "N")]
+    pub chapter: Option<usize>,
+
+    /// Go to the chapter or section with this title
+    #[arg(long, value_name = "TITLE")]
+    pub section: Option<String>,
+
+    /// Go this far into the text of the book (0-100)
+    #[arg(short = 'p', long, value_name = "PERCENT")]
+    pub percent: Option<u8>,
+
+    /// Go to the first match of a regex, with all matches highlighted
+    #[arg(short = 's', long, value_name = "REGEX")]
+    pub search: Option<String>,
+
+    /// Go to the bookmark with this label
+    #[arg(short = 'b', long, value_name = "LABEL")]
+    pub bookmark: Option<String>,
+}
+
+impl PositionArgs {
+    /// The position given, if any
+    pub fn position(&self) -> Option<StartPosition> {
+        let position = if let Some(cfi) = &self.cfi {
+            StartPosition::Cfi(cfi.clone())
+        } else if let Some(chapter) = self.chapter {
+            StartPosition::Chapter(chapter)
+        } else if let Some(title) = &self.section {
+            StartPosition::Section(title.clone())
+        } else if let Some(percent) = self.percent {
+            StartPosition::Percent(percent)
+        } else if let Some(query) = &self.search {
+            StartPosition::Search(query.clone())
+        } else {
+            StartPosition::Bookmark(self.bookmark.clone()?)
+        };
+        Some(position)
+    }
 }
 
 impl Cli {
     /// Validate CLI arguments
-    /// Returns error if max_width is out of bounds (40-200)
+    /// Returns error if max_width or the dump width is out of bounds (40-200),
+    /// the chapter or percent is out of range, the image protocol, export or
+    /// dump format is unknown, the CFI, chapter range or a search pattern is
+    /// malformed, or `reef remote goto` has no position (grep patterns are
+    /// checked by `reef grep`, which reports errors with exit status 2)
     pub fn validate(&self) -> Result<(), String> {
-        if let Some(width) = self.max_width {
-            if width < MIN_MAX_WIDTH {
-                return Err(format!("Max width too small (minimum {})", MIN_MAX_WIDTH));
+        match &self.command {
+            Some(Command::Export { format, .. }) => {
+                ExportFormat::parse(format)?;
             }
-            if width > MAX_MAX_WIDTH {
-                return Err(format!("Max width too large (maximum {})", MAX_MAX_WIDTH));
+            Some(Command::Dump {
+                format,
+                width,
+                chapters,
+                ..
+            }) => {
+                DumpFormat::parse(format)?;
+                if let Some(chapters) = chapters {
+                    ChapterRange::parse(chapters)?;
+                }
+                check_width("--width", *width)?;
             }
+            Some(Command::Remote { command, .. }) => match command {
+                RemoteCommand::Open { position, .. } => {
+                    if let Some(position) = position.position() {
+                        check_position(&position)?;
+                    }
+                }
+                RemoteCommand::Goto { position } => {
+                    let position = position.position().ok_or(
+                        "goto needs a position: --cfi, --chapter, --section, --percent, \
+                         --search or --bookmark",
+                    )?;
+                    check_position(&position)?;
+                }
+                RemoteCommand::Search { query } => {
+                    SearchEngine::compile(query, false, false)?;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        if let Some(images) = &self.images {
+            GraphicsProtocol::parse(images)?;
+        }
+
+        if let Some(position) = self.position.position() {
+            check_position(&position)?;
+        }
+
+        if let Some(width) = self.max_width {
+            check_width("--max-width", width)?;
         }
         Ok(())
     }
 }
+
+fn check_position(position: &StartPosition) -> Result<(), String> {
+    match position {
+        StartPosition::Cfi(cfi) => {
+            Cfi::parse(cfi).map_err(|e| format!("Invalid CFI: {}", e))?;
+        }
+        StartPosition::Chapter(0) => return Err("Chapters are numbered from 1".to_string()),
+        StartPosition::Percent(percent) if *percent > 100 => {
+            return Err(format!("Percent must be 0-100, got {}", percent));
+        }
+        StartPosition::Search(query) => {
+            SearchEngine::compile(query, false, false)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Check a width option, naming the option in the error
+fn check_width(option: &str, width: usize) -> Result<(), String> {
+    if !(MIN_MAX_WIDTH..=MAX_MAX_WIDTH).contains(&width) {
+        return Err(format!(
+            "{} must be {}-{}, got {}",
+            option, MIN_MAX_WIDTH, MAX_MAX_WIDTH, width
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Copying text out of the reader
+//!
+//! Turns rendered lines back into plain text and hands it to the system
+//! clipboard. Wrapped prose is joined into whole paragraphs again, while code
+//! is taken from the document model so it keeps its original lines and
+//! indentation whatever the wrap mode. The text is delivered through the
+//! OSC 52 escape sequence, which works over SSH and in most terminals, and
+//! through an external command such as `wl-copy` when one is configured.
+
+use crate::constants::MAX_OSC52_PAYLOAD;
+use crate::types::{Chapter, ContentAnchor, LineStyle};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::process::{Command, Stdio};
+
+/// Build the OSC 52 sequence that sets the clipboard to `text`
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", BASE64.encode(text))
+}
+
+/// Copy text to the clipboard
+///
+/// # Arguments
+/// * `out` - Terminal output the OSC 52 sequence is written to
+/// * `text` - Text to copy
+/// * `command` - Optional external command that receives the text on stdin
+///
+/// # Returns
+/// * `Ok(())` - The text was handed to the terminal or the command
+/// * `Err(String)` - Neither way could deliver the text
+pub fn copy(out: &mut impl Write, text: &str, command: Option<&str>) -> Result<(), String> {
+    let sequence = osc52(text);
+    let sent_osc52 = sequence.len() <= MAX_OSC52_PAYLOAD
+        && out
+            .write_all(sequence.as_bytes())
+            .and_then(|_| out.flush())
+            .is_ok();
+
+    match command {
+        Some(command) => run_command(command, text).or_else(|e| {
+            if sent_osc52 {
+                log::warn!("Clipboard command failed, relying on OSC 52: {}", e);
+                Ok(())
+            } else {
+                Err(e)
+            }
+        }),
+        None if sent_osc52 => Ok(()),
+        None => Err(format!(
+            "{} bytes is too much for OSC 52, set clipboard_command in the config",
+            text.len()
+        )),
+    }
+}
+
+/// Pipe text into an external clipboard command such as `wl-copy` or `xclip -sel c`
+fn run_command(command: &str, text: &str) -> Result<(), String> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| "Clipboard command is empty".to_string())?;
+
+    let mut child = Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Cannot run '{}': {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Cannot write to '{}': {}", program, e))?;
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("'{}' did not finish: {}", program, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("'{}' failed with {}", program, status))
+    }
+}
+
+/// Plain text of the code block containing `line`, with its original indentation
+///
+/// # Returns
+/// * `Some(text)` - The whole code block, one source line per line
+/// * `None` - The line is not part of a code block
+pub fn code_block_text(chapter: &Chapter, line: usize) -> Option<String> {
+    let block = chapter.code_block_at(line)?;
+    Some(selection_text(chapter, block.start..=block.end - 1))
+}
+
+/// Plain text of a range of rendered lines
+///
+/// Rows of a wrapped paragraph are joined back into one line, code rows are
+/// replaced by the source lines they show and image rows are left out.
+pub fn selection_text(chapter: &Chapter, lines: RangeInclusive<usize>) -> String {
+    let end = (*lines.end()).min(chapter.content_lines.len().saturating_sub(1));
+    let Some(rows) = chapter.content_lines.get(*lines.start()..=end) else {
+        return String::new();
+    };
+
+    let mut out: Vec<String> = Vec::new();
+    let mut last_code: Option<(&[usize], usize)> = None;
+    let mut last_prose: Option<&ContentAnchor> = None;
+
+    for row in rows {
+        match (&row.style, &row.anchor) {
+            (LineStyle::Image { .. }, _) => {
+                last_code = None;
+                last_prose = None;
+            }
+            (LineStyle::CodeBlock { .. }, Some(anchor)) => {
+                last_prose = None;
+                let Some(code_lines) = chapter.document.code_lines(&anchor.element_path) else {
+                    out.push(row.text.clone());
+                    continue;
+                };
+                // Soft-wrapped rows point into the middle of their source line
+                let idx = code_lines
+                    .iter()
+                    .rposition(|l| l.offset <= anchor.char_offset)
+                    .unwrap_or(0);
+                let key = (anchor.element_path.as_slice(), idx);
+                if last_code != Some(key) {
+                    out.push(code_lines[idx].text.clone());
+                    last_code = Some(key);
+                }
+            }
+            (_, Some(anchor))
+                if last_prose.is_some_and(|prev| {
+                    prev.element_path == anchor.element_path
+                        && prev.char_offset < anchor.char_offset
+                }) =>
+            {
+                // Continuation of a wrapped paragraph
+                if let Some(last) = out.last_mut() {
+                    if !last.ends_with('-') {
+                        last.push(' ');
+                    }
+                    last.push_str(row.text.trim_end());
+                }
+                last_prose = Some(anchor);
+            }
+            _ => {
+                out.push(row.text.trim_end().to_string());
+                last_code = None;
+                last_prose = row.anchor.as_ref();
+            }
+        }
+    }
+
+    // Blank separators at the edges of a selection carry no content
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+    let leading = out.iter().take_while(|l| l.is_empty()).count();
+    out.drain(..leading);
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+
+    fn chapter(html: &str, wrap_code: bool) -> Chapter {
+        let mut chapter = Chapter {
+            title: "Test".to_string(),
+            sections: Vec::new(),
+            content_lines: Vec::new(),
+            file_path: html.to_string(),
+            href: "test.xhtml".to_string(),
+            document: crate::epub::document::build_document(html),
+        };
+        render_chapter(&mut chapter, Some(24), 100, wrap_code);
+        chapter
+    }
+
+    const HTML: &str = "<p>A paragraph long enough to wrap over several rows.</p>\
+        <pre><code>fn main() {\n    println!(\"a rather long line of code\");\n}</code></pre>";
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(osc52("hi"), "\x1b]52;c;aGk=\x07");
+    }
+
+    #[test]
+    fn test_selection_joins_wrapped_prose() {
+        let chapter = chapter(HTML, false);
+        let last = chapter.content_lines.len() - 1;
+        assert_eq!(
+            selection_text(&chapter, 0..=last),
+            "A paragraph long enough to wrap over several rows.\n\n\
+             fn main() {\n    println!(\"a rather long line of code\");\n}"
+        );
+    }
+
+    #[test]
+    fn test_code_block_keeps_source_lines_when_wrapped() {
+        let chapter = chapter(HTML, true);
+        let code_row = chapter
+            .content_lines
+            .iter()
+            .position(|l| l.text.contains("println"))
+            .unwrap();
+        assert!(chapter.content_lines[code_row + 1].text.contains('↪'));
+        assert_eq!(
+            code_block_text(&chapter, code_row + 1).unwrap(),
+            "fn main() {\n    println!(\"a rather long line of code\");\n}"
+        );
+        assert!(code_block_text(&chapter, 0).is_none());
+    }
+
+    #[test]
+    fn test_copy_without_command() {
+        let mut out = Vec::new();
+        copy(&mut out, "text", None).unwrap();
+        assert_eq!(out, osc52("text").into_bytes());
+
+        let mut out = Vec::new();
+        assert!(copy(&mut out, &"x".repeat(MAX_OSC52_PAYLOAD), None).is_err());
+        assert!(out.is_empty());
+    }
+}
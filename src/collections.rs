@@ -0,0 +1,165 @@
+//! Reading status and tags of books
+//!
+//! Books are sorted into reading lists with a [`ReadingStatus`] and any
+//! number of free-form tags. Both are stored per book identity in
+//! `collections.json` by [`crate::persistence::PersistenceManager`], and the
+//! library view can be filtered by them with `@status` and `#tag` words.
+
+use serde::{Deserialize, Serialize};
+
+/// Where a book stands in the user's reading lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadingStatus {
+    ToRead,
+    Reading,
+    Finished,
+    /// Consulted rather than read through; never marked finished
+    Reference,
+}
+
+impl ReadingStatus {
+    pub const ALL: [ReadingStatus; 4] = [
+        ReadingStatus::ToRead,
+        ReadingStatus::Reading,
+        ReadingStatus::Finished,
+        ReadingStatus::Reference,
+    ];
+
+    /// The status after `status`, cycling through no status at all
+    pub fn cycle(status: Option<Self>) -> Option<Self> {
+        match status {
+            None => Some(Self::ALL[0]),
+            Some(status) => {
+                let idx = Self::ALL.iter().position(|&s| s == status).unwrap_or(0);
+                Self::ALL.get(idx + 1).copied()
+            }
+        }
+    }
+
+    /// Display name of the status, as used in filters
+    pub fn name(self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "to-read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+            ReadingStatus::Reference => "reference",
+        }
+    }
+}
+
+/// Reading status and tags of one book
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookCollections {
+    #[serde(default)]
+    pub status: Option<ReadingStatus>,
+    /// Normalized tags, sorted
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl BookCollections {
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none() && self.tags.is_empty()
+    }
+
+    /// Whether the book matches a `#tag` or `@status` filter word
+    ///
+    /// Both match by prefix, so `#fic` finds books tagged `fiction`.
+    pub fn matches(&self, word: &str) -> bool {
+        if let Some(tag) = word.strip_prefix('#') {
+            self.tags.iter().any(|t| t.starts_with(tag))
+        } else if let Some(status) = word.strip_prefix('@') {
+            self.status.is_some_and(|s| s.name().starts_with(status))
+        } else {
+            false
+        }
+    }
+}
+
+/// Utilities for editing reading status and tags
+pub struct CollectionManager;
+
+impl CollectionManager {
+    /// Parse a comma separated list of tags as typed by the user
+    ///
+    /// Tags are lowercased, a leading `#` is dropped and inner whitespace
+    /// becomes `-`, so `#Science Fiction` and `science-fiction` are the same.
+    ///
+    /// # Returns
+    /// The distinct tags, sorted
+    pub fn parse_tags(input: &str) -> Vec<String> {
+        let mut tags: Vec<String> = input
+            .split(',')
+            .map(|tag| {
+                tag.trim()
+                    .trim_start_matches('#')
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join("-")
+                    .to_lowercase()
+            })
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Tags in the form [`parse_tags`](Self::parse_tags) reads back
+    pub fn format_tags(tags: &[String]) -> String {
+        tags.join(", ")
+    }
+
+    /// Whether reaching the end of a book should mark it finished
+    pub fn finishes(status: Option<ReadingStatus>) -> bool {
+        !matches!(
+            status,
+            Some(ReadingStatus::Finished | ReadingStatus::Reference)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            CollectionManager::parse_tags(" #Science  Fiction, classics,,science-fiction "),
+            vec!["classics", "science-fiction"]
+        );
+        assert!(CollectionManager::parse_tags(" , ").is_empty());
+
+        let tags = CollectionManager::parse_tags("b, a");
+        assert_eq!(CollectionManager::format_tags(&tags), "a, b");
+    }
+
+    #[test]
+    fn test_status_cycle_and_filters() {
+        let mut status = None;
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            status = ReadingStatus::cycle(status);
+            seen.push(status);
+        }
+        assert_eq!(seen[0], Some(ReadingStatus::ToRead));
+        assert_eq!(seen[3], Some(ReadingStatus::Reference));
+        assert_eq!(seen[4], None);
+
+        let book = BookCollections {
+            status: Some(ReadingStatus::ToRead),
+            tags: vec!["fiction".to_string()],
+        };
+        assert!(book.matches("#fic"));
+        assert!(!book.matches("#classics"));
+        assert!(book.matches("@to"));
+        assert!(!book.matches("@reading"));
+        assert!(!book.matches("fiction"));
+
+        assert!(CollectionManager::finishes(Some(ReadingStatus::Reading)));
+        assert!(CollectionManager::finishes(None));
+        assert!(!CollectionManager::finishes(Some(ReadingStatus::Reference)));
+    }
+}
@@ -18,6 +18,9 @@ pub const MAX_SEARCH_INPUT_LENGTH: usize = 500;
 /// Maximum length for bookmark label input (characters)
 pub const MAX_BOOKMARK_INPUT_LENGTH: usize = 100;
 
+/// Maximum length for highlight note input (characters)
+pub const MAX_NOTE_INPUT_LENGTH: usize = 1000;
+
 /// Minimum allowed value for max_width CLI parameter
 pub const MIN_MAX_WIDTH: usize = 40;
 
@@ -48,5 +51,38 @@ pub const MIN_BOOKMARKS_PANEL_WIDTH: u16 = 20;
 /// Maximum width for bookmarks panel
 pub const MAX_BOOKMARKS_PANEL_WIDTH: u16 = 80;
 
+/// Characters used to build link-hint labels (home row first)
+pub const LINK_HINT_CHARS: &str = "asdfghjkl";
+
+/// Maximum number of positions kept in the jump list
+pub const MAX_JUMP_LIST_SIZE: usize = 100;
+
+/// Nominal terminal cell width in pixels, used to size images in the layout
+pub const NOMINAL_CELL_WIDTH: u32 = 8;
+
+/// Nominal terminal cell height in pixels, used to size images in the layout
+pub const NOMINAL_CELL_HEIGHT: u32 = 16;
+
+/// Maximum number of rows an inline image may occupy
+pub const MAX_IMAGE_ROWS: usize = 24;
+
+/// Maximum number of decoded (or scaled) images kept in memory
+pub const IMAGE_CACHE_SIZE: usize = 16;
+
+/// Largest zoom factor in the image viewer
+pub const MAX_IMAGE_ZOOM: f64 = 8.0;
+
+/// Zoom factor applied per zoom step in the image viewer
+pub const IMAGE_ZOOM_STEP: f64 = 1.5;
+
+/// Fraction of the visible region moved per pan step in the image viewer
+pub const IMAGE_PAN_STEP: f64 = 0.25;
+
+/// Columns a code block scrolls per horizontal scroll step
+pub const CODE_SCROLL_STEP: isize = 8;
+
+/// Largest OSC 52 sequence sent to the terminal; longer text needs a clipboard command
+pub const MAX_OSC52_PAYLOAD: usize = 100_000;
+
 /// Debounce timeout for terminal resize events in milliseconds
 pub const RESIZE_DEBOUNCE_MS: u64 = 200;
@@ -0,0 +1,615 @@
+//! Printing books as text
+//!
+//! `reef dump` writes the chapters of a book to stdout without opening the
+//! reader. Plain text is laid out by the renderer exactly as the reader shows
+//! it, wrapped at a given width. Paragraph output puts every block on a single
+//! line for tools that wrap text themselves, and Markdown output keeps
+//! headings, lists, code blocks with their languages, emphasis and links.
+
+use crate::constants::UI_MARGIN_WIDTH;
+use crate::epub::document::{Block, BlockKind, InlineText, TableRow, TextBlock};
+use crate::epub::render_chapter;
+use crate::types::{Book, InlineStyle, LineStyle, RenderedLine};
+use std::ops::Range;
+
+/// Output formats of a dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Rendered lines wrapped at the dump width
+    Text,
+    /// One line per paragraph
+    Paragraphs,
+    Markdown,
+}
+
+impl DumpFormat {
+    /// Parse a format name as given on the command line
+    ///
+    /// # Returns
+    /// * `Ok(DumpFormat)` - For `text`/`txt`, `paragraphs` or `markdown`/`md`
+    /// * `Err(String)` - Unknown format name
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" | "txt" => Ok(DumpFormat::Text),
+            "paragraphs" => Ok(DumpFormat::Paragraphs),
+            "markdown" | "md" => Ok(DumpFormat::Markdown),
+            other => Err(format!(
+                "Unknown dump format '{}' (expected text, paragraphs or markdown)",
+                other
+            )),
+        }
+    }
+}
+
+/// Chapters selected for a dump, 1-based and inclusive as typed by the user
+///
+/// The default range selects the whole book.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChapterRange {
+    pub first: Option<usize>,
+    pub last: Option<usize>,
+}
+
+impl ChapterRange {
+    /// Parse a chapter range such as `3`, `2-5`, `4-` or `-2`
+    ///
+    /// # Returns
+    /// * `Ok(ChapterRange)` - The chapters selected by the range
+    /// * `Err(String)` - Malformed, zero or reversed chapter numbers
+    pub fn parse(range: &str) -> Result<Self, String> {
+        let number = |part: &str| -> Result<Option<usize>, String> {
+            let part = part.trim();
+            if part.is_empty() {
+                return Ok(None);
+            }
+            match part.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(format!("Invalid chapter number '{}'", part)),
+            }
+        };
+
+        let (first, last) = match range.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => {
+                let chapter = number(range)?;
+                if chapter.is_none() {
+                    return Err("Empty chapter range".to_string());
+                }
+                (chapter, chapter)
+            }
+        };
+        if let (Some(first), Some(last)) = (first, last)
+            && first > last
+        {
+            return Err(format!("Chapter range {}-{} is reversed", first, last));
+        }
+        Ok(ChapterRange { first, last })
+    }
+
+    /// Chapter indices of the range within a book
+    ///
+    /// # Arguments
+    /// * `total` - Number of chapters in the book
+    ///
+    /// # Errors
+    /// Returns an error when the range starts past the last chapter
+    pub fn indices(&self, total: usize) -> Result<Range<usize>, String> {
+        let start = self.first.unwrap_or(1) - 1;
+        if start >= total {
+            return Err(format!(
+                "Chapter {} does not exist (the book has {} chapters)",
+                start + 1,
+                total
+            ));
+        }
+        let end = self.last.unwrap_or(total).min(total);
+        Ok(start..end)
+    }
+}
+
+/// Write chapters of a book as text
+///
+/// # Arguments
+/// * `book` - Parsed book; chapters are re-rendered for the text format
+/// * `chapters` - Indices of the chapters to write
+/// * `format` - Output format
+/// * `width` - Line width of the text format
+///
+/// # Returns
+/// The chapters separated by blank lines, ending with a newline
+pub fn dump_book(
+    book: &mut Book,
+    chapters: Range<usize>,
+    format: DumpFormat,
+    width: usize,
+) -> String {
+    let mut parts = Vec::new();
+    for chapter in &mut book.chapters[chapters] {
+        let text = match format {
+            DumpFormat::Text => {
+                // The renderer keeps a margin for the reader's UI, add it back
+                let terminal_width = (width + UI_MARGIN_WIDTH).min(u16::MAX as usize) as u16;
+                render_chapter(chapter, None, terminal_width, true);
+                text_lines(&chapter.content_lines)
+            }
+            DumpFormat::Paragraphs => {
+                let mut writer = BlockWriter::new(false);
+                writer.blocks(&chapter.document.blocks);
+                writer.finish()
+            }
+            DumpFormat::Markdown => {
+                let mut writer = BlockWriter::new(true);
+                writer.blocks(&chapter.document.blocks);
+                writer.finish()
+            }
+        };
+        if !text.is_empty() {
+            parts.push(text);
+        }
+    }
+
+    let mut output = parts.join("\n\n");
+    output.push('\n');
+    output
+}
+
+/// Rendered lines as text, without image rows and runs of blank lines
+fn text_lines(lines: &[RenderedLine]) -> String {
+    let mut text = String::new();
+    let mut blank = true;
+    for line in lines {
+        if matches!(line.style, LineStyle::Image { .. }) {
+            continue;
+        }
+        let content = line.text.trim_end();
+        if content.is_empty() {
+            if !blank {
+                text.push('\n');
+            }
+            blank = true;
+            continue;
+        }
+        text.push_str(content);
+        text.push('\n');
+        blank = false;
+    }
+    text.trim_end().to_string()
+}
+
+/// Writes document blocks as unwrapped paragraphs or Markdown
+struct BlockWriter {
+    markdown: bool,
+    /// Finished paragraphs, joined by blank lines
+    paragraphs: Vec<String>,
+}
+
+impl BlockWriter {
+    fn new(markdown: bool) -> Self {
+        BlockWriter {
+            markdown,
+            paragraphs: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> String {
+        self.paragraphs.join("\n\n")
+    }
+
+    fn push(&mut self, paragraph: String) {
+        if !paragraph.trim().is_empty() {
+            self.paragraphs.push(paragraph);
+        }
+    }
+
+    fn inline(&self, content: &InlineText) -> String {
+        if self.markdown {
+            let text = markdown_inline(content);
+            // Text that would read as a heading, quote or list item
+            if text.starts_with(['#', '>']) || text.starts_with("- ") || text.starts_with("+ ") {
+                format!("\\{}", text)
+            } else {
+                text
+            }
+        } else {
+            content.collapsed().text
+        }
+    }
+
+    fn blocks(&mut self, blocks: &[Block]) {
+        for block in blocks {
+            self.block(&block.kind);
+        }
+    }
+
+    fn block(&mut self, kind: &BlockKind) {
+        match kind {
+            BlockKind::Heading { level, content } => {
+                let text = self.inline(content);
+                if text.is_empty() {
+                    // Spacer headings some books use for layout
+                } else if self.markdown {
+                    let level = (*level).clamp(1, 6) as usize;
+                    self.push(format!("{} {}", "#".repeat(level), text));
+                } else {
+                    self.push(text);
+                }
+            }
+            BlockKind::Paragraph(content)
+            | BlockKind::Link(content)
+            | BlockKind::Inline(content) => {
+                let text = self.inline(content);
+                self.push(text);
+            }
+            BlockKind::Quote(content) => {
+                let text = self.inline(content);
+                if self.markdown {
+                    self.push(format!("> {}", text));
+                } else {
+                    self.push(text);
+                }
+            }
+            BlockKind::Code { language, lines } => {
+                let code = lines
+                    .iter()
+                    .map(|line| line.text.trim_end())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if self.markdown {
+                    let fence = code_fence(&code);
+                    let language = language.as_deref().unwrap_or("");
+                    self.push(format!("{}{}\n{}\n{}", fence, language, code, fence));
+                } else {
+                    self.push(code);
+                }
+            }
+            BlockKind::Image {
+                src, alt, caption, ..
+            } => {
+                let alt = alt.trim();
+                if self.markdown {
+                    let src = src.as_deref().unwrap_or("");
+                    self.push(format!("![{}]({})", escape_markdown(alt), link_target(src)));
+                } else if alt.is_empty() {
+                    self.push("[Image]".to_string());
+                } else {
+                    self.push(format!("[Image: {}]", alt));
+                }
+                if let Some(caption) = caption {
+                    let caption = InlineText::plain(caption.as_str());
+                    let text = self.inline(&caption);
+                    self.push(text);
+                }
+            }
+            BlockKind::List { ordered, items } => {
+                let list = self.list(*ordered, items);
+                self.push(list);
+            }
+            BlockKind::Navigation { links } => {
+                let list = self.list(false, links);
+                self.push(list);
+            }
+            BlockKind::DefinitionList {
+                terms,
+                descriptions,
+            } => {
+                for term in terms {
+                    let text = self.inline(&term.content);
+                    if self.markdown {
+                        self.push(format!("**{}**", text));
+                    } else {
+                        self.push(text);
+                    }
+                }
+                for description in descriptions {
+                    let text = self.inline(&description.content);
+                    if self.markdown {
+                        self.push(format!(": {}", text));
+                    } else {
+                        self.push(format!("  {}", text));
+                    }
+                }
+            }
+            BlockKind::Table { rows, caption } => {
+                if let Some(caption) = caption {
+                    let text = self.inline(caption);
+                    self.push(text);
+                }
+                let table = self.table(rows);
+                self.push(table);
+            }
+            BlockKind::Rule => {
+                let rule = if self.markdown { "---" } else { "* * *" };
+                self.push(rule.to_string());
+            }
+            BlockKind::Aside { children, .. } => {
+                let mut inner = BlockWriter::new(self.markdown);
+                inner.blocks(children);
+                let text = inner.finish();
+                if self.markdown {
+                    let quoted = text
+                        .lines()
+                        .map(|line| format!("> {}", line).trim_end().to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.push(quoted);
+                } else {
+                    self.push(text);
+                }
+            }
+            BlockKind::Figure(children) => self.blocks(children),
+        }
+    }
+
+    fn list(&self, ordered: bool, items: &[TextBlock]) -> String {
+        items
+            .iter()
+            .map(|item| self.inline(&item.content))
+            .filter(|text| !text.is_empty())
+            .enumerate()
+            .map(|(idx, text)| {
+                let marker = match (ordered, self.markdown) {
+                    (true, _) => format!("{}. ", idx + 1),
+                    (false, true) => "- ".to_string(),
+                    (false, false) => "• ".to_string(),
+                };
+                format!("{}{}", marker, text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn table(&self, rows: &[TableRow]) -> String {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| self.inline(&cell.content))
+                    .collect()
+            })
+            .collect();
+
+        if !self.markdown {
+            return cells
+                .iter()
+                .map(|row| row.join("\t"))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        // Markdown tables need a header row; the first row serves as one
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return String::new();
+        }
+        let row_line = |row: &[String]| {
+            let mut line = String::from("|");
+            for col in 0..columns {
+                let cell = row.get(col).map(|c| c.replace('|', "\\|"));
+                line.push_str(&format!(" {} |", cell.unwrap_or_default()));
+            }
+            line
+        };
+        let mut lines = vec![
+            row_line(&cells[0]),
+            format!("|{}", " --- |".repeat(columns)),
+        ];
+        lines.extend(cells[1..].iter().map(|row| row_line(row)));
+        lines.join("\n")
+    }
+}
+
+/// Markdown delimiters of an inline style, if it has any
+fn style_marker(style: &InlineStyle) -> Option<&'static str> {
+    match style {
+        InlineStyle::Bold => Some("**"),
+        InlineStyle::Italic => Some("*"),
+        InlineStyle::Strikethrough => Some("~~"),
+        InlineStyle::Code => Some("`"),
+        InlineStyle::Underline | InlineStyle::Highlight | InlineStyle::NoteRef => None,
+    }
+}
+
+/// A span of inline markup open at some point of the text
+#[derive(Debug, Clone, PartialEq)]
+enum Markup {
+    /// Index into the text's links
+    Link(usize),
+    Style(&'static str),
+}
+
+/// Inline text as Markdown, with emphasis, code spans and links
+///
+/// Whitespace at the edges of a span is moved outside of it, since Markdown
+/// does not allow delimiters next to the inner whitespace.
+fn markdown_inline(content: &InlineText) -> String {
+    let content = content.collapsed();
+    let text = &content.text;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+
+    // Markup active at every character, outermost first
+    let active = |offset: usize| -> Vec<Markup> {
+        let mut markup: Vec<Markup> = content
+            .links
+            .iter()
+            .enumerate()
+            .filter(|(_, (start, end, _))| (*start..*end).contains(&offset))
+            .map(|(idx, _)| Markup::Link(idx))
+            .take(1)
+            .collect();
+        for marker in ["**", "*", "~~", "`"] {
+            let styled = content.styles.iter().any(|(start, end, style)| {
+                style_marker(style) == Some(marker) && (*start..*end).contains(&offset)
+            });
+            if styled {
+                markup.push(Markup::Style(marker));
+            }
+        }
+        markup
+    };
+    let mut states: Vec<Vec<Markup>> = chars.iter().map(|(offset, _)| active(*offset)).collect();
+
+    // Spaces only keep markup shared by both of their neighbours
+    for idx in 0..chars.len() {
+        if chars[idx].1 == ' ' {
+            let before = idx.checked_sub(1).map(|i| states[i].clone());
+            let after = states.get(idx + 1).cloned();
+            states[idx] = match (before, after) {
+                (Some(before), Some(after)) => before
+                    .into_iter()
+                    .filter(|markup| after.contains(markup))
+                    .collect(),
+                _ => Vec::new(),
+            };
+        }
+    }
+
+    let close = |markup: &Markup, output: &mut String| match markup {
+        Markup::Link(idx) => {
+            output.push_str("](");
+            output.push_str(&link_target(&content.links[*idx].2));
+            output.push(')');
+        }
+        Markup::Style(marker) => output.push_str(marker),
+    };
+
+    let mut output = String::with_capacity(text.len());
+    let mut open: Vec<Markup> = Vec::new();
+    for ((_, ch), state) in chars.iter().zip(&states) {
+        // Close everything above the first span that ends here
+        if let Some(keep) = open.iter().position(|markup| !state.contains(markup)) {
+            for markup in open.drain(keep..).rev() {
+                close(&markup, &mut output);
+            }
+        }
+        for markup in state {
+            if !open.contains(markup) {
+                output.push_str(match markup {
+                    Markup::Link(_) => "[",
+                    Markup::Style(marker) => marker,
+                });
+                open.push(markup.clone());
+            }
+        }
+
+        if open.contains(&Markup::Style("`")) {
+            output.push(*ch);
+        } else {
+            push_escaped(&mut output, *ch);
+        }
+    }
+    for markup in open.iter().rev() {
+        close(markup, &mut output);
+    }
+    output
+}
+
+fn push_escaped(output: &mut String, ch: char) {
+    if matches!(ch, '\\' | '`' | '*' | '_' | '[' | ']') {
+        output.push('\\');
+    }
+    output.push(ch);
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for ch in text.chars() {
+        push_escaped(&mut output, ch);
+    }
+    output
+}
+
+/// Link target with the characters that would end it early encoded
+fn link_target(href: &str) -> String {
+    href.replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+/// A code fence longer than any backtick run inside the code
+fn code_fence(code: &str) -> String {
+    let longest = code.split(|ch| ch != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BookMetadata, Chapter};
+
+    fn book(chapters: &[&str]) -> Book {
+        Book {
+            metadata: BookMetadata {
+                title: "Test".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: chapters
+                .iter()
+                .map(|html| Chapter {
+                    title: String::new(),
+                    sections: Vec::new(),
+                    content_lines: Vec::new(),
+                    file_path: html.to_string(),
+                    href: "test.xhtml".to_string(),
+                    document: crate::epub::document::build_document(html),
+                })
+                .collect(),
+            images: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_chapter_ranges() {
+        let range = |s| ChapterRange::parse(s).and_then(|r| r.indices(10));
+        assert_eq!(range("3"), Ok(2..3));
+        assert_eq!(range("2-5"), Ok(1..5));
+        assert_eq!(range("8-"), Ok(7..10));
+        assert_eq!(range("-2"), Ok(0..2));
+        assert_eq!(range("9-20"), Ok(8..10));
+        assert!(range("11").is_err());
+        assert!(range("0").is_err());
+        assert!(range("5-3").is_err());
+        assert!(range("x").is_err());
+        assert!(range("").is_err());
+    }
+
+    #[test]
+    fn test_markdown_keeps_structure() {
+        let mut book = book(&[
+            "<h1>One</h1><p>Some <b>bold</b> and <i>italic </i>text with \
+             <a href=\"notes.xhtml#n1\">a <code>link</code></a>.</p>\
+             <ul><li>first</li><li>second</li></ul>\
+             <pre><code class=\"language-rust\">fn main() {}</code></pre>",
+            "<h2>Two</h2><p>2 * 3 = 6</p>",
+        ]);
+        let markdown = dump_book(&mut book, 0..2, DumpFormat::Markdown, 80);
+        assert_eq!(
+            markdown,
+            "# One\n\n\
+             Some **bold** and *italic* text with [a `link`](notes.xhtml#n1).\n\n\
+             - first\n- second\n\n\
+             ```rust\nfn main() {}\n```\n\n\
+             ## Two\n\n\
+             2 \\* 3 = 6\n"
+        );
+    }
+
+    #[test]
+    fn test_text_wraps_and_paragraphs_do_not() {
+        let paragraph = "A paragraph long enough to wrap over several rows of text.";
+        let html = format!("<p>{}</p><p>Next.</p>", paragraph);
+        let mut book = book(&[html.as_str()]);
+
+        let text = dump_book(&mut book, 0..1, DumpFormat::Text, 20);
+        assert!(text.lines().all(|line| line.chars().count() <= 20));
+        assert!(text.lines().count() > 3);
+        assert!(text.ends_with("\n\nNext.\n"));
+
+        let paragraphs = dump_book(&mut book, 0..1, DumpFormat::Paragraphs, 20);
+        assert_eq!(paragraphs, format!("{}\n\nNext.\n", paragraph));
+    }
+}
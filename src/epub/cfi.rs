@@ -0,0 +1,562 @@
+//! EPUB Canonical Fragment Identifier (CFI) support
+//!
+//! CFIs are the standard way EPUB reading systems (Calibre, KOReader, Thorium)
+//! exchange locations. This module converts between CFIs and reef's
+//! `ContentAnchor`s. Only the subset of the spec needed for reading positions
+//! is supported: a spine step, an indirection into the content document,
+//! element and text steps with optional id assertions, and a terminating
+//! character offset. Ranges and spatial/temporal offsets are rejected.
+//!
+//! Character offsets are counted in Unicode scalar values.
+
+use crate::epub::document::element_path;
+use crate::epub::renderer::text_prefix_len;
+use crate::types::{Book, Chapter, ContentAnchor};
+use scraper::{ElementRef, Html};
+use std::fmt;
+
+/// Step index of the `<spine>` element inside the package document
+///
+/// The spine is the third child of `<package>` (after metadata and manifest)
+/// in virtually every EPUB, which is also what other reading systems assume.
+const SPINE_STEP: usize = 6;
+
+/// A single step in a CFI path
+///
+/// Even indices address child elements, odd indices address the text
+/// between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfiStep {
+    pub index: usize,
+    /// Optional id assertion (`/4[chap01]`) used to recover from restructured documents
+    pub assertion: Option<String>,
+}
+
+/// A parsed EPUB CFI pointing at a location inside a spine item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfi {
+    /// Zero-based index of the spine item (equal to reef's chapter index)
+    pub spine_idx: usize,
+    /// Steps inside the content document, starting below the `<html>` element
+    pub steps: Vec<CfiStep>,
+    /// Character offset into the text addressed by the last step
+    pub offset: Option<usize>,
+}
+
+impl Cfi {
+    /// Parse a CFI string such as `epubcfi(/6/4[ch01]!/4/10/3:12)`
+    ///
+    /// # Returns
+    /// * `Ok(Cfi)` - Successfully parsed location
+    /// * `Err(String)` - Malformed or unsupported CFI
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let inner = input
+            .trim()
+            .strip_prefix("epubcfi(")
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| format!("Not an EPUB CFI: '{}'", input))?;
+
+        if inner.contains(',') {
+            return Err("CFI ranges are not supported".to_string());
+        }
+
+        let (package_path, content_path) = inner
+            .split_once('!')
+            .ok_or_else(|| "CFI has no content document indirection ('!')".to_string())?;
+
+        // Package path: /6/N - the last step selects the spine itemref
+        let (package_steps, _) = parse_steps(package_path)?;
+        let itemref = match package_steps.as_slice() {
+            [_, .., last] => last,
+            _ => return Err("CFI package path must address a spine item".to_string()),
+        };
+        if itemref.index < 2 || itemref.index % 2 != 0 {
+            return Err(format!("Invalid spine step: {}", itemref.index));
+        }
+
+        let (steps, offset) = parse_steps(content_path)?;
+        if steps.is_empty() {
+            return Err("CFI has an empty content document path".to_string());
+        }
+        if steps.iter().any(|s| s.index == 0) {
+            return Err("CFI step indices start at 1".to_string());
+        }
+        if steps[..steps.len() - 1].iter().any(|s| s.index % 2 != 0) {
+            return Err("CFI text steps may only appear last".to_string());
+        }
+
+        Ok(Cfi {
+            spine_idx: itemref.index / 2 - 1,
+            steps,
+            offset,
+        })
+    }
+}
+
+impl fmt::Display for Cfi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "epubcfi(/{}/{}!", SPINE_STEP, (self.spine_idx + 1) * 2)?;
+        for step in &self.steps {
+            write!(f, "/{}", step.index)?;
+            if let Some(assertion) = &step.assertion {
+                write!(f, "[{}]", escape_assertion(assertion))?;
+            }
+        }
+        if let Some(offset) = self.offset {
+            write!(f, ":{}", offset)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Generate a CFI for a content anchor in a chapter
+///
+/// # Returns
+/// * `Some(String)` - CFI string for the anchor position
+/// * `None` - Chapter doesn't exist or has no `<body>`
+pub fn generate(book: &Book, chapter_idx: usize, anchor: &ContentAnchor) -> Option<String> {
+    let chapter = book.chapters.get(chapter_idx)?;
+    let html = Html::parse_document(&chapter.file_path);
+    let root = html.root_element();
+
+    let (body_idx, body) = child_elements(root)
+        .enumerate()
+        .find(|(_, el)| el.value().name() == "body")?;
+    let mut steps = vec![element_step(body_idx, body)];
+
+    // Descend to the block element the anchor refers to
+    let mut element = body;
+    for &idx in &anchor.element_path {
+        match child_elements(element).nth(idx) {
+            Some(child) => {
+                steps.push(element_step(idx, child));
+                element = child;
+            }
+            None => break,
+        }
+    }
+
+    // Locate the text node holding the anchor offset, which counts the
+    // rendered bullet or number of list items
+    let prefix_len = text_prefix_len(&chapter.document, &anchor.element_path);
+    let mut remaining = anchor.char_offset.saturating_sub(prefix_len);
+    let offset = locate_text(element, &mut remaining, &mut steps);
+
+    let cfi = Cfi {
+        spine_idx: chapter_idx,
+        steps,
+        offset,
+    };
+    Some(cfi.to_string())
+}
+
+/// Resolve a CFI to a chapter and content anchor
+///
+/// The anchor refers to the innermost rendered block containing the CFI
+/// target; use `Chapter::line_for_anchor` to get the rendered line.
+///
+/// # Returns
+/// * `Ok((chapter_idx, ContentAnchor))` - Resolved position
+/// * `Err(String)` - Malformed CFI or location outside the book
+pub fn resolve(book: &Book, cfi: &str) -> Result<(usize, ContentAnchor), String> {
+    let cfi = Cfi::parse(cfi)?;
+    let chapter = book
+        .chapters
+        .get(cfi.spine_idx)
+        .ok_or_else(|| format!("CFI spine item {} is out of range", cfi.spine_idx + 1))?;
+
+    let html = Html::parse_document(&chapter.file_path);
+    let mut element = html.root_element();
+    let mut text_step = None;
+
+    for step in &cfi.steps {
+        if step.index % 2 == 1 {
+            // Text chunk after (index - 1) / 2 child elements
+            text_step = Some((step.index - 1) / 2);
+            break;
+        }
+
+        let by_index = child_elements(element).nth(step.index / 2 - 1);
+        let matches_assertion = |el: &ElementRef| match &step.assertion {
+            Some(id) => el.value().attr("id") == Some(id.as_str()),
+            None => true,
+        };
+
+        element = match by_index {
+            Some(el) if matches_assertion(&el) => el,
+            // Index and id disagree - trust the id if it exists in the document
+            _ => step
+                .assertion
+                .as_deref()
+                .and_then(|id| find_by_id(&html, id))
+                .or(by_index)
+                .ok_or_else(|| format!("CFI step /{} not found in chapter", step.index))?,
+        };
+    }
+
+    let chunk = text_step.map(|k| (k, cfi.offset.unwrap_or(0)));
+    let target_path = element_path(element);
+
+    // Map onto the innermost ancestor that produced rendered lines
+    let block_depth = rendered_block_depth(chapter, &target_path);
+    let mut block = element;
+    for _ in block_depth..target_path.len() {
+        block = match block.parent().and_then(ElementRef::wrap) {
+            Some(parent) => parent,
+            None => break,
+        };
+    }
+
+    let mut char_offset = 0;
+    text_before(block, element, chunk, &mut char_offset);
+
+    // Rendered text starts with the bullet or number of list items; the start
+    // of the block stays at 0, the anchor of its first line
+    let block_path = &target_path[..block_depth];
+    if char_offset > 0 {
+        char_offset += text_prefix_len(&chapter.document, block_path);
+    }
+
+    Ok((
+        cfi.spine_idx,
+        ContentAnchor {
+            element_path: block_path.to_vec(),
+            char_offset,
+        },
+    ))
+}
+
+/// Parse a sequence of `/N[assertion]` steps with an optional `:offset` suffix
+fn parse_steps(path: &str) -> Result<(Vec<CfiStep>, Option<usize>), String> {
+    let mut steps = Vec::new();
+    let mut offset = None;
+    let mut chars = path.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '/' | ':' => {
+                let mut digits = String::new();
+                while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(*d);
+                    chars.next();
+                }
+                let value: usize = digits
+                    .parse()
+                    .map_err(|_| format!("Expected a number after '{}' in CFI", c))?;
+
+                let mut assertion = None;
+                if chars.peek() == Some(&'[') {
+                    chars.next();
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('^') => text.extend(chars.next()),
+                            Some(']') => break,
+                            Some(ch) => text.push(ch),
+                            None => return Err("Unterminated CFI assertion".to_string()),
+                        }
+                    }
+                    assertion = Some(text);
+                }
+
+                if c == ':' {
+                    if chars.peek().is_some() {
+                        return Err("CFI character offset must be the final component".to_string());
+                    }
+                    offset = Some(value);
+                } else {
+                    // Assertions may carry parameters (id;s=b) - keep only the id
+                    let id = assertion
+                        .map(|a| a.split(';').next().unwrap_or_default().to_string())
+                        .filter(|a| !a.is_empty());
+                    steps.push(CfiStep {
+                        index: value,
+                        assertion: id,
+                    });
+                }
+            }
+            '~' | '@' => {
+                return Err("Temporal and spatial CFI offsets are not supported".to_string());
+            }
+            c if c.is_whitespace() => {}
+            c => return Err(format!("Unexpected character '{}' in CFI", c)),
+        }
+    }
+
+    Ok((steps, offset))
+}
+
+fn escape_assertion(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '^' | '[' | ']' | '(' | ')' | ',' | ';' | '=') {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn child_elements<'a>(element: ElementRef<'a>) -> impl Iterator<Item = ElementRef<'a>> {
+    element.children().filter_map(ElementRef::wrap)
+}
+
+fn element_step(idx: usize, element: ElementRef) -> CfiStep {
+    CfiStep {
+        index: (idx + 1) * 2,
+        assertion: element.value().attr("id").map(|s| s.to_string()),
+    }
+}
+
+fn find_by_id<'a>(html: &'a Html, id: &str) -> Option<ElementRef<'a>> {
+    html.root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .find(|el| el.value().attr("id") == Some(id))
+}
+
+/// Walk text below `element` until `remaining` bytes are consumed
+///
+/// Pushes the steps leading to the text chunk and returns the character
+/// offset within it, or `None` if the element has no text at that offset.
+fn locate_text(
+    element: ElementRef,
+    remaining: &mut usize,
+    steps: &mut Vec<CfiStep>,
+) -> Option<usize> {
+    let mut elements_seen = 0;
+
+    for child in element.children() {
+        if let Some(text) = child.value().as_text() {
+            if *remaining < text.len() {
+                steps.push(CfiStep {
+                    index: elements_seen * 2 + 1,
+                    assertion: None,
+                });
+                let byte_offset = (0..=*remaining)
+                    .rev()
+                    .find(|&i| text.is_char_boundary(i))
+                    .unwrap_or(0);
+                return Some(text[..byte_offset].chars().count());
+            }
+            *remaining -= text.len();
+        } else if let Some(child_element) = ElementRef::wrap(child) {
+            steps.push(element_step(elements_seen, child_element));
+            if let Some(offset) = locate_text(child_element, remaining, steps) {
+                return Some(offset);
+            }
+            steps.pop();
+            elements_seen += 1;
+        }
+    }
+
+    None
+}
+
+/// Number of leading path components that name an element with rendered lines
+fn rendered_block_depth(chapter: &Chapter, path: &[usize]) -> usize {
+    (0..=path.len())
+        .rev()
+        .find(|&depth| {
+            chapter
+                .content_lines
+                .iter()
+                .filter_map(|line| line.anchor.as_ref())
+                .any(|anchor| anchor.element_path == path[..depth])
+        })
+        .unwrap_or(path.len())
+}
+
+/// Accumulate the byte length of text inside `element` that precedes the target
+///
+/// `chunk` is the text chunk index and character offset inside `target`; when
+/// absent the position is the start of `target`. Returns true once reached.
+fn text_before(
+    element: ElementRef,
+    target: ElementRef,
+    chunk: Option<(usize, usize)>,
+    total: &mut usize,
+) -> bool {
+    let is_target = element.id() == target.id();
+    if is_target && chunk.is_none() {
+        return true;
+    }
+
+    let mut elements_seen = 0;
+    for child in element.children() {
+        if let Some(text) = child.value().as_text() {
+            if is_target
+                && let Some((k, chars)) = chunk
+                && k == elements_seen
+            {
+                *total += text.chars().take(chars).map(char::len_utf8).sum::<usize>();
+                return true;
+            }
+            *total += text.len();
+        } else if let Some(child_element) = ElementRef::wrap(child) {
+            if text_before(child_element, target, chunk, total) {
+                return true;
+            }
+            elements_seen += 1;
+        }
+    }
+
+    is_target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+    use crate::types::BookMetadata;
+
+    fn create_test_book() -> Book {
+        let chapters = [
+            "<html><head><title>One</title></head><body><h1>One</h1><p>First chapter.</p></body></html>",
+            r#"<html><head><title>Two</title></head><body>
+                <h1 id="ch2">Two</h1>
+                <p>Opening paragraph.</p>
+                <p id="target">Some <em>emphasised</em> words and then the interesting part.</p>
+            </body></html>"#,
+            "<html><head><title>Three</title></head><body><ol>\
+                <li>First item.</li>\
+                <li>Second item with enough words to wrap onto another line.</li>\
+            </ol></body></html>",
+        ];
+
+        let mut chapters: Vec<Chapter> = chapters
+            .iter()
+            .enumerate()
+            .map(|(idx, html)| Chapter {
+                title: format!("Chapter {}", idx + 1),
+                sections: Vec::new(),
+                content_lines: Vec::new(),
+                file_path: html.to_string(),
+                href: format!("ch{}.xhtml", idx + 1),
+                document: crate::epub::document::build_document(html),
+            })
+            .collect();
+        for chapter in &mut chapters {
+            render_chapter(chapter, Some(30), 100, false);
+        }
+
+        Book {
+            metadata: BookMetadata {
+                title: "Test Book".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters,
+            images: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_cfi() {
+        let cfi = Cfi::parse("epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)").unwrap();
+        assert_eq!(cfi.spine_idx, 1);
+        assert_eq!(cfi.steps.len(), 3);
+        assert_eq!(cfi.steps[0].assertion.as_deref(), Some("body01"));
+        assert_eq!(cfi.steps[2].index, 3);
+        assert_eq!(cfi.offset, Some(10));
+    }
+
+    #[test]
+    fn test_format_roundtrip() {
+        let text = "epubcfi(/6/4!/4[body^[1^]]/10/3:10)";
+        let cfi = Cfi::parse(text).unwrap();
+        assert_eq!(cfi.steps[0].assertion.as_deref(), Some("body[1]"));
+        assert_eq!(cfi.to_string(), text);
+    }
+
+    #[test]
+    fn test_parse_invalid_cfi() {
+        assert!(Cfi::parse("/6/4!/4/2").is_err());
+        assert!(Cfi::parse("epubcfi(/6/4)").is_err());
+        assert!(Cfi::parse("epubcfi(/6/3!/4/2)").is_err());
+        assert!(Cfi::parse("epubcfi(/6/4!/4/2,/1:0,/1:5)").is_err());
+        assert!(Cfi::parse("epubcfi(/6/4!/4/1/2)").is_err());
+        assert!(Cfi::parse("epubcfi(/6/4!/0)").is_err());
+    }
+
+    #[test]
+    fn test_generate_and_resolve_roundtrip() {
+        let book = create_test_book();
+        let chapter = &book.chapters[1];
+        let line = chapter
+            .content_lines
+            .iter()
+            .position(|l| l.text.contains("interesting"))
+            .unwrap();
+        let anchor = chapter.anchor_at_line(line).unwrap();
+
+        assert!(anchor.char_offset > 0);
+
+        let cfi = generate(&book, 1, &anchor).unwrap();
+        assert_eq!(cfi, "epubcfi(/6/4!/4/6[target]/3:11)");
+
+        let (chapter_idx, resolved) = resolve(&book, &cfi).unwrap();
+        assert_eq!(chapter_idx, 1);
+        assert_eq!(resolved, anchor);
+    }
+
+    #[test]
+    fn test_list_item_offsets_leave_out_the_number() {
+        let book = create_test_book();
+        let chapter = &book.chapters[2];
+        let line = chapter
+            .content_lines
+            .iter()
+            .position(|l| l.text.contains("another"))
+            .unwrap();
+        let anchor = chapter.anchor_at_line(line).unwrap();
+        let rendered = chapter.content_lines[line].text.trim();
+
+        // The offset counts the text of the <li> only, not the rendered "2. "
+        let item = "Second item with enough words to wrap onto another line.";
+        let offset = item.find(rendered).unwrap();
+        let cfi = generate(&book, 2, &anchor).unwrap();
+        assert_eq!(cfi, format!("epubcfi(/6/6!/4/2/4/1:{})", offset));
+
+        let (_, resolved) = resolve(&book, &cfi).unwrap();
+        assert_eq!(resolved, anchor);
+
+        // The start of an item is the start of its first line
+        let first_line = chapter
+            .content_lines
+            .iter()
+            .position(|l| l.text.starts_with("2. "))
+            .unwrap();
+        let (_, resolved) = resolve(&book, "epubcfi(/6/6!/4/2/4/1:0)").unwrap();
+        assert_eq!(chapter.line_for_anchor(&resolved), first_line);
+    }
+
+    #[test]
+    fn test_resolve_inside_inline_element() {
+        let book = create_test_book();
+
+        // Offset 3 inside <em> ("emp|hasised") - maps onto the paragraph block
+        let (chapter_idx, anchor) = resolve(&book, "epubcfi(/6/4!/4/6/2/1:3)").unwrap();
+        assert_eq!(chapter_idx, 1);
+        assert_eq!(anchor.element_path, vec![2]);
+        assert_eq!(anchor.char_offset, "Some emp".len());
+    }
+
+    #[test]
+    fn test_resolve_prefers_id_assertion() {
+        let book = create_test_book();
+
+        // Index points at the wrong paragraph, but the assertion names the right one
+        let (_, anchor) = resolve(&book, "epubcfi(/6/4!/4/4[target])").unwrap();
+        assert_eq!(anchor.element_path, vec![2]);
+    }
+
+    #[test]
+    fn test_resolve_out_of_range_spine() {
+        let book = create_test_book();
+        assert!(resolve(&book, "epubcfi(/6/20!/4/2)").is_err());
+    }
+}
@@ -0,0 +1,1245 @@
+//! Width-independent document model for chapter content
+//!
+//! Chapter HTML is converted once into a flat sequence of blocks (paragraphs,
+//! headings, lists, tables, code, images, ...) carrying inline styles, link
+//! targets and element ids. The renderer lays these blocks out into
+//! `RenderedLine`s for a given width, so re-wrapping never touches the HTML.
+
+use crate::epub::code_highlight::CodeHighlighter;
+use crate::types::{ContentAnchor, InlineStyle};
+use lazy_static::lazy_static;
+use ratatui::style::Color;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+lazy_static! {
+    static ref CODE_HIGHLIGHTER: CodeHighlighter = CodeHighlighter::new();
+}
+
+/// Semantic types (`epub:type` values or `doc-*` roles) of footnote bodies
+const NOTE_TYPES: &[&str] = &["footnote", "endnote", "note", "rearnote"];
+
+/// Parsed chapter content, ready to be laid out at any width
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+    /// Paragraphs of footnotes and endnotes, keyed by the note's id
+    pub notes: HashMap<String, Vec<String>>,
+}
+
+/// A block-level piece of content and where it came from
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub kind: BlockKind,
+    /// Element path of the source element (see `element_path`)
+    pub source: Vec<usize>,
+    /// Ids of the element and of any wrapping containers that start with it
+    pub ids: Vec<String>,
+}
+
+/// The different kinds of block content
+#[derive(Debug, Clone)]
+pub enum BlockKind {
+    Heading {
+        level: u8,
+        content: InlineText,
+    },
+    Paragraph(InlineText),
+    Quote(InlineText),
+    /// A link that appears outside of any paragraph
+    Link(InlineText),
+    /// Inline markup (span, em, ...) that appears outside of any paragraph
+    Inline(InlineText),
+    Code {
+        language: Option<String>,
+        lines: Vec<CodeLine>,
+    },
+    Image {
+        src: Option<String>,
+        alt: String,
+        /// Resolved image resource, filled in when the book is parsed
+        resource: Option<ImageResource>,
+        /// Text of the enclosing figure's `figcaption`
+        caption: Option<String>,
+    },
+    List {
+        ordered: bool,
+        items: Vec<TextBlock>,
+    },
+    DefinitionList {
+        terms: Vec<TextBlock>,
+        descriptions: Vec<TextBlock>,
+    },
+    Table {
+        rows: Vec<TableRow>,
+        /// Text of the table's `<caption>`
+        caption: Option<InlineText>,
+    },
+    Rule,
+    Aside {
+        children: Vec<Block>,
+        /// True when the aside is a footnote or endnote
+        note: bool,
+    },
+    Figure(Vec<Block>),
+    Navigation {
+        links: Vec<TextBlock>,
+    },
+}
+
+/// An image resource found in the EPUB archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageResource {
+    /// Path of the image inside the archive
+    pub path: String,
+    /// Size in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Text of a sub-block element such as a list item or definition
+#[derive(Debug, Clone)]
+pub struct TextBlock {
+    pub content: InlineText,
+    pub source: Vec<usize>,
+    /// Id of the element itself
+    pub id: Option<String>,
+}
+
+/// A single table row
+#[derive(Debug, Clone)]
+pub struct TableRow {
+    pub cells: Vec<TableCell>,
+    pub source: Vec<usize>,
+}
+
+/// A single table cell
+#[derive(Debug, Clone)]
+pub struct TableCell {
+    pub content: InlineText,
+    /// True for `<th>` cells and cells inside `<thead>`
+    pub header: bool,
+    /// Number of columns and rows the cell covers, at least 1
+    pub colspan: usize,
+    pub rowspan: usize,
+    pub align: CellAlign,
+}
+
+/// Horizontal alignment of a table cell's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// One source line of a code block with its syntax colors
+#[derive(Debug, Clone)]
+pub struct CodeLine {
+    pub text: String,
+    /// Color spans (start, end, color) as byte offsets into `text`
+    pub colors: Vec<(usize, usize, Color)>,
+    /// Byte offset of the line within the code block's text
+    pub offset: usize,
+}
+
+/// Text with inline markup, all ranges are byte offsets into `text`
+#[derive(Debug, Clone, Default)]
+pub struct InlineText {
+    pub text: String,
+    /// Inline styles (start, end, style)
+    pub styles: Vec<(usize, usize, InlineStyle)>,
+    /// Hyperlinks (start, end, href)
+    pub links: Vec<(usize, usize, String)>,
+    /// Ids of inline elements (offset, id)
+    pub ids: Vec<(usize, String)>,
+}
+
+impl InlineText {
+    /// Create unstyled text
+    pub fn plain(text: impl Into<String>) -> Self {
+        InlineText {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Copy with runs of whitespace collapsed to single spaces and the ends trimmed
+    pub fn collapsed(&self) -> Self {
+        let mut text = String::with_capacity(self.text.len());
+        // New position of every old character offset and of the end
+        let mut offsets = vec![0; self.text.len() + 1];
+        let mut pending_space = false;
+
+        for (index, ch) in self.text.char_indices() {
+            if ch.is_whitespace() {
+                pending_space = !text.is_empty();
+                offsets[index] = text.len();
+            } else {
+                if pending_space {
+                    text.push(' ');
+                    pending_space = false;
+                }
+                offsets[index] = text.len();
+                text.push(ch);
+            }
+        }
+        offsets[self.text.len()] = text.len();
+
+        fn remap<T: Clone>(
+            spans: &[(usize, usize, T)],
+            offsets: &[usize],
+        ) -> Vec<(usize, usize, T)> {
+            spans
+                .iter()
+                .map(|(start, end, value)| (offsets[*start], offsets[*end], value.clone()))
+                .filter(|(start, end, _)| end > start)
+                .collect()
+        }
+        InlineText {
+            styles: remap(&self.styles, &offsets),
+            links: remap(&self.links, &offsets),
+            ids: self
+                .ids
+                .iter()
+                .map(|(pos, id)| (offsets[*pos], id.clone()))
+                .collect(),
+            text,
+        }
+    }
+
+    /// Append another piece of inline text, shifting its ranges to follow ours
+    pub fn append(&mut self, other: &InlineText) {
+        let offset = self.text.len();
+        self.text.push_str(&other.text);
+        self.styles.extend(
+            other
+                .styles
+                .iter()
+                .map(|(start, end, style)| (start + offset, end + offset, style.clone())),
+        );
+        self.links.extend(
+            other
+                .links
+                .iter()
+                .map(|(start, end, href)| (start + offset, end + offset, href.clone())),
+        );
+        self.ids
+            .extend(other.ids.iter().map(|(pos, id)| (pos + offset, id.clone())));
+    }
+
+    /// Copy of this text with `prefix` (bullet, number, indent) in front
+    pub fn prefixed(&self, prefix: &str) -> Self {
+        let mut result = InlineText::plain(prefix);
+        result.append(self);
+        result
+    }
+}
+
+impl Document {
+    /// Visit every image block, including those nested in asides and figures
+    pub fn for_each_image(&mut self, mut f: impl FnMut(&str, &mut Option<ImageResource>)) {
+        fn visit(blocks: &mut [Block], f: &mut impl FnMut(&str, &mut Option<ImageResource>)) {
+            for block in blocks {
+                match &mut block.kind {
+                    BlockKind::Image {
+                        src: Some(src),
+                        resource,
+                        ..
+                    } => f(src, resource),
+                    BlockKind::Aside { children, .. } | BlockKind::Figure(children) => {
+                        visit(children, f)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        visit(&mut self.blocks, &mut f);
+    }
+
+    /// Image blocks with a resolved resource, in document order
+    pub fn images(&self) -> Vec<&Block> {
+        fn visit<'a>(blocks: &'a [Block], images: &mut Vec<&'a Block>) {
+            for block in blocks {
+                match &block.kind {
+                    BlockKind::Image {
+                        resource: Some(_), ..
+                    } => images.push(block),
+                    BlockKind::Aside { children, .. } | BlockKind::Figure(children) => {
+                        visit(children, images)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut images = Vec::new();
+        visit(&self.blocks, &mut images);
+        images
+    }
+
+    /// Number of words in the text, not counting navigation or image alt text
+    pub fn word_count(&self) -> usize {
+        fn words(text: &str) -> usize {
+            text.split_whitespace().count()
+        }
+        fn text_blocks(blocks: &[TextBlock]) -> usize {
+            blocks.iter().map(|block| words(&block.content.text)).sum()
+        }
+        fn visit(blocks: &[Block]) -> usize {
+            blocks
+                .iter()
+                .map(|block| match &block.kind {
+                    BlockKind::Heading { content, .. }
+                    | BlockKind::Paragraph(content)
+                    | BlockKind::Quote(content)
+                    | BlockKind::Link(content)
+                    | BlockKind::Inline(content) => words(&content.text),
+                    BlockKind::Code { lines, .. } => lines.iter().map(|l| words(&l.text)).sum(),
+                    BlockKind::Image { caption, .. } => caption.as_deref().map_or(0, words),
+                    BlockKind::List { items, .. } => text_blocks(items),
+                    BlockKind::DefinitionList {
+                        terms,
+                        descriptions,
+                    } => text_blocks(terms) + text_blocks(descriptions),
+                    BlockKind::Table { rows, caption } => {
+                        let cells: usize = rows
+                            .iter()
+                            .flat_map(|row| &row.cells)
+                            .map(|cell| words(&cell.content.text))
+                            .sum();
+                        cells + caption.as_ref().map_or(0, |c| words(&c.text))
+                    }
+                    BlockKind::Aside { children, .. } | BlockKind::Figure(children) => {
+                        visit(children)
+                    }
+                    BlockKind::Rule | BlockKind::Navigation { .. } => 0,
+                })
+                .sum()
+        }
+        visit(&self.blocks)
+    }
+
+    /// Source lines of the code block laid out from the element at `source`
+    pub fn code_lines(&self, source: &[usize]) -> Option<&[CodeLine]> {
+        fn visit<'a>(blocks: &'a [Block], source: &[usize]) -> Option<&'a [CodeLine]> {
+            blocks.iter().find_map(|block| match &block.kind {
+                BlockKind::Code { lines, .. } if block.source == source => Some(lines.as_slice()),
+                BlockKind::Aside { children, .. } | BlockKind::Figure(children) => {
+                    visit(children, source)
+                }
+                _ => None,
+            })
+        }
+        visit(&self.blocks, source)
+    }
+
+    /// Find the source position of an element id
+    ///
+    /// Ids of block elements map to the start of the block, ids of inline
+    /// elements to their offset in the block's text.
+    pub fn anchor_for_id(&self, id: &str) -> Option<ContentAnchor> {
+        find_id(&self.blocks, id)
+    }
+}
+
+fn find_id(blocks: &[Block], id: &str) -> Option<ContentAnchor> {
+    let anchor = |source: &[usize], char_offset: usize| ContentAnchor {
+        element_path: source.to_vec(),
+        char_offset,
+    };
+    let in_text = |content: &InlineText, source: &[usize]| {
+        content
+            .ids
+            .iter()
+            .find(|(_, element_id)| element_id == id)
+            .map(|(offset, _)| anchor(source, *offset))
+    };
+    let in_text_blocks = |items: &[TextBlock]| {
+        items.iter().find_map(|item| {
+            if item.id.as_deref() == Some(id) {
+                Some(anchor(&item.source, 0))
+            } else {
+                in_text(&item.content, &item.source)
+            }
+        })
+    };
+
+    for block in blocks {
+        if block.ids.iter().any(|block_id| block_id == id) {
+            return Some(anchor(&block.source, 0));
+        }
+
+        let found = match &block.kind {
+            BlockKind::Heading { content, .. }
+            | BlockKind::Paragraph(content)
+            | BlockKind::Quote(content)
+            | BlockKind::Link(content)
+            | BlockKind::Inline(content) => in_text(content, &block.source),
+            BlockKind::List { items, .. } | BlockKind::Navigation { links: items } => {
+                in_text_blocks(items)
+            }
+            BlockKind::DefinitionList {
+                terms,
+                descriptions,
+            } => in_text_blocks(terms).or_else(|| in_text_blocks(descriptions)),
+            BlockKind::Table { rows, .. } => rows.iter().find_map(|row| {
+                row.cells
+                    .iter()
+                    .find_map(|cell| in_text(&cell.content, &row.source))
+            }),
+            BlockKind::Aside { children, .. } | BlockKind::Figure(children) => {
+                find_id(children, id)
+            }
+            BlockKind::Code { .. } | BlockKind::Image { .. } | BlockKind::Rule => None,
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+
+    None
+}
+
+/// Build the document model for a chapter's HTML
+///
+/// # Arguments
+/// * `html` - Chapter XHTML content
+///
+/// # Returns
+/// The chapter's blocks in document order
+pub fn build_document(html: &str) -> Document {
+    let html = Html::parse_document(html);
+
+    // Find the body or root element
+    let body_selector = Selector::parse("body").ok();
+    let root = body_selector
+        .as_ref()
+        .and_then(|sel| html.select(sel).next())
+        .unwrap_or_else(|| html.root_element());
+
+    let mut builder = Builder::default();
+    builder.container(root);
+
+    let id_selector = Selector::parse("[id]").unwrap();
+    let notes = root
+        .select(&id_selector)
+        .filter(|element| has_semantic_type(*element, NOTE_TYPES))
+        .filter_map(|element| {
+            let id = element.value().attr("id")?;
+            Some((id.to_string(), note_paragraphs(element)))
+        })
+        .collect();
+
+    Document {
+        blocks: builder.blocks,
+        notes,
+    }
+}
+
+/// Extract the text of the element with the given id as a note
+///
+/// Used for note targets that are not marked up as notes, e.g. an endnote
+/// list item or a paragraph starting with an anchor. When the id sits on an
+/// inline element, the enclosing block is used instead.
+///
+/// # Arguments
+/// * `html` - Chapter XHTML content
+/// * `id` - Id of the note target
+///
+/// # Returns
+/// The note's paragraphs, or `None` if the id does not exist or has no text
+pub fn note_for_id(html: &str, id: &str) -> Option<Vec<String>> {
+    let html = Html::parse_document(html);
+    let id_selector = Selector::parse("[id]").unwrap();
+    let target = html
+        .select(&id_selector)
+        .find(|element| element.value().attr("id") == Some(id))?;
+
+    let block = std::iter::once(target)
+        .chain(target.ancestors().filter_map(ElementRef::wrap))
+        .find(|element| {
+            matches!(
+                element.value().name(),
+                "p" | "li" | "dd" | "aside" | "section" | "div" | "blockquote" | "body"
+            )
+        })?;
+
+    let paragraphs = note_paragraphs(block);
+    (!paragraphs.is_empty()).then_some(paragraphs)
+}
+
+/// Split a note element into paragraphs of whitespace-collapsed text
+fn note_paragraphs(element: ElementRef) -> Vec<String> {
+    let collapse = |element: ElementRef| {
+        element
+            .text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let p_selector = Selector::parse("p").unwrap();
+    let paragraphs: Vec<String> = element
+        .select(&p_selector)
+        .map(collapse)
+        .filter(|text| !text.is_empty())
+        .collect();
+
+    if paragraphs.is_empty() {
+        let text = collapse(element);
+        if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![text]
+        }
+    } else {
+        paragraphs
+    }
+}
+
+/// Check an element's `epub:type` and ARIA `doc-*` role against semantic types
+fn has_semantic_type(element: ElementRef, types: &[&str]) -> bool {
+    let epub_types = element.value().attr("epub:type").unwrap_or("");
+    let role = element.value().attr("role").unwrap_or("");
+    epub_types
+        .split_whitespace()
+        .chain(
+            role.split_whitespace()
+                .filter_map(|r| r.strip_prefix("doc-")),
+        )
+        .any(|t| types.contains(&t))
+}
+
+/// Check whether a link is a reference to a footnote or endnote
+///
+/// Besides explicit `noteref` markup, EPUB2 books usually mark notes with a
+/// superscript link to a fragment, so that is accepted as well.
+fn is_noteref(link: ElementRef) -> bool {
+    if has_semantic_type(link, &["noteref"]) {
+        return true;
+    }
+
+    let is_sup = |element: ElementRef| element.value().name() == "sup";
+    let in_sup = link.parent().and_then(ElementRef::wrap).is_some_and(is_sup);
+    let wraps_sup = link.children().filter_map(ElementRef::wrap).any(is_sup);
+    (in_sup || wraps_sup)
+        && link
+            .value()
+            .attr("href")
+            .is_some_and(|href| href.contains('#'))
+}
+
+/// Accumulates blocks while walking the element tree
+#[derive(Default)]
+struct Builder {
+    blocks: Vec<Block>,
+    /// Ids of flattened containers, attached to the next block pushed
+    pending_ids: Vec<String>,
+}
+
+impl Builder {
+    fn push(&mut self, element: ElementRef, kind: BlockKind) {
+        let mut ids = std::mem::take(&mut self.pending_ids);
+        if let Some(id) = element.value().attr("id") {
+            ids.push(id.to_string());
+        }
+        self.blocks.push(Block {
+            kind,
+            source: element_path(element),
+            ids,
+        });
+    }
+
+    fn container(&mut self, element: ElementRef) {
+        for child in element.children() {
+            if let Some(child_element) = ElementRef::wrap(child) {
+                self.element(child_element);
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let tag_name = element.value().name();
+
+        match tag_name {
+            // Headings
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = tag_name[1..].parse().unwrap_or(3);
+                let content = extract_inline_text(element);
+                self.push(element, BlockKind::Heading { level, content });
+            }
+
+            // Code blocks
+            "pre" => {
+                let kind = code_block(element);
+                self.push(element, kind);
+            }
+
+            // Images, including SVG-wrapped covers (`<image xlink:href>`)
+            "img" | "image" => {
+                let src = element
+                    .value()
+                    .attr("src")
+                    // `xlink:href` is namespaced, so match on the local name
+                    .or_else(|| {
+                        element
+                            .value()
+                            .attrs()
+                            .find(|(name, _)| *name == "href")
+                            .map(|(_, value)| value)
+                    });
+                let kind = BlockKind::Image {
+                    src: src.map(|s| s.to_string()),
+                    alt: element.value().attr("alt").unwrap_or("").to_string(),
+                    resource: None,
+                    caption: None,
+                };
+                self.push(element, kind);
+            }
+
+            // Paragraphs (figure captions read like one)
+            "p" | "figcaption" => {
+                let content = extract_inline_text(element);
+                // Paragraphs that only wrap an image keep the image
+                let img_selector = Selector::parse("img, image").unwrap();
+                if content.text.trim().is_empty() && element.select(&img_selector).next().is_some()
+                {
+                    if let Some(id) = element.value().attr("id") {
+                        self.pending_ids.push(id.to_string());
+                    }
+                    self.container(element);
+                } else {
+                    self.push(element, BlockKind::Paragraph(content));
+                }
+            }
+
+            // Blockquotes
+            "blockquote" => self.push(element, BlockKind::Quote(extract_inline_text(element))),
+
+            // Lists (EPUB3)
+            "ul" | "ol" => {
+                let li_selector = Selector::parse("li").unwrap();
+                let items = element.select(&li_selector).map(text_block).collect();
+                let ordered = tag_name == "ol";
+                self.push(element, BlockKind::List { ordered, items });
+            }
+            "dl" => {
+                let dt_selector = Selector::parse("dt").unwrap();
+                let dd_selector = Selector::parse("dd").unwrap();
+                let terms = element.select(&dt_selector).map(text_block).collect();
+                let descriptions = element.select(&dd_selector).map(text_block).collect();
+                self.push(
+                    element,
+                    BlockKind::DefinitionList {
+                        terms,
+                        descriptions,
+                    },
+                );
+            }
+
+            // Tables (basic EPUB3 support)
+            "table" => {
+                let kind = table(element);
+                self.push(element, kind);
+            }
+
+            // Horizontal rules
+            "hr" => self.push(element, BlockKind::Rule),
+
+            // EPUB3 semantic elements
+            "aside" | "figure" => {
+                let mut inner = Builder::default();
+                inner.container(element);
+                let kind = if tag_name == "aside" {
+                    BlockKind::Aside {
+                        children: inner.blocks,
+                        note: has_semantic_type(element, NOTE_TYPES),
+                    }
+                } else {
+                    let caption_selector = Selector::parse("figcaption").unwrap();
+                    if let Some(caption) = element.select(&caption_selector).next() {
+                        let text = extract_inline_text(caption).text.trim().to_string();
+                        set_image_captions(&mut inner.blocks, &text);
+                    }
+                    BlockKind::Figure(inner.blocks)
+                };
+                self.push(element, kind);
+            }
+            "nav" => {
+                let a_selector = Selector::parse("a").unwrap();
+                let links = element.select(&a_selector).map(text_block).collect();
+                self.push(element, BlockKind::Navigation { links });
+            }
+
+            // Links (extract text only)
+            "a" => self.push(element, BlockKind::Link(extract_inline_text(element))),
+
+            // Inline elements that shouldn't create new blocks
+            "span" | "em" | "strong" | "i" | "b" | "code" => {
+                let content = extract_inline_text(element);
+                if !content.text.trim().is_empty() {
+                    self.push(element, BlockKind::Inline(content));
+                }
+            }
+
+            // Divs, sections and other containers - recurse into children
+            _ => {
+                if let Some(id) = element.value().attr("id") {
+                    self.pending_ids.push(id.to_string());
+                }
+                self.container(element);
+            }
+        }
+    }
+}
+
+/// Attach a figure's caption to the images inside it
+fn set_image_captions(blocks: &mut [Block], text: &str) {
+    for block in blocks {
+        match &mut block.kind {
+            BlockKind::Image { caption, .. } if !text.is_empty() => {
+                *caption = Some(text.to_string())
+            }
+            BlockKind::Aside { children, .. } | BlockKind::Figure(children) => {
+                set_image_captions(children, text)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn text_block(element: ElementRef) -> TextBlock {
+    TextBlock {
+        content: extract_inline_text(element),
+        source: element_path(element),
+        id: element.value().attr("id").map(|s| s.to_string()),
+    }
+}
+
+/// Largest `colspan`/`rowspan` accepted, guards against bogus markup
+const MAX_CELL_SPAN: usize = 100;
+
+fn table(element: ElementRef) -> BlockKind {
+    let tr_selector = Selector::parse("tr").unwrap();
+    let caption_selector = Selector::parse("caption").unwrap();
+
+    // Rows of nested tables belong to the cell that contains them
+    let own_rows = element.select(&tr_selector).filter(|tr| {
+        tr.ancestors()
+            .filter_map(ElementRef::wrap)
+            .find(|e| e.value().name() == "table")
+            .is_some_and(|table| table.id() == element.id())
+    });
+
+    let rows = own_rows
+        .map(|tr| {
+            let in_thead = tr
+                .parent()
+                .and_then(ElementRef::wrap)
+                .is_some_and(|parent| parent.value().name() == "thead");
+            let row_align = cell_align(tr);
+            let cells = tr
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .map(|cell| {
+                    let header = in_thead || cell.value().name() == "th";
+                    let span = |name: &str| {
+                        cell.value()
+                            .attr(name)
+                            .and_then(|v| v.trim().parse::<usize>().ok())
+                            .unwrap_or(1)
+                            .clamp(1, MAX_CELL_SPAN)
+                    };
+                    let default_align = if header {
+                        CellAlign::Center
+                    } else {
+                        CellAlign::Left
+                    };
+                    TableCell {
+                        content: extract_inline_text(cell).collapsed(),
+                        header,
+                        colspan: span("colspan"),
+                        rowspan: span("rowspan"),
+                        align: cell_align(cell).or(row_align).unwrap_or(default_align),
+                    }
+                })
+                .collect();
+            TableRow {
+                cells,
+                source: element_path(tr),
+            }
+        })
+        .collect();
+
+    let caption = element
+        .select(&caption_selector)
+        .next()
+        .map(|caption| extract_inline_text(caption).collapsed())
+        .filter(|caption| !caption.text.is_empty());
+
+    BlockKind::Table { rows, caption }
+}
+
+/// Alignment set on a table element with `align` or a `text-align` style
+fn cell_align(element: ElementRef) -> Option<CellAlign> {
+    let style = element.value().attr("style").unwrap_or("");
+    let from_style = style.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        (property.trim() == "text-align").then(|| value.trim())
+    });
+    match from_style
+        .or(element.value().attr("align"))?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "left" | "start" | "justify" => Some(CellAlign::Left),
+        "center" => Some(CellAlign::Center),
+        "right" | "end" => Some(CellAlign::Right),
+        _ => None,
+    }
+}
+
+fn code_block(element: ElementRef) -> BlockKind {
+    let code_selector = Selector::parse("code").unwrap();
+    let mut lines = Vec::new();
+
+    let Some(code_elem) = element.select(&code_selector).next() else {
+        // Treat as preformatted text without highlighting
+        let text = get_text_content(element);
+        let mut offset = 0;
+        for line in text.lines() {
+            lines.push(CodeLine {
+                text: line.to_string(),
+                colors: Vec::new(),
+                offset,
+            });
+            offset += line.len() + 1;
+        }
+        return BlockKind::Code {
+            language: None,
+            lines,
+        };
+    };
+
+    let code_text = get_text_content(code_elem);
+    let language = detect_language(code_elem);
+
+    // Highlight code - returns Vec<(text_chunk, color)>
+    let highlighted = CODE_HIGHLIGHTER.highlight_code(&code_text, language.as_deref());
+
+    // The highlighted output is a sequence of (text, color) tuples where each text chunk
+    // has a uniform color. We need to split by newlines and track color ranges per line.
+    let mut current_line = String::new();
+    let mut current_line_colors: Vec<(usize, usize, Color)> = Vec::new();
+    let mut byte_offset = 0; // Position in current line
+    let mut line_start = 0; // Byte offset of current line within the code text
+
+    for (text_chunk, color) in highlighted {
+        for ch in text_chunk.chars() {
+            if ch == '\n' {
+                // End of line - flush current line
+                let line_len = current_line.len();
+                lines.push(CodeLine {
+                    text: std::mem::take(&mut current_line),
+                    colors: std::mem::take(&mut current_line_colors),
+                    offset: line_start,
+                });
+                line_start += line_len + 1;
+                byte_offset = 0;
+            } else {
+                let start_offset = byte_offset;
+                current_line.push(ch);
+                byte_offset += ch.len_utf8();
+
+                // Extend the last color range if it matches and is contiguous
+                match current_line_colors.last_mut() {
+                    Some(last) if last.2 == color && last.1 == start_offset => {
+                        last.1 = byte_offset;
+                    }
+                    _ => current_line_colors.push((start_offset, byte_offset, color)),
+                }
+            }
+        }
+    }
+
+    // Flush any remaining content (last line without trailing newline)
+    if !current_line.is_empty() {
+        lines.push(CodeLine {
+            text: current_line,
+            colors: current_line_colors,
+            offset: line_start,
+        });
+    }
+
+    BlockKind::Code { language, lines }
+}
+
+fn get_text_content(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join("")
+}
+
+/// Compute the element-child index path from `<body>` down to an element
+///
+/// The path only depends on the document structure, never on wrap width,
+/// which makes it usable as a stable anchor for reading positions.
+pub(crate) fn element_path(element: ElementRef) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut current = element;
+
+    while current.value().name() != "body" {
+        let index = current
+            .prev_siblings()
+            .filter(|sibling| sibling.value().is_element())
+            .count();
+        path.push(index);
+
+        match current.parent().and_then(ElementRef::wrap) {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+
+    path.reverse();
+    path
+}
+
+/// Extract text content with inline styles, links and ids
+fn extract_inline_text(element: ElementRef) -> InlineText {
+    fn process_children(element: ElementRef, result: &mut InlineText, current: &[InlineStyle]) {
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                let start = result.text.len();
+                result.text.push_str(text);
+                let end = result.text.len();
+
+                // Add all current styles for this text range
+                if end > start {
+                    for style in current {
+                        result.styles.push((start, end, style.clone()));
+                    }
+                }
+            } else if let Some(child_elem) = ElementRef::wrap(child) {
+                let start = result.text.len();
+                if let Some(id) = child_elem.value().attr("id") {
+                    result.ids.push((start, id.to_string()));
+                }
+
+                // Determine which styles to add for this tag
+                let mut new_styles = current.to_vec();
+                match child_elem.value().name() {
+                    "strong" | "b" => new_styles.push(InlineStyle::Bold),
+                    "em" | "i" => new_styles.push(InlineStyle::Italic),
+                    "code" => new_styles.push(InlineStyle::Code),
+                    "u" => new_styles.push(InlineStyle::Underline),
+                    "s" | "del" | "strike" => new_styles.push(InlineStyle::Strikethrough),
+                    "mark" => new_styles.push(InlineStyle::Highlight),
+                    "a" if is_noteref(child_elem) => new_styles.push(InlineStyle::NoteRef),
+                    _ => {}
+                }
+
+                // Process children with accumulated styles
+                process_children(child_elem, result, &new_styles);
+
+                if child_elem.value().name() == "a"
+                    && let Some(href) = child_elem.value().attr("href")
+                {
+                    let end = result.text.len();
+                    if end > start {
+                        result.links.push((start, end, href.to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut result = InlineText::default();
+    process_children(element, &mut result, &[]);
+
+    // A link element's own target covers all of its text
+    if element.value().name() == "a"
+        && let Some(href) = element.value().attr("href")
+        && !result.text.is_empty()
+    {
+        result.links.push((0, result.text.len(), href.to_string()));
+    }
+
+    result
+}
+
+fn detect_language(code_element: ElementRef) -> Option<String> {
+    let classes = code_element.value().attr("class")?;
+
+    const KNOWN_LANGUAGES: &[&str] = &[
+        "rust",
+        "python",
+        "javascript",
+        "typescript",
+        "java",
+        "c",
+        "cpp",
+        "go",
+        "ruby",
+        "php",
+        "swift",
+        "kotlin",
+        "scala",
+        "haskell",
+        "elixir",
+        "erlang",
+        "clojure",
+        "bash",
+        "sh",
+        "shell",
+        "sql",
+        "html",
+        "css",
+        "json",
+        "xml",
+        "yaml",
+        "markdown",
+        "md",
+        "toml",
+    ];
+
+    for class in classes.split_whitespace() {
+        // Check for "language-X" pattern
+        if let Some(lang) = class.strip_prefix("language-") {
+            return Some(lang.to_string());
+        }
+
+        // Check for "highlight-X" pattern
+        if let Some(lang) = class.strip_prefix("highlight-") {
+            return Some(lang.to_string());
+        }
+
+        // Check for "sourceCode X" pattern
+        if let Some(lang) = class.strip_prefix("sourceCode") {
+            return Some(lang.trim().to_string());
+        }
+
+        // Check if it's a known language name directly
+        if KNOWN_LANGUAGES.contains(&class) {
+            return Some(class.to_string());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_blocks_in_order() {
+        let doc = build_document("<h1>Title</h1><p>Text</p><hr/><ul><li>a</li><li>b</li></ul>");
+        assert_eq!(doc.blocks.len(), 4);
+        assert!(matches!(
+            doc.blocks[0].kind,
+            BlockKind::Heading { level: 1, .. }
+        ));
+        assert!(matches!(doc.blocks[1].kind, BlockKind::Paragraph(_)));
+        assert!(matches!(doc.blocks[2].kind, BlockKind::Rule));
+        match &doc.blocks[3].kind {
+            BlockKind::List { ordered, items } => {
+                assert!(!ordered);
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[1].source, vec![3, 1]);
+            }
+            other => panic!("Expected list, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_inline_links_styles_and_ids() {
+        let doc = build_document(
+            r##"<p>See <a href="ch2.xhtml#s1"><em>there</em></a> and <span id="x">here</span>.</p>"##,
+        );
+        let BlockKind::Paragraph(content) = &doc.blocks[0].kind else {
+            panic!("Expected paragraph");
+        };
+        assert_eq!(content.text, "See there and here.");
+        assert_eq!(content.links, vec![(4, 9, "ch2.xhtml#s1".to_string())]);
+        assert_eq!(content.styles, vec![(4, 9, InlineStyle::Italic)]);
+        assert_eq!(content.ids, vec![(14, "x".to_string())]);
+    }
+
+    #[test]
+    fn test_word_count() {
+        let doc = build_document(
+            "<nav><a href=\"a.xhtml\">Skipped link</a></nav><h1>Two words</h1>\
+             <p>Three  more\nwords</p><ul><li>one</li></ul><img alt=\"not counted\" src=\"a.png\"/>\
+             <table><tr><td>a b</td></tr></table>",
+        );
+        assert_eq!(doc.word_count(), 8);
+    }
+
+    #[test]
+    fn test_container_ids_attach_to_first_block() {
+        let doc = build_document(r#"<section id="intro"><p id="p1">One</p><p>Two</p></section>"#);
+        assert_eq!(
+            doc.blocks[0].ids,
+            vec!["intro".to_string(), "p1".to_string()]
+        );
+        assert!(doc.blocks[1].ids.is_empty());
+        assert_eq!(doc.blocks[1].source, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_anchor_for_id() {
+        let doc = build_document(
+            r#"<div id="top"><p>One</p></div><aside><p>Note <span id="fn1">1</span></p></aside><ol><li>a</li><li id="i2">b</li></ol>"#,
+        );
+        assert_eq!(doc.anchor_for_id("top").unwrap().element_path, vec![0, 0]);
+        let inline = doc.anchor_for_id("fn1").unwrap();
+        assert_eq!(inline.element_path, vec![1, 0]);
+        assert_eq!(inline.char_offset, 5);
+        assert_eq!(doc.anchor_for_id("i2").unwrap().element_path, vec![2, 1]);
+        assert!(doc.anchor_for_id("missing").is_none());
+    }
+
+    #[test]
+    fn test_noterefs_and_notes() {
+        let doc = build_document(
+            r##"<p>Text<a epub:type="noteref" href="#fn1">1</a> and more<sup><a href="notes.xhtml#n2">2</a></sup>.</p>
+            <aside epub:type="footnote" id="fn1"><p>First   note.</p><p>Second
+            paragraph.</p></aside>
+            <p>See <a href="#fn1">this</a>.</p>"##,
+        );
+        let BlockKind::Paragraph(content) = &doc.blocks[0].kind else {
+            panic!("Expected paragraph");
+        };
+        assert_eq!(
+            content.styles,
+            vec![(4, 5, InlineStyle::NoteRef), (14, 15, InlineStyle::NoteRef)]
+        );
+        assert!(matches!(
+            doc.blocks[1].kind,
+            BlockKind::Aside { note: true, .. }
+        ));
+        assert_eq!(
+            doc.notes.get("fn1").unwrap(),
+            &vec!["First note.".to_string(), "Second paragraph.".to_string()]
+        );
+        let BlockKind::Paragraph(content) = &doc.blocks[2].kind else {
+            panic!("Expected paragraph");
+        };
+        assert!(content.styles.is_empty());
+    }
+
+    #[test]
+    fn test_note_for_untyped_target() {
+        let html = r#"<ol><li id="en1">An <em>endnote</em>.</li></ol>
+            <p><a id="en2"></a>2. Another one.</p>"#;
+        assert_eq!(
+            note_for_id(html, "en1").unwrap(),
+            vec!["An endnote.".to_string()]
+        );
+        assert_eq!(
+            note_for_id(html, "en2").unwrap(),
+            vec!["2. Another one.".to_string()]
+        );
+        assert!(note_for_id(html, "missing").is_none());
+    }
+
+    #[test]
+    fn test_images_in_figures_and_svg() {
+        let mut doc = build_document(
+            r#"<figure><img src="../images/a.png" alt="A"/></figure>
+            <svg xmlns:xlink="http://www.w3.org/1999/xlink"><image xlink:href="cover.jpg"/></svg>"#,
+        );
+        let mut sources = Vec::new();
+        doc.for_each_image(|src, resource| {
+            sources.push(src.to_string());
+            *resource = Some(ImageResource {
+                path: src.to_string(),
+                width: 1,
+                height: 1,
+            });
+        });
+        assert_eq!(sources, vec!["../images/a.png", "cover.jpg"]);
+        assert!(matches!(
+            doc.blocks[1].kind,
+            BlockKind::Image {
+                resource: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_code_block_lines() {
+        let doc =
+            build_document("<pre><code class=\"language-rust\">fn a() {}\nlet b = 1;</code></pre>");
+        let BlockKind::Code { language, lines } = &doc.blocks[0].kind else {
+            panic!("Expected code block");
+        };
+        assert_eq!(language.as_deref(), Some("rust"));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].text, "let b = 1;");
+        assert_eq!(lines[1].offset, 10);
+    }
+
+    #[test]
+    fn test_figure_caption_attached_to_images() {
+        let mut doc = build_document(
+            r#"<figure><img src="a.png" alt="A"/><figcaption>Figure 1. <em>Layout</em></figcaption></figure>
+            <p><img src="b.png" alt="B"/></p>"#,
+        );
+        doc.for_each_image(|src, resource| {
+            *resource = Some(ImageResource {
+                path: src.to_string(),
+                width: 1,
+                height: 1,
+            });
+        });
+
+        let captions: Vec<_> = doc
+            .images()
+            .iter()
+            .map(|block| match &block.kind {
+                BlockKind::Image { caption, .. } => caption.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(captions, vec![Some("Figure 1. Layout".to_string()), None]);
+
+        // The caption is still shown as text below the figure's image
+        let BlockKind::Figure(children) = &doc.blocks[0].kind else {
+            panic!("expected a figure");
+        };
+        assert!(
+            matches!(&children[1].kind, BlockKind::Paragraph(text) if text.text == "Figure 1. Layout")
+        );
+    }
+
+    #[test]
+    fn test_table_cells() {
+        let doc = build_document(
+            r#"<table><caption>Prices</caption>
+            <thead><tr><td>Item</td><td>Cost</td></tr></thead>
+            <tr><td colspan="2" style="text-align: right">Fresh
+                <b>fruit</b></td></tr>
+            <tr><td>Nested<table><tr><td>inner</td></tr></table></td></tr>
+            </table>"#,
+        );
+        let BlockKind::Table { rows, caption } = &doc.blocks[0].kind else {
+            panic!("expected a table");
+        };
+        assert_eq!(caption.as_ref().unwrap().text, "Prices");
+        assert_eq!(rows.len(), 3);
+
+        // Cells in <thead> are headers even without <th>
+        assert!(rows[0].cells[0].header);
+        assert_eq!(rows[0].cells[0].align, CellAlign::Center);
+
+        let cell = &rows[1].cells[0];
+        assert!(!cell.header);
+        assert_eq!((cell.colspan, cell.rowspan), (2, 1));
+        assert_eq!(cell.align, CellAlign::Right);
+        assert_eq!(cell.content.text, "Fresh fruit");
+        assert_eq!(cell.content.styles, vec![(6, 11, InlineStyle::Bold)]);
+    }
+}
@@ -1,8 +1,11 @@
 //! EPUB parsing and rendering functionality
 
+pub mod cfi;
 pub mod code_highlight;
+pub mod document;
 pub mod parser;
 pub mod renderer;
+pub mod table;
 
-pub use parser::parse_epub;
+pub use parser::{parse_epub, read_details, read_metadata};
 pub use renderer::render_chapter;
@@ -1,5 +1,8 @@
+use crate::epub::document::{ImageResource, build_document};
 use crate::error::{AppError, Result};
-use crate::types::{Book, BookMetadata, Chapter, Section};
+use crate::images::ImageStore;
+use crate::links::{join_path, percent_decode};
+use crate::types::{Book, BookDetails, BookMetadata, Chapter, Section};
 use epub::doc::EpubDoc;
 use std::collections::HashMap;
 use std::path::Path;
@@ -73,6 +76,13 @@ pub fn parse_epub<P: AsRef<Path>>(path: P) -> Result<Book> {
 
     // Parse chapters
     let mut chapters = Vec::new();
+    // Only image headers are read here, the store reads images when drawn
+    let mut images = ImageStore::new(path.as_ref().to_path_buf());
+    let mut archive = std::fs::File::open(&path)
+        .map_err(|e| AppError::InvalidEpub(e.to_string()))
+        .and_then(|file| {
+            zip::ZipArchive::new(file).map_err(|e| AppError::InvalidEpub(e.to_string()))
+        })?;
 
     // Get the spine (reading order)
     let spine_len = doc.spine.len();
@@ -150,19 +160,124 @@ pub fn parse_epub<P: AsRef<Path>>(path: P) -> Result<Book> {
             })
             .collect();
 
+        // Path of the chapter inside the archive, used to resolve links
+        let href = doc
+            .get_current_path()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+
+        // Build the document model once, rendering only lays it out
+        let mut document = build_document(&content_html);
+        log::debug!("  Built document with {} blocks", document.blocks.len());
+
+        // Record the size of the images the chapter shows for layout
+        document.for_each_image(|src, resource| {
+            let path = join_path(&href, &percent_decode(src));
+            *resource = images
+                .read_size(&mut archive, &path)
+                .map(|(width, height)| ImageResource {
+                    path,
+                    width,
+                    height,
+                });
+        });
+
         chapters.push(Chapter {
             title,
             sections,
             content_lines: Vec::new(), // Will be rendered after parsing
             file_path: content_html,   // Store HTML content here for now
+            href,
+            document,
         });
     }
 
     log::info!(
-        "Successfully parsed EPUB: {} chapters extracted",
-        chapters.len()
+        "Successfully parsed EPUB: {} chapters, {} images",
+        chapters.len(),
+        images.len()
     );
-    Ok(Book { metadata, chapters })
+    Ok(Book {
+        metadata,
+        chapters,
+        images,
+    })
+}
+
+/// Read only the metadata and chapter count of an EPUB file
+///
+/// Much cheaper than [`parse_epub`]: no chapter content or images are
+/// extracted. Used to index libraries.
+///
+/// # Returns
+/// * `Ok((BookMetadata, usize))` - Metadata and number of chapters (spine items)
+/// * `Err(AppError)` - File not found or invalid EPUB
+pub fn read_metadata<P: AsRef<Path>>(path: P) -> Result<(BookMetadata, usize)> {
+    if !path.as_ref().exists() {
+        return Err(AppError::FileNotFound(
+            path.as_ref().to_string_lossy().to_string(),
+        ));
+    }
+
+    let doc = EpubDoc::new(&path).map_err(|e| AppError::InvalidEpub(format!("{}", e)))?;
+    Ok((parse_metadata(&doc), doc.spine.len()))
+}
+
+/// Read the package metadata that the reader itself has no use for
+///
+/// # Returns
+/// * `Ok(BookDetails)` - Subjects, description, series and cover presence
+/// * `Err(AppError)` - File not found or invalid EPUB
+pub fn read_details<P: AsRef<Path>>(path: P) -> Result<BookDetails> {
+    if !path.as_ref().exists() {
+        return Err(AppError::FileNotFound(
+            path.as_ref().to_string_lossy().to_string(),
+        ));
+    }
+
+    let doc = EpubDoc::new(&path).map_err(|e| AppError::InvalidEpub(format!("{}", e)))?;
+    let value = |property: &str| {
+        doc.mdata(property)
+            .map(|m| m.value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    // EPUB 3 collections, then the `calibre:series` meta most books carry
+    let collection = doc.metadata.iter().find(|m| {
+        m.property == "belongs-to-collection"
+            && m.refinement("collection-type")
+                .is_none_or(|r| r.value.trim() == "series")
+    });
+    let (series, series_index) = match collection {
+        Some(item) => (
+            Some(item.value.trim().to_string()),
+            item.refinement("group-position")
+                .map(|r| r.value.trim().to_string()),
+        ),
+        None => (value("calibre:series"), value("calibre:series_index")),
+    };
+
+    Ok(BookDetails {
+        subjects: doc
+            .metadata
+            .iter()
+            .filter(|m| m.property == "subject")
+            .map(|m| m.value.trim().to_string())
+            .filter(|subject| !subject.is_empty())
+            .collect(),
+        description: value("description").map(|d| plain_text(&d)),
+        series,
+        series_index,
+        cover: doc.get_cover_id().is_some(),
+    })
+}
+
+/// Text of a metadata value that may contain HTML, whitespace collapsed
+fn plain_text(html: &str) -> String {
+    let fragment = scraper::Html::parse_fragment(html);
+    // Separate text nodes so paragraphs do not run into each other
+    let text = fragment.root_element().text().collect::<Vec<_>>().join(" ");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
 fn parse_metadata(doc: &EpubDoc<std::io::BufReader<std::fs::File>>) -> BookMetadata {
@@ -176,6 +291,7 @@ fn parse_metadata(doc: &EpubDoc<std::io::BufReader<std::fs::File>>) -> BookMetad
         publisher: doc.mdata("publisher").map(|m| m.value.clone()),
         publication_date: doc.mdata("date").map(|m| m.value.clone()),
         language: doc.mdata("language").map(|m| m.value.clone()),
+        identifier: doc.unique_identifier.clone(),
     }
 }
 
@@ -268,6 +384,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_plain_text_description() {
+        assert_eq!(
+            plain_text("<p>A <b>short</b>\n  story.</p><p>Second &amp; last.</p>"),
+            "A short story. Second & last."
+        );
+        assert_eq!(plain_text("No markup"), "No markup");
+    }
+
     #[test]
     fn test_extract_fragment_id() {
         // Test extracting fragment from URL
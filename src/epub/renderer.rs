@@ -1,30 +1,42 @@
 use crate::constants::UI_MARGIN_WIDTH;
-use crate::epub::code_highlight::CodeHighlighter;
-use crate::types::{Chapter, InlineStyle, LineStyle, RenderedLine};
-use lazy_static::lazy_static;
-use scraper::{ElementRef, Html, Selector};
+use crate::epub::document::{Block, BlockKind, CodeLine, Document, InlineText, TextBlock};
+use crate::epub::table::layout_table;
+use crate::images::image_cells;
+use crate::types::{Chapter, ContentAnchor, InlineStyle, LineStyle, RenderedLine};
+use ratatui::style::Color;
+use textwrap::core::display_width;
 use textwrap::wrap;
 
-lazy_static! {
-    static ref CODE_HIGHLIGHTER: CodeHighlighter = CodeHighlighter::new();
-}
+/// Marker starting the continuation rows of a soft-wrapped code line
+const CODE_CONTINUATION: &str = "↪ ";
+
+/// Color of the continuation marker
+const CODE_CONTINUATION_COLOR: Color = Color::DarkGray;
 
-/// Render a chapter's HTML content into styled text lines
+/// Render a chapter's document into styled text lines
 ///
-/// Converts HTML to wrapped text with appropriate styling for headings,
-/// code blocks, quotes, etc. Updates the chapter's content_lines and
-/// section start_line positions.
+/// Lays the chapter's document model out as wrapped text with appropriate
+/// styling for headings, code blocks, quotes, etc. Updates the chapter's
+/// content_lines and section start_line positions. The HTML is not parsed
+/// again, so this is cheap enough to run on every resize.
 ///
 /// # Arguments
 /// * `chapter` - Mutable chapter to render (updates content_lines and section positions)
 /// * `max_width` - Optional maximum line width (None = use terminal width)
 /// * `terminal_width` - Current terminal width in columns
-pub fn render_chapter(chapter: &mut Chapter, max_width: Option<usize>, terminal_width: u16) {
+/// * `wrap_code` - Soft-wrap long code lines instead of leaving them for horizontal scrolling
+pub fn render_chapter(
+    chapter: &mut Chapter,
+    max_width: Option<usize>,
+    terminal_width: u16,
+    wrap_code: bool,
+) {
     log::debug!(
-        "Rendering chapter '{}': max_width={:?}, terminal_width={}",
+        "Rendering chapter '{}': max_width={:?}, terminal_width={}, wrap_code={}",
         chapter.title,
         max_width,
-        terminal_width
+        terminal_width,
+        wrap_code
     );
 
     // Determine effective width
@@ -37,17 +49,13 @@ pub fn render_chapter(chapter: &mut Chapter, max_width: Option<usize>, terminal_
     let width = width.saturating_sub(UI_MARGIN_WIDTH); // Reserve space for margins/UI
     log::debug!("  Effective rendering width: {} columns", width);
 
-    // Parse HTML content from the chapter's file
-    let html = Html::parse_fragment(&chapter.file_path);
-    let html_len = chapter.file_path.len();
-
-    // Extract and render content, also track heading positions
-    let (rendered_lines, headings) = extract_and_render(&html, width);
+    // Lay out content, also track heading positions
+    let (rendered_lines, headings) = layout_document(&chapter.document, width, wrap_code);
     log::debug!(
-        "  Rendered {} lines, found {} headings from {} bytes of HTML",
+        "  Rendered {} lines, found {} headings from {} blocks",
         rendered_lines.len(),
         headings.len(),
-        html_len
+        chapter.document.blocks.len()
     );
 
     // If chapter has no sections from TOC, extract them from HTML headings
@@ -65,7 +73,7 @@ pub fn render_chapter(chapter: &mut Chapter, max_width: Option<usize>, terminal_
                 chapter.sections.push(crate::types::Section {
                     title: heading.text.clone(),
                     start_line: heading.line_number,
-                    fragment_id: heading.id.clone(),
+                    fragment_id: heading.ids.last().cloned(),
                 });
             }
         }
@@ -93,9 +101,7 @@ pub fn render_chapter(chapter: &mut Chapter, max_width: Option<usize>, terminal_
                 );
 
                 for heading in &headings {
-                    if let Some(ref heading_id) = heading.id
-                        && heading_id == section_fragment
-                    {
+                    if heading.ids.contains(section_fragment) {
                         log::debug!(
                             "  ✓ Matched by fragment ID to heading '{}' at line {}",
                             heading.text,
@@ -161,399 +167,371 @@ struct HeadingInfo {
     text: String,
     level: u8,
     line_number: usize,
-    id: Option<String>,
+    ids: Vec<String>,
 }
 
-fn extract_and_render(html: &Html, width: usize) -> (Vec<RenderedLine>, Vec<HeadingInfo>) {
+/// Lay out a document's blocks as lines of at most `width` columns
+///
+/// Code lines are only held to `width` when `wrap_code` is set, otherwise they
+/// stay whole and the view scrolls them horizontally.
+fn layout_document(
+    document: &Document,
+    width: usize,
+    wrap_code: bool,
+) -> (Vec<RenderedLine>, Vec<HeadingInfo>) {
     let mut rendered_lines = Vec::new();
     let mut headings = Vec::new();
 
-    // Find the body or root element
-    let body_selector = Selector::parse("body").ok();
-    let root = if let Some(ref sel) = body_selector {
-        html.select(sel).next()
-    } else {
-        None
-    };
-
-    let start_element = root.unwrap_or_else(|| html.root_element());
-
-    // Process all child nodes
-    process_element(
-        start_element,
-        &mut rendered_lines,
-        &mut headings,
-        width,
-        false,
-    );
+    for block in &document.blocks {
+        layout_block(block, &mut rendered_lines, &mut headings, width, wrap_code);
+    }
 
     (rendered_lines, headings)
 }
 
-fn process_element(
-    element: ElementRef,
+fn layout_block(
+    block: &Block,
     lines: &mut Vec<RenderedLine>,
     headings: &mut Vec<HeadingInfo>,
     width: usize,
-    in_paragraph: bool,
+    wrap_code: bool,
 ) {
-    let tag_name = element.value().name();
-
-    match tag_name {
-        // Headings
-        "h1" => process_heading(element, lines, headings, width, 1, LineStyle::Heading1),
-        "h2" => process_heading(element, lines, headings, width, 2, LineStyle::Heading2),
-        "h3" | "h4" | "h5" | "h6" => {
-            let level = match tag_name {
-                "h3" => 3,
-                "h4" => 4,
-                "h5" => 5,
-                "h6" => 6,
-                _ => 3,
+    let source = &block.source;
+
+    match &block.kind {
+        BlockKind::Heading { level, content } => {
+            headings.push(HeadingInfo {
+                text: content.text.clone(),
+                level: *level,
+                line_number: lines.len(),
+                ids: block.ids.clone(),
+            });
+            let style = match level {
+                1 => LineStyle::Heading1,
+                2 => LineStyle::Heading2,
+                _ => LineStyle::Heading3,
             };
-            process_heading(element, lines, headings, width, level, LineStyle::Heading3);
+            add_text_lines(lines, content, width, style, source);
+            add_blank_line(lines);
         }
-
-        // Code blocks
-        "pre" => process_code_block(element, lines),
-
-        // Images
-        "img" => process_image(element, lines),
-
-        // Paragraphs
-        "p" => process_paragraph(element, lines, width),
-
-        // Blockquotes
-        "blockquote" => process_blockquote(element, lines, width),
-
-        // Lists (EPUB3)
-        "ul" => process_unordered_list(element, lines, width),
-        "ol" => process_ordered_list(element, lines, width),
-        "dl" => process_definition_list(element, lines, width),
-
-        // Tables (basic EPUB3 support)
-        "table" => process_table(element, lines, width),
-
-        // Horizontal rules
-        "hr" => process_horizontal_rule(lines, width),
-
-        // EPUB3 semantic elements
-        "aside" | "figure" | "figcaption" => {
-            process_semantic_container(element, lines, headings, width)
+        BlockKind::Paragraph(content) => {
+            add_text_lines(lines, content, width, LineStyle::Normal, source);
+            add_blank_line(lines);
         }
-        "nav" => process_navigation(element, lines, headings, width),
-
-        // Links (extract text only)
-        "a" => {
-            if !in_paragraph {
-                process_link(element, lines, width);
+        BlockKind::Quote(content) => {
+            add_text_lines(lines, content, width, LineStyle::Quote, source);
+            add_blank_line(lines);
+        }
+        BlockKind::Link(content) => {
+            add_text_lines(lines, content, width, LineStyle::Link, source);
+        }
+        BlockKind::Inline(content) => {
+            add_text_lines(lines, content, width, LineStyle::Normal, source);
+        }
+        BlockKind::Code {
+            language,
+            lines: code_lines,
+        } => {
+            let style = LineStyle::CodeBlock {
+                language: language.clone(),
+            };
+            for code_line in code_lines {
+                if wrap_code {
+                    lines.extend(wrap_code_line(code_line, width, &style, source));
+                } else {
+                    let mut line = plain_line(
+                        code_line.text.clone(),
+                        style.clone(),
+                        source,
+                        code_line.offset,
+                    );
+                    line.syntax_colors = code_line.colors.clone();
+                    lines.push(line);
+                }
             }
+            add_blank_line(lines);
         }
+        BlockKind::Image {
+            src, alt, resource, ..
+        } => {
+            // Reserve rows for the picture itself, the placeholder becomes its caption
+            if let Some(resource) = resource
+                && width > 0
+            {
+                let (cols, rows) = image_cells(resource.width, resource.height, width);
+                for row in 0..rows {
+                    let style = LineStyle::Image {
+                        path: resource.path.clone(),
+                        row,
+                        rows,
+                        cols,
+                    };
+                    lines.push(plain_line(String::new(), style, source, 0));
+                }
+            }
 
-        // Divs and sections - recurse into children
-        "div" | "section" | "article" | "body" | "html" | "main" => {
-            process_container(element, lines, headings, width);
+            // Fall back to the image's file name when there is no alt text
+            let alt = match src.as_deref().and_then(|s| s.rsplit('/').next()) {
+                Some(name) if alt.is_empty() => name,
+                _ => alt.as_str(),
+            };
+            let placeholder = if alt.is_empty() {
+                "[Image]".to_string()
+            } else {
+                let truncated = if alt.len() > 50 {
+                    format!("{}...", &alt[..alt.floor_char_boundary(50)])
+                } else {
+                    alt.to_string()
+                };
+                format!("[Image: {}]", truncated)
+            };
+            lines.push(plain_line(placeholder, LineStyle::Normal, source, 0));
+            add_blank_line(lines);
         }
-
-        // Inline elements that shouldn't create new blocks
-        "span" | "em" | "strong" | "i" | "b" | "code" => {
-            if !in_paragraph {
-                process_inline_as_block(element, lines, width);
+        BlockKind::List { ordered, items } => {
+            for (index, item) in items.iter().enumerate() {
+                // Ordered lists reserve a little more room for the number
+                let indent = if *ordered { 3 } else { 2 };
+                add_prefixed_lines(
+                    lines,
+                    &list_prefix(*ordered, index),
+                    item,
+                    width.saturating_sub(indent),
+                    LineStyle::Normal,
+                );
             }
+            add_blank_line(lines);
         }
-
-        // Other block elements
-        _ => {
-            process_container(element, lines, headings, width);
+        BlockKind::DefinitionList {
+            terms,
+            descriptions,
+        } => {
+            for term in terms {
+                add_text_lines(
+                    lines,
+                    &term.content,
+                    width,
+                    LineStyle::Heading3,
+                    &term.source,
+                );
+            }
+            for description in descriptions {
+                add_prefixed_lines(
+                    lines,
+                    DEFINITION_PREFIX,
+                    description,
+                    width.saturating_sub(2),
+                    LineStyle::Normal,
+                );
+            }
+            add_blank_line(lines);
         }
-    }
-}
-
-fn process_heading(
-    element: ElementRef,
-    lines: &mut Vec<RenderedLine>,
-    headings: &mut Vec<HeadingInfo>,
-    width: usize,
-    level: u8,
-    style: LineStyle,
-) {
-    let (text, inline_styles) = extract_text_with_inline_styles(element);
-    let start_line = lines.len();
-    let id = element.value().attr("id").map(|s| s.to_string());
-    headings.push(HeadingInfo {
-        text: text.clone(),
-        level,
-        line_number: start_line,
-        id,
-    });
-    add_text_lines(lines, &text, width, style, inline_styles);
-    add_blank_line(lines);
-}
-
-fn process_code_block(element: ElementRef, lines: &mut Vec<RenderedLine>) {
-    let code_selector = Selector::parse("code").unwrap();
-    if let Some(code_elem) = element.select(&code_selector).next() {
-        let code_text = get_text_content(code_elem);
-        let language = detect_language(code_elem);
-
-        // Highlight code - returns Vec<(text_chunk, color)>
-        let highlighted = CODE_HIGHLIGHTER.highlight_code(&code_text, language.as_deref());
-
-        // Build lines with syntax highlighting colors
-        // The highlighted output is a sequence of (text, color) tuples where each text chunk
-        // has a uniform color. We need to split by newlines and track color ranges per line.
-        let mut current_line = String::new();
-        let mut current_line_colors: Vec<(usize, usize, ratatui::style::Color)> = Vec::new();
-        let mut char_offset = 0; // Position in current line
-
-        for (text_chunk, color) in highlighted {
-            // Process this text chunk character by character
-            for ch in text_chunk.chars() {
-                if ch == '\n' {
-                    // End of line - flush current line
-                    lines.push(RenderedLine {
-                        text: current_line.clone(),
-                        style: LineStyle::CodeBlock {
-                            language: language.clone(),
-                        },
-                        search_matches: Vec::new(),
-                        inline_styles: Vec::new(),
-                        syntax_colors: current_line_colors.clone(),
-                    });
-                    current_line.clear();
-                    current_line_colors.clear();
-                    char_offset = 0;
-                } else {
-                    // Regular character - add to current line
-                    let start_offset = char_offset;
-                    current_line.push(ch);
-                    char_offset += 1;
-
-                    // Add or extend color range
-                    if let Some(last) = current_line_colors.last_mut() {
-                        // If the last color matches and is contiguous, extend it
-                        if last.2 == color && last.1 == start_offset {
-                            last.1 = char_offset;
-                        } else {
-                            // Different color or gap - start new range
-                            current_line_colors.push((start_offset, char_offset, color));
-                        }
-                    } else {
-                        // First character in line
-                        current_line_colors.push((start_offset, char_offset, color));
-                    }
-                }
+        BlockKind::Table { rows, caption } => {
+            if let Some(caption) = caption {
+                add_text_lines(lines, caption, width, LineStyle::Normal, source);
             }
+            layout_table(rows, lines, width, source);
+            add_blank_line(lines);
         }
-
-        // Flush any remaining content (last line without trailing newline)
-        if !current_line.is_empty() {
-            lines.push(RenderedLine {
-                text: current_line,
-                style: LineStyle::CodeBlock {
-                    language: language.clone(),
-                },
-                search_matches: Vec::new(),
-                inline_styles: Vec::new(),
-                syntax_colors: current_line_colors,
-            });
+        BlockKind::Rule => {
+            lines.push(plain_line(
+                "─".repeat(width.min(80)),
+                LineStyle::Normal,
+                source,
+                0,
+            ));
+            add_blank_line(lines);
         }
-    } else {
-        // Treat as preformatted text without highlighting
-        let text = get_text_content(element);
-        for line in text.lines() {
-            lines.push(RenderedLine {
-                text: line.to_string(),
-                style: LineStyle::CodeBlock { language: None },
-                search_matches: Vec::new(),
-                inline_styles: Vec::new(),
-                syntax_colors: Vec::new(),
-            });
+        BlockKind::Aside { children, note } => {
+            let title = if *note {
+                "┌─ Note ─"
+            } else {
+                "┌─ Aside ─"
+            };
+            lines.push(plain_line(title.to_string(), LineStyle::Quote, source, 0));
+            for child in children {
+                layout_block(child, lines, headings, width, wrap_code);
+            }
+            let mut closing = plain_line("└─────────".to_string(), LineStyle::Quote, source, 0);
+            closing.anchor = None;
+            lines.push(closing);
+            add_blank_line(lines);
+        }
+        BlockKind::Figure(children) => {
+            lines.push(plain_line(
+                "[Figure]".to_string(),
+                LineStyle::Normal,
+                source,
+                0,
+            ));
+            for child in children {
+                layout_block(child, lines, headings, width, wrap_code);
+            }
+        }
+        BlockKind::Navigation { links } => {
+            // Navigation elements are typically TOC - render them minimally
+            lines.push(plain_line(
+                "─── Navigation ───".to_string(),
+                LineStyle::Heading3,
+                source,
+                0,
+            ));
+            for link in links {
+                if !link.content.text.trim().is_empty() {
+                    let text = link.content.prefixed(NAVIGATION_PREFIX);
+                    let mut line = plain_line(text.text, LineStyle::Link, &link.source, 0);
+                    line.links = text.links;
+                    lines.push(line);
+                }
+            }
+            add_blank_line(lines);
         }
     }
-    add_blank_line(lines);
 }
 
-fn process_image(element: ElementRef, lines: &mut Vec<RenderedLine>) {
-    let alt_text = element.value().attr("alt").unwrap_or("");
-    let placeholder = if alt_text.is_empty() {
-        "[Image]".to_string()
-    } else {
-        let truncated = if alt_text.len() > 50 {
-            format!("{}...", &alt_text[..50])
-        } else {
-            alt_text.to_string()
-        };
-        format!("[Image: {}]", truncated)
-    };
+/// Indent in front of definition descriptions
+const DEFINITION_PREFIX: &str = "  ";
 
-    lines.push(RenderedLine {
-        text: placeholder,
-        style: LineStyle::Normal,
-        search_matches: Vec::new(),
-        inline_styles: Vec::new(),
-        syntax_colors: Vec::new(),
-    });
-    add_blank_line(lines);
-}
-
-fn process_paragraph(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let (text, inline_styles) = extract_text_with_inline_styles(element);
-    add_text_lines(lines, &text, width, LineStyle::Normal, inline_styles);
-    add_blank_line(lines);
-}
-
-fn process_blockquote(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let (text, inline_styles) = extract_text_with_inline_styles(element);
-    add_text_lines(lines, &text, width, LineStyle::Quote, inline_styles);
-    add_blank_line(lines);
-}
+/// Marker in front of navigation links
+const NAVIGATION_PREFIX: &str = "→ ";
 
-fn process_link(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let (text, inline_styles) = extract_text_with_inline_styles(element);
-    add_text_lines(lines, &text, width, LineStyle::Link, inline_styles);
-}
-
-fn process_container(
-    element: ElementRef,
-    lines: &mut Vec<RenderedLine>,
-    headings: &mut Vec<HeadingInfo>,
-    width: usize,
-) {
-    for child in element.children() {
-        if let Some(child_element) = ElementRef::wrap(child) {
-            process_element(child_element, lines, headings, width, false);
-        }
+/// Number or bullet in front of a list item
+fn list_prefix(ordered: bool, index: usize) -> String {
+    if ordered {
+        format!("{}. ", index + 1)
+    } else {
+        "• ".to_string()
     }
 }
 
-fn process_inline_as_block(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let (text, inline_styles) = extract_text_with_inline_styles(element);
-    if !text.trim().is_empty() {
-        add_text_lines(lines, &text, width, LineStyle::Normal, inline_styles);
+/// Byte length of the prefix (bullet, number, indent) rendered in front of
+/// the text of the sub-block at `path`
+///
+/// Anchor offsets of list items, definitions and navigation links count the
+/// prefix, the text nodes of the document don't.
+pub fn text_prefix_len(document: &Document, path: &[usize]) -> usize {
+    fn find(blocks: &[Block], path: &[usize]) -> Option<usize> {
+        blocks.iter().find_map(|block| match &block.kind {
+            BlockKind::List { ordered, items } => items
+                .iter()
+                .position(|item| item.source == path)
+                .map(|index| list_prefix(*ordered, index).len()),
+            BlockKind::DefinitionList { descriptions, .. } => descriptions
+                .iter()
+                .any(|description| description.source == path)
+                .then_some(DEFINITION_PREFIX.len()),
+            BlockKind::Navigation { links } => links
+                .iter()
+                .any(|link| link.source == path)
+                .then_some(NAVIGATION_PREFIX.len()),
+            BlockKind::Aside { children, .. } | BlockKind::Figure(children) => find(children, path),
+            _ => None,
+        })
     }
+    find(&document.blocks, path).unwrap_or(0)
 }
 
-fn get_text_content(element: ElementRef) -> String {
-    element.text().collect::<Vec<_>>().join("")
-}
-
-/// Extract text content with inline styling information
-/// Returns: (text, Vec<(start, end, InlineStyle)>)
-fn extract_text_with_inline_styles(
-    element: ElementRef,
-) -> (String, Vec<(usize, usize, InlineStyle)>) {
-    let mut result = String::new();
-    let mut inline_styles = Vec::new();
-
-    fn process_children(
-        element: ElementRef,
-        result: &mut String,
-        inline_styles: &mut Vec<(usize, usize, InlineStyle)>,
-        current_styles: &[InlineStyle],
-    ) {
-        for child in element.children() {
-            if let Some(text) = child.value().as_text() {
-                let start = result.len();
-                result.push_str(text);
-                let end = result.len();
-
-                // Add all current styles for this text range
-                for style in current_styles {
-                    if end > start {
-                        inline_styles.push((start, end, style.clone()));
-                    }
-                }
-            } else if let Some(child_elem) = ElementRef::wrap(child) {
-                let tag = child_elem.value().name();
-
-                // Determine which styles to add for this tag
-                let mut new_styles = current_styles.to_vec();
-                match tag {
-                    "strong" | "b" => new_styles.push(InlineStyle::Bold),
-                    "em" | "i" => new_styles.push(InlineStyle::Italic),
-                    "code" => new_styles.push(InlineStyle::Code),
-                    "u" => new_styles.push(InlineStyle::Underline),
-                    "s" | "del" | "strike" => new_styles.push(InlineStyle::Strikethrough),
-                    "mark" => new_styles.push(InlineStyle::Highlight),
-                    _ => {}
-                }
-
-                // Process children with accumulated styles
-                process_children(child_elem, result, inline_styles, &new_styles);
-            }
-        }
+/// Split a code line into rows of at most `width` columns
+///
+/// Unlike prose, code is broken at the column limit rather than between
+/// words. Continuation rows repeat the line's indentation followed by
+/// [`CODE_CONTINUATION`], so wrapped code keeps its shape.
+fn wrap_code_line(
+    code_line: &CodeLine,
+    width: usize,
+    style: &LineStyle,
+    source: &[usize],
+) -> Vec<RenderedLine> {
+    let text = &code_line.text;
+    let indent = &text[..text.len() - text.trim_start().len()];
+    let mut prefix = format!("{}{}", indent, CODE_CONTINUATION);
+    if display_width(&prefix) * 2 > width {
+        // Deeply indented code would leave no room for the continuation
+        prefix = CODE_CONTINUATION.to_string();
     }
+    let prefix_width = display_width(&prefix);
+    let marker_start = prefix.len() - CODE_CONTINUATION.len();
+
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while start < text.len() || rows.is_empty() {
+        let (lead, limit) = if rows.is_empty() {
+            ("", width)
+        } else {
+            (prefix.as_str(), width.saturating_sub(prefix_width))
+        };
 
-    process_children(element, &mut result, &mut inline_styles, &[]);
-
-    (result, inline_styles)
-}
-
-fn detect_language(code_element: ElementRef) -> Option<String> {
-    let classes = code_element.value().attr("class")?;
-
-    const KNOWN_LANGUAGES: &[&str] = &[
-        "rust",
-        "python",
-        "javascript",
-        "typescript",
-        "java",
-        "c",
-        "cpp",
-        "go",
-        "ruby",
-        "php",
-        "swift",
-        "kotlin",
-        "scala",
-        "haskell",
-        "elixir",
-        "erlang",
-        "clojure",
-        "bash",
-        "sh",
-        "shell",
-        "sql",
-        "html",
-        "css",
-        "json",
-        "xml",
-        "yaml",
-        "markdown",
-        "md",
-        "toml",
-    ];
-
-    for class in classes.split_whitespace() {
-        // Check for "language-X" pattern
-        if let Some(lang) = class.strip_prefix("language-") {
-            return Some(lang.to_string());
+        // Take characters up to the column limit, but always at least one
+        let mut end = start;
+        let mut columns = 0;
+        for (idx, ch) in text[start..].char_indices() {
+            let ch_width = display_width(&text[start + idx..start + idx + ch.len_utf8()]);
+            if columns + ch_width > limit && end > start {
+                break;
+            }
+            columns += ch_width;
+            end = start + idx + ch.len_utf8();
         }
 
-        // Check for "highlight-X" pattern
-        if let Some(lang) = class.strip_prefix("highlight-") {
-            return Some(lang.to_string());
+        let mut line = plain_line(
+            format!("{}{}", lead, &text[start..end]),
+            style.clone(),
+            source,
+            code_line.offset + start,
+        );
+        if !lead.is_empty() {
+            line.syntax_colors
+                .push((marker_start, lead.len(), CODE_CONTINUATION_COLOR));
         }
+        line.syntax_colors.extend(
+            clip_spans(&code_line.colors, start, end)
+                .into_iter()
+                .map(|(s, e, color)| (s + lead.len(), e + lead.len(), color)),
+        );
+        rows.push(line);
+        start = end;
+    }
+    rows
+}
 
-        // Check for "sourceCode X" pattern
-        if let Some(lang) = class.strip_prefix("sourceCode") {
-            return Some(lang.trim().to_string());
-        }
+/// Add wrapped lines for a sub-block whose text gets a prefix (bullet, number, indent)
+fn add_prefixed_lines(
+    lines: &mut Vec<RenderedLine>,
+    prefix: &str,
+    item: &TextBlock,
+    width: usize,
+    style: LineStyle,
+) {
+    let text = item.content.prefixed(prefix);
+    add_text_lines(lines, &text, width, style, &item.source);
+}
 
-        // Check if it's a known language name directly
-        if KNOWN_LANGUAGES.contains(&class) {
-            return Some(class.to_string());
-        }
+/// Build a single unwrapped line anchored at `char_offset` within `source`
+pub(super) fn plain_line(
+    text: String,
+    style: LineStyle,
+    source: &[usize],
+    char_offset: usize,
+) -> RenderedLine {
+    RenderedLine {
+        text,
+        style,
+        search_matches: Vec::new(),
+        inline_styles: Vec::new(),
+        syntax_colors: Vec::new(),
+        links: Vec::new(),
+        anchor: Some(ContentAnchor {
+            element_path: source.to_vec(),
+            char_offset,
+        }),
     }
-
-    None
 }
 
 /// Add wrapped text lines with inline styling preserved across line breaks
 ///
 /// This function handles the complex task of maintaining inline style ranges
-/// (bold, italic, code, etc.) when text is wrapped across multiple lines.
+/// (bold, italic, code, etc.) and link spans when text is wrapped across
+/// multiple lines.
 ///
 /// # How it works:
 /// 1. Wraps text to fit within the specified width
@@ -573,27 +551,57 @@ fn detect_language(code_element: ElementRef) -> Option<String> {
 ///
 /// # Arguments:
 /// * `lines` - Accumulator for rendered lines
-/// * `text` - Raw text content to wrap and style
+/// * `content` - Text with inline styles and links in original text coordinates
 /// * `width` - Maximum line width in characters
 /// * `style` - Block-level style (Heading, Normal, Quote, etc.)
-/// * `inline_styles` - Vec of (start, end, InlineStyle) tuples in original text coordinates
+/// * `source` - Element path of the block the text came from (see `element_path`)
 fn add_text_lines(
     lines: &mut Vec<RenderedLine>,
-    text: &str,
+    content: &InlineText,
     width: usize,
     style: LineStyle,
-    inline_styles: Vec<(usize, usize, InlineStyle)>,
+    source: &[usize],
 ) {
-    if text.trim().is_empty() {
+    if content.text.trim().is_empty() {
         return;
     }
 
+    for wrapped_line in wrap_inline(content, width) {
+        lines.push(RenderedLine {
+            text: wrapped_line.text,
+            style: style.clone(),
+            search_matches: Vec::new(), // Will be populated during search
+            inline_styles: wrapped_line.styles,
+            syntax_colors: Vec::new(),
+            links: wrapped_line.links,
+            anchor: Some(ContentAnchor {
+                element_path: source.to_vec(),
+                char_offset: wrapped_line.offset,
+            }),
+        });
+    }
+}
+
+/// One line of wrapped inline text, with its spans relative to the line
+pub(super) struct WrappedLine {
+    pub text: String,
+    pub styles: Vec<(usize, usize, InlineStyle)>,
+    pub links: Vec<(usize, usize, String)>,
+    /// Byte offset of the line within the original text
+    pub offset: usize,
+}
+
+/// Wrap inline text to `width` columns, splitting its styles and links per line
+pub(super) fn wrap_inline(content: &InlineText, width: usize) -> Vec<WrappedLine> {
+    let text = content.text.as_str();
+
     // Wrap text to fit within width (textwrap handles word boundaries intelligently)
-    let wrapped = wrap(text, width);
+    let wrapped = wrap(text, width.max(1));
 
     // Track our position in the original text as we process wrapped lines
     // This is crucial for mapping inline styles from original text to wrapped lines
     let mut char_offset = 0;
+    let mut result = Vec::with_capacity(wrapped.len());
 
     for wrapped_line in wrapped {
         let line_text = wrapped_line.to_string();
@@ -603,41 +611,12 @@ fn add_text_lines(
         // Example: if char_offset=10 and line_len=20, this line spans chars [10, 30)
         let line_end = char_offset + line_len;
 
-        // Find inline styles that overlap with this wrapped line and adjust their positions
-        let mut line_inline_styles = Vec::new();
-        for (start, end, style_type) in &inline_styles {
-            // Check if this style range overlaps with current line's range [char_offset, line_end)
-            // A style overlaps if it ends after our start AND starts before our end
-            if *end > char_offset && *start < line_end {
-                // The style overlaps! Now translate its position to be relative to this line.
-                //
-                // Example: Original text "Hello **world** friend"
-                //          Style range: [6, 11] (bold "world")
-                //          Line 1: "Hello"        [0, 5]   - no overlap
-                //          Line 2: "**world**"    [6, 13]  - overlaps at [6, 11]
-                //                   Relative to line 2: [0, 5]
-                //
-                // new_start = max(style_start, line_start) - line_start
-                //           = max(6, 6) - 6 = 0
-                // new_end = min(style_end, line_end) - line_start
-                //         = min(11, 13) - 6 = 5
-                let new_start = (*start).max(char_offset) - char_offset;
-                let new_end = (*end).min(line_end) - char_offset;
-
-                // Only add if we have a valid range (prevents zero-length or negative ranges)
-                if new_end > new_start {
-                    line_inline_styles.push((new_start, new_end, style_type.clone()));
-                }
-            }
-        }
-
-        // Create the rendered line with adjusted inline styles
-        lines.push(RenderedLine {
+        // Find inline styles and links that overlap with this wrapped line
+        result.push(WrappedLine {
             text: line_text,
-            style: style.clone(),
-            search_matches: Vec::new(), // Will be populated during search
-            inline_styles: line_inline_styles,
-            syntax_colors: Vec::new(),
+            styles: clip_spans(&content.styles, char_offset, line_end),
+            links: clip_spans(&content.links, char_offset, line_end),
+            offset: char_offset,
         });
 
         // Move our position forward in the original text
@@ -657,228 +636,55 @@ fn add_text_lines(
             char_offset += 1;
         }
     }
-}
-
-fn add_blank_line(lines: &mut Vec<RenderedLine>) {
-    lines.push(RenderedLine {
-        text: String::new(),
-        style: LineStyle::Normal,
-        search_matches: Vec::new(),
-        inline_styles: Vec::new(),
-        syntax_colors: Vec::new(),
-    });
-}
-
-// EPUB3 feature handlers
-
-fn process_unordered_list(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let li_selector = Selector::parse("li").unwrap();
-    for li in element.select(&li_selector) {
-        let (text, inline_styles) = extract_text_with_inline_styles(li);
-        let bullet_text = format!("• {}", text);
-        // Adjust inline style positions for the bullet prefix (2 chars)
-        let adjusted_styles: Vec<_> = inline_styles
-            .into_iter()
-            .map(|(start, end, style)| (start + 2, end + 2, style))
-            .collect();
-        add_text_lines(
-            lines,
-            &bullet_text,
-            width.saturating_sub(2),
-            LineStyle::Normal,
-            adjusted_styles,
-        );
-    }
-    add_blank_line(lines);
-}
-
-fn process_ordered_list(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let li_selector = Selector::parse("li").unwrap();
-    for (index, li) in element.select(&li_selector).enumerate() {
-        let (text, inline_styles) = extract_text_with_inline_styles(li);
-        let numbered_text = format!("{}. {}", index + 1, text);
-        // Adjust inline style positions for the number prefix
-        let prefix_len = format!("{}. ", index + 1).len();
-        let adjusted_styles: Vec<_> = inline_styles
-            .into_iter()
-            .map(|(start, end, style)| (start + prefix_len, end + prefix_len, style))
-            .collect();
-        add_text_lines(
-            lines,
-            &numbered_text,
-            width.saturating_sub(3),
-            LineStyle::Normal,
-            adjusted_styles,
-        );
-    }
-    add_blank_line(lines);
-}
 
-fn process_definition_list(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    let dt_selector = Selector::parse("dt").unwrap();
-    let dd_selector = Selector::parse("dd").unwrap();
-
-    // Process definition terms
-    for dt in element.select(&dt_selector) {
-        let (text, inline_styles) = extract_text_with_inline_styles(dt);
-        add_text_lines(lines, &text, width, LineStyle::Heading3, inline_styles);
-    }
-
-    // Process definition descriptions
-    for dd in element.select(&dd_selector) {
-        let (text, inline_styles) = extract_text_with_inline_styles(dd);
-        let indented_text = format!("  {}", text);
-        // Adjust inline style positions for the indent (2 chars)
-        let adjusted_styles: Vec<_> = inline_styles
-            .into_iter()
-            .map(|(start, end, style)| (start + 2, end + 2, style))
-            .collect();
-        add_text_lines(
-            lines,
-            &indented_text,
-            width.saturating_sub(2),
-            LineStyle::Normal,
-            adjusted_styles,
-        );
-    }
-
-    add_blank_line(lines);
+    result
 }
 
-fn process_table(element: ElementRef, lines: &mut Vec<RenderedLine>, width: usize) {
-    // Simple table rendering - just extract text row by row
-    lines.push(RenderedLine {
-        text: "[Table]".to_string(),
-        style: LineStyle::Normal,
-        search_matches: Vec::new(),
-        inline_styles: Vec::new(),
-        syntax_colors: Vec::new(),
-    });
-
-    let tr_selector = Selector::parse("tr").unwrap();
-    let td_selector = Selector::parse("td, th").unwrap();
-
-    for tr in element.select(&tr_selector) {
-        let mut row_text = String::new();
-        let mut row_inline_styles = Vec::new();
-        let mut current_pos = 0;
-
-        for (index, td) in tr.select(&td_selector).enumerate() {
-            if index > 0 {
-                row_text.push_str(" | ");
-                current_pos += 3;
+/// Translate the spans overlapping `[line_start, line_end)` to line-relative positions
+fn clip_spans<T: Clone>(
+    spans: &[(usize, usize, T)],
+    line_start: usize,
+    line_end: usize,
+) -> Vec<(usize, usize, T)> {
+    let mut clipped = Vec::new();
+    for (start, end, value) in spans {
+        // Check if this range overlaps with current line's range [line_start, line_end)
+        // A range overlaps if it ends after our start AND starts before our end
+        if *end > line_start && *start < line_end {
+            // The range overlaps! Now translate its position to be relative to this line.
+            //
+            // Example: Original text "Hello **world** friend"
+            //          Style range: [6, 11] (bold "world")
+            //          Line 1: "Hello"        [0, 5]   - no overlap
+            //          Line 2: "**world**"    [6, 13]  - overlaps at [6, 11]
+            //                   Relative to line 2: [0, 5]
+            //
+            // new_start = max(style_start, line_start) - line_start
+            //           = max(6, 6) - 6 = 0
+            // new_end = min(style_end, line_end) - line_start
+            //         = min(11, 13) - 6 = 5
+            let new_start = (*start).max(line_start) - line_start;
+            let new_end = (*end).min(line_end) - line_start;
+
+            // Only add if we have a valid range (prevents zero-length or negative ranges)
+            if new_end > new_start {
+                clipped.push((new_start, new_end, value.clone()));
             }
-            let (text, inline_styles) = extract_text_with_inline_styles(td);
-            // Adjust inline style positions for the current position in row
-            for (start, end, style) in inline_styles {
-                row_inline_styles.push((start + current_pos, end + current_pos, style));
-            }
-            row_text.push_str(&text);
-            current_pos += text.len();
-        }
-        if !row_text.trim().is_empty() {
-            add_text_lines(
-                lines,
-                &row_text,
-                width,
-                LineStyle::Normal,
-                row_inline_styles,
-            );
         }
     }
-
-    add_blank_line(lines);
+    clipped
 }
 
-fn process_horizontal_rule(lines: &mut Vec<RenderedLine>, width: usize) {
-    let rule = "─".repeat(width.min(80));
+fn add_blank_line(lines: &mut Vec<RenderedLine>) {
     lines.push(RenderedLine {
-        text: rule,
+        text: String::new(),
         style: LineStyle::Normal,
         search_matches: Vec::new(),
         inline_styles: Vec::new(),
         syntax_colors: Vec::new(),
+        links: Vec::new(),
+        anchor: None,
     });
-    add_blank_line(lines);
-}
-
-fn process_semantic_container(
-    element: ElementRef,
-    lines: &mut Vec<RenderedLine>,
-    _headings: &mut Vec<HeadingInfo>,
-    _width: usize,
-) {
-    // Add a visual separator for semantic containers
-    let tag_name = element.value().name();
-    if tag_name == "aside" {
-        lines.push(RenderedLine {
-            text: "┌─ Aside ─".to_string(),
-            style: LineStyle::Quote,
-            search_matches: Vec::new(),
-            inline_styles: Vec::new(),
-            syntax_colors: Vec::new(),
-        });
-    } else if tag_name == "figure" {
-        lines.push(RenderedLine {
-            text: "[Figure]".to_string(),
-            style: LineStyle::Normal,
-            search_matches: Vec::new(),
-            inline_styles: Vec::new(),
-            syntax_colors: Vec::new(),
-        });
-    }
-
-    // Process children
-    for child in element.children() {
-        if let Some(child_element) = ElementRef::wrap(child) {
-            process_element(child_element, lines, _headings, _width, false);
-        }
-    }
-
-    if tag_name == "aside" {
-        lines.push(RenderedLine {
-            text: "└─────────".to_string(),
-            style: LineStyle::Quote,
-            search_matches: Vec::new(),
-            inline_styles: Vec::new(),
-            syntax_colors: Vec::new(),
-        });
-        add_blank_line(lines);
-    }
-}
-
-fn process_navigation(
-    element: ElementRef,
-    lines: &mut Vec<RenderedLine>,
-    _headings: &mut Vec<HeadingInfo>,
-    _width: usize,
-) {
-    // Navigation elements are typically TOC - we can skip or render minimally
-    lines.push(RenderedLine {
-        text: "─── Navigation ───".to_string(),
-        style: LineStyle::Heading3,
-        search_matches: Vec::new(),
-        inline_styles: Vec::new(),
-        syntax_colors: Vec::new(),
-    });
-
-    // Process links in navigation
-    let a_selector = Selector::parse("a").unwrap();
-    for link in element.select(&a_selector) {
-        let text = get_text_content(link);
-        if !text.trim().is_empty() {
-            let nav_item = format!("→ {}", text);
-            lines.push(RenderedLine {
-                text: nav_item,
-                style: LineStyle::Link,
-                search_matches: Vec::new(),
-                inline_styles: Vec::new(),
-                syntax_colors: Vec::new(),
-            });
-        }
-    }
-
-    add_blank_line(lines);
 }
 
 #[cfg(test)]
@@ -891,6 +697,8 @@ mod tests {
             sections: Vec::new(),
             content_lines: Vec::new(),
             file_path: html_content.to_string(),
+            href: "test.xhtml".to_string(),
+            document: crate::epub::document::build_document(html_content),
         }
     }
 
@@ -899,7 +707,7 @@ mod tests {
         let html = "<p>This is a simple paragraph.</p>";
         let mut chapter = create_test_chapter(html);
 
-        render_chapter(&mut chapter, Some(80), 100);
+        render_chapter(&mut chapter, Some(80), 100, false);
 
         assert!(!chapter.content_lines.is_empty());
         assert!(
@@ -914,7 +722,7 @@ mod tests {
         let html = "<h1>Main Heading</h1><p>Content here.</p>";
         let mut chapter = create_test_chapter(html);
 
-        render_chapter(&mut chapter, Some(80), 100);
+        render_chapter(&mut chapter, Some(80), 100, false);
 
         assert!(!chapter.content_lines.is_empty());
         // Find the heading line
@@ -936,7 +744,7 @@ mod tests {
         "#;
         let mut chapter = create_test_chapter(html);
 
-        render_chapter(&mut chapter, Some(80), 100);
+        render_chapter(&mut chapter, Some(80), 100, false);
 
         // Should extract h2 headings as sections
         assert!(chapter.sections.len() >= 2);
@@ -960,7 +768,7 @@ mod tests {
         let html = format!("<p>{}</p>", long_text);
         let mut chapter = create_test_chapter(&html);
 
-        render_chapter(&mut chapter, Some(40), 100);
+        render_chapter(&mut chapter, Some(40), 100, false);
 
         // Should wrap into multiple lines
         assert!(chapter.content_lines.len() > 1);
@@ -976,7 +784,7 @@ mod tests {
         let mut chapter = create_test_chapter(html);
 
         // Set max_width smaller than terminal width
-        render_chapter(&mut chapter, Some(50), 200);
+        render_chapter(&mut chapter, Some(50), 200, false);
 
         // Should use max_width, not terminal width
         assert!(!chapter.content_lines.is_empty());
@@ -987,10 +795,140 @@ mod tests {
         let html = "";
         let mut chapter = create_test_chapter(html);
 
-        render_chapter(&mut chapter, Some(80), 100);
+        render_chapter(&mut chapter, Some(80), 100, false);
 
         // Should handle empty content gracefully
         // May have 0 or 1 empty line
         assert!(chapter.content_lines.len() <= 1);
     }
+
+    #[test]
+    fn test_anchor_survives_rewrap() {
+        let long_text = (0..60)
+            .map(|i| format!("word{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let html = format!("<h1>Title</h1><p>Intro</p><p>{}</p>", long_text);
+        let mut chapter = create_test_chapter(&html);
+
+        // Pick a line in the middle of the long paragraph at a narrow width
+        render_chapter(&mut chapter, Some(40), 100, false);
+        let narrow_line = chapter
+            .content_lines
+            .iter()
+            .position(|l| l.text.contains("word30"))
+            .unwrap();
+        let first_word = chapter.content_lines[narrow_line]
+            .text
+            .split(' ')
+            .next()
+            .unwrap()
+            .to_string();
+        let anchor = chapter.anchor_at_line(narrow_line).unwrap();
+        assert_eq!(anchor.element_path, vec![2]);
+
+        // The same anchor must land on the line holding that text at another width
+        render_chapter(&mut chapter, Some(120), 200, false);
+        let wide_line = chapter.line_for_anchor(&anchor);
+        let wide_words: Vec<&str> = chapter.content_lines[wide_line].text.split(' ').collect();
+        assert!(wide_words.contains(&first_word.as_str()));
+    }
+
+    #[test]
+    fn test_rerender_uses_document_model() {
+        let mut chapter = create_test_chapter("<h2 id=\"s\">Section</h2><p>Some text to wrap</p>");
+        render_chapter(&mut chapter, Some(80), 100, false);
+        let wide = chapter.content_lines.len();
+
+        // Re-wrapping must not depend on the HTML any more
+        chapter.file_path.clear();
+        render_chapter(&mut chapter, Some(10), 100, false);
+        assert!(chapter.content_lines.len() > wide);
+        assert_eq!(chapter.sections[0].fragment_id.as_deref(), Some("s"));
+    }
+
+    #[test]
+    fn test_blank_lines_use_preceding_anchor() {
+        let html = "<p>First</p><p>Second</p>";
+        let mut chapter = create_test_chapter(html);
+
+        render_chapter(&mut chapter, Some(80), 100, false);
+
+        // Line 1 is the blank separator after the first paragraph
+        assert!(chapter.content_lines[1].anchor.is_none());
+        let anchor = chapter.anchor_at_line(1).unwrap();
+        assert_eq!(anchor.element_path, vec![0]);
+        assert_eq!(chapter.line_for_anchor(&anchor), 0);
+    }
+
+    #[test]
+    fn test_image_reserves_rows_before_caption() {
+        let mut chapter = create_test_chapter(r#"<img src="a.png" alt="Diagram"/><p>After</p>"#);
+        chapter.document.for_each_image(|_, resource| {
+            *resource = Some(crate::epub::document::ImageResource {
+                path: "a.png".to_string(),
+                width: 160,
+                height: 32,
+            });
+        });
+
+        render_chapter(&mut chapter, Some(80), 100, false);
+
+        // 160x32 pixels at the nominal 8x16 cell size is 20 columns by 2 rows
+        let expected = |row| LineStyle::Image {
+            path: "a.png".to_string(),
+            row,
+            rows: 2,
+            cols: 20,
+        };
+        assert_eq!(chapter.content_lines[0].style, expected(0));
+        assert_eq!(chapter.content_lines[1].style, expected(1));
+        assert_eq!(chapter.content_lines[2].text, "[Image: Diagram]");
+        assert_eq!(chapter.content_lines[4].text, "After");
+    }
+
+    #[test]
+    fn test_code_lines_wrap_only_when_asked() {
+        let code = "    let value = compute(alpha, beta, gamma);";
+        let html = format!(
+            "<pre><code class=\"language-rust\">{}\nfn next() {{}}</code></pre>",
+            code
+        );
+        let mut chapter = create_test_chapter(&html);
+
+        // Without soft-wrap the line stays whole for horizontal scrolling
+        render_chapter(&mut chapter, Some(24), 100, false);
+        assert_eq!(chapter.content_lines[0].text, code);
+        assert_eq!(chapter.content_lines[1].text, "fn next() {}");
+
+        // Soft-wrapped rows fit the 20 column width and keep the indentation
+        render_chapter(&mut chapter, Some(24), 100, true);
+        let rows: Vec<&str> = chapter.content_lines[..3]
+            .iter()
+            .map(|l| l.text.as_str())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "    let value = comp",
+                "    ↪ ute(alpha, bet",
+                "    ↪ a, gamma);"
+            ]
+        );
+        assert_eq!(chapter.content_lines[3].text, "fn next() {}");
+
+        let offsets: Vec<usize> = chapter.content_lines[..3]
+            .iter()
+            .map(|l| l.anchor.as_ref().unwrap().char_offset)
+            .collect();
+        assert_eq!(offsets, [0, 20, 34]);
+
+        for line in &chapter.content_lines[..4] {
+            assert!(display_width(&line.text) <= 20);
+            for &(start, end, _) in &line.syntax_colors {
+                assert!(start < end && end <= line.text.len());
+                assert!(line.text.is_char_boundary(start) && line.text.is_char_boundary(end));
+            }
+        }
+    }
 }
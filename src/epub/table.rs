@@ -0,0 +1,574 @@
+//! Grid layout for tables
+//!
+//! Tables are measured and drawn as a box-drawing grid: column widths come
+//! from the longest word and the full text of each cell, cells wrap within
+//! their columns, and `colspan`/`rowspan` cells merge the grid lines they
+//! cover. Tables whose columns cannot fit the text width are shown as one
+//! "card" per row instead, listing each cell under its column heading.
+
+use super::document::{CellAlign, InlineText, TableCell, TableRow};
+use super::renderer::{WrappedLine, plain_line, wrap_inline};
+use crate::types::{ContentAnchor, InlineStyle, LineStyle, RenderedLine};
+use ratatui::style::Color;
+use textwrap::core::display_width;
+
+/// Longest word that widens a column, longer words are broken across lines
+const MAX_WORD_WIDTH: usize = 16;
+
+/// Color of grid lines and card borders
+const BORDER_COLOR: Color = Color::DarkGray;
+
+/// A cell at its position in the grid
+struct GridCell<'a> {
+    cell: &'a TableCell,
+    row: usize,
+    col: usize,
+    colspan: usize,
+    /// Rows covered, clamped to the end of the table
+    rowspan: usize,
+}
+
+/// Lay out a table as a grid, or as cards when it is too wide
+///
+/// # Arguments
+/// * `rows` - Table rows from the document model
+/// * `lines` - Accumulator for rendered lines
+/// * `width` - Text width in columns
+/// * `source` - Element path of the table
+pub fn layout_table(
+    rows: &[TableRow],
+    lines: &mut Vec<RenderedLine>,
+    width: usize,
+    source: &[usize],
+) {
+    let (cells, columns) = place_cells(rows);
+    if cells.is_empty() {
+        return;
+    }
+
+    match column_widths(&cells, columns, width) {
+        Some(widths) => draw_grid(rows, &cells, &widths, lines, source),
+        None => {
+            log::debug!(
+                "Table with {} columns does not fit in {} columns, showing cards",
+                columns,
+                width
+            );
+            draw_cards(rows, &cells, columns, lines, width);
+        }
+    }
+}
+
+/// Assign every cell its grid position, skipping slots taken by row spans
+///
+/// # Returns
+/// The placed cells and the number of grid columns
+fn place_cells(rows: &[TableRow]) -> (Vec<GridCell<'_>>, usize) {
+    let mut occupied: Vec<Vec<bool>> = vec![Vec::new(); rows.len()];
+    let mut cells = Vec::new();
+    let mut columns = 0;
+
+    for (row_idx, row) in rows.iter().enumerate() {
+        let mut col = 0;
+        for cell in &row.cells {
+            while occupied[row_idx].get(col).copied().unwrap_or(false) {
+                col += 1;
+            }
+
+            let rowspan = cell.rowspan.min(rows.len() - row_idx);
+            for slots in &mut occupied[row_idx..row_idx + rowspan] {
+                if slots.len() < col + cell.colspan {
+                    slots.resize(col + cell.colspan, false);
+                }
+                slots[col..col + cell.colspan].fill(true);
+            }
+
+            cells.push(GridCell {
+                cell,
+                row: row_idx,
+                col,
+                colspan: cell.colspan,
+                rowspan,
+            });
+            col += cell.colspan;
+            columns = columns.max(col);
+        }
+    }
+
+    (cells, columns)
+}
+
+/// Number of leading rows made up of header cells only
+fn header_rows(rows: &[TableRow]) -> usize {
+    rows.iter()
+        .take_while(|row| !row.cells.is_empty() && row.cells.iter().all(|cell| cell.header))
+        .count()
+}
+
+/// Text width of each column
+///
+/// Columns get their full text width when everything fits; otherwise each
+/// starts at its longest word and the remaining space is shared in
+/// proportion to how much more each column would like.
+///
+/// # Returns
+/// `None` if even the narrowest layout is wider than `width`
+fn column_widths(cells: &[GridCell], columns: usize, width: usize) -> Option<Vec<usize>> {
+    // Every column adds " │ " (or "│ " and " │" at the edges) around its text
+    let available = width.checked_sub(3 * columns + 1)?;
+
+    let mut min = vec![1; columns];
+    let mut max = vec![1; columns];
+    let mut by_span: Vec<&GridCell> = cells.iter().collect();
+    by_span.sort_by_key(|cell| cell.colspan);
+    for cell in by_span {
+        let text = &cell.cell.content.text;
+        let longest_word = text
+            .split_whitespace()
+            .map(display_width)
+            .max()
+            .unwrap_or(0)
+            .min(MAX_WORD_WIDTH);
+        let span = cell.col..cell.col + cell.colspan;
+        widen(&mut min[span.clone()], longest_word);
+        widen(&mut max[span], display_width(text));
+    }
+    for (max, min) in max.iter_mut().zip(&min) {
+        *max = (*max).max(*min);
+    }
+
+    let min_total: usize = min.iter().sum();
+    let max_total: usize = max.iter().sum();
+    if min_total > available {
+        return None;
+    }
+    if max_total <= available {
+        return Some(max);
+    }
+
+    // Share the space beyond the minimum by how much more each column wants
+    let extra = available - min_total;
+    let wanted = max_total - min_total;
+    let mut widths: Vec<usize> = min
+        .iter()
+        .zip(&max)
+        .map(|(lo, hi)| lo + (hi - lo) * extra / wanted)
+        .collect();
+
+    // Hand out what the rounding left over
+    let mut left = available - widths.iter().sum::<usize>();
+    for (width, hi) in widths.iter_mut().zip(&max) {
+        if left == 0 {
+            break;
+        }
+        if *width < *hi {
+            *width += 1;
+            left -= 1;
+        }
+    }
+
+    Some(widths)
+}
+
+/// Grow spanned columns so together they hold `needed` columns of text
+fn widen(columns: &mut [usize], needed: usize) {
+    // Spanned columns also gain the separators between them
+    let have = columns.iter().sum::<usize>() + 3 * (columns.len() - 1);
+    if needed > have {
+        let missing = needed - have;
+        let count = columns.len();
+        for (index, width) in columns.iter_mut().enumerate() {
+            *width += missing / count + usize::from(index < missing % count);
+        }
+    }
+}
+
+/// Text width of a cell spanning `colspan` columns from `col`
+fn span_width(widths: &[usize], col: usize, colspan: usize) -> usize {
+    widths[col..col + colspan].iter().sum::<usize>() + 3 * (colspan - 1)
+}
+
+/// Box-drawing character joining grid lines in the given directions
+fn junction(up: bool, down: bool, left: bool, right: bool, double: bool) -> char {
+    let horizontal = left || right;
+    match (up, down, horizontal, double) {
+        (false, false, _, false) => '─',
+        (false, false, _, true) => '═',
+        (_, _, false, _) => '│',
+        _ => match (up, down, left, right, double) {
+            (false, true, false, true, false) => '┌',
+            (false, true, true, false, false) => '┐',
+            (true, false, false, true, false) => '└',
+            (true, false, true, false, false) => '┘',
+            (true, true, false, true, false) => '├',
+            (true, true, true, false, false) => '┤',
+            (false, true, true, true, false) => '┬',
+            (true, false, true, true, false) => '┴',
+            (_, _, _, _, false) => '┼',
+            (false, true, false, true, true) => '╒',
+            (false, true, true, false, true) => '╕',
+            (true, false, false, true, true) => '╘',
+            (true, false, true, false, true) => '╛',
+            (true, true, false, true, true) => '╞',
+            (true, true, true, false, true) => '╡',
+            (false, true, true, true, true) => '╤',
+            (true, false, true, true, true) => '╧',
+            _ => '╪',
+        },
+    }
+}
+
+/// Draw the table as a grid with wrapped cells
+fn draw_grid(
+    rows: &[TableRow],
+    cells: &[GridCell],
+    widths: &[usize],
+    lines: &mut Vec<RenderedLine>,
+    source: &[usize],
+) {
+    let wrapped: Vec<Vec<WrappedLine>> = cells
+        .iter()
+        .map(|cell| {
+            if cell.cell.content.text.is_empty() {
+                Vec::new()
+            } else {
+                wrap_inline(
+                    &cell.cell.content,
+                    span_width(widths, cell.col, cell.colspan),
+                )
+            }
+        })
+        .collect();
+
+    // Rows are as tall as their tallest single-row cell
+    let mut heights = vec![1; rows.len()];
+    for (cell, text) in cells.iter().zip(&wrapped) {
+        if cell.rowspan == 1 {
+            heights[cell.row] = heights[cell.row].max(text.len());
+        }
+    }
+
+    // Rules between rows only when rows are hard to tell apart without them
+    let header_rows = header_rows(rows);
+    let all_rules = heights.iter().any(|h| *h > 1) || cells.iter().any(|c| c.rowspan > 1);
+    let rule_after = |row: usize| row + 1 < rows.len() && (all_rules || row + 1 == header_rows);
+
+    // Cells spanning rows may need the last of those rows to grow
+    for (cell, text) in cells.iter().zip(&wrapped) {
+        let spanned = cell.row..cell.row + cell.rowspan;
+        let last = spanned.end - 1;
+        let room = heights[spanned.clone()].iter().sum::<usize>()
+            + spanned
+                .clone()
+                .filter(|row| *row < last && rule_after(*row))
+                .count();
+        if text.len() > room {
+            heights[last] += text.len() - room;
+        }
+    }
+
+    // Vertical layout: the first content line of every row and the rule lines
+    let mut row_top = Vec::with_capacity(rows.len());
+    let mut rule_lines = vec![Some(false)];
+    let mut line_rows = vec![None];
+    for (row, height) in heights.iter().enumerate() {
+        row_top.push(rule_lines.len());
+        rule_lines.extend(std::iter::repeat_n(None, *height));
+        line_rows.extend(std::iter::repeat_n(Some(row), *height));
+        if rule_after(row) {
+            rule_lines.push(Some(row + 1 == header_rows));
+            line_rows.push(Some(row + 1));
+        }
+    }
+    rule_lines.push(Some(false));
+    line_rows.push(rows.len().checked_sub(1));
+    let height = rule_lines.len();
+
+    // Horizontal layout: the position of every column boundary
+    let mut boundaries = vec![0];
+    for width in widths {
+        boundaries.push(boundaries.last().unwrap_or(&0) + width + 3);
+    }
+    let total_width = boundaries.last().copied().unwrap_or(0) + 1;
+
+    // Points inside a cell, where grid lines are not drawn
+    let mut owner: Vec<Vec<Option<usize>>> = vec![vec![None; total_width]; height];
+    for (index, cell) in cells.iter().enumerate() {
+        let last = cell.row + cell.rowspan - 1;
+        let (x0, x1) = (boundaries[cell.col], boundaries[cell.col + cell.colspan]);
+        for line in &mut owner[row_top[cell.row]..row_top[last] + heights[last]] {
+            line[x0 + 1..x1].fill(Some(index));
+        }
+    }
+    let is_boundary: Vec<bool> = (0..total_width).map(|x| boundaries.contains(&x)).collect();
+    let horizontal = |y: usize, x: usize| rule_lines[y].is_some() && owner[y][x].is_none();
+    let vertical = |y: usize, x: usize| is_boundary[x] && owner[y][x].is_none();
+
+    for y in 0..height {
+        let mut line = RenderedLine {
+            text: String::new(),
+            style: LineStyle::Normal,
+            search_matches: Vec::new(),
+            inline_styles: Vec::new(),
+            syntax_colors: Vec::new(),
+            links: Vec::new(),
+            anchor: Some(ContentAnchor {
+                element_path: line_rows[y]
+                    .map_or(source, |row| rows[row].source.as_slice())
+                    .to_vec(),
+                char_offset: 0,
+            }),
+        };
+
+        let mut x = 0;
+        while x < total_width {
+            if let Some(index) = owner[y][x] {
+                // The whole inside of the cell on this line, x is its left padding
+                let cell = &cells[index];
+                let x1 = boundaries[cell.col + cell.colspan];
+                let text = wrapped[index].get(y - row_top[cell.row]);
+                push_cell_text(&mut line, cell.cell, text, x1 - x - 2);
+                x = x1;
+                continue;
+            }
+
+            let ch = if horizontal(y, x) || vertical(y, x) {
+                junction(
+                    vertical(y, x) && y > 0 && vertical(y - 1, x),
+                    vertical(y, x) && y + 1 < height && vertical(y + 1, x),
+                    horizontal(y, x) && x > 0 && horizontal(y, x - 1),
+                    horizontal(y, x) && x + 1 < total_width && horizontal(y, x + 1),
+                    rule_lines[y] == Some(true),
+                )
+            } else {
+                ' '
+            };
+            push_border(&mut line, ch);
+            x += 1;
+        }
+
+        lines.push(line);
+    }
+}
+
+/// Append padded cell text to a grid line, keeping the cell's styles and links
+fn push_cell_text(
+    line: &mut RenderedLine,
+    cell: &TableCell,
+    text: Option<&WrappedLine>,
+    width: usize,
+) {
+    let text_width = text.map_or(0, |t| display_width(&t.text));
+    let pad = width.saturating_sub(text_width);
+    let left_pad = match cell.align {
+        CellAlign::Left => 0,
+        CellAlign::Center => pad / 2,
+        CellAlign::Right => pad,
+    };
+
+    line.text.push(' ');
+    line.text.extend(std::iter::repeat_n(' ', left_pad));
+    if let Some(text) = text {
+        let start = line.text.len();
+        line.text.push_str(&text.text);
+        let end = line.text.len();
+
+        line.inline_styles.extend(shifted(&text.styles, start));
+        line.links.extend(shifted(&text.links, start));
+        if cell.header && end > start {
+            line.inline_styles.push((start, end, InlineStyle::Bold));
+        }
+    }
+    line.text
+        .extend(std::iter::repeat_n(' ', pad - left_pad + 1));
+}
+
+/// Append a grid character, coloring runs of border characters together
+fn push_border(line: &mut RenderedLine, ch: char) {
+    let start = line.text.len();
+    line.text.push(ch);
+    if ch == ' ' {
+        return;
+    }
+    let end = line.text.len();
+    match line.syntax_colors.last_mut() {
+        Some((_, last_end, _)) if *last_end == start => *last_end = end,
+        _ => line.syntax_colors.push((start, end, BORDER_COLOR)),
+    }
+}
+
+/// Draw one card per body row, each cell labelled with its column heading
+fn draw_cards(
+    rows: &[TableRow],
+    cells: &[GridCell],
+    columns: usize,
+    lines: &mut Vec<RenderedLine>,
+    width: usize,
+) {
+    // A table of only headings has no records, show the headings themselves
+    let header_rows = match header_rows(rows) {
+        count if count == rows.len() => 0,
+        count => count,
+    };
+
+    let mut labels = vec![String::new(); columns];
+    for cell in cells.iter().filter(|cell| cell.row < header_rows) {
+        for label in &mut labels[cell.col..cell.col + cell.colspan] {
+            if !label.is_empty() {
+                label.push_str(" / ");
+            }
+            label.push_str(&cell.cell.content.text);
+        }
+    }
+
+    let rule = "─".repeat(width.saturating_sub(1).min(40));
+    for (row_idx, row) in rows.iter().enumerate().skip(header_rows) {
+        lines.push(card_border(format!("┌{}", rule), &row.source));
+
+        // Cells covering this row, including those spanning down from above
+        for (col, label) in labels.iter().enumerate() {
+            let Some(cell) = cells.iter().find(|cell| {
+                cell.col == col && cell.row <= row_idx && row_idx < cell.row + cell.rowspan
+            }) else {
+                continue;
+            };
+            if cell.cell.content.text.is_empty() {
+                continue;
+            }
+
+            let mut text = InlineText::default();
+            if !label.is_empty() {
+                text = InlineText::plain(format!("{}: ", label));
+                text.styles.push((0, label.len() + 1, InlineStyle::Bold));
+            }
+            let start = text.text.len();
+            text.append(&cell.cell.content);
+            if cell.cell.header {
+                text.styles
+                    .push((start, text.text.len(), InlineStyle::Bold));
+            }
+
+            for wrapped in wrap_inline(&text, width.saturating_sub(2)) {
+                let mut line = card_border("│ ".to_string(), &row.source);
+                let shift = line.text.len();
+                line.inline_styles = shifted(&wrapped.styles, shift);
+                line.links = shifted(&wrapped.links, shift);
+                line.text.push_str(&wrapped.text);
+                lines.push(line);
+            }
+        }
+
+        lines.push(card_border(format!("└{}", rule), &row.source));
+    }
+}
+
+/// Spans moved `by` bytes to the right
+fn shifted<T: Clone>(spans: &[(usize, usize, T)], by: usize) -> Vec<(usize, usize, T)> {
+    spans
+        .iter()
+        .map(|(start, end, value)| (start + by, end + by, value.clone()))
+        .collect()
+}
+
+/// A card line starting with border characters (up to the first space)
+fn card_border(text: String, source: &[usize]) -> RenderedLine {
+    let border_end = text.find(' ').unwrap_or(text.len());
+    let mut line = plain_line(text, LineStyle::Normal, source, 0);
+    line.syntax_colors.push((0, border_end, BORDER_COLOR));
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::document::{BlockKind, build_document};
+
+    fn render(html: &str, width: usize) -> Vec<String> {
+        let doc = build_document(html);
+        let BlockKind::Table { rows, .. } = &doc.blocks[0].kind else {
+            panic!("expected a table");
+        };
+        let mut lines = Vec::new();
+        layout_table(rows, &mut lines, width, &[0]);
+        lines.into_iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn test_grid_with_header() {
+        let lines = render(
+            r#"<table><thead><tr><th>Name</th><th>Qty</th></tr></thead>
+            <tr><td>Apple</td><td align="right">3</td></tr>
+            <tr><td>Fig</td><td align="right">12</td></tr></table>"#,
+            40,
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "┌───────┬─────┐",
+                "│ Name  │ Qty │",
+                "╞═══════╪═════╡",
+                "│ Apple │   3 │",
+                "│ Fig   │  12 │",
+                "└───────┴─────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_colspan_and_rowspan_merge_grid_lines() {
+        let lines = render(
+            r#"<table>
+            <tr><td rowspan="2">A</td><td colspan="2">B</td></tr>
+            <tr><td>C</td><td>D</td></tr></table>"#,
+            40,
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "┌───┬───────┐",
+                "│ A │ B     │",
+                "│   ├───┬───┤",
+                "│   │ C │ D │",
+                "└───┴───┴───┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cells_wrap_within_columns() {
+        let lines = render(
+            "<table><tr><td>one two three four</td><td>x</td></tr></table>",
+            16,
+        );
+        // 16 columns leave 9 for text: "x" keeps 1, the rest goes to the first column
+        assert_eq!(
+            lines,
+            vec![
+                "┌──────────┬───┐",
+                "│ one two  │ x │",
+                "│ three    │   │",
+                "│ four     │   │",
+                "└──────────┴───┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wide_table_falls_back_to_cards() {
+        let lines = render(
+            r#"<table><tr><th>Term</th><th>Meaning</th><th>Notes</th></tr>
+            <tr><td>alphabetical</td><td>ordered</td><td>sorted</td></tr></table>"#,
+            20,
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "┌───────────────────",
+                "│ Term: alphabetical",
+                "│ Meaning: ordered",
+                "│ Notes: sorted",
+                "└───────────────────",
+            ]
+        );
+    }
+}
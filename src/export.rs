@@ -0,0 +1,377 @@
+//! Exporting bookmarks as notes
+//!
+//! Writes the bookmarks of a book to Markdown, JSON or Org-mode, grouped by
+//! chapter under the chapter titles and headed by the book metadata. Every
+//! bookmark carries the paragraph it points into as context, so the export is
+//! readable without the book at hand. Context is taken from rendered chapters,
+//! so the book must have been rendered (at any width) before exporting.
+
+use crate::types::{Book, Bookmark, Chapter};
+use serde::Serialize;
+
+/// Longest context excerpt, in characters, written for a bookmark
+const MAX_CONTEXT_LENGTH: usize = 400;
+
+/// File formats bookmarks can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Org,
+}
+
+impl ExportFormat {
+    /// Parse a format name as given on the command line
+    ///
+    /// # Returns
+    /// * `Ok(ExportFormat)` - For `markdown`/`md`, `json` or `org`
+    /// * `Err(String)` - Unknown format name
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            "org" => Ok(ExportFormat::Org),
+            other => Err(format!(
+                "Unknown export format '{}' (expected markdown, json or org)",
+                other
+            )),
+        }
+    }
+
+    /// File extension used for the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Org => "org",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportDocument<'a> {
+    title: &'a str,
+    author: Option<&'a str>,
+    publisher: Option<&'a str>,
+    publication_date: Option<&'a str>,
+    language: Option<&'a str>,
+    chapters: Vec<ExportChapter<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExportChapter<'a> {
+    /// 1-based chapter number
+    number: usize,
+    title: String,
+    bookmarks: Vec<ExportBookmark<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExportBookmark<'a> {
+    label: &'a str,
+    cfi: Option<&'a str>,
+    context: String,
+}
+
+/// Render the bookmarks of a book in the given format
+///
+/// # Arguments
+/// * `book` - Book the bookmarks belong to, with its chapters rendered
+/// * `bookmarks` - Bookmarks to export
+/// * `format` - Output format
+///
+/// # Returns
+/// * `Ok(String)` - Contents of the export file
+/// * `Err(String)` - JSON serialization failed
+pub fn export_bookmarks(
+    book: &Book,
+    bookmarks: &[Bookmark],
+    format: ExportFormat,
+) -> Result<String, String> {
+    let document = collect(book, bookmarks);
+    match format {
+        ExportFormat::Markdown => Ok(to_markdown(&document)),
+        ExportFormat::Json => serde_json::to_string_pretty(&document)
+            .map(|json| json + "\n")
+            .map_err(|e| format!("Failed to serialize bookmarks: {}", e)),
+        ExportFormat::Org => Ok(to_org(&document)),
+    }
+}
+
+/// File name for an export of the given book, e.g. `moby-dick-bookmarks.md`
+pub fn default_file_name(book: &Book, format: ExportFormat) -> String {
+    let mut stem = String::new();
+    for c in book.metadata.title.chars() {
+        if c.is_alphanumeric() {
+            stem.extend(c.to_lowercase());
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    let stem = if stem.is_empty() { "book" } else { stem };
+    format!("{}-bookmarks.{}", stem, format.extension())
+}
+
+/// Group bookmarks by chapter, in reading order
+fn collect<'a>(book: &'a Book, bookmarks: &'a [Bookmark]) -> ExportDocument<'a> {
+    let mut sorted: Vec<&Bookmark> = bookmarks.iter().collect();
+    sorted.sort_by(|a, b| {
+        (a.chapter_idx, &a.anchor, a.line).cmp(&(b.chapter_idx, &b.anchor, b.line))
+    });
+
+    let mut chapters: Vec<ExportChapter> = Vec::new();
+    for bookmark in sorted {
+        let chapter = book.chapters.get(bookmark.chapter_idx);
+        let entry = ExportBookmark {
+            label: &bookmark.label,
+            cfi: bookmark.cfi.as_deref(),
+            context: chapter.map(|c| context(c, bookmark)).unwrap_or_default(),
+        };
+
+        match chapters.last_mut() {
+            Some(last) if last.number == bookmark.chapter_idx + 1 => last.bookmarks.push(entry),
+            _ => chapters.push(ExportChapter {
+                number: bookmark.chapter_idx + 1,
+                title: chapter
+                    .map(|c| c.title.trim())
+                    .filter(|title| !title.is_empty())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("Chapter {}", bookmark.chapter_idx + 1)),
+                bookmarks: vec![entry],
+            }),
+        }
+    }
+
+    let metadata = &book.metadata;
+    ExportDocument {
+        title: metadata.title.trim(),
+        author: metadata.author.as_deref(),
+        publisher: metadata.publisher.as_deref(),
+        publication_date: metadata.publication_date.as_deref(),
+        language: metadata.language.as_deref(),
+        chapters,
+    }
+}
+
+/// Text of the paragraph a bookmark points into
+fn context(chapter: &Chapter, bookmark: &Bookmark) -> String {
+    let line = match &bookmark.anchor {
+        Some(anchor) => chapter.line_for_anchor(anchor),
+        None => bookmark.line,
+    };
+    let Some(path) = chapter
+        .content_lines
+        .get(line)
+        .and_then(|l| l.anchor.as_ref())
+        .map(|a| &a.element_path)
+    else {
+        return String::new();
+    };
+
+    // All rows wrapped from the same block element
+    let same_block = |idx: &usize| {
+        chapter.content_lines[*idx]
+            .anchor
+            .as_ref()
+            .map(|a| &a.element_path)
+            == Some(path)
+    };
+    let start = (0..line)
+        .rev()
+        .take_while(same_block)
+        .last()
+        .unwrap_or(line);
+    let end = (line + 1..chapter.content_lines.len())
+        .take_while(same_block)
+        .last()
+        .unwrap_or(line);
+
+    let text = crate::clipboard::selection_text(chapter, start..=end);
+    if text.chars().count() > MAX_CONTEXT_LENGTH {
+        let short: String = text.chars().take(MAX_CONTEXT_LENGTH).collect();
+        format!("{}…", short.trim_end())
+    } else {
+        text
+    }
+}
+
+fn to_markdown(document: &ExportDocument) -> String {
+    let mut out = format!("# {}\n\n", document.title);
+    if let Some(author) = document.author {
+        out.push_str(&format!("*by {}*\n\n", author));
+    }
+    let details = [
+        ("Publisher", document.publisher),
+        ("Published", document.publication_date),
+        ("Language", document.language),
+    ];
+    let mut has_details = false;
+    for (name, value) in details {
+        if let Some(value) = value {
+            out.push_str(&format!("- **{}:** {}\n", name, value));
+            has_details = true;
+        }
+    }
+    if has_details {
+        out.push('\n');
+    }
+
+    for chapter in &document.chapters {
+        out.push_str(&format!("## {}\n\n", chapter.title));
+        for bookmark in &chapter.bookmarks {
+            out.push_str(&format!("### {}\n\n", bookmark.label));
+            if !bookmark.context.is_empty() {
+                for line in bookmark.context.lines() {
+                    if line.is_empty() {
+                        out.push_str(">\n");
+                    } else {
+                        out.push_str(&format!("> {}\n", line));
+                    }
+                }
+                out.push('\n');
+            }
+            if let Some(cfi) = bookmark.cfi {
+                out.push_str(&format!("`{}`\n\n", cfi));
+            }
+        }
+    }
+    out
+}
+
+fn to_org(document: &ExportDocument) -> String {
+    let mut out = format!("#+TITLE: {}\n", document.title);
+    if let Some(author) = document.author {
+        out.push_str(&format!("#+AUTHOR: {}\n", author));
+    }
+    if let Some(date) = document.publication_date {
+        out.push_str(&format!("#+DATE: {}\n", date));
+    }
+    if let Some(language) = document.language {
+        out.push_str(&format!("#+LANGUAGE: {}\n", language));
+    }
+    if let Some(publisher) = document.publisher {
+        out.push_str(&format!("#+PUBLISHER: {}\n", publisher));
+    }
+    out.push('\n');
+
+    for chapter in &document.chapters {
+        out.push_str(&format!("* {}\n", chapter.title));
+        for bookmark in &chapter.bookmarks {
+            out.push_str(&format!("** {}\n", bookmark.label));
+            if let Some(cfi) = bookmark.cfi {
+                out.push_str(&format!(":PROPERTIES:\n:CFI: {}\n:END:\n", cfi));
+            }
+            if !bookmark.context.is_empty() {
+                out.push_str("#+BEGIN_QUOTE\n");
+                for line in bookmark.context.lines() {
+                    // Lines that would read as headlines or keywords are comma-escaped
+                    if line.starts_with('*') || line.starts_with("#+") {
+                        out.push(',');
+                    }
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                out.push_str("#+END_QUOTE\n");
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+    use crate::types::{BookMetadata, ContentAnchor};
+
+    fn chapter(title: &str, html: &str) -> Chapter {
+        let mut chapter = Chapter {
+            title: title.to_string(),
+            sections: Vec::new(),
+            content_lines: Vec::new(),
+            file_path: html.to_string(),
+            href: "test.xhtml".to_string(),
+            document: crate::epub::document::build_document(html),
+        };
+        render_chapter(&mut chapter, Some(24), 100, false);
+        chapter
+    }
+
+    fn book() -> Book {
+        Book {
+            metadata: BookMetadata {
+                title: "The Test: A Book".to_string(),
+                author: Some("Ann Author".to_string()),
+                publisher: None,
+                publication_date: Some("2020".to_string()),
+                language: Some("en".to_string()),
+                identifier: None,
+            },
+            chapters: vec![
+                chapter(
+                    "Opening",
+                    "<p>Intro.</p><p>A paragraph long enough to wrap over several rows.</p>",
+                ),
+                chapter("", "<p>* Starred line</p>"),
+            ],
+            images: Default::default(),
+        }
+    }
+
+    fn bookmark(chapter_idx: usize, label: &str, anchor: Option<ContentAnchor>) -> Bookmark {
+        Bookmark {
+            chapter_idx,
+            line: 0,
+            label: label.to_string(),
+            anchor,
+            cfi: Some("epubcfi(/6/2!/4/2/1:0)".to_string()),
+        }
+    }
+
+    fn bookmarks(book: &Book) -> Vec<Bookmark> {
+        // Point into the middle of the wrapped paragraph
+        let wrapped = book.chapters[0].content_lines[3].anchor.clone();
+        vec![bookmark(1, "Star", None), bookmark(0, "Wrapped", wrapped)]
+    }
+
+    #[test]
+    fn test_markdown_groups_by_chapter_with_context() {
+        let book = book();
+        let markdown = export_bookmarks(&book, &bookmarks(&book), ExportFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("# The Test: A Book\n\n*by Ann Author*\n\n"));
+        assert!(markdown.contains("- **Published:** 2020\n"));
+        let opening = markdown.find("## Opening").unwrap();
+        let untitled = markdown.find("## Chapter 2").unwrap();
+        assert!(opening < untitled);
+        assert!(markdown.contains(
+            "### Wrapped\n\n> A paragraph long enough to wrap over several rows.\n\n\
+             `epubcfi(/6/2!/4/2/1:0)`"
+        ));
+    }
+
+    #[test]
+    fn test_org_escapes_headline_lines() {
+        let book = book();
+        let org = export_bookmarks(&book, &bookmarks(&book), ExportFormat::Org).unwrap();
+        assert!(org.starts_with("#+TITLE: The Test: A Book\n#+AUTHOR: Ann Author\n"));
+        assert!(org.contains("* Chapter 2\n** Star\n:PROPERTIES:\n:CFI: epubcfi("));
+        assert!(org.contains("#+BEGIN_QUOTE\n,* Starred line\n#+END_QUOTE\n"));
+    }
+
+    #[test]
+    fn test_json_and_file_name() {
+        let book = book();
+        let json = export_bookmarks(&book, &bookmarks(&book), ExportFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["author"], "Ann Author");
+        assert_eq!(value["chapters"][0]["number"], 1);
+        assert_eq!(value["chapters"][1]["bookmarks"][0]["label"], "Star");
+        assert_eq!(
+            default_file_name(&book, ExportFormat::Org),
+            "the-test-a-book-bookmarks.org"
+        );
+        assert_eq!(ExportFormat::parse("MD"), Ok(ExportFormat::Markdown));
+        assert!(ExportFormat::parse("pdf").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Searching books from the command line
+//!
+//! `reef grep` runs the reader's search over the rendered text of any number
+//! of books and prints the matching lines grep style: a heading per book, then
+//! `chapter:line:text` for matches and `chapter-line-text` for context lines,
+//! with `--` between groups that are not adjacent. Lines are numbered from 1.
+
+use crate::library::LibraryManager;
+use crate::types::{Book, ContentAnchor, SearchMatch};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Books named on the command line
+///
+/// Files are taken as given; directories are searched for EPUB files the
+/// way library directories are.
+pub fn book_paths(paths: &[String]) -> Vec<String> {
+    let mut books = Vec::new();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            books.extend(LibraryManager::find_books(Path::new(path)));
+        } else {
+            books.push(path.clone());
+        }
+    }
+    books
+}
+
+/// Find the matches of a pattern in a rendered book
+///
+/// The lines wrapped from one block are joined with spaces before matching,
+/// so a phrase broken across lines is found. Matches are reported at the
+/// line and column where they start.
+pub fn find_matches(book: &Book, regex: &Regex) -> Vec<SearchMatch> {
+    let mut matches = Vec::new();
+    for (chapter_idx, chapter) in book.chapters.iter().enumerate() {
+        let lines = &chapter.content_lines;
+        let mut first = 0;
+        while first < lines.len() {
+            // Lines of a block share the element path of their anchor; blank
+            // lines have no anchor and separate blocks
+            let path = lines[first].anchor.as_ref().map(|a| &a.element_path);
+            let count = match path {
+                Some(path) => lines[first..]
+                    .iter()
+                    .take_while(|line| line.anchor.as_ref().map(|a| &a.element_path) == Some(path))
+                    .count(),
+                None => 1,
+            };
+            let block = &lines[first..first + count];
+
+            let mut text = String::new();
+            let mut line_starts = Vec::with_capacity(block.len());
+            for line in block {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                line_starts.push(text.len());
+                text.push_str(line.text.trim_end());
+            }
+
+            for mat in regex.find_iter(&text) {
+                let idx = line_starts.partition_point(|&start| start <= mat.start()) - 1;
+                let column = mat.start() - line_starts[idx];
+                matches.push(SearchMatch {
+                    chapter_idx,
+                    line: first + idx,
+                    column,
+                    match_length: mat.len(),
+                    anchor: block[idx].anchor.as_ref().map(|a| ContentAnchor {
+                        element_path: a.element_path.clone(),
+                        char_offset: a.char_offset + column,
+                    }),
+                });
+            }
+            first += count;
+        }
+    }
+    matches
+}
+
+/// Format the lines of a book that contain matches
+///
+/// # Arguments
+/// * `book` - Rendered book the matches were found in
+/// * `path` - Path of the book, shown in the heading
+/// * `matches` - Search results in book order
+/// * `context` - Number of lines to show before and after each matching line
+///
+/// # Returns
+/// The heading and matching lines, or `None` when there are no matches
+pub fn format_matches(
+    book: &Book,
+    path: &str,
+    matches: &[SearchMatch],
+    context: usize,
+) -> Option<String> {
+    if matches.is_empty() {
+        return None;
+    }
+
+    let matching: BTreeSet<(usize, usize)> =
+        matches.iter().map(|m| (m.chapter_idx, m.line)).collect();
+
+    let mut output = format!("{} ({})\n", book.metadata.title.trim(), path);
+    // Last line printed, to merge overlapping context and separate groups
+    let mut printed: Option<(usize, usize)> = None;
+    for &(chapter_idx, line) in &matching {
+        let chapter = &book.chapters[chapter_idx];
+        let first = line.saturating_sub(context);
+        let last = (line + context).min(chapter.content_lines.len().saturating_sub(1));
+
+        let start = match printed {
+            Some((chapter, printed_line))
+                if chapter == chapter_idx && printed_line + 1 >= first =>
+            {
+                printed_line + 1
+            }
+            Some(_) if context > 0 => {
+                output.push_str("--\n");
+                first
+            }
+            _ => first,
+        };
+        for idx in start..=last {
+            let separator = if matching.contains(&(chapter_idx, idx)) {
+                ':'
+            } else {
+                '-'
+            };
+            output.push_str(&format!(
+                "{}{}{}{}{}\n",
+                chapter.title,
+                separator,
+                idx + 1,
+                separator,
+                chapter.content_lines[idx].text.trim_end()
+            ));
+        }
+        printed = Some((chapter_idx, last.max(start.saturating_sub(1))));
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+    use crate::search::SearchEngine;
+    use crate::types::{BookMetadata, Chapter};
+
+    const LONG_PARAGRAPH: &str = "<p>This paragraph is long enough to be wrapped \
+        across two lines, which must not hide a phrase split by the wrap.</p>";
+
+    fn book() -> Book {
+        let chapter = |title: &str, html: &str| {
+            let mut chapter = Chapter {
+                title: title.to_string(),
+                sections: Vec::new(),
+                content_lines: Vec::new(),
+                file_path: html.to_string(),
+                href: "test.xhtml".to_string(),
+                document: crate::epub::document::build_document(html),
+            };
+            render_chapter(&mut chapter, None, 80, false);
+            chapter
+        };
+        Book {
+            metadata: BookMetadata {
+                title: "Grep Test".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: vec![
+                chapter(
+                    "One",
+                    "<p>alpha</p><p>beta</p><p>gamma</p><p>delta</p><p>alpha again</p>",
+                ),
+                chapter("Two", "<p>nothing</p><p>Alpha</p>"),
+                chapter("Three", LONG_PARAGRAPH),
+            ],
+            images: Default::default(),
+        }
+    }
+
+    fn grep(book: &Book, pattern: &str, ignore_case: bool, context: usize) -> Option<String> {
+        let regex = SearchEngine::compile(pattern, ignore_case, false).unwrap();
+        let matches = find_matches(book, &regex);
+        format_matches(book, "/books/test.epub", &matches, context)
+    }
+
+    #[test]
+    fn test_matching_lines() {
+        let book = book();
+        assert_eq!(
+            grep(&book, "alpha", false, 0).unwrap(),
+            "Grep Test (/books/test.epub)\nOne:1:alpha\nOne:9:alpha again\n"
+        );
+        assert!(
+            grep(&book, "Alpha", true, 0)
+                .unwrap()
+                .ends_with("Two:3:Alpha\n")
+        );
+        assert_eq!(grep(&book, "epsilon", true, 0), None);
+    }
+
+    #[test]
+    fn test_phrase_across_wrapped_lines() {
+        let book = book();
+        let lines = &book.chapters[2].content_lines;
+        let second = &lines[1].text;
+        let (before, after) = (lines[0].text.trim_end(), second.trim_end());
+        let last_word = before.rsplit(' ').next().unwrap();
+        let first_word = after.split(' ').next().unwrap();
+        let phrase = format!("{} {}", last_word, first_word);
+
+        let regex = SearchEngine::compile(&phrase, false, true).unwrap();
+        let matches = find_matches(&book, &regex);
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].chapter_idx, matches[0].line), (2, 0));
+        assert_eq!(matches[0].column, before.len() - last_word.len());
+
+        // Shown at the line it starts on
+        let text = format_matches(&book, "/books/test.epub", &matches, 0).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some(format!("Three:1:{}", before).as_str())
+        );
+    }
+
+    #[test]
+    fn test_context_lines_merge_and_separate() {
+        let book = book();
+        // Paragraphs are separated by blank lines, so two lines reach the next one
+        assert_eq!(
+            grep(&book, "alpha|beta", false, 2).unwrap(),
+            "Grep Test (/books/test.epub)\n\
+             One:1:alpha\nOne-2-\nOne:3:beta\nOne-4-\nOne-5-gamma\n\
+             --\n\
+             One-7-delta\nOne-8-\nOne:9:alpha again\nOne-10-\n"
+        );
+    }
+}
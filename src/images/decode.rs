@@ -0,0 +1,287 @@
+//! PNG and JPEG decoding
+//!
+//! Images come from arbitrary EPUB files, so they are decoded with the
+//! `image` crate and their size is checked against a pixel limit before any
+//! pixels are allocated. GIF and SVG are not supported and are shown as text
+//! placeholders.
+
+use super::RgbImage;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use std::io::{Cursor, Read};
+
+/// The eight bytes every PNG file starts with
+pub const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Largest image that is decoded, in pixels
+const MAX_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// Bytes read first when looking for the size of an image in a stream
+const HEADER_CHUNK: usize = 4096;
+
+/// Read the header of a PNG or JPEG image and check its size
+fn decoder(bytes: &[u8]) -> Result<impl ImageDecoder + '_, String> {
+    let format = match image::guess_format(bytes) {
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg)) => format,
+        _ => return Err("Unsupported image format".to_string()),
+    };
+    let decoder = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_decoder()
+        .map_err(|e| e.to_string())?;
+
+    let (width, height) = decoder.dimensions();
+    if width == 0 || height == 0 {
+        return Err("Empty image".to_string());
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(format!("Image too large: {}x{}", width, height));
+    }
+    Ok(decoder)
+}
+
+/// Read the pixel size of a PNG or JPEG image from its header
+///
+/// # Returns
+/// * `Ok((width, height))` - Size of an image [`decode`] will accept
+/// * `Err(String)` - Unsupported format, corrupt header or too many pixels
+pub fn size(bytes: &[u8]) -> Result<(u32, u32), String> {
+    decoder(bytes).map(|decoder| decoder.dimensions())
+}
+
+/// Read the pixel size of a PNG or JPEG image from the start of a stream
+///
+/// Reads more of the stream only while the header is incomplete, so the
+/// pixel data of an image in a compressed archive is not extracted. JPEG
+/// headers can follow large metadata segments, so the amount read grows
+/// until the header has been seen.
+///
+/// # Returns
+/// * `Ok((width, height))` - Size of an image [`decode`] will accept
+/// * `Err(String)` - Unsupported format, corrupt header or read error
+pub fn read_size(mut reader: impl Read) -> Result<(u32, u32), String> {
+    let mut bytes = Vec::new();
+    let mut limit = HEADER_CHUNK;
+    loop {
+        (&mut reader)
+            .take((limit - bytes.len()) as u64)
+            .read_to_end(&mut bytes)
+            .map_err(|e| e.to_string())?;
+        match size(&bytes) {
+            Ok(size) => return Ok(size),
+            // The whole stream was read, or it isn't an image at all
+            Err(e) if bytes.len() < limit || image::guess_format(&bytes).is_err() => {
+                return Err(e);
+            }
+            Err(_) => limit *= 4,
+        }
+    }
+}
+
+/// Decode a PNG or JPEG image
+///
+/// # Arguments
+/// * `bytes` - Raw image file contents
+///
+/// # Returns
+/// * `Ok(RgbImage)` - Decoded pixels, transparency flattened onto white
+/// * `Err(String)` - Unsupported format, corrupt data or too many pixels
+pub fn decode(bytes: &[u8]) -> Result<RgbImage, String> {
+    let image = DynamicImage::from_decoder(decoder(bytes)?)
+        .map_err(|e| e.to_string())?
+        .into_rgba8();
+
+    let (width, height) = image.dimensions();
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        pixels.extend([flatten(r, a), flatten(g, a), flatten(b, a)]);
+    }
+    Ok(RgbImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Blend a color with the given alpha onto a white background
+fn flatten(value: u8, alpha: u8) -> u8 {
+    let alpha = alpha as u32;
+    ((value as u32 * alpha + 255 * (255 - alpha)) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_round_trip() {
+        let image = RgbImage {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                255, 0, 0, 0, 255, 0, 0, 0, 255, //
+                10, 20, 30, 40, 50, 60, 70, 80, 90,
+            ],
+        };
+        let decoded = decode(&super::super::encode::png(&image)).unwrap();
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn test_png_palette_with_transparency() {
+        // 2x1, 1-bit palette: opaque red and fully transparent
+        let mut png = PNG_SIGNATURE.to_vec();
+        let chunk = super::super::encode::png_chunk;
+        chunk(&mut png, b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 1, 3, 0, 0, 0]);
+        chunk(&mut png, b"PLTE", &[255, 0, 0, 0, 0, 0]);
+        chunk(&mut png, b"tRNS", &[255, 0]);
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        std::io::Write::write_all(&mut encoder, &[0, 0b0100_0000]).unwrap();
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+
+        let image = decode(&png).unwrap();
+        assert_eq!(image.pixel(0, 0), [255, 0, 0]);
+        assert_eq!(image.pixel(1, 0), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_jpeg_baseline() {
+        // 16x8 YCbCr 2x1 subsampled: dark left block, light right block,
+        // neutral chroma (DC-only blocks, all quantizers 1)
+        let image = decode(&test_jpeg()).unwrap();
+        assert_eq!((image.width, image.height), (16, 8));
+        assert_eq!(image.pixel(0, 0), [32, 32, 32]);
+        assert_eq!(image.pixel(15, 7), [224, 224, 224]);
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        assert!(decode(b"GIF89a").is_err());
+        assert!(decode(&[0xFF, 0xD8, 0xFF, 0xC2, 0, 2]).is_err());
+    }
+
+    #[test]
+    fn test_malformed_images_are_errors() {
+        // Empty start-of-scan segment
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01, 0x01, 0x11,
+            0x00, 0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9,
+        ];
+        assert!(decode(&jpeg).is_err());
+
+        // Truncated anywhere, without panicking
+        let jpeg = test_jpeg();
+        let png = super::super::encode::png(&RgbImage {
+            width: 2,
+            height: 2,
+            pixels: vec![0; 12],
+        });
+        // Missing scan data is filled in, as other decoders do; headers are needed
+        let scan_start = jpeg.len() - 8;
+        for len in 0..jpeg.len() {
+            let result = decode(&jpeg[..len]);
+            assert!(
+                len >= scan_start || result.is_err(),
+                "JPEG truncated to {}",
+                len
+            );
+        }
+        // Everything up to the end chunk is needed
+        for len in 0..png.len() - 12 {
+            assert!(decode(&png[..len]).is_err(), "PNG truncated to {}", len);
+        }
+    }
+
+    #[test]
+    fn test_read_size_stops_after_the_header() {
+        // A reader that fails past the first chunk: the pixels must not be needed
+        struct Limited<'a>(&'a [u8], usize);
+        impl Read for Limited<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.1 >= HEADER_CHUNK {
+                    return Err(std::io::Error::other("read past the header"));
+                }
+                let n = buf
+                    .len()
+                    .min(self.0.len() - self.1)
+                    .min(HEADER_CHUNK - self.1);
+                buf[..n].copy_from_slice(&self.0[self.1..self.1 + n]);
+                self.1 += n;
+                Ok(n)
+            }
+        }
+
+        // Noise does not compress, so the image data spans many chunks
+        let pixels = (0..300u32 * 300 * 3)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        let png = super::super::encode::png(&RgbImage {
+            width: 300,
+            height: 300,
+            pixels,
+        });
+        assert!(png.len() > 4 * HEADER_CHUNK);
+        assert_eq!(read_size(Limited(&png, 0)), Ok((300, 300)));
+
+        // A JPEG whose header follows a large comment segment
+        let jpeg = test_jpeg();
+        let mut padded = jpeg[..2].to_vec();
+        padded.extend([0xFF, 0xFE, 0xFF, 0xFF]);
+        padded.extend([b' '; 0xFFFD]);
+        padded.extend(&jpeg[2..]);
+        assert_eq!(read_size(padded.as_slice()), Ok((16, 8)));
+
+        assert!(read_size(&b"GIF89a"[..]).is_err());
+        // Ends inside the header
+        assert!(read_size(&png[..20]).is_err());
+    }
+
+    #[test]
+    fn test_huge_image_is_rejected_before_decoding() {
+        // 100000 x 100000 pixels claimed, with a tiny data stream
+        let mut png = PNG_SIGNATURE.to_vec();
+        let chunk = super::super::encode::png_chunk;
+        let mut header = Vec::new();
+        header.extend(100_000u32.to_be_bytes());
+        header.extend(100_000u32.to_be_bytes());
+        header.extend([8, 2, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &header);
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        std::io::Write::write_all(&mut encoder, &[0; 1024]).unwrap();
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+
+        assert!(size(&png).unwrap_err().contains("too large"));
+        assert!(decode(&png).is_err());
+    }
+
+    fn test_jpeg() -> Vec<u8> {
+        let segment = |jpeg: &mut Vec<u8>, marker: u8, data: &[u8]| {
+            jpeg.extend([0xFF, marker]);
+            jpeg.extend((data.len() as u16 + 2).to_be_bytes());
+            jpeg.extend(data);
+        };
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        let mut quant = vec![0];
+        quant.extend([1; 64]);
+        segment(&mut jpeg, 0xDB, &quant);
+        segment(
+            &mut jpeg,
+            0xC0,
+            &[8, 0, 8, 0, 16, 3, 1, 0x21, 0, 2, 0x11, 0, 3, 0x11, 0],
+        );
+        // Standard luminance DC table
+        let mut dc = vec![0x00, 0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+        dc.extend(0..12);
+        segment(&mut jpeg, 0xC4, &dc);
+        // AC table with a single one-bit code for end-of-block
+        let mut ac = vec![0x10, 1];
+        ac.extend([0; 16]);
+        segment(&mut jpeg, 0xC4, &ac);
+        segment(&mut jpeg, 0xDA, &[3, 1, 0, 2, 0, 3, 0, 0, 63, 0]);
+        // DC differences -768, +1536, 0, 0 each followed by end-of-block
+        jpeg.extend([0xFE, 0x3F, 0xDF, 0xEC, 0x00, 0x03, 0xFF, 0xD9]);
+        jpeg
+    }
+}
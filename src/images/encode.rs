@@ -0,0 +1,236 @@
+//! Terminal graphics escape sequences
+//!
+//! Builds the output for the Kitty graphics protocol, iTerm2 inline images
+//! and DEC Sixel from decoded pixels. Each function returns the complete
+//! sequence to write at the current cursor position.
+
+use super::{Crop, RgbImage};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+
+/// Maximum base64 payload per Kitty escape sequence
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Encode an image as a PNG file (used for iTerm2 inline images)
+pub fn png(image: &RgbImage) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((image.width as usize * 3 + 1) * image.height as usize);
+    for row in image.pixels.chunks(image.width as usize * 3) {
+        raw.push(0); // No filter
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(image.width.to_be_bytes());
+    header.extend(image.height.to_be_bytes());
+    header.extend([8, 2, 0, 0, 0]); // 8-bit RGB, not interlaced
+
+    let mut png = super::decode::PNG_SIGNATURE.to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &compress(&raw));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub(super) fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(kind.iter().chain(data));
+    png.extend(crc.to_be_bytes());
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    // Writing to a Vec cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+/// Upload an image to a Kitty-compatible terminal under `id` without showing it
+pub fn kitty_transmit(image: &RgbImage, id: u32) -> String {
+    let payload = BASE64.encode(compress(&image.pixels));
+    let mut out = String::new();
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = if index + 1 < chunks.len() { 1 } else { 0 };
+        let chunk = std::str::from_utf8(chunk).unwrap_or("");
+        if index == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=t,f=24,o=z,s={},v={},i={},q=2,m={};{}\x1b\\",
+                image.width, image.height, id, more, chunk
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    out
+}
+
+/// Show the `source` rectangle (pixels) of an uploaded Kitty image
+/// scaled into `cols` x `rows` cells
+pub fn kitty_place(id: u32, source: &Crop, cols: u16, rows: u16) -> String {
+    format!(
+        "\x1b_Ga=p,i={},x={},y={},w={},h={},c={},r={},C=1,q=2\x1b\\",
+        id, source.x, source.y, source.width, source.height, cols, rows
+    )
+}
+
+/// Remove all Kitty image placements, keeping uploaded image data
+pub fn kitty_delete_placements() -> &'static str {
+    "\x1b_Ga=d,d=a,q=2\x1b\\"
+}
+
+/// Remove a Kitty image with its placements and free its data
+pub fn kitty_delete(id: u32) -> String {
+    format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", id)
+}
+
+/// Remove all Kitty images and free their data
+pub fn kitty_delete_all() -> &'static str {
+    "\x1b_Ga=d,d=A,q=2\x1b\\"
+}
+
+/// Show an image as an iTerm2 inline image stretched over `cols` x `rows` cells
+pub fn iterm2(image: &RgbImage, cols: u16, rows: u16) -> String {
+    let data = png(image);
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0:{}\x07",
+        data.len(),
+        cols,
+        rows,
+        BASE64.encode(&data)
+    )
+}
+
+/// Encode an image as DEC Sixel graphics using a 6x6x6 color cube
+pub fn sixel(image: &RgbImage) -> String {
+    const LEVELS: u32 = 6;
+    let level = |v: u8| (v as u32 * (LEVELS - 1) + 127) / 255;
+    let color_index =
+        |p: [u8; 3]| (level(p[0]) * LEVELS * LEVELS + level(p[1]) * LEVELS + level(p[2])) as usize;
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", image.width, image.height);
+
+    // Palette definitions, colors in percent
+    let palette_size = (LEVELS * LEVELS * LEVELS) as usize;
+    for index in 0..palette_size {
+        let (r, g, b) = (
+            index / 36 % 6 * 100 / 5,
+            index / 6 % 6 * 100 / 5,
+            index % 6 * 100 / 5,
+        );
+        out.push_str(&format!("#{};2;{};{};{}", index, r, g, b));
+    }
+
+    let indices: Vec<usize> = image
+        .pixels
+        .chunks(3)
+        .map(|p| color_index([p[0], p[1], p[2]]))
+        .collect();
+    let width = image.width as usize;
+    let height = image.height as usize;
+
+    for band_top in (0..height).step_by(6) {
+        let band_rows = (height - band_top).min(6);
+
+        // Sixel bit patterns per color used in this band
+        let mut used: Vec<usize> = Vec::new();
+        let mut patterns = vec![0u8; palette_size * width];
+        for dy in 0..band_rows {
+            for x in 0..width {
+                let color = indices[(band_top + dy) * width + x];
+                if !used.contains(&color) {
+                    used.push(color);
+                }
+                patterns[color * width + x] |= 1 << dy;
+            }
+        }
+
+        for (n, &color) in used.iter().enumerate() {
+            if n > 0 {
+                out.push('$'); // Back to the start of the band
+            }
+            out.push_str(&format!("#{}", color));
+            let row = &patterns[color * width..(color + 1) * width];
+            let mut x = 0;
+            while x < width {
+                let run = row[x..].iter().take_while(|&&p| p == row[x]).count();
+                let ch = (63 + row[x]) as char;
+                if run > 3 {
+                    out.push_str(&format!("!{}{}", run, ch));
+                } else {
+                    out.extend(std::iter::repeat_n(ch, run));
+                }
+                x += run;
+            }
+        }
+        out.push('-'); // Next band
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 3]) -> RgbImage {
+        RgbImage {
+            width,
+            height,
+            pixels: color.repeat((width * height) as usize),
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND".iter()), 0xAE42_6082);
+    }
+
+    #[test]
+    fn test_kitty_chunks() {
+        let image = RgbImage {
+            width: 64,
+            height: 64,
+            // Pseudo-random noise that does not compress below one chunk
+            pixels: (0..64 * 64 * 3u32)
+                .scan(12345u32, |state, _| {
+                    *state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    Some((*state >> 16) as u8)
+                })
+                .collect(),
+        };
+        let out = kitty_transmit(&image, 7);
+        assert!(out.starts_with("\x1b_Ga=t,f=24,o=z,s=64,v=64,i=7,q=2,m=1;"));
+        assert!(out.ends_with("\x1b\\"));
+        assert!(out.contains("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn test_sixel_runs() {
+        let out = sixel(&solid(10, 6, [255, 0, 0]));
+        assert!(out.starts_with("\x1bP0;1;0q\"1;1;10;6"));
+        // Red is index 5*36 = 180; a full band of 10 columns is one run
+        assert!(out.ends_with("#180!10~-\x1b\\"));
+    }
+}
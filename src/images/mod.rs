@@ -0,0 +1,406 @@
+//! Image extraction, decoding and terminal display
+//!
+//! Only the header of each image is read while parsing (to know how many
+//! rows it occupies); the image stays in the EPUB archive until it first
+//! scrolls into view; then it is read, decoded and drawn with the best
+//! graphics protocol the terminal supports.
+
+pub mod decode;
+pub mod encode;
+pub mod protocol;
+pub mod viewer;
+
+use crate::constants::{IMAGE_CACHE_SIZE, MAX_IMAGE_ROWS, NOMINAL_CELL_HEIGHT, NOMINAL_CELL_WIDTH};
+use crate::types::Book;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::PathBuf;
+use std::sync::Arc;
+use zip::ZipArchive;
+
+/// Decoded image pixels, 8-bit RGB without alpha
+///
+/// Transparent images are flattened onto white when decoded, so diagrams
+/// with dark strokes stay readable on dark terminal backgrounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGB triples
+    pub pixels: Vec<u8>,
+}
+
+impl RgbImage {
+    /// Color of the pixel at (x, y)
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// Scale the image to exactly `width` x `height` pixels
+    ///
+    /// Each target pixel averages the source pixels it covers, which keeps
+    /// thin lines visible when shrinking large diagrams.
+    pub fn resize(&self, width: u32, height: u32) -> RgbImage {
+        let width = width.max(1);
+        let height = height.max(1);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+
+        for ty in 0..height {
+            let y0 = (ty as u64 * self.height as u64 / height as u64) as u32;
+            let y1 = (((ty + 1) as u64 * self.height as u64).div_ceil(height as u64) as u32)
+                .clamp(y0 + 1, self.height.max(1));
+            for tx in 0..width {
+                let x0 = (tx as u64 * self.width as u64 / width as u64) as u32;
+                let x1 = (((tx + 1) as u64 * self.width as u64).div_ceil(width as u64) as u32)
+                    .clamp(x0 + 1, self.width.max(1));
+
+                let mut sum = [0u32; 3];
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let p = self.pixel(x, y);
+                        for c in 0..3 {
+                            sum[c] += p[c] as u32;
+                        }
+                    }
+                }
+                let count = (y1 - y0) * (x1 - x0);
+                pixels.extend(sum.iter().map(|s| (s / count) as u8));
+            }
+        }
+
+        RgbImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Copy of the pixels inside `crop`, clamped to the image
+    pub fn crop(&self, crop: &Crop) -> RgbImage {
+        let x = crop.x.min(self.width);
+        let y = crop.y.min(self.height);
+        let width = crop.width.min(self.width - x);
+        let height = crop.height.min(self.height - y);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * 3;
+            pixels.extend_from_slice(&self.pixels[start..start + width as usize * 3]);
+        }
+        RgbImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Copy of the rows `top..top + height`
+    pub fn crop_rows(&self, top: u32, height: u32) -> RgbImage {
+        let top = top.min(self.height);
+        let height = height.min(self.height - top);
+        let row_len = self.width as usize * 3;
+        let start = top as usize * row_len;
+        RgbImage {
+            width: self.width,
+            height,
+            pixels: self.pixels[start..start + height as usize * row_len].to_vec(),
+        }
+    }
+}
+
+/// A rectangle of source pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Image resources of a book, keyed by their path in the archive
+///
+/// Holds only the size of each image; the bytes are read from the EPUB
+/// file when an image is drawn, so commands that never show images don't
+/// extract them.
+#[derive(Debug, Clone, Default)]
+pub struct ImageStore {
+    /// EPUB file the images are read from
+    archive: Option<PathBuf>,
+    /// Size of each image referenced by the chapters, `None` if unusable
+    sizes: HashMap<String, Option<(u32, u32)>>,
+}
+
+impl ImageStore {
+    /// Create a store for the images of the EPUB file at `archive`
+    pub fn new(archive: PathBuf) -> Self {
+        ImageStore {
+            archive: Some(archive),
+            sizes: HashMap::new(),
+        }
+    }
+
+    /// Read the size of an image from its header in the archive
+    ///
+    /// Sizes are remembered, so an image shown by several chapters is read once.
+    ///
+    /// # Arguments
+    /// * `archive` - The open EPUB archive
+    /// * `path` - Path of the image in the archive
+    ///
+    /// # Returns
+    /// `Some((width, height))` for decodable PNG and JPEG images
+    pub fn read_size<R: Read + Seek>(
+        &mut self,
+        archive: &mut ZipArchive<R>,
+        path: &str,
+    ) -> Option<(u32, u32)> {
+        if let Some(size) = self.sizes.get(path) {
+            return *size;
+        }
+        let size = match archive.by_name(path) {
+            Ok(entry) => decode::read_size(entry)
+                .map_err(|e| log::debug!("  Unsupported image {}: {}", path, e))
+                .ok(),
+            Err(_) => {
+                log::debug!("  Image not found in archive: {}", path);
+                None
+            }
+        };
+        self.sizes.insert(path.to_string(), size);
+        size
+    }
+
+    /// Read the raw bytes of an image from the archive
+    ///
+    /// # Errors
+    /// Returns an error if the image is not one of the book's usable images
+    /// or cannot be read from the EPUB file
+    pub fn load(&self, path: &str) -> Result<Vec<u8>, String> {
+        let archive = match (&self.archive, self.sizes.get(path)) {
+            (Some(archive), Some(Some(_))) => archive,
+            _ => return Err("Not an image of the book".to_string()),
+        };
+        let file = File::open(archive).map_err(|e| e.to_string())?;
+        let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+        let mut entry = archive.by_name(path).map_err(|e| e.to_string())?;
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    /// Number of usable images
+    pub fn len(&self) -> usize {
+        self.sizes.values().filter(|size| size.is_some()).count()
+    }
+
+    /// Check whether the book has no usable images
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Scaled image cache key: (path, crop, width, height)
+type ScaledKey = (String, Option<Crop>, u32, u32);
+
+/// Cache of decoded and scaled images for the current book
+///
+/// Uses interior mutability so images can be decoded while the UI is
+/// being drawn from a shared reference to the application state.
+#[derive(Default)]
+pub struct ImageCache {
+    decoded: RefCell<HashMap<String, Option<Arc<RgbImage>>>>,
+    scaled: RefCell<HashMap<ScaledKey, Arc<RgbImage>>>,
+}
+
+impl ImageCache {
+    /// Get an image decoded at its natural size
+    ///
+    /// # Returns
+    /// The pixels, or `None` if the image is missing or cannot be decoded
+    pub fn decoded(&self, book: &Book, path: &str) -> Option<Arc<RgbImage>> {
+        if let Some(cached) = self.decoded.borrow().get(path) {
+            return cached.clone();
+        }
+
+        let image = match book
+            .images
+            .load(path)
+            .and_then(|bytes| decode::decode(&bytes))
+        {
+            Ok(image) => Some(Arc::new(image)),
+            Err(e) => {
+                log::warn!("Failed to decode image '{}': {}", path, e);
+                None
+            }
+        };
+
+        let mut decoded = self.decoded.borrow_mut();
+        if decoded.len() >= IMAGE_CACHE_SIZE {
+            decoded.clear();
+        }
+        decoded.insert(path.to_string(), image.clone());
+        image
+    }
+
+    /// Get an image, or the `crop` region of it, scaled to `width` x `height` pixels
+    pub fn scaled(
+        &self,
+        book: &Book,
+        path: &str,
+        crop: Option<Crop>,
+        width: u32,
+        height: u32,
+    ) -> Option<Arc<RgbImage>> {
+        let key = (path.to_string(), crop, width, height);
+        if let Some(image) = self.scaled.borrow().get(&key) {
+            return Some(image.clone());
+        }
+
+        let decoded = self.decoded(book, path)?;
+        let image = match &crop {
+            Some(crop) => decoded.crop(crop).resize(width, height),
+            None => decoded.resize(width, height),
+        };
+        let image = Arc::new(image);
+        let mut scaled = self.scaled.borrow_mut();
+        if scaled.len() >= IMAGE_CACHE_SIZE {
+            scaled.clear();
+        }
+        scaled.insert(key, image.clone());
+        Some(image)
+    }
+
+    /// Drop all cached images (e.g. when another book is opened)
+    pub fn clear(&mut self) {
+        self.decoded.get_mut().clear();
+        self.scaled.get_mut().clear();
+    }
+}
+
+/// Number of terminal cells an image occupies in a column of `max_cols`
+///
+/// Sizes assume a nominal cell of `NOMINAL_CELL_WIDTH` x `NOMINAL_CELL_HEIGHT`
+/// pixels so layout does not depend on the terminal. Small images keep
+/// their natural size, large ones are shrunk to fit the width and at most
+/// `MAX_IMAGE_ROWS` rows, preserving the aspect ratio.
+///
+/// # Returns
+/// `(columns, rows)`, both at least 1
+pub fn image_cells(width: u32, height: u32, max_cols: usize) -> (u16, u16) {
+    let width = width.max(1) as f64;
+    let height = height.max(1) as f64;
+    let cell_ratio = NOMINAL_CELL_HEIGHT as f64 / NOMINAL_CELL_WIDTH as f64;
+
+    let mut cols = (width / NOMINAL_CELL_WIDTH as f64)
+        .ceil()
+        .min(max_cols.max(1) as f64);
+    let mut rows = (cols * height / width / cell_ratio).round().max(1.0);
+    if rows > MAX_IMAGE_ROWS as f64 {
+        rows = MAX_IMAGE_ROWS as f64;
+        cols = (rows * cell_ratio * width / height).round().max(1.0);
+    }
+
+    (cols as u16, rows as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard() -> RgbImage {
+        // 4x2: left half black, right half white
+        let mut pixels = Vec::new();
+        for _ in 0..2 {
+            for x in 0..4 {
+                let v = if x < 2 { 0 } else { 255 };
+                pixels.extend([v, v, v]);
+            }
+        }
+        RgbImage {
+            width: 4,
+            height: 2,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn test_resize_averages_pixels() {
+        let small = checkerboard().resize(2, 1);
+        assert_eq!(small.pixel(0, 0), [0, 0, 0]);
+        assert_eq!(small.pixel(1, 0), [255, 255, 255]);
+
+        let single = checkerboard().resize(1, 1);
+        assert_eq!(single.pixel(0, 0), [127, 127, 127]);
+
+        let large = checkerboard().resize(8, 4);
+        assert_eq!(large.pixel(3, 3), [0, 0, 0]);
+        assert_eq!(large.pixel(4, 0), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_crop() {
+        let image = checkerboard();
+        let right = image.crop(&Crop {
+            x: 2,
+            y: 1,
+            width: 5,
+            height: 1,
+        });
+        assert_eq!((right.width, right.height), (2, 1));
+        assert_eq!(right.pixel(0, 0), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_crop_rows() {
+        let cropped = checkerboard().resize(4, 4).crop_rows(3, 5);
+        assert_eq!(cropped.height, 1);
+        assert_eq!(cropped.pixels.len(), 12);
+    }
+
+    /// An archive holding `files`, with a store that has read their sizes
+    pub(crate) fn image_store(files: &[(&str, &[u8])]) -> (ImageStore, tempfile::TempDir) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, bytes) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            std::io::Write::write_all(&mut zip, bytes).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut store = ImageStore::new(path.clone());
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        for (name, _) in files {
+            store.read_size(&mut archive, name);
+        }
+        (store, dir)
+    }
+
+    #[test]
+    fn test_image_store() {
+        let png = encode::png(&checkerboard());
+        let (store, _dir) = image_store(&[("a.png", &png), ("b.gif", b"GIF89a....")]);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.sizes["a.png"], Some((4, 2)));
+        assert_eq!(store.sizes["b.gif"], None);
+
+        assert_eq!(store.load("a.png").unwrap(), png);
+        assert!(store.load("b.gif").is_err());
+        assert!(store.load("missing.png").is_err());
+    }
+
+    #[test]
+    fn test_image_cells() {
+        // Natural size when it fits
+        assert_eq!(image_cells(80, 32, 60), (10, 2));
+        // Shrunk to the available width
+        assert_eq!(image_cells(1600, 800, 50), (50, 13));
+        // Tall images are limited in height
+        let (cols, rows) = image_cells(100, 5000, 80);
+        assert_eq!(rows as usize, MAX_IMAGE_ROWS);
+        assert!(cols >= 1);
+    }
+}
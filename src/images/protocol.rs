@@ -0,0 +1,478 @@
+//! Terminal graphics protocol detection and image presentation
+//!
+//! Kitty, iTerm2 and Sixel images are written straight to the terminal after
+//! ratatui has drawn a frame, at positions recorded while laying out the
+//! content view. Half-block images are drawn into the ratatui buffer like
+//! any other widget and need nothing from this module beyond detection.
+
+use super::{Crop, ImageCache, encode};
+use crate::constants::{NOMINAL_CELL_HEIGHT, NOMINAL_CELL_WIDTH};
+use crate::types::Book;
+use crossterm::{cursor::MoveTo, queue};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/// How images are drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    /// Kitty graphics protocol (kitty, WezTerm, Ghostty, Konsole)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm, mintty)
+    Iterm2,
+    /// DEC Sixel graphics (foot, mlterm, xterm -ti vt340, Windows Terminal)
+    Sixel,
+    /// Unicode upper half blocks with true-color foreground and background
+    HalfBlocks,
+}
+
+impl GraphicsProtocol {
+    /// Pick a protocol from the environment the terminal advertises
+    pub fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let term_program = var("TERM_PROGRAM");
+
+        let protocol = if !var("KITTY_WINDOW_ID").is_empty()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+            || term_program == "ghostty"
+            || !var("KONSOLE_VERSION").is_empty()
+        {
+            GraphicsProtocol::Kitty
+        } else if term_program == "iTerm.app"
+            || term_program == "WezTerm"
+            || term_program == "mintty"
+        {
+            GraphicsProtocol::Iterm2
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || term.starts_with("contour")
+            || !var("WT_SESSION").is_empty()
+        {
+            GraphicsProtocol::Sixel
+        } else {
+            GraphicsProtocol::HalfBlocks
+        };
+
+        log::info!("Detected graphics protocol: {:?}", protocol);
+        protocol
+    }
+
+    /// Parse a protocol name as given on the command line
+    ///
+    /// # Returns
+    /// * `Ok(Some(protocol))` - An explicit protocol
+    /// * `Ok(None)` - `auto`, detect from the environment
+    /// * `Err(String)` - Unknown name
+    pub fn parse(name: &str) -> Result<Option<Self>, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(None),
+            "kitty" => Ok(Some(GraphicsProtocol::Kitty)),
+            "iterm2" | "iterm" => Ok(Some(GraphicsProtocol::Iterm2)),
+            "sixel" => Ok(Some(GraphicsProtocol::Sixel)),
+            "halfblocks" | "blocks" => Ok(Some(GraphicsProtocol::HalfBlocks)),
+            _ => Err(format!(
+                "Unknown image protocol '{}' (expected auto, kitty, iterm2, sixel or halfblocks)",
+                name
+            )),
+        }
+    }
+}
+
+/// An image (or the visible part of one) to show in the content view
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImagePlacement {
+    /// Archive path of the image
+    pub path: String,
+    /// Screen column of the image's left edge
+    pub x: u16,
+    /// Screen row of the first visible image row
+    pub y: u16,
+    /// Width of the whole image in cells
+    pub cols: u16,
+    /// Height of the whole image in cells
+    pub rows: u16,
+    /// Image rows scrolled out above the view
+    pub skip: u16,
+    /// Image rows visible on screen
+    pub visible: u16,
+    /// Part of the image to show, `None` for all of it
+    pub crop: Option<Crop>,
+}
+
+/// Writes protocol images for the placements of the last drawn frame
+pub struct GraphicsRenderer {
+    pub protocol: GraphicsProtocol,
+    /// Placements requested by the frame being drawn
+    placements: Vec<ImagePlacement>,
+    /// Placements currently on screen
+    shown: Vec<ImagePlacement>,
+    /// Images uploaded to a Kitty terminal, by (path, width, height)
+    kitty_ids: HashMap<(String, u32, u32), u32>,
+    /// Id for the next image uploaded to a Kitty terminal
+    next_kitty_id: u32,
+}
+
+impl GraphicsRenderer {
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        GraphicsRenderer {
+            protocol,
+            placements: Vec::new(),
+            shown: Vec::new(),
+            kitty_ids: HashMap::new(),
+            next_kitty_id: 1,
+        }
+    }
+
+    /// Check whether images are drawn as text cells inside the ratatui buffer
+    pub fn uses_cells(&self) -> bool {
+        self.protocol == GraphicsProtocol::HalfBlocks
+    }
+
+    /// Record the images the frame being drawn wants to show
+    pub fn set_placements(&mut self, placements: Vec<ImagePlacement>) {
+        self.placements = placements;
+    }
+
+    /// Check whether the screen must be cleared before presenting
+    ///
+    /// Sixel and iTerm2 images are pixels in the text grid that ratatui does
+    /// not know about; when they move or disappear, the whole screen has to
+    /// be redrawn to remove them.
+    pub fn needs_clear(&self) -> bool {
+        matches!(
+            self.protocol,
+            GraphicsProtocol::Sixel | GraphicsProtocol::Iterm2
+        ) && !self.shown.is_empty()
+            && self.placements != self.shown
+    }
+
+    /// Write the images of the current frame to the terminal
+    ///
+    /// Does nothing when the placements have not changed since the last
+    /// frame, so scrolling text elsewhere does not re-send image data.
+    /// Kitty images no placement uses anymore are deleted from the terminal,
+    /// so resizing and zooming don't fill its image storage.
+    pub fn present(
+        &mut self,
+        out: &mut impl Write,
+        cache: &ImageCache,
+        book: Option<&Book>,
+    ) -> io::Result<()> {
+        if self.uses_cells() || self.placements == self.shown {
+            return Ok(());
+        }
+        if self.protocol == GraphicsProtocol::Kitty {
+            out.write_all(encode::kitty_delete_placements().as_bytes())?;
+        }
+
+        let (cell_width, cell_height) = cell_size();
+        let mut used_kitty_ids = HashSet::new();
+        if let Some(book) = book {
+            for placement in &self.placements {
+                match self.protocol {
+                    GraphicsProtocol::Kitty => {
+                        let Some(decoded) = cache.decoded(book, &placement.path) else {
+                            continue;
+                        };
+                        let ((width, height), source) = kitty_source(
+                            placement,
+                            (decoded.width, decoded.height),
+                            (cell_width, cell_height),
+                        );
+                        let key = (placement.path.clone(), width, height);
+                        let id = match self.kitty_ids.get(&key) {
+                            Some(id) => *id,
+                            None => {
+                                let Some(image) =
+                                    cache.scaled(book, &placement.path, None, width, height)
+                                else {
+                                    continue;
+                                };
+                                let id = self.next_kitty_id;
+                                self.next_kitty_id += 1;
+                                out.write_all(encode::kitty_transmit(&image, id).as_bytes())?;
+                                self.kitty_ids.insert(key, id);
+                                id
+                            }
+                        };
+                        used_kitty_ids.insert(id);
+                        queue!(out, MoveTo(placement.x, placement.y))?;
+                        let place =
+                            encode::kitty_place(id, &source, placement.cols, placement.visible);
+                        out.write_all(place.as_bytes())?;
+                    }
+                    GraphicsProtocol::Iterm2 | GraphicsProtocol::Sixel => {
+                        let width = placement.cols as u32 * cell_width;
+                        let height = placement.rows as u32 * cell_height;
+                        let Some(image) =
+                            cache.scaled(book, &placement.path, placement.crop, width, height)
+                        else {
+                            continue;
+                        };
+                        let visible = image.crop_rows(
+                            placement.skip as u32 * cell_height,
+                            placement.visible as u32 * cell_height,
+                        );
+                        let data = if self.protocol == GraphicsProtocol::Iterm2 {
+                            encode::iterm2(&visible, placement.cols, placement.visible)
+                        } else {
+                            encode::sixel(&visible)
+                        };
+                        queue!(out, MoveTo(placement.x, placement.y))?;
+                        out.write_all(data.as_bytes())?;
+                    }
+                    GraphicsProtocol::HalfBlocks => {}
+                }
+            }
+        }
+
+        // Free images that are no longer shown
+        let mut unused = Vec::new();
+        self.kitty_ids.retain(|_, id| {
+            let used = used_kitty_ids.contains(id);
+            if !used {
+                unused.push(*id);
+            }
+            used
+        });
+        for id in unused {
+            out.write_all(encode::kitty_delete(id).as_bytes())?;
+        }
+
+        out.flush()?;
+        self.shown = self.placements.clone();
+        Ok(())
+    }
+
+    /// Remove all images from the terminal (e.g. when quitting or switching books)
+    pub fn clear(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.protocol == GraphicsProtocol::Kitty && !self.kitty_ids.is_empty() {
+            out.write_all(encode::kitty_delete_all().as_bytes())?;
+            out.flush()?;
+        }
+        self.kitty_ids.clear();
+        self.shown.clear();
+        Ok(())
+    }
+}
+
+/// Size to upload an image at for a Kitty placement, and the rectangle of
+/// that upload the placement shows
+///
+/// The whole image is uploaded at the scale its shown region is drawn at,
+/// but never above its natural size, so panning in the viewer and scrolling
+/// in the text only move the source rectangle of one upload per zoom level.
+///
+/// # Arguments
+/// * `placement` - Where and at what size the image is shown
+/// * `natural` - Size of the decoded image in pixels
+/// * `cell` - Size of a terminal cell in pixels
+///
+/// # Returns
+/// `((width, height), source)` with `source` in pixels of the upload
+fn kitty_source(
+    placement: &ImagePlacement,
+    natural: (u32, u32),
+    cell: (u32, u32),
+) -> ((u32, u32), Crop) {
+    let crop = placement.crop.unwrap_or(Crop {
+        x: 0,
+        y: 0,
+        width: natural.0,
+        height: natural.1,
+    });
+    let scale = |shown: u32, region: u32| (shown as f64 / region.max(1) as f64).min(1.0);
+    let scale_x = scale(placement.cols as u32 * cell.0, crop.width);
+    let scale_y = scale(placement.rows as u32 * cell.1, crop.height);
+    let pixels = |value: f64| (value.round() as u32).max(1);
+    let size = (
+        pixels(natural.0 as f64 * scale_x),
+        pixels(natural.1 as f64 * scale_y),
+    );
+
+    // Image rows scrolled out of the view are left out of the region
+    let rows = placement.rows.max(1) as f64;
+    let region_height = crop.height as f64 * scale_y;
+    let source = Crop {
+        x: (crop.x as f64 * scale_x).round() as u32,
+        y: (crop.y as f64 * scale_y + region_height * placement.skip as f64 / rows).round() as u32,
+        width: pixels(crop.width as f64 * scale_x),
+        height: pixels(region_height * placement.visible as f64 / rows),
+    };
+    (size, source)
+}
+
+/// Size of a terminal cell in pixels, or the nominal size if unknown
+pub fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as u32,
+            (size.height / size.rows) as u32,
+        ),
+        _ => (NOMINAL_CELL_WIDTH, NOMINAL_CELL_HEIGHT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_protocol() {
+        assert_eq!(GraphicsProtocol::parse("auto"), Ok(None));
+        assert_eq!(
+            GraphicsProtocol::parse("Kitty"),
+            Ok(Some(GraphicsProtocol::Kitty))
+        );
+        assert_eq!(
+            GraphicsProtocol::parse("halfblocks"),
+            Ok(Some(GraphicsProtocol::HalfBlocks))
+        );
+        assert!(GraphicsProtocol::parse("ascii").is_err());
+    }
+
+    #[test]
+    fn test_kitty_images_are_deleted_when_unused() {
+        let png = encode::png(&super::super::RgbImage {
+            width: 400,
+            height: 400,
+            pixels: vec![128; 400 * 400 * 3],
+        });
+        let (images, _dir) = super::super::tests::image_store(&[("a.png", &png)]);
+        let book = Book {
+            metadata: crate::types::BookMetadata {
+                title: "Images".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: Vec::new(),
+            images,
+        };
+        let cache = ImageCache::default();
+        let mut renderer = GraphicsRenderer::new(GraphicsProtocol::Kitty);
+        // A region of `size` pixels at `x`: smaller regions are zoomed in further
+        let placement = |size: u32, x: u32| ImagePlacement {
+            path: "a.png".to_string(),
+            x: 0,
+            y: 0,
+            cols: 4,
+            rows: 2,
+            skip: 0,
+            visible: 2,
+            crop: Some(Crop {
+                x,
+                y: 0,
+                width: size,
+                height: size,
+            }),
+        };
+        let present = |renderer: &mut GraphicsRenderer, placements: Vec<ImagePlacement>| {
+            renderer.set_placements(placements);
+            let mut out = Vec::new();
+            renderer.present(&mut out, &cache, Some(&book)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = present(&mut renderer, vec![placement(400, 0)]);
+        assert!(out.contains("a=t,") && out.contains(",i=1,"));
+        assert!(!out.contains("d=I"));
+
+        // Each zoom level replaces the previous upload
+        for (size, id) in [(200, 2), (100, 3)] {
+            let out = present(&mut renderer, vec![placement(size, 0)]);
+            assert!(out.contains(&format!(",i={},", id)));
+            assert!(out.contains(&encode::kitty_delete(id - 1)));
+        }
+        assert_eq!(renderer.kitty_ids.len(), 1);
+
+        // Panning moves the source rectangle of the same upload
+        let out = present(&mut renderer, vec![placement(100, 300)]);
+        assert!(!out.contains("a=t,") && !out.contains("d=I"));
+        assert!(out.contains("a=p,i=3,"));
+
+        // Ids of deleted images are not handed out again
+        let out = present(&mut renderer, vec![placement(400, 0), placement(100, 0)]);
+        assert!(out.contains(",i=4,") && out.contains("a=p,i=3,"));
+        assert!(!out.contains("d=I"));
+        let out = present(&mut renderer, Vec::new());
+        assert!(out.contains(&encode::kitty_delete(3)) && out.contains(&encode::kitty_delete(4)));
+        assert!(renderer.kitty_ids.is_empty());
+    }
+
+    #[test]
+    fn test_kitty_source() {
+        let placement = ImagePlacement {
+            path: "a.png".to_string(),
+            x: 0,
+            y: 0,
+            cols: 4,
+            rows: 2,
+            skip: 0,
+            visible: 2,
+            crop: None,
+        };
+        let cell = (8, 16);
+        let crop = |x, y, width, height| Crop {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        // Shrunk to the cells it covers
+        let source = kitty_source(&placement, (400, 400), cell);
+        assert_eq!(source, ((32, 32), crop(0, 0, 32, 32)));
+        // Scrolled half out of view
+        let scrolled = ImagePlacement {
+            skip: 1,
+            visible: 1,
+            ..placement.clone()
+        };
+        let source = kitty_source(&scrolled, (400, 400), cell);
+        assert_eq!(source, ((32, 32), crop(0, 16, 32, 16)));
+
+        // Zoomed in twice: panning keeps the upload size
+        let zoomed = |x| ImagePlacement {
+            crop: Some(crop(x, 100, 200, 200)),
+            ..placement.clone()
+        };
+        let source = kitty_source(&zoomed(100), (400, 400), cell);
+        assert_eq!(source, ((64, 64), crop(16, 16, 32, 32)));
+        let source = kitty_source(&zoomed(200), (400, 400), cell);
+        assert_eq!(source, ((64, 64), crop(32, 16, 32, 32)));
+
+        // Never uploaded above the natural size
+        let source = kitty_source(&zoomed(100), (300, 300), (64, 128));
+        assert_eq!(source, ((300, 300), crop(100, 100, 200, 200)));
+    }
+
+    #[test]
+    fn test_needs_clear_only_when_pixels_move() {
+        let placement = ImagePlacement {
+            path: "a.png".to_string(),
+            x: 0,
+            y: 2,
+            cols: 10,
+            rows: 5,
+            skip: 0,
+            visible: 5,
+            crop: None,
+        };
+        let mut renderer = GraphicsRenderer::new(GraphicsProtocol::Sixel);
+        renderer.set_placements(vec![placement.clone()]);
+        assert!(!renderer.needs_clear());
+
+        renderer.shown = vec![placement.clone()];
+        assert!(!renderer.needs_clear());
+        renderer.set_placements(vec![ImagePlacement { y: 1, ..placement }]);
+        assert!(renderer.needs_clear());
+
+        renderer.protocol = GraphicsProtocol::Kitty;
+        assert!(!renderer.needs_clear());
+    }
+}
@@ -0,0 +1,265 @@
+//! Full-screen image viewer state
+//!
+//! The viewer lists every displayable image of the book so figures can be
+//! paged through without returning to the text. Zoom and pan select the
+//! region of the current image that is shown; the layout scales that region
+//! to the largest size the screen allows. On Kitty terminals the image is
+//! uploaded once per zoom level and panning only moves the region shown.
+
+use super::Crop;
+use crate::constants::{IMAGE_PAN_STEP, IMAGE_ZOOM_STEP, MAX_IMAGE_ZOOM};
+use crate::epub::document::BlockKind;
+use crate::types::{Book, ContentAnchor};
+
+/// An image of the book as listed in the viewer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewerImage {
+    /// Chapter containing the image
+    pub chapter_idx: usize,
+    /// Position of the image in the chapter
+    pub anchor: ContentAnchor,
+    /// Archive path of the image
+    pub path: String,
+    /// Size in pixels
+    pub width: u32,
+    pub height: u32,
+    pub alt: String,
+    /// Caption of the figure the image belongs to
+    pub caption: Option<String>,
+}
+
+/// List all images of a book that can be displayed, in reading order
+pub fn book_images(book: &Book) -> Vec<ViewerImage> {
+    let mut images = Vec::new();
+    for (chapter_idx, chapter) in book.chapters.iter().enumerate() {
+        for block in chapter.document.images() {
+            if let BlockKind::Image {
+                alt,
+                resource: Some(resource),
+                caption,
+                ..
+            } = &block.kind
+            {
+                images.push(ViewerImage {
+                    chapter_idx,
+                    anchor: ContentAnchor {
+                        element_path: block.source.clone(),
+                        char_offset: 0,
+                    },
+                    path: resource.path.clone(),
+                    width: resource.width,
+                    height: resource.height,
+                    alt: alt.clone(),
+                    caption: caption.clone(),
+                });
+            }
+        }
+    }
+    images
+}
+
+/// State of the full-screen image viewer
+#[derive(Debug, Clone)]
+pub struct ImageViewer {
+    pub images: Vec<ViewerImage>,
+    pub index: usize,
+    /// Magnification, 1.0 shows the whole image
+    pub zoom: f64,
+    /// Center of the visible region as a fraction of the image size
+    center: (f64, f64),
+}
+
+impl ImageViewer {
+    /// Open the viewer on image `index` of `images`
+    ///
+    /// # Returns
+    /// `None` if there is no image at `index`
+    pub fn new(images: Vec<ViewerImage>, index: usize) -> Option<Self> {
+        (index < images.len()).then_some(ImageViewer {
+            images,
+            index,
+            zoom: 1.0,
+            center: (0.5, 0.5),
+        })
+    }
+
+    /// The image being shown
+    pub fn current(&self) -> &ViewerImage {
+        &self.images[self.index]
+    }
+
+    /// Show the next image of the book
+    pub fn next(&mut self) {
+        if self.index + 1 < self.images.len() {
+            self.index += 1;
+            self.reset();
+        }
+    }
+
+    /// Show the previous image of the book
+    pub fn previous(&mut self) {
+        if self.index > 0 {
+            self.index -= 1;
+            self.reset();
+        }
+    }
+
+    /// Show the whole image again
+    pub fn reset(&mut self) {
+        self.zoom = 1.0;
+        self.center = (0.5, 0.5);
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.set_zoom(self.zoom * IMAGE_ZOOM_STEP);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.set_zoom(self.zoom / IMAGE_ZOOM_STEP);
+    }
+
+    fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.clamp(1.0, MAX_IMAGE_ZOOM);
+        self.pan(0.0, 0.0);
+    }
+
+    /// Move the visible region by steps of `IMAGE_PAN_STEP` of its size
+    ///
+    /// # Arguments
+    /// * `dx` - Steps to the right (negative for left)
+    /// * `dy` - Steps down (negative for up)
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        let half = 0.5 / self.zoom;
+        let step = IMAGE_PAN_STEP / self.zoom;
+        self.center = (
+            (self.center.0 + dx * step).clamp(half, 1.0 - half),
+            (self.center.1 + dy * step).clamp(half, 1.0 - half),
+        );
+    }
+
+    /// Region of the current image that is visible
+    pub fn crop(&self) -> Crop {
+        let image = self.current();
+        let size = |full: u32| ((full as f64 / self.zoom).round() as u32).clamp(1, full.max(1));
+        let start = |full: u32, visible: u32, center: f64| {
+            ((center * full as f64 - visible as f64 / 2.0)
+                .round()
+                .max(0.0) as u32)
+                .min(full.saturating_sub(visible))
+        };
+
+        let width = size(image.width);
+        let height = size(image.height);
+        Crop {
+            x: start(image.width, width, self.center.0),
+            y: start(image.height, height, self.center.1),
+            width,
+            height,
+        }
+    }
+
+    /// Largest size in cells the visible region can be shown at
+    ///
+    /// # Arguments
+    /// * `cols`, `rows` - Space available for the image
+    /// * `cell` - Size of a terminal cell in pixels
+    ///
+    /// # Returns
+    /// `(columns, rows)` keeping the region's aspect ratio
+    pub fn fit(&self, cols: u16, rows: u16, cell: (u32, u32)) -> (u16, u16) {
+        let crop = self.crop();
+        let (cell_width, cell_height) = (cell.0.max(1) as f64, cell.1.max(1) as f64);
+        let scale = f64::min(
+            cols as f64 * cell_width / crop.width as f64,
+            rows as f64 * cell_height / crop.height as f64,
+        );
+        let fit_cols = (crop.width as f64 * scale / cell_width).round();
+        let fit_rows = (crop.height as f64 * scale / cell_height).round();
+        (
+            fit_cols.clamp(1.0, cols.max(1) as f64) as u16,
+            fit_rows.clamp(1.0, rows.max(1) as f64) as u16,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(name: &str, width: u32, height: u32) -> ViewerImage {
+        ViewerImage {
+            chapter_idx: 0,
+            anchor: ContentAnchor::default(),
+            path: name.to_string(),
+            width,
+            height,
+            alt: String::new(),
+            caption: None,
+        }
+    }
+
+    #[test]
+    fn test_zoom_and_pan_stay_inside_image() {
+        let mut viewer = ImageViewer::new(vec![image("a.png", 400, 200)], 0).unwrap();
+        assert_eq!(
+            viewer.crop(),
+            Crop {
+                x: 0,
+                y: 0,
+                width: 400,
+                height: 200
+            }
+        );
+
+        // Panning without zoom has nowhere to go
+        viewer.pan(1.0, 1.0);
+        assert_eq!(viewer.crop().x, 0);
+
+        viewer.set_zoom(2.0);
+        assert_eq!(
+            viewer.crop(),
+            Crop {
+                x: 100,
+                y: 50,
+                width: 200,
+                height: 100
+            }
+        );
+
+        viewer.pan(10.0, -10.0);
+        let crop = viewer.crop();
+        assert_eq!((crop.x, crop.y), (200, 0));
+
+        viewer.zoom_out();
+        viewer.zoom_out();
+        assert_eq!(viewer.zoom, 1.0);
+        assert_eq!(viewer.crop().x, 0);
+    }
+
+    #[test]
+    fn test_navigation_resets_view() {
+        let images = vec![image("a.png", 10, 10), image("b.png", 10, 10)];
+        assert!(ImageViewer::new(images.clone(), 2).is_none());
+
+        let mut viewer = ImageViewer::new(images, 0).unwrap();
+        viewer.zoom_in();
+        viewer.next();
+        assert_eq!(viewer.current().path, "b.png");
+        assert_eq!(viewer.zoom, 1.0);
+        viewer.next();
+        assert_eq!(viewer.index, 1);
+        viewer.previous();
+        viewer.previous();
+        assert_eq!(viewer.index, 0);
+    }
+
+    #[test]
+    fn test_fit_keeps_aspect_ratio() {
+        let viewer = ImageViewer::new(vec![image("a.png", 1200, 1600)], 0).unwrap();
+        // Height limited: 40 rows of 16px = 640px tall, 480px = 60 columns wide
+        assert_eq!(viewer.fit(200, 40, (8, 16)), (60, 40));
+
+        let wide = ImageViewer::new(vec![image("b.png", 1000, 100)], 0).unwrap();
+        assert_eq!(wide.fit(100, 40, (8, 16)), (100, 5));
+    }
+}
@@ -0,0 +1,213 @@
+//! Calibre annotation export reader
+//!
+//! Calibre's viewer exports annotations as JSON, either as a
+//! `calibre_annotation_collection` object or as a bare list. Highlights carry
+//! a CFI relative to their spine item plus the spine index, bookmarks and the
+//! last read position carry a full `epubcfi(...)` string.
+
+use super::{AnnotationKind, ImportedAnnotation, ImportedData, ImportedPosition, Position};
+use crate::types::HighlightColor;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Collection { annotations: Vec<Annotation> },
+    List(Vec<Annotation>),
+}
+
+#[derive(Deserialize)]
+struct Annotation {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    removed: bool,
+    timestamp: Option<String>,
+    // Bookmarks and last read position
+    title: Option<String>,
+    pos: Option<String>,
+    // Highlights
+    spine_index: Option<usize>,
+    start_cfi: Option<String>,
+    end_cfi: Option<String>,
+    highlighted_text: Option<String>,
+    notes: Option<String>,
+    style: Option<Style>,
+}
+
+#[derive(Deserialize)]
+struct Style {
+    which: Option<String>,
+}
+
+/// Read the annotations and last read position from a Calibre export
+///
+/// # Returns
+/// * `Ok(ImportedData)` - Everything found in the file
+/// * `Err(String)` - Not a Calibre annotation export
+pub fn parse(source: &str) -> Result<ImportedData, String> {
+    let export: Export = serde_json::from_str(source)
+        .map_err(|e| format!("Not a Calibre annotation export: {}", e))?;
+    let annotations = match export {
+        Export::Collection { annotations } | Export::List(annotations) => annotations,
+    };
+
+    let mut data = ImportedData::default();
+    for annotation in annotations.into_iter().filter(|a| !a.removed) {
+        let created = annotation
+            .timestamp
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc));
+
+        match annotation.kind.as_str() {
+            "highlight" => {
+                let (Some(spine_idx), Some(start), Some(end)) = (
+                    annotation.spine_index,
+                    annotation.start_cfi.as_deref(),
+                    annotation.end_cfi.as_deref(),
+                ) else {
+                    continue;
+                };
+                data.annotations.push(ImportedAnnotation {
+                    start: Position::Cfi(spine_cfi(spine_idx, start)),
+                    kind: AnnotationKind::Highlight {
+                        end: Position::Cfi(spine_cfi(spine_idx, end)),
+                        text: annotation.highlighted_text.unwrap_or_default(),
+                        note: annotation.notes.filter(|n| !n.trim().is_empty()),
+                        color: color(annotation.style.and_then(|s| s.which).as_deref()),
+                    },
+                    created,
+                });
+            }
+            "bookmark" => {
+                let Some(pos) = annotation.pos.as_deref() else {
+                    continue;
+                };
+                data.annotations.push(ImportedAnnotation {
+                    start: Position::Cfi(full_cfi(pos)),
+                    kind: AnnotationKind::Bookmark {
+                        label: annotation
+                            .title
+                            .unwrap_or_else(|| "Calibre bookmark".to_string()),
+                    },
+                    created,
+                });
+            }
+            "last-read" => {
+                let Some(pos) = annotation.pos.as_deref() else {
+                    continue;
+                };
+                // Several devices may each have a last read position, keep the latest
+                if data.position.as_ref().is_none_or(|p| p.updated < created) {
+                    data.position = Some(ImportedPosition {
+                        position: Position::Cfi(full_cfi(pos)),
+                        updated: created,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(data)
+}
+
+/// Drop the step for the document's `<html>` element that Calibre puts first
+fn strip_root_step(path: &str) -> &str {
+    match path.strip_prefix("/2/") {
+        Some(rest) => &path[path.len() - rest.len() - 1..],
+        None => path,
+    }
+}
+
+/// Combine a spine index and an in-document CFI into a full CFI
+fn spine_cfi(spine_idx: usize, path: &str) -> String {
+    format!(
+        "epubcfi(/6/{}!{})",
+        (spine_idx + 1) * 2,
+        strip_root_step(path)
+    )
+}
+
+/// Normalize Calibre's `epubcfi(/N/2/4/...)` positions, which have no
+/// indirection step, into standard `epubcfi(/6/N!/4/...)` form
+fn full_cfi(pos: &str) -> String {
+    let inner = pos
+        .trim()
+        .strip_prefix("epubcfi(")
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(pos);
+    if inner.contains('!') {
+        return format!("epubcfi({})", inner);
+    }
+
+    let (spine_step, path) = match inner.strip_prefix('/').and_then(|s| s.split_once('/')) {
+        Some((step, rest)) => (step, &inner[inner.len() - rest.len() - 1..]),
+        None => return pos.to_string(),
+    };
+    format!("epubcfi(/6/{}!{})", spine_step, strip_root_step(path))
+}
+
+fn color(name: Option<&str>) -> HighlightColor {
+    match name.unwrap_or_default() {
+        "green" => HighlightColor::Green,
+        "blue" | "purple" => HighlightColor::Blue,
+        "red" | "pink" => HighlightColor::Pink,
+        _ => HighlightColor::Yellow,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_collection() {
+        let source = r#"{
+            "type": "calibre_annotation_collection",
+            "version": 1,
+            "annotations": [
+                {"type": "highlight", "spine_index": 2, "start_cfi": "/2/4/2/6/1:3",
+                 "end_cfi": "/2/4/2/6/1:20", "highlighted_text": "some words",
+                 "notes": "why", "style": {"kind": "color", "type": "builtin", "which": "green"},
+                 "timestamp": "2023-04-01T10:00:00.000Z", "uuid": "a"},
+                {"type": "highlight", "removed": true, "spine_index": 1,
+                 "start_cfi": "/2/4/1:0", "end_cfi": "/2/4/1:1", "uuid": "b"},
+                {"type": "bookmark", "title": "Start here", "pos_type": "epubcfi",
+                 "pos": "epubcfi(/8/2/4/2[ch]/4/1:0)", "timestamp": "2023-04-01T09:00:00Z"},
+                {"type": "last-read", "pos_type": "epubcfi",
+                 "pos": "epubcfi(/6/4!/4/10/1:0)", "timestamp": "2023-03-01T09:00:00Z"},
+                {"type": "last-read", "pos_type": "epubcfi",
+                 "pos": "epubcfi(/10/2/4/8/1:0)", "timestamp": "2023-05-01T09:00:00Z"}
+            ]
+        }"#;
+        let data = parse(source).unwrap();
+        assert_eq!(data.annotations.len(), 2);
+        assert_eq!(
+            data.annotations[0].start,
+            Position::Cfi("epubcfi(/6/6!/4/2/6/1:3)".to_string())
+        );
+        match &data.annotations[0].kind {
+            AnnotationKind::Highlight {
+                end, note, color, ..
+            } => {
+                assert_eq!(end, &Position::Cfi("epubcfi(/6/6!/4/2/6/1:20)".to_string()));
+                assert_eq!(note.as_deref(), Some("why"));
+                assert_eq!(*color, HighlightColor::Green);
+            }
+            other => panic!("expected a highlight, got {:?}", other),
+        }
+        assert_eq!(
+            data.annotations[1].start,
+            Position::Cfi("epubcfi(/6/8!/4/2[ch]/4/1:0)".to_string())
+        );
+        assert_eq!(
+            data.position.unwrap().position,
+            Position::Cfi("epubcfi(/6/10!/4/8/1:0)".to_string())
+        );
+
+        assert!(parse("[]").unwrap().annotations.is_empty());
+        assert!(parse("{\"not\": \"calibre\"}").is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! KOReader sidecar (`metadata.epub.lua`) reader
+//!
+//! KOReader keeps highlights, bookmarks and the last position of a book in a
+//! Lua table next to the book (in `book.sdr/`). Positions are CREngine
+//! xpointers such as `/body/DocFragment[12]/body/div/p[3]/text().15`, which
+//! are translated into CFIs against the book's own documents.
+
+use super::lua::{self, LuaTable, LuaValue};
+use super::{AnnotationKind, ImportedAnnotation, ImportedData, ImportedPosition, Position};
+use crate::epub::cfi::{Cfi, CfiStep};
+use crate::types::{Book, HighlightColor};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use scraper::{ElementRef, Html};
+
+/// Read the annotations and last position from a KOReader sidecar file
+///
+/// Both the current `annotations` list and the older `bookmarks` list are
+/// understood; entries with a start and end position are highlights.
+///
+/// # Returns
+/// * `Ok(ImportedData)` - Everything found in the file
+/// * `Err(String)` - Not a Lua table
+pub fn parse(source: &str) -> Result<ImportedData, String> {
+    let value = lua::parse(source)?;
+    let root = value
+        .as_table()
+        .ok_or_else(|| "KOReader sidecar does not contain a table".to_string())?;
+
+    let entries = root
+        .get_table("annotations")
+        .or_else(|| root.get_table("bookmarks"))
+        .map(LuaTable::array)
+        .unwrap_or_default();
+    let legacy = root.get_table("annotations").is_none();
+
+    let annotations = entries
+        .into_iter()
+        .filter_map(LuaValue::as_table)
+        .filter_map(|entry| annotation(entry, legacy))
+        .collect();
+
+    // The sidecar has no timestamp for the position itself, only the day the
+    // book status was last changed
+    let updated = root
+        .get_table("summary")
+        .and_then(|summary| summary.get_str("modified"))
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc());
+    let position = root
+        .get_str("last_xpointer")
+        .map(|xpointer| ImportedPosition {
+            position: Position::XPointer(xpointer.to_string()),
+            updated,
+        });
+
+    Ok(ImportedData {
+        annotations,
+        position,
+    })
+}
+
+fn annotation(entry: &LuaTable, legacy: bool) -> Option<ImportedAnnotation> {
+    let created = entry.get_str("datetime").and_then(parse_datetime);
+    let chapter = entry.get_str("chapter").map(str::trim).unwrap_or_default();
+
+    if let (Some(pos0), Some(pos1)) = (entry.get_str("pos0"), entry.get_str("pos1")) {
+        // Old sidecars kept the highlighted text in `notes`
+        let (text, note) = if legacy {
+            (entry.get_str("notes"), None)
+        } else {
+            (entry.get_str("text"), entry.get_str("note"))
+        };
+        return Some(ImportedAnnotation {
+            start: Position::XPointer(pos0.to_string()),
+            kind: AnnotationKind::Highlight {
+                end: Position::XPointer(pos1.to_string()),
+                text: text.unwrap_or_default().trim().to_string(),
+                note: note
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty())
+                    .map(String::from),
+                color: color(entry.get_str("color")),
+            },
+            created,
+        });
+    }
+
+    let page = entry.get_str("page")?;
+    let label = [
+        entry.get_str("note"),
+        entry.get_str("notes"),
+        entry.get_str("text"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(str::trim)
+    .find(|label| !label.is_empty())
+    .unwrap_or(if chapter.is_empty() {
+        "KOReader bookmark"
+    } else {
+        chapter
+    });
+    Some(ImportedAnnotation {
+        start: Position::XPointer(page.to_string()),
+        kind: AnnotationKind::Bookmark {
+            label: label.to_string(),
+        },
+        created,
+    })
+}
+
+/// KOReader writes local times as `2024-05-01 21:13:08`
+fn parse_datetime(text: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|datetime| datetime.and_utc())
+}
+
+fn color(name: Option<&str>) -> HighlightColor {
+    match name.unwrap_or_default() {
+        "green" | "olive" | "cyan" => HighlightColor::Green,
+        "blue" | "purple" => HighlightColor::Blue,
+        "red" | "pink" => HighlightColor::Pink,
+        _ => HighlightColor::Yellow,
+    }
+}
+
+/// Translate a CREngine xpointer into a CFI for the same book
+///
+/// `DocFragment[N]` selects spine item N (1-based). The element steps below
+/// `body` count same-named siblings (1-based), the optional `text()[k]` step
+/// selects the k-th text node and `.offset` is a character offset.
+///
+/// # Returns
+/// * `Ok(String)` - Equivalent CFI
+/// * `Err(String)` - Malformed xpointer or a path missing from the book
+pub fn xpointer_to_cfi(book: &Book, xpointer: &str) -> Result<String, String> {
+    let rest = xpointer
+        .strip_prefix("/body/DocFragment[")
+        .ok_or_else(|| format!("Unsupported xpointer '{}'", xpointer))?;
+    let (fragment, rest) = rest
+        .split_once(']')
+        .ok_or_else(|| format!("Malformed xpointer '{}'", xpointer))?;
+    let spine_idx = fragment
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .ok_or_else(|| format!("Malformed xpointer '{}'", xpointer))?;
+    let chapter = book
+        .chapters
+        .get(spine_idx)
+        .ok_or_else(|| format!("DocFragment {} is not in the book", spine_idx + 1))?;
+
+    // The character offset follows a '.' in the last step
+    let (path, offset) = match rest.rsplit_once('.') {
+        Some((path, offset)) if !offset.contains('/') => (path, offset.parse::<usize>().ok()),
+        _ => (rest, None),
+    };
+
+    let html = Html::parse_document(&chapter.file_path);
+    let mut element = html.root_element();
+    let mut steps = Vec::new();
+    let mut text_offset = None;
+
+    for step in path.split('/').filter(|s| !s.is_empty()) {
+        let (name, nth) = match step.split_once('[') {
+            Some((name, nth)) => (
+                name,
+                nth.trim_end_matches(']')
+                    .parse::<usize>()
+                    .map_err(|_| format!("Malformed xpointer step '{}'", step))?,
+            ),
+            None => (step, 1),
+        };
+
+        if name == "text()" {
+            // Text steps sit between element steps: 2k+1 follows k elements
+            let mut texts_seen = 0;
+            let mut elements_seen = 0;
+            for child in element.children() {
+                if child.value().is_element() {
+                    elements_seen += 1;
+                } else if child.value().is_text() {
+                    texts_seen += 1;
+                    if texts_seen == nth {
+                        break;
+                    }
+                }
+            }
+            if texts_seen < nth {
+                return Err(format!("Text node {} not found in chapter", step));
+            }
+            steps.push(CfiStep {
+                index: elements_seen * 2 + 1,
+                assertion: None,
+            });
+            text_offset = offset;
+            break;
+        }
+
+        let (idx, child) = element
+            .children()
+            .filter_map(ElementRef::wrap)
+            .enumerate()
+            .filter(|(_, el)| el.value().name() == name)
+            .nth(nth.saturating_sub(1))
+            .ok_or_else(|| format!("Element {} not found in chapter", step))?;
+        steps.push(CfiStep {
+            index: (idx + 1) * 2,
+            assertion: child.value().attr("id").map(str::to_string),
+        });
+        element = child;
+    }
+
+    if steps.is_empty() {
+        return Err(format!(
+            "Xpointer '{}' has no path into the chapter",
+            xpointer
+        ));
+    }
+    Ok(Cfi {
+        spine_idx,
+        steps,
+        offset: text_offset,
+    }
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BookMetadata, Chapter};
+
+    #[test]
+    fn test_parse_annotations_and_position() {
+        let source = r#"return {
+    ["annotations"] = {
+        [1] = {
+            ["chapter"] = "One",
+            ["datetime"] = "2024-05-01 21:13:08",
+            ["page"] = "/body/DocFragment[2]/body/p[2]/text().0",
+        },
+        [2] = {
+            ["color"] = "blue",
+            ["note"] = "Remember this",
+            ["pos0"] = "/body/DocFragment[2]/body/p[1]/text().4",
+            ["pos1"] = "/body/DocFragment[2]/body/p[1]/text().9",
+            ["text"] = "quick",
+        },
+    },
+    ["last_xpointer"] = "/body/DocFragment[2]/body/p[2].0",
+    ["summary"] = { ["modified"] = "2024-05-02", ["status"] = "reading" },
+}"#;
+        let data = parse(source).unwrap();
+        assert_eq!(data.annotations.len(), 2);
+        assert!(matches!(
+            &data.annotations[0].kind,
+            AnnotationKind::Bookmark { label } if label == "One"
+        ));
+        match &data.annotations[1].kind {
+            AnnotationKind::Highlight {
+                text, note, color, ..
+            } => {
+                assert_eq!(text, "quick");
+                assert_eq!(note.as_deref(), Some("Remember this"));
+                assert_eq!(*color, HighlightColor::Blue);
+            }
+            other => panic!("expected a highlight, got {:?}", other),
+        }
+        let position = data.position.unwrap();
+        assert_eq!(
+            position.position,
+            Position::XPointer("/body/DocFragment[2]/body/p[2].0".to_string())
+        );
+        assert_eq!(
+            position.updated.unwrap().to_rfc3339(),
+            "2024-05-02T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_xpointer_to_cfi() {
+        let html = "<html><head></head><body><div id=\"c\"><p>One</p><p>Two <b>bold</b> tail</p></div></body></html>";
+        let chapter = Chapter {
+            title: "Test".to_string(),
+            sections: Vec::new(),
+            content_lines: Vec::new(),
+            file_path: html.to_string(),
+            href: "test.xhtml".to_string(),
+            document: crate::epub::document::build_document(html),
+        };
+        let book = Book {
+            metadata: BookMetadata {
+                title: "Test".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: vec![chapter.clone(), chapter],
+            images: Default::default(),
+        };
+
+        assert_eq!(
+            xpointer_to_cfi(&book, "/body/DocFragment[2]/body/div/p[2]/text()[2].3").unwrap(),
+            "epubcfi(/6/4!/4/2[c]/4/3:3)"
+        );
+        assert_eq!(
+            xpointer_to_cfi(&book, "/body/DocFragment[1]/body/div/p.0").unwrap(),
+            "epubcfi(/6/2!/4/2[c]/2)"
+        );
+        assert!(xpointer_to_cfi(&book, "/body/DocFragment[3]/body/p.0").is_err());
+        assert!(xpointer_to_cfi(&book, "/body/DocFragment[1]/body/div/p[5].0").is_err());
+    }
+}
@@ -0,0 +1,436 @@
+//! Reader for the Lua table literals KOReader stores its sidecar files in
+//!
+//! Only data is supported: a single (optionally `return`ed) value made of
+//! tables, strings, numbers, booleans and nil. No code is ever executed.
+
+/// A Lua value
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table(LuaTable),
+}
+
+/// A Lua table as an ordered list of key/value pairs
+///
+/// Positional entries get the implicit integer keys 1, 2, ... like in Lua.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LuaTable {
+    pub entries: Vec<(LuaValue, LuaValue)>,
+}
+
+impl LuaValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LuaValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            LuaValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&LuaTable> {
+        match self {
+            LuaValue::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+impl LuaTable {
+    /// Value stored under a string key
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    /// String value stored under a key, ignoring other types
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(LuaValue::as_str)
+    }
+
+    /// Table value stored under a key
+    pub fn get_table(&self, key: &str) -> Option<&LuaTable> {
+        self.get(key).and_then(LuaValue::as_table)
+    }
+
+    /// Values under integer keys, in key order (the "array part" of the table)
+    pub fn array(&self) -> Vec<&LuaValue> {
+        let mut items: Vec<(f64, &LuaValue)> = self
+            .entries
+            .iter()
+            .filter_map(|(k, v)| k.as_number().map(|n| (n, v)))
+            .collect();
+        items.sort_by(|a, b| a.0.total_cmp(&b.0));
+        items.into_iter().map(|(_, v)| v).collect()
+    }
+}
+
+/// Parse a Lua data file such as `return { ["key"] = "value" }`
+///
+/// # Returns
+/// * `Ok(LuaValue)` - The returned value
+/// * `Err(String)` - Syntax error, with the byte position it was found at
+pub fn parse(source: &str) -> Result<LuaValue, String> {
+    let mut parser = Parser {
+        src: source.as_bytes(),
+        pos: 0,
+    };
+    parser.skip_trivia();
+    if parser.src[parser.pos..].starts_with(b"return")
+        && !parser
+            .src
+            .get(parser.pos + 6)
+            .is_some_and(|c| is_ident_char(*c))
+    {
+        parser.pos += 6;
+    }
+    let value = parser.value()?;
+    parser.skip_trivia();
+    if parser.pos < parser.src.len() {
+        return Err(parser.error("unexpected text after value"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("Lua syntax error at byte {}: {}", self.pos, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    /// Skip whitespace and `--` comments (line and `--[[ ]]` block comments)
+    fn skip_trivia(&mut self) {
+        loop {
+            while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            if !self.src[self.pos..].starts_with(b"--") {
+                return;
+            }
+            self.pos += 2;
+            if self.long_bracket_level().is_some() {
+                // Errors in comments are ignored, an unterminated one ends the input
+                if self.long_string().is_err() {
+                    self.pos = self.src.len();
+                }
+            } else {
+                while self.peek().is_some_and(|c| c != b'\n') {
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<LuaValue, String> {
+        self.skip_trivia();
+        match self.peek() {
+            Some(b'{') => self.table(),
+            Some(b'"') | Some(b'\'') => self.quoted_string().map(LuaValue::String),
+            Some(b'[') if self.long_bracket_level().is_some() => {
+                self.long_string().map(LuaValue::String)
+            }
+            Some(c) if c == b'-' || c == b'.' || c.is_ascii_digit() => self.number(),
+            Some(c) if is_ident_char(c) => match self.identifier().as_str() {
+                "nil" => Ok(LuaValue::Nil),
+                "true" => Ok(LuaValue::Bool(true)),
+                "false" => Ok(LuaValue::Bool(false)),
+                other => Err(self.error(&format!("unsupported expression '{}'", other))),
+            },
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn table(&mut self) -> Result<LuaValue, String> {
+        self.pos += 1; // '{'
+        let mut table = LuaTable::default();
+        let mut next_index = 1.0;
+
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(LuaValue::Table(table));
+                }
+                None => return Err(self.error("unterminated table")),
+                _ => {}
+            }
+
+            let entry = if self.peek() == Some(b'[') && self.long_bracket_level().is_none() {
+                // [key] = value
+                self.pos += 1;
+                let key = self.value()?;
+                self.skip_trivia();
+                if self.peek() != Some(b']') {
+                    return Err(self.error("expected ']'"));
+                }
+                self.pos += 1;
+                self.expect_equals()?;
+                (key, self.value()?)
+            } else if self
+                .peek()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == b'_')
+                && self.followed_by_equals()
+            {
+                // key = value
+                let key = self.identifier();
+                self.expect_equals()?;
+                (LuaValue::String(key), self.value()?)
+            } else {
+                let value = self.value()?;
+                let key = LuaValue::Number(next_index);
+                next_index += 1.0;
+                (key, value)
+            };
+            table.entries.push(entry);
+
+            self.skip_trivia();
+            match self.peek() {
+                Some(b',') | Some(b';') => self.pos += 1,
+                Some(b'}') => {}
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(is_ident_char) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.src[start..self.pos]).into_owned()
+    }
+
+    /// Whether the identifier at the cursor is followed by `=` (and not `==`)
+    fn followed_by_equals(&self) -> bool {
+        let mut pos = self.pos;
+        while self.src.get(pos).is_some_and(|c| is_ident_char(*c)) {
+            pos += 1;
+        }
+        while self.src.get(pos).is_some_and(|c| c.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        self.src.get(pos) == Some(&b'=') && self.src.get(pos + 1) != Some(&b'=')
+    }
+
+    fn expect_equals(&mut self) -> Result<(), String> {
+        self.skip_trivia();
+        if self.peek() != Some(b'=') {
+            return Err(self.error("expected '='"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<LuaValue, String> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+            self.skip_trivia();
+        }
+        let digits_start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'+' | b'-'))
+        {
+            // A sign is only part of the number right after an exponent
+            let c = self.src[self.pos];
+            if matches!(c, b'+' | b'-') && !matches!(self.src[self.pos - 1], b'e' | b'E') {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        let text = String::from_utf8_lossy(&self.src[digits_start..self.pos]);
+        let negative = self.src[start] == b'-';
+        let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => i64::from_str_radix(hex, 16).map(|n| n as f64).ok(),
+            None => text.parse::<f64>().ok(),
+        }
+        .ok_or_else(|| self.error(&format!("invalid number '{}'", text)))?;
+        Ok(LuaValue::Number(if negative { -value } else { value }))
+    }
+
+    fn quoted_string(&mut self) -> Result<String, String> {
+        let quote = self.src[self.pos];
+        self.pos += 1;
+        let mut bytes = Vec::new();
+
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => break,
+                b'\n' => return Err(self.error("unescaped newline in string")),
+                b'\\' => self.escape(&mut bytes)?,
+                c => bytes.push(c),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn escape(&mut self, bytes: &mut Vec<u8>) -> Result<(), String> {
+        let Some(c) = self.peek() else {
+            return Err(self.error("unterminated escape"));
+        };
+        self.pos += 1;
+        match c {
+            b'n' | b'\n' => bytes.push(b'\n'),
+            b't' => bytes.push(b'\t'),
+            b'r' => bytes.push(b'\r'),
+            b'a' => bytes.push(0x07),
+            b'b' => bytes.push(0x08),
+            b'f' => bytes.push(0x0c),
+            b'v' => bytes.push(0x0b),
+            b'\\' | b'"' | b'\'' => bytes.push(c),
+            b'z' => {
+                while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+                    self.pos += 1;
+                }
+            }
+            b'x' => {
+                let hex = self
+                    .src
+                    .get(self.pos..self.pos + 2)
+                    .and_then(|h| std::str::from_utf8(h).ok())
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or_else(|| self.error("invalid \\x escape"))?;
+                self.pos += 2;
+                bytes.push(hex);
+            }
+            b'u' => {
+                let rest = &self.src[self.pos..];
+                let end = rest
+                    .iter()
+                    .position(|&c| c == b'}')
+                    .filter(|_| rest.first() == Some(&b'{'))
+                    .ok_or_else(|| self.error("invalid \\u escape"))?;
+                let ch = std::str::from_utf8(&rest[1..end])
+                    .ok()
+                    .and_then(|h| u32::from_str_radix(h, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error("invalid \\u escape"))?;
+                self.pos += end + 1;
+                bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            c if c.is_ascii_digit() => {
+                // Up to three decimal digits
+                let mut value = u32::from(c - b'0');
+                for _ in 0..2 {
+                    match self.peek() {
+                        Some(d) if d.is_ascii_digit() => {
+                            value = value * 10 + u32::from(d - b'0');
+                            self.pos += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                let byte = u8::try_from(value).map_err(|_| self.error("escape out of range"))?;
+                bytes.push(byte);
+            }
+            _ => return Err(self.error("invalid escape")),
+        }
+        Ok(())
+    }
+
+    /// Level of a long bracket (`[[` is 0, `[==[` is 2) starting at the cursor
+    fn long_bracket_level(&self) -> Option<usize> {
+        let rest = &self.src[self.pos..];
+        if rest.first() != Some(&b'[') {
+            return None;
+        }
+        let level = rest[1..].iter().take_while(|&&c| c == b'=').count();
+        (rest.get(level + 1) == Some(&b'[')).then_some(level)
+    }
+
+    fn long_string(&mut self) -> Result<String, String> {
+        let level = self.long_bracket_level().unwrap_or(0);
+        self.pos += level + 2;
+        // A newline right after the opening bracket is skipped
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+
+        let close = format!("]{}]", "=".repeat(level));
+        let rest = &self.src[self.pos..];
+        let end = rest
+            .windows(close.len())
+            .position(|w| w == close.as_bytes())
+            .ok_or_else(|| self.error("unterminated long string"))?;
+        let text = String::from_utf8_lossy(&rest[..end]).into_owned();
+        self.pos += end + close.len();
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_koreader_style_table() {
+        let source = r#"-- we can read Lua syntax here!
+return {
+    ["annotations"] = {
+        [1] = {
+            ["note"] = "Line one\
+line two",
+            ["pos0"] = "/body/DocFragment[3]/body/p[2]/text().4",
+        },
+    },
+    ["percent_finished"] = 0.25,
+    ["cre_dom_version"] = { 20240114, -1, 0x10 },
+    plain_key = true, ["nothing"] = nil; ["quote"] = 'it\'s \65\u{e9}',
+    ["long"] = [==[
+raw ]] text]==],
+}
+"#;
+        let value = parse(source).unwrap();
+        let table = value.as_table().unwrap();
+
+        let annotation = table.get_table("annotations").unwrap().array()[0]
+            .as_table()
+            .unwrap();
+        assert_eq!(annotation.get_str("note"), Some("Line one\nline two"));
+        assert_eq!(table.get("percent_finished"), Some(&LuaValue::Number(0.25)));
+        let version: Vec<_> = table
+            .get_table("cre_dom_version")
+            .unwrap()
+            .array()
+            .into_iter()
+            .filter_map(LuaValue::as_number)
+            .collect();
+        assert_eq!(version, vec![20240114.0, -1.0, 16.0]);
+        assert_eq!(table.get("plain_key"), Some(&LuaValue::Bool(true)));
+        assert_eq!(table.get_str("quote"), Some("it's Aé"));
+        assert_eq!(table.get_str("long"), Some("raw ]] text"));
+
+        assert!(parse("return { [1] = }").is_err());
+        assert!(parse("return os.execute('rm')").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Importing highlights, bookmarks and positions from other readers
+//!
+//! Each importer turns another reader's annotation file into an
+//! `ImportedData` of positions in that reader's own notation. `merge` then
+//! resolves those positions against the book (via CFIs) and adds what is new
+//! to reef's bookmarks, highlights and reading progress. Anything that cannot
+//! be placed in the book is listed in the `ImportReport` instead.
+//!
+//! Supported sources:
+//! - KOReader `metadata.epub.lua` sidecar files
+//! - Calibre annotation JSON exports
+
+pub mod calibre;
+pub mod koreader;
+pub mod lua;
+
+use crate::annotations::AnnotationManager;
+use crate::bookmarks::BookmarkManager;
+use crate::epub::cfi;
+use crate::persistence::ReadingProgress;
+use crate::types::{Book, Bookmark, ContentAnchor, Highlight, HighlightColor};
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// Longest bookmark label reef accepts, in bytes
+const MAX_IMPORTED_LABEL_LENGTH: usize = 100;
+
+/// A position in the notation of the reader it was imported from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Position {
+    /// EPUB CFI (`epubcfi(/6/4!/4/2/1:0)`)
+    Cfi(String),
+    /// CREngine xpointer used by KOReader
+    XPointer(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnnotationKind {
+    Bookmark {
+        label: String,
+    },
+    Highlight {
+        end: Position,
+        text: String,
+        note: Option<String>,
+        color: HighlightColor,
+    },
+}
+
+/// A bookmark or highlight read from another reader
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedAnnotation {
+    pub start: Position,
+    pub kind: AnnotationKind,
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// Last reading position read from another reader
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedPosition {
+    pub position: Position,
+    /// When the position was recorded, if the source says
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// Everything read from an annotation file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportedData {
+    pub annotations: Vec<ImportedAnnotation>,
+    pub position: Option<ImportedPosition>,
+}
+
+/// Outcome of merging imported data into reef's stores
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub bookmarks_added: usize,
+    pub highlights_added: usize,
+    /// Entries that were already present
+    pub duplicates: usize,
+    /// Chapter the reading position was moved to
+    pub position_chapter: Option<usize>,
+    /// Description of every entry that could not be placed, with the reason
+    pub unplaced: Vec<String>,
+}
+
+/// Read an annotation file, telling KOReader and Calibre files apart by content
+///
+/// # Returns
+/// * `Ok(ImportedData)` - Parsed annotations and position
+/// * `Err(String)` - Unreadable or unrecognized file
+pub fn read_file(path: &Path) -> Result<ImportedData, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    // Calibre exports are JSON, KOReader sidecars are Lua
+    if source.trim_start().starts_with(['{', '[']) {
+        calibre::parse(&source)
+    } else {
+        koreader::parse(&source)
+    }
+}
+
+/// Merge imported annotations and position into reef's stores for a book
+///
+/// Chapters must be rendered so bookmark lines can be computed. Entries
+/// matching an existing bookmark or highlight are skipped. The reading
+/// position is replaced unless reef's saved position is newer.
+///
+/// # Arguments
+/// * `book` - Book the annotations belong to
+/// * `data` - Imported annotations and position
+/// * `bookmarks` - Bookmarks of the book, extended in place
+/// * `highlights` - Highlights of the book, extended in place
+/// * `progress` - Saved reading progress of the book, replaced in place
+pub fn merge(
+    book: &Book,
+    data: &ImportedData,
+    bookmarks: &mut Vec<Bookmark>,
+    highlights: &mut Vec<Highlight>,
+    progress: &mut Option<ReadingProgress>,
+) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    for annotation in &data.annotations {
+        let result = match &annotation.kind {
+            AnnotationKind::Bookmark { label } => {
+                merge_bookmark(book, annotation, label, bookmarks, &mut report)
+            }
+            AnnotationKind::Highlight {
+                end,
+                text,
+                note,
+                color,
+            } => resolve(book, &annotation.start).and_then(|(chapter_idx, start)| {
+                let (end_chapter, end) = resolve(book, end)?;
+                if end_chapter != chapter_idx {
+                    return Err("highlight spans several chapters".to_string());
+                }
+                if highlights
+                    .iter()
+                    .any(|h| h.chapter_idx == chapter_idx && h.start == start && h.end == end)
+                {
+                    report.duplicates += 1;
+                    return Ok(());
+                }
+                AnnotationManager::add(
+                    highlights,
+                    Highlight {
+                        chapter_idx,
+                        start,
+                        end,
+                        text: text.clone(),
+                        color: *color,
+                        note: note.clone(),
+                        created: annotation.created.unwrap_or_else(Utc::now),
+                    },
+                )?;
+                report.highlights_added += 1;
+                Ok(())
+            }),
+        };
+
+        if let Err(e) = result {
+            report
+                .unplaced
+                .push(format!("{}: {}", describe(annotation), e));
+        }
+    }
+
+    if let Some(imported) = &data.position {
+        let newer_saved = progress
+            .as_ref()
+            .is_some_and(|saved| imported.updated.is_some_and(|t| saved.last_read > t));
+        if newer_saved {
+            log::info!("Keeping saved reading position, it is newer than the imported one");
+        } else {
+            match resolve(book, &imported.position) {
+                Ok((chapter_idx, anchor)) => {
+                    let line = book.chapters[chapter_idx].line_for_anchor(&anchor);
+                    // TOC expansion and jump history are kept
+                    let (toc_expansion_state, jump_list) = progress
+                        .take()
+                        .map(|p| (p.toc_expansion_state, p.jump_list))
+                        .unwrap_or_default();
+                    *progress = Some(ReadingProgress {
+                        chapter_idx,
+                        line,
+                        scroll_offset: line,
+                        last_read: imported.updated.unwrap_or_else(Utc::now),
+                        toc_expansion_state,
+                        anchor: Some(anchor),
+                        jump_list,
+                        percent: Some(book.percent_read(chapter_idx, line)),
+                    });
+                    report.position_chapter = Some(chapter_idx);
+                }
+                Err(e) => report.unplaced.push(format!("Reading position: {}", e)),
+            }
+        }
+    }
+
+    log::info!(
+        "Import merged: {} bookmarks, {} highlights, {} duplicates, {} unplaced",
+        report.bookmarks_added,
+        report.highlights_added,
+        report.duplicates,
+        report.unplaced.len()
+    );
+    report
+}
+
+fn merge_bookmark(
+    book: &Book,
+    annotation: &ImportedAnnotation,
+    label: &str,
+    bookmarks: &mut Vec<Bookmark>,
+    report: &mut ImportReport,
+) -> Result<(), String> {
+    let (chapter_idx, anchor) = resolve(book, &annotation.start)?;
+    if bookmarks
+        .iter()
+        .any(|b| b.chapter_idx == chapter_idx && b.anchor.as_ref() == Some(&anchor))
+    {
+        report.duplicates += 1;
+        return Ok(());
+    }
+
+    let line = book.chapters[chapter_idx].line_for_anchor(&anchor);
+    BookmarkManager::add_bookmark(
+        bookmarks,
+        chapter_idx,
+        line,
+        Some(anchor),
+        truncate_label(label),
+    )?;
+    report.bookmarks_added += 1;
+    Ok(())
+}
+
+/// Resolve a foreign position to a chapter and content anchor
+fn resolve(book: &Book, position: &Position) -> Result<(usize, ContentAnchor), String> {
+    match position {
+        Position::Cfi(cfi) => cfi::resolve(book, cfi),
+        Position::XPointer(xpointer) => {
+            cfi::resolve(book, &koreader::xpointer_to_cfi(book, xpointer)?)
+        }
+    }
+}
+
+/// First line of a label, shortened to what `BookmarkManager` accepts
+fn truncate_label(label: &str) -> String {
+    let label = label.lines().next().unwrap_or_default().trim();
+    if label.len() <= MAX_IMPORTED_LABEL_LENGTH {
+        return label.to_string();
+    }
+    let mut end = MAX_IMPORTED_LABEL_LENGTH - 3;
+    while !label.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &label[..end])
+}
+
+/// Short human-readable description of an annotation for the report
+fn describe(annotation: &ImportedAnnotation) -> String {
+    let (kind, text) = match &annotation.kind {
+        AnnotationKind::Bookmark { label } => ("Bookmark", label.as_str()),
+        AnnotationKind::Highlight { text, .. } => ("Highlight", text.as_str()),
+    };
+    let excerpt: String = text.chars().take(40).collect();
+    let ellipsis = if text.chars().count() > 40 { "..." } else { "" };
+    format!("{} \"{}{}\"", kind, excerpt.trim(), ellipsis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+    use crate::types::{BookMetadata, Chapter};
+
+    fn book() -> Book {
+        let html = "<html><head></head><body><p>First paragraph of text.</p>\
+                    <p>Second paragraph, long enough to wrap onto another row.</p></body></html>";
+        let mut chapter = Chapter {
+            title: "Test".to_string(),
+            sections: Vec::new(),
+            content_lines: Vec::new(),
+            file_path: html.to_string(),
+            href: "test.xhtml".to_string(),
+            document: crate::epub::document::build_document(html),
+        };
+        render_chapter(&mut chapter, Some(24), 100, false);
+        Book {
+            metadata: BookMetadata {
+                title: "Test".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: vec![chapter],
+            images: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_merge_places_dedupes_and_reports() {
+        let book = book();
+        let data = ImportedData {
+            annotations: vec![
+                ImportedAnnotation {
+                    start: Position::XPointer("/body/DocFragment[1]/body/p[2]/text().30".into()),
+                    kind: AnnotationKind::Bookmark {
+                        label: "Wrapped".to_string(),
+                    },
+                    created: None,
+                },
+                ImportedAnnotation {
+                    start: Position::Cfi("epubcfi(/6/2!/4/2/1:6)".to_string()),
+                    kind: AnnotationKind::Highlight {
+                        end: Position::Cfi("epubcfi(/6/2!/4/2/1:15)".to_string()),
+                        text: "paragraph".to_string(),
+                        note: Some("note".to_string()),
+                        color: HighlightColor::Pink,
+                    },
+                    created: None,
+                },
+                ImportedAnnotation {
+                    start: Position::Cfi("epubcfi(/6/8!/4/2/1:0)".to_string()),
+                    kind: AnnotationKind::Bookmark {
+                        label: "Elsewhere".to_string(),
+                    },
+                    created: None,
+                },
+            ],
+            position: Some(ImportedPosition {
+                position: Position::Cfi("epubcfi(/6/2!/4/4/1:0)".to_string()),
+                updated: None,
+            }),
+        };
+
+        let mut bookmarks = Vec::new();
+        let mut highlights = Vec::new();
+        let mut progress = None;
+        let report = merge(&book, &data, &mut bookmarks, &mut highlights, &mut progress);
+
+        assert_eq!(report.bookmarks_added, 1);
+        assert_eq!(report.highlights_added, 1);
+        assert_eq!(report.unplaced.len(), 1);
+        assert!(report.unplaced[0].starts_with("Bookmark \"Elsewhere\""));
+
+        // The bookmark lands on the wrapped row holding its offset
+        let chapter = &book.chapters[0];
+        assert!(
+            bookmarks[0].line
+                > chapter.line_for_anchor(&ContentAnchor {
+                    element_path: vec![1],
+                    char_offset: 0,
+                })
+        );
+        assert_eq!(highlights[0].start.char_offset, 6);
+        assert_eq!(highlights[0].end.char_offset, 15);
+        assert_eq!(report.position_chapter, Some(0));
+        assert_eq!(
+            progress
+                .as_ref()
+                .unwrap()
+                .anchor
+                .as_ref()
+                .unwrap()
+                .element_path,
+            vec![1]
+        );
+
+        // Importing again adds nothing, and a newer saved position is kept
+        progress.as_mut().unwrap().last_read = Utc::now();
+        let mut data = data;
+        data.position.as_mut().unwrap().updated = Some(DateTime::<Utc>::MIN_UTC);
+        data.position.as_mut().unwrap().position =
+            Position::Cfi("epubcfi(/6/2!/4/2/1:0)".to_string());
+        let report = merge(&book, &data, &mut bookmarks, &mut highlights, &mut progress);
+        assert_eq!((report.bookmarks_added, report.highlights_added), (0, 0));
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.position_chapter, None);
+        assert_eq!(progress.unwrap().anchor.unwrap().element_path, vec![1]);
+    }
+}
@@ -0,0 +1,249 @@
+//! Book information and tables of contents for scripts
+//!
+//! `reef info` and `reef toc` print what the reader knows about a book
+//! without opening it: aligned text for people, or JSON for scripts. Chapters
+//! are numbered from 1, as in `reef dump --chapters`.
+
+use crate::types::{Book, BookDetails, BookMetadata};
+use serde::Serialize;
+
+/// Width of the labels of `reef info`, including the colon
+const LABEL_WIDTH: usize = 13;
+
+/// Width descriptions are wrapped at in text output
+const TEXT_WIDTH: usize = 80;
+
+/// Everything `reef info` reports about a book
+#[derive(Debug, Serialize)]
+pub struct BookInfo<'a> {
+    #[serde(flatten)]
+    pub metadata: &'a BookMetadata,
+    #[serde(flatten)]
+    pub details: BookDetails,
+    /// Number of spine items, which the reader shows as chapters
+    pub spine_length: usize,
+    pub word_count: usize,
+}
+
+impl<'a> BookInfo<'a> {
+    /// Collect the information of a parsed book
+    pub fn new(book: &'a Book, details: BookDetails) -> Self {
+        BookInfo {
+            metadata: &book.metadata,
+            details,
+            spine_length: book.chapters.len(),
+            word_count: book
+                .chapters
+                .iter()
+                .map(|chapter| chapter.document.word_count())
+                .sum(),
+        }
+    }
+
+    /// Aligned `Label: value` lines, leaving out values the book lacks
+    pub fn to_text(&self) -> String {
+        let metadata = self.metadata;
+        let details = &self.details;
+        let series = details
+            .series
+            .as_ref()
+            .map(|series| match &details.series_index {
+                Some(index) => format!("{} #{}", series, index),
+                None => series.clone(),
+            });
+        let subjects = (!details.subjects.is_empty()).then(|| details.subjects.join(", "));
+        let cover = if details.cover { "yes" } else { "no" };
+
+        let fields = [
+            ("Title", Some(metadata.title.clone())),
+            ("Author", metadata.author.clone()),
+            ("Publisher", metadata.publisher.clone()),
+            ("Published", metadata.publication_date.clone()),
+            ("Language", metadata.language.clone()),
+            ("Identifier", metadata.identifier.clone()),
+            ("Series", series),
+            ("Subjects", subjects),
+            ("Cover", Some(cover.to_string())),
+            ("Spine items", Some(self.spine_length.to_string())),
+            ("Words", Some(self.word_count.to_string())),
+        ];
+
+        let mut text = String::new();
+        for (label, value) in fields {
+            if let Some(value) = value {
+                text.push_str(&format!(
+                    "{:<width$}{}\n",
+                    format!("{}:", label),
+                    value,
+                    width = LABEL_WIDTH
+                ));
+            }
+        }
+
+        if let Some(description) = &details.description {
+            let label = format!("{:<width$}", "Description:", width = LABEL_WIDTH);
+            let indent = " ".repeat(LABEL_WIDTH);
+            let options = textwrap::Options::new(TEXT_WIDTH)
+                .initial_indent(&label)
+                .subsequent_indent(&indent);
+            text.push_str(&textwrap::fill(description, options));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The information as a JSON object
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct TocChapter<'a> {
+    chapter: usize,
+    title: &'a str,
+    href: &'a str,
+    sections: Vec<TocSection<'a>>,
+}
+
+#[derive(Serialize)]
+struct TocSection<'a> {
+    title: &'a str,
+    href: String,
+}
+
+/// The chapters of a book with their sections, as shown in the TOC panel
+fn toc_chapters(book: &Book) -> Vec<TocChapter<'_>> {
+    book.chapters
+        .iter()
+        .enumerate()
+        .map(|(idx, chapter)| TocChapter {
+            chapter: idx + 1,
+            title: &chapter.title,
+            href: &chapter.href,
+            sections: chapter
+                .sections
+                .iter()
+                .map(|section| TocSection {
+                    title: &section.title,
+                    href: match &section.fragment_id {
+                        Some(fragment) => format!("{}#{}", chapter.href, fragment),
+                        None => chapter.href.clone(),
+                    },
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// The table of contents as numbered chapters with their sections indented
+pub fn toc_text(book: &Book) -> String {
+    let width = book.chapters.len().to_string().len();
+    let mut text = String::new();
+    for chapter in toc_chapters(book) {
+        text.push_str(&format!("{:>width$}. {}\n", chapter.chapter, chapter.title));
+        for section in chapter.sections {
+            text.push_str(&format!("{:width$}    {}\n", "", section.title));
+        }
+    }
+    text
+}
+
+/// The table of contents as a JSON array of chapters
+pub fn toc_json(book: &Book) -> Result<String, String> {
+    serde_json::to_string_pretty(&toc_chapters(book)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Chapter, Section};
+
+    fn chapter(title: &str, href: &str, html: &str, sections: &[(&str, Option<&str>)]) -> Chapter {
+        Chapter {
+            title: title.to_string(),
+            sections: sections
+                .iter()
+                .map(|(title, fragment)| Section {
+                    title: title.to_string(),
+                    start_line: 0,
+                    fragment_id: fragment.map(str::to_string),
+                })
+                .collect(),
+            content_lines: Vec::new(),
+            file_path: html.to_string(),
+            href: href.to_string(),
+            document: crate::epub::document::build_document(html),
+        }
+    }
+
+    fn book() -> Book {
+        let chapters = (1..=10)
+            .map(|n| chapter(&format!("Part {}", n), "p.xhtml", "<p>one two</p>", &[]))
+            .collect::<Vec<_>>();
+        let mut book = Book {
+            metadata: BookMetadata {
+                title: "Test".to_string(),
+                author: Some("Ann Author".to_string()),
+                publisher: None,
+                publication_date: None,
+                language: Some("en".to_string()),
+                identifier: Some("urn:isbn:123".to_string()),
+            },
+            chapters,
+            images: Default::default(),
+        };
+        book.chapters[0] = chapter(
+            "Opening",
+            "text/ch1.xhtml",
+            "<h1>Opening</h1><p>Three more words</p>",
+            &[("First", Some("s1")), ("Second", None)],
+        );
+        book
+    }
+
+    #[test]
+    fn test_info_text_and_json() {
+        let book = book();
+        let details = BookDetails {
+            subjects: vec!["Fiction".to_string(), "Classics".to_string()],
+            description: Some("A story.".to_string()),
+            series: Some("Tests".to_string()),
+            series_index: Some("2".to_string()),
+            cover: true,
+        };
+        let info = BookInfo::new(&book, details);
+        assert_eq!(info.word_count, 4 + 9 * 2);
+
+        let text = info.to_text();
+        assert!(text.starts_with("Title:       Test\nAuthor:      Ann Author\nLanguage:    en\n"));
+        assert!(text.contains("Series:      Tests #2\nSubjects:    Fiction, Classics\n"));
+        assert!(text.contains("Spine items: 10\n"));
+        assert!(!text.contains("Publisher:"));
+        assert!(text.ends_with("Description: A story.\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&info.to_json().unwrap()).unwrap();
+        assert_eq!(json["title"], "Test");
+        assert_eq!(json["publisher"], serde_json::Value::Null);
+        assert_eq!(json["identifier"], "urn:isbn:123");
+        assert_eq!(json["subjects"][1], "Classics");
+        assert_eq!(json["cover"], true);
+        assert_eq!(json["spine_length"], 10);
+        assert_eq!(json["word_count"], 22);
+    }
+
+    #[test]
+    fn test_toc_text_and_json() {
+        let book = book();
+        let text = toc_text(&book);
+        assert!(text.starts_with(" 1. Opening\n      First\n      Second\n 2. Part 2\n"));
+        assert!(text.ends_with("10. Part 10\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&toc_json(&book).unwrap()).unwrap();
+        assert_eq!(json[0]["chapter"], 1);
+        assert_eq!(json[0]["href"], "text/ch1.xhtml");
+        assert_eq!(json[0]["sections"][0]["href"], "text/ch1.xhtml#s1");
+        assert_eq!(json[0]["sections"][1]["href"], "text/ch1.xhtml");
+        assert_eq!(json[9]["title"], "Part 10");
+    }
+}
@@ -0,0 +1,167 @@
+//! Jump list for back/forward navigation history
+//!
+//! Works like vim's jump list: the position before every jump (TOC, bookmark,
+//! search, chapter/section and link navigation) is recorded, and the reader
+//! can walk backward and forward through those positions. Plain scrolling is
+//! never recorded.
+
+use crate::constants::MAX_JUMP_LIST_SIZE;
+use crate::types::Location;
+
+/// Recorded jump origins and the current position within them
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct JumpList {
+    entries: Vec<Location>,
+    /// Index of the entry we are at; equal to `entries.len()` when not walking the list
+    index: usize,
+}
+
+impl JumpList {
+    /// Record the position a jump is being made from
+    ///
+    /// Any positions ahead of the current one (left by walking backward)
+    /// are dropped, like the forward history of a web browser.
+    pub fn push(&mut self, origin: Location) {
+        self.entries.truncate(self.index);
+        if !self
+            .entries
+            .last()
+            .is_some_and(|last| last.same_place(&origin))
+        {
+            self.entries.push(origin);
+        }
+        if self.entries.len() > MAX_JUMP_LIST_SIZE {
+            let excess = self.entries.len() - MAX_JUMP_LIST_SIZE;
+            self.entries.drain(..excess);
+        }
+        self.index = self.entries.len();
+    }
+
+    /// Step back to the previous recorded position
+    ///
+    /// # Arguments
+    /// * `current` - Position the reader is at now, kept so `forward` can return to it
+    ///
+    /// # Returns
+    /// The position to jump to, or None at the start of the list
+    pub fn back(&mut self, current: Location) -> Option<Location> {
+        if self.index == 0 || self.entries.is_empty() {
+            return None;
+        }
+
+        // Leaving the newest position: remember it so we can come forward again
+        if self.index >= self.entries.len() {
+            if self
+                .entries
+                .last()
+                .is_some_and(|last| last.same_place(&current))
+            {
+                self.index = self.entries.len() - 1;
+                if self.index == 0 {
+                    return None;
+                }
+            } else {
+                self.entries.push(current);
+                self.index = self.entries.len() - 1;
+            }
+        }
+
+        self.index -= 1;
+        self.entries.get(self.index).cloned()
+    }
+
+    /// Step forward again after stepping back
+    ///
+    /// # Returns
+    /// The position to jump to, or None at the end of the list
+    pub fn forward(&mut self) -> Option<Location> {
+        if self.index + 1 >= self.entries.len() {
+            return None;
+        }
+
+        self.index += 1;
+        self.entries.get(self.index).cloned()
+    }
+
+    /// Number of recorded positions
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether no positions have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(chapter_idx: usize, line: usize) -> Location {
+        Location {
+            chapter_idx,
+            line,
+            anchor: None,
+        }
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut list = JumpList::default();
+        list.push(loc(0, 10));
+        list.push(loc(2, 0));
+
+        // Currently at chapter 5
+        assert_eq!(list.back(loc(5, 3)), Some(loc(2, 0)));
+        assert_eq!(list.back(loc(2, 0)), Some(loc(0, 10)));
+        assert_eq!(list.back(loc(0, 10)), None);
+        assert_eq!(list.forward(), Some(loc(2, 0)));
+        assert_eq!(list.forward(), Some(loc(5, 3)));
+        assert_eq!(list.forward(), None);
+    }
+
+    #[test]
+    fn test_push_drops_forward_history() {
+        let mut list = JumpList::default();
+        list.push(loc(0, 0));
+        list.push(loc(1, 0));
+        assert_eq!(list.back(loc(2, 0)), Some(loc(1, 0)));
+
+        // Jumping somewhere new from here forgets chapter 2
+        list.push(loc(1, 0));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.forward(), None);
+        assert_eq!(list.back(loc(3, 0)), Some(loc(1, 0)));
+    }
+
+    #[test]
+    fn test_consecutive_duplicates_collapse() {
+        let mut list = JumpList::default();
+        list.push(loc(0, 5));
+        list.push(loc(0, 5));
+        assert_eq!(list.len(), 1);
+
+        // Going back from the recorded position itself has nowhere to go
+        assert_eq!(list.back(loc(0, 5)), None);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let mut list = JumpList::default();
+        for line in 0..MAX_JUMP_LIST_SIZE + 10 {
+            list.push(loc(0, line));
+        }
+        assert_eq!(list.len(), MAX_JUMP_LIST_SIZE);
+        assert_eq!(list.back(loc(1, 0)), Some(loc(0, MAX_JUMP_LIST_SIZE + 9)));
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut list = JumpList::default();
+        list.push(loc(3, 42));
+        let json = serde_json::to_string(&list).unwrap();
+        let mut loaded: JumpList = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.back(loc(0, 0)), Some(loc(3, 42)));
+    }
+}
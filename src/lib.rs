@@ -1,13 +1,28 @@
 // Library exports
+pub mod annotations;
 pub mod app;
 pub mod async_tasks;
 pub mod bookmarks;
 pub mod cli;
+pub mod clipboard;
+pub mod collections;
 pub mod constants;
+pub mod dump;
 pub mod epub;
 pub mod error;
+pub mod export;
+pub mod grep;
+pub mod images;
+pub mod import;
+pub mod info;
+pub mod jump_list;
+pub mod library;
+pub mod links;
 pub mod persistence;
+#[cfg(unix)]
+pub mod remote;
 pub mod search;
+pub mod state_file;
 pub mod toc;
 pub mod types;
 pub mod ui;
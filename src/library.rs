@@ -0,0 +1,555 @@
+//! Library of EPUB files found in the configured directories
+//!
+//! A scan walks the library directories (plus the recently opened books) for
+//! `.epub` files and reads their metadata. Entries whose file size and
+//! modification time are unchanged are reused from the previous index, so a
+//! rescan only opens new or changed books. The index is kept in
+//! `library.json` by [`PersistenceManager`].
+//!
+//! The library view lists the entries in one of the [`LibrarySort`] orders,
+//! narrowed by a fuzzy filter on title, author and path and by the books'
+//! tags and reading status.
+
+use crate::collections::BookCollections;
+use crate::epub;
+use crate::persistence::{PersistenceManager, ReadingProgress};
+use crate::types::{BookMetadata, LibrarySort};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Directories deeper than this below a library directory are not scanned
+const MAX_SCAN_DEPTH: usize = 16;
+
+/// A book in the library index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    /// Canonical path of the EPUB file
+    pub path: String,
+    /// Identity the book's state is saved under (empty until scanned)
+    pub book_id: String,
+    pub metadata: BookMetadata,
+    /// Number of chapters, to show progress against
+    pub chapters: usize,
+    /// Reading position when the index was last refreshed
+    #[serde(default)]
+    pub progress: Option<EntryProgress>,
+    size: u64,
+    modified: Option<u64>,
+}
+
+/// Reading position summary of a library entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryProgress {
+    pub chapter_idx: usize,
+    pub last_read: DateTime<Utc>,
+    /// Share of the book read, if it was saved with the position
+    #[serde(default)]
+    pub percent: Option<u8>,
+}
+
+impl LibraryEntry {
+    /// Placeholder for a known book that has not been scanned yet
+    pub fn unscanned(path: &str) -> Self {
+        let title = Path::new(path)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or(path)
+            .to_string();
+        LibraryEntry {
+            path: path.to_string(),
+            book_id: String::new(),
+            metadata: BookMetadata {
+                title,
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: 0,
+            progress: None,
+            size: 0,
+            modified: None,
+        }
+    }
+
+    /// Share of the book read, in percent
+    ///
+    /// Positions saved without a percentage are estimated from the chapter.
+    pub fn percent_read(&self) -> Option<u8> {
+        let progress = self.progress.as_ref()?;
+        progress.percent.or_else(|| {
+            (self.chapters > 0).then(|| (progress.chapter_idx * 100 / self.chapters) as u8)
+        })
+    }
+
+    /// When the book was last read, relative to `now` ("3d ago")
+    pub fn last_read_label(&self, now: DateTime<Utc>) -> Option<String> {
+        let last_read = self.progress.as_ref()?.last_read;
+        let elapsed = now.signed_duration_since(last_read);
+        Some(if elapsed.num_minutes() < 1 {
+            "just now".to_string()
+        } else if elapsed.num_hours() < 1 {
+            format!("{}m ago", elapsed.num_minutes())
+        } else if elapsed.num_days() < 1 {
+            format!("{}h ago", elapsed.num_hours())
+        } else if elapsed.num_days() < 30 {
+            format!("{}d ago", elapsed.num_days())
+        } else {
+            last_read
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d")
+                .to_string()
+        })
+    }
+}
+
+/// Utilities for building and ordering the library index
+pub struct LibraryManager;
+
+impl LibraryManager {
+    /// Expand the configured library directories
+    ///
+    /// A leading `~` is replaced with the home directory.
+    pub fn library_dirs(configured: &[String]) -> Vec<PathBuf> {
+        let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        configured
+            .iter()
+            .map(|dir| match (dir.strip_prefix('~'), &home) {
+                (Some(rest), Some(home)) => home.join(rest.trim_start_matches(['/', '\\'])),
+                _ => PathBuf::from(dir),
+            })
+            .collect()
+    }
+
+    /// Whether a book lies inside one of the library directories
+    pub fn in_library_dirs(path: &str, dirs: &[PathBuf]) -> bool {
+        dirs.iter()
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .any(|dir| Path::new(path).starts_with(dir))
+    }
+
+    /// The EPUB files below a directory, sorted by canonical path
+    ///
+    /// Hidden files and directories are skipped, as in library scans.
+    pub fn find_books(dir: &Path) -> Vec<String> {
+        let mut found = BTreeSet::new();
+        find_epubs(dir, 0, &mut found);
+        found.into_iter().collect()
+    }
+
+    /// Scan for books, reusing unchanged entries of the previous index
+    ///
+    /// # Arguments
+    /// * `persistence` - Used to identify new books by content
+    /// * `dirs` - Directories searched recursively for `.epub` files
+    /// * `extra_paths` - Further books to include (such as recent books)
+    /// * `previous` - The index from the last scan
+    ///
+    /// # Returns
+    /// Entries for every readable book found, ordered by path
+    pub fn scan(
+        persistence: &PersistenceManager,
+        dirs: &[PathBuf],
+        extra_paths: &[String],
+        previous: &[LibraryEntry],
+    ) -> Vec<LibraryEntry> {
+        let mut paths = BTreeSet::new();
+        for dir in dirs {
+            if dir.is_dir() {
+                find_epubs(dir, 0, &mut paths);
+            } else {
+                log::warn!("Library directory not found: {}", dir.display());
+            }
+        }
+        paths.extend(
+            extra_paths
+                .iter()
+                .filter_map(|path| fs::canonicalize(path).ok())
+                .filter_map(|path| path.to_str().map(str::to_string)),
+        );
+
+        let previous: HashMap<&str, &LibraryEntry> = previous
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+
+        let mut reused = 0;
+        let entries: Vec<LibraryEntry> = paths
+            .iter()
+            .filter_map(|path| {
+                let (size, modified) = file_stamp(Path::new(path))?;
+                if let Some(entry) = previous.get(path.as_str())
+                    && entry.size == size
+                    && entry.modified == modified
+                    && !entry.book_id.is_empty()
+                {
+                    reused += 1;
+                    return Some((*entry).clone());
+                }
+                index_book(persistence, path, size, modified)
+            })
+            .collect();
+        log::info!(
+            "Library scan found {} books ({} unchanged)",
+            entries.len(),
+            reused
+        );
+        entries
+    }
+
+    /// Add placeholders for books not in the index yet
+    pub fn add_unscanned(entries: &mut Vec<LibraryEntry>, paths: &[String]) {
+        for path in paths {
+            if !entries.iter().any(|entry| &entry.path == path) {
+                entries.push(LibraryEntry::unscanned(path));
+            }
+        }
+    }
+
+    /// Refresh the progress of every entry from the saved reading progress
+    pub fn apply_progress(
+        entries: &mut [LibraryEntry],
+        progress: &HashMap<String, ReadingProgress>,
+    ) {
+        for entry in entries.iter_mut() {
+            entry.progress = progress.get(&entry.book_id).map(|progress| EntryProgress {
+                chapter_idx: progress.chapter_idx,
+                last_read: progress.last_read,
+                percent: progress.percent,
+            });
+        }
+    }
+
+    /// The entries shown in the library view
+    ///
+    /// Without a filter every entry is listed in the chosen order. With a
+    /// filter, only entries matching each of its words are listed: `#tag`
+    /// and `@status` words select by tag and reading status, other words
+    /// match the title, author or path fuzzily and list the best matches first.
+    ///
+    /// # Arguments
+    /// * `entries` - The library
+    /// * `collections` - Reading status and tags by book identity
+    /// * `filter` - Filter typed by the user
+    /// * `sort` - Order of the entries
+    ///
+    /// # Returns
+    /// Indices into `entries`, in display order
+    pub fn view(
+        entries: &[LibraryEntry],
+        collections: &HashMap<String, BookCollections>,
+        filter: &str,
+        sort: LibrarySort,
+    ) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..entries.len()).collect();
+        indices.sort_by(|&a, &b| Self::compare(&entries[a], &entries[b], sort));
+
+        let (collection_words, words): (Vec<String>, Vec<String>) = filter
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .partition(|word| word.starts_with(['#', '@']));
+        if !collection_words.is_empty() {
+            let no_collections = BookCollections::default();
+            indices.retain(|&idx| {
+                let book = collections
+                    .get(&entries[idx].book_id)
+                    .unwrap_or(&no_collections);
+                collection_words.iter().all(|word| book.matches(word))
+            });
+        }
+        if words.is_empty() {
+            return indices;
+        }
+
+        let mut matches: Vec<(usize, i64)> = indices
+            .into_iter()
+            .filter_map(|idx| match_score(&entries[idx], &words).map(|score| (idx, score)))
+            .collect();
+        // Stable, so equal matches keep the chosen order
+        matches.sort_by_key(|&(_, score)| Reverse(score));
+        matches.into_iter().map(|(idx, _)| idx).collect()
+    }
+
+    fn compare(a: &LibraryEntry, b: &LibraryEntry, sort: LibrarySort) -> std::cmp::Ordering {
+        let title = |entry: &LibraryEntry| entry.metadata.title.trim().to_lowercase();
+        let author = |entry: &LibraryEntry| {
+            // Books without an author go last
+            entry
+                .metadata
+                .author
+                .as_ref()
+                .map(|author| author.trim().to_lowercase())
+                .map_or((1, String::new()), |author| (0, author))
+        };
+        let last_read = |entry: &LibraryEntry| entry.progress.as_ref().map(|p| p.last_read);
+
+        let order = match sort {
+            LibrarySort::Recent => last_read(b).cmp(&last_read(a)),
+            LibrarySort::Title => std::cmp::Ordering::Equal,
+            LibrarySort::Author => author(a).cmp(&author(b)),
+            LibrarySort::Progress => b.percent_read().cmp(&a.percent_read()),
+        };
+        order.then_with(|| title(a).cmp(&title(b)))
+    }
+}
+
+/// Score of an entry for a filter; every word must match a field
+fn match_score(entry: &LibraryEntry, words: &[String]) -> Option<i64> {
+    let fields = [
+        entry.metadata.title.to_lowercase(),
+        entry
+            .metadata
+            .author
+            .as_deref()
+            .unwrap_or_default()
+            .to_lowercase(),
+        entry.path.to_lowercase(),
+    ];
+    words.iter().try_fold(0, |total, word| {
+        // Title and author matches rank above matches in the path
+        let best = fields
+            .iter()
+            .enumerate()
+            .filter_map(|(field, text)| {
+                fuzzy_score(word, text).map(|score| if field < 2 { score * 2 } else { score })
+            })
+            .max()?;
+        Some(total + best)
+    })
+}
+
+/// Fuzzy match of a lowercase pattern against a lowercase text
+///
+/// The characters of `pattern` must appear in `text` in order. Consecutive
+/// characters and characters at the start of a word score higher; gaps
+/// between them lower the score.
+///
+/// # Returns
+/// The score, or `None` if `text` does not contain the pattern's characters
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+
+    for c in pattern.chars() {
+        let found = position + text[position..].iter().position(|&t| t == c)?;
+        score += 1;
+        match previous {
+            Some(prev) if found == prev + 1 => score += 5,
+            Some(prev) => score -= (found - prev - 1).min(5) as i64,
+            None => {}
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+fn find_epubs(dir: &Path, depth: usize, found: &mut BTreeSet<String>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        log::warn!("Cannot read library directory {}", dir.display());
+        return;
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        // Directory symlinks are not followed, so links cannot form loops
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if depth < MAX_SCAN_DEPTH {
+                find_epubs(&path, depth + 1, found);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"))
+            && path.is_file()
+            && let Some(path) = fs::canonicalize(&path)
+                .ok()
+                .and_then(|path| path.to_str().map(str::to_string))
+        {
+            found.insert(path);
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, Option<u64>)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as u64);
+    Some((metadata.len(), modified))
+}
+
+fn index_book(
+    persistence: &PersistenceManager,
+    path: &str,
+    size: u64,
+    modified: Option<u64>,
+) -> Option<LibraryEntry> {
+    let (metadata, chapters) = epub::read_metadata(path)
+        .map_err(|e| log::warn!("Skipping {} in library: {}", path, e))
+        .ok()?;
+    let identity = persistence
+        .book_identity(path, metadata.identifier.as_deref())
+        .map_err(|e| log::warn!("Skipping {} in library: {}", path, e))
+        .ok()?;
+
+    Some(LibraryEntry {
+        path: path.to_string(),
+        book_id: identity.id,
+        metadata,
+        chapters,
+        progress: None,
+        size,
+        modified,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, author: Option<&str>, last_read: Option<i64>) -> LibraryEntry {
+        let mut entry = LibraryEntry::unscanned(&format!("/books/{}.epub", title));
+        entry.metadata.author = author.map(str::to_string);
+        entry.chapters = 10;
+        entry.progress = last_read.map(|seconds| EntryProgress {
+            chapter_idx: seconds as usize / 100,
+            last_read: DateTime::from_timestamp(seconds, 0).unwrap(),
+            percent: None,
+        });
+        entry
+    }
+
+    fn titles(entries: &[LibraryEntry], view: Vec<usize>) -> Vec<&str> {
+        view.into_iter()
+            .map(|idx| entries[idx].metadata.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_view_orders() {
+        let entries = vec![
+            entry("zebra", Some("Kafka"), None),
+            entry("Apple", None, None),
+            entry("old", Some("austen"), Some(500)),
+            entry("new", Some("Borges"), Some(200)),
+        ];
+        let view = |sort| {
+            titles(
+                &entries,
+                LibraryManager::view(&entries, &HashMap::new(), "", sort),
+            )
+        };
+
+        assert_eq!(view(LibrarySort::Recent), ["old", "new", "Apple", "zebra"]);
+        assert_eq!(view(LibrarySort::Title), ["Apple", "new", "old", "zebra"]);
+        assert_eq!(view(LibrarySort::Author), ["old", "new", "zebra", "Apple"]);
+        assert_eq!(
+            view(LibrarySort::Progress),
+            ["old", "new", "Apple", "zebra"]
+        );
+
+        assert_eq!(entries[2].percent_read(), Some(50));
+        assert_eq!(entries[0].percent_read(), None);
+        let now = DateTime::from_timestamp(500 + 3 * 3600, 0).unwrap();
+        assert_eq!(entries[2].last_read_label(now).as_deref(), Some("3h ago"));
+    }
+
+    #[test]
+    fn test_fuzzy_filter() {
+        let mut entries = vec![
+            entry("The Metamorphosis", Some("Franz Kafka"), None),
+            entry("Moby Dick", Some("Herman Melville"), None),
+            entry("Meditations", Some("Marcus Aurelius"), None),
+        ];
+        entries[2].path = "/books/kafka-notes/meditations.epub".to_string();
+        let filter = |query| {
+            titles(
+                &entries,
+                LibraryManager::view(&entries, &HashMap::new(), query, LibrarySort::Title),
+            )
+        };
+
+        assert_eq!(filter("meta"), ["The Metamorphosis", "Meditations"]);
+        assert_eq!(filter("mdk"), ["Moby Dick"]);
+        // Author matches rank above path matches
+        assert_eq!(filter("kafka"), ["The Metamorphosis", "Meditations"]);
+        // Every word has to match
+        assert_eq!(filter("kafka med"), ["Meditations"]);
+        assert!(filter("xyz").is_empty());
+
+        // Tag and status words select by collections
+        let mut collections = HashMap::new();
+        for (idx, tag) in [(0, "classics"), (2, "philosophy")] {
+            entries[idx].book_id = format!("book-{}", idx);
+            collections.insert(
+                entries[idx].book_id.clone(),
+                BookCollections {
+                    status: Some(crate::collections::ReadingStatus::Reading),
+                    tags: vec![tag.to_string()],
+                },
+            );
+        }
+        let view = LibraryManager::view(&entries, &collections, "#phil @read", LibrarySort::Title);
+        assert_eq!(titles(&entries, view), ["Meditations"]);
+        let view =
+            LibraryManager::view(&entries, &collections, "@reading kafka", LibrarySort::Title);
+        assert_eq!(titles(&entries, view), ["The Metamorphosis", "Meditations"]);
+
+        assert!(fuzzy_score("ab", "a_b").unwrap() < fuzzy_score("ab", "abc").unwrap());
+        assert_eq!(fuzzy_score("ba", "ab"), None);
+    }
+
+    #[test]
+    fn test_find_epubs_skips_hidden_and_other_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let nested = temp.path().join("fiction/classics");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(temp.path().join(".trash")).unwrap();
+        for file in [
+            "a.epub",
+            "fiction/B.EPUB",
+            "fiction/classics/c.epub",
+            "notes.txt",
+            ".trash/d.epub",
+        ] {
+            fs::write(temp.path().join(file), b"").unwrap();
+        }
+
+        let mut found = BTreeSet::new();
+        find_epubs(temp.path(), 0, &mut found);
+        let names: Vec<String> = found
+            .iter()
+            .map(|path| {
+                Path::new(path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names.len(), 3);
+        assert!(names.contains(&"B.EPUB".to_string()));
+        assert!(!names.contains(&"d.epub".to_string()));
+    }
+}
@@ -0,0 +1,376 @@
+//! Internal hyperlink navigation
+//!
+//! This module resolves link targets found in chapter content to positions
+//! in the book, and generates the labels used by link-hint mode.
+
+use crate::constants::LINK_HINT_CHARS;
+use crate::epub::document::note_for_id;
+use crate::types::{Book, Chapter, Footnote, InlineStyle, LinkHint, LinkTarget};
+
+/// Utilities for following hyperlinks
+pub struct LinkNavigator;
+
+impl LinkNavigator {
+    /// Resolve a link target relative to the chapter that contains it
+    ///
+    /// # Arguments
+    /// * `book` - Book containing the link
+    /// * `from_chapter` - Index of the chapter the link appears in
+    /// * `href` - Raw link target (e.g. `ch02.xhtml#sec-4-2`, `#note1`)
+    ///
+    /// # Returns
+    /// * `Ok(LinkTarget)` - Position inside the book, or an external URL
+    /// * `Err(String)` - Target file or fragment not found in the book
+    pub fn resolve(book: &Book, from_chapter: usize, href: &str) -> Result<LinkTarget, String> {
+        if Self::is_external(href) {
+            return Ok(LinkTarget::External(href.to_string()));
+        }
+
+        let (chapter_idx, fragment) = Self::locate(book, from_chapter, href)?;
+
+        let anchor = match fragment.as_deref() {
+            Some(id) => {
+                let chapter = &book.chapters[chapter_idx];
+                let anchor = chapter
+                    .document
+                    .anchor_for_id(id)
+                    .ok_or_else(|| format!("Link anchor not found: #{}", id))?;
+                Some(anchor)
+            }
+            None => None,
+        };
+
+        log::debug!(
+            "Resolved link '{}' to chapter {} ({:?})",
+            href,
+            chapter_idx,
+            anchor
+        );
+        Ok(LinkTarget::Internal {
+            chapter_idx,
+            anchor,
+        })
+    }
+
+    /// Look up the footnote or endnote a link points to
+    ///
+    /// Targets marked up as notes are always found. Other targets (e.g. a
+    /// plain endnote list item) are only treated as notes when the link
+    /// itself is a note reference.
+    ///
+    /// # Arguments
+    /// * `book` - Book containing the link
+    /// * `from_chapter` - Index of the chapter the link appears in
+    /// * `href` - Raw link target
+    /// * `is_noteref` - Whether the link is marked as a note reference
+    ///
+    /// # Returns
+    /// The note's content, or `None` if the link does not lead to a note
+    pub fn find_note(
+        book: &Book,
+        from_chapter: usize,
+        href: &str,
+        is_noteref: bool,
+    ) -> Option<Footnote> {
+        if Self::is_external(href) {
+            return None;
+        }
+
+        let (chapter_idx, fragment) = Self::locate(book, from_chapter, href).ok()?;
+        let id = fragment?;
+        let chapter = &book.chapters[chapter_idx];
+
+        let paragraphs = match chapter.document.notes.get(&id) {
+            Some(paragraphs) => paragraphs.clone(),
+            None if is_noteref => note_for_id(&chapter.file_path, &id)?,
+            None => return None,
+        };
+
+        log::debug!("Found note '{}' in chapter {}", id, chapter_idx);
+        Some(Footnote {
+            chapter_title: chapter.title.clone(),
+            paragraphs,
+        })
+    }
+
+    /// Collect the links on a range of rendered lines and label them
+    ///
+    /// # Arguments
+    /// * `chapter` - Chapter being displayed
+    /// * `start` - First visible line
+    /// * `end` - One past the last visible line
+    ///
+    /// # Returns
+    /// Hints in reading order, each with a unique label
+    pub fn visible_hints(chapter: &Chapter, start: usize, end: usize) -> Vec<LinkHint> {
+        let end = end.min(chapter.content_lines.len());
+        let links: Vec<_> = chapter
+            .content_lines
+            .get(start..end)
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+            .flat_map(|(offset, line)| {
+                line.links.iter().map(move |(column, end, href)| {
+                    let note = line.inline_styles.iter().any(|(s, e, style)| {
+                        *style == InlineStyle::NoteRef && s < end && e > column
+                    });
+                    (start + offset, *column, href.clone(), note)
+                })
+            })
+            .collect();
+
+        Self::hint_labels(links.len())
+            .into_iter()
+            .zip(links)
+            .map(|(label, (line, column, href, note))| LinkHint {
+                label,
+                line,
+                column,
+                href,
+                note,
+            })
+            .collect()
+    }
+
+    /// Generate `count` labels of equal length from the hint alphabet
+    ///
+    /// Equal lengths mean no label is a prefix of another, so a label can be
+    /// followed as soon as it has been typed completely.
+    pub fn hint_labels(count: usize) -> Vec<String> {
+        let chars: Vec<char> = LINK_HINT_CHARS.chars().collect();
+        let mut length = 1;
+        while chars.len().pow(length) < count {
+            length += 1;
+        }
+
+        (0..count)
+            .map(|mut n| {
+                let mut label = vec![chars[0]; length as usize];
+                for slot in label.iter_mut().rev() {
+                    *slot = chars[n % chars.len()];
+                    n /= chars.len();
+                }
+                label.into_iter().collect()
+            })
+            .collect()
+    }
+
+    /// Find the chapter and fragment id a link target points to
+    fn locate(
+        book: &Book,
+        from_chapter: usize,
+        href: &str,
+    ) -> Result<(usize, Option<String>), String> {
+        let (path, fragment) = match href.split_once('#') {
+            Some((path, fragment)) => (path, Some(percent_decode(fragment))),
+            None => (href, None),
+        };
+
+        let chapter_idx = if path.is_empty() {
+            from_chapter
+        } else {
+            let base = book
+                .chapters
+                .get(from_chapter)
+                .map(|ch| ch.href.as_str())
+                .unwrap_or("");
+            let target = join_path(base, &percent_decode(path));
+            book.chapters
+                .iter()
+                .position(|ch| ch.href == target)
+                .ok_or_else(|| format!("Link target not in book: {}", path))?
+        };
+
+        Ok((chapter_idx, fragment.filter(|id| !id.is_empty())))
+    }
+
+    /// Check whether a link leaves the book (has a URL scheme)
+    fn is_external(href: &str) -> bool {
+        let before_path = href.split(['/', '#', '?']).next().unwrap_or("");
+        before_path.contains(':')
+    }
+}
+
+/// Resolve a relative path against the file it appears in
+pub(crate) fn join_path(base_file: &str, relative: &str) -> String {
+    let mut parts: Vec<&str> = base_file.split('/').collect();
+    parts.pop(); // Drop the file name, keep its directory
+
+    for part in relative.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// Decode `%XX` escapes in a link target
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = text.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+    use crate::types::BookMetadata;
+
+    fn create_test_book() -> Book {
+        let chapters = [
+            (
+                "OEBPS/text/ch1.xhtml",
+                r##"<p>See <a href="ch2.xhtml#sec%202">section two</a>, <a href="#note">the note</a>
+                    or <a href="https://example.com">the web</a>.</p>
+                    <p id="note">A note.</p>
+                    <p>Noted<a epub:type="noteref" href="notes.xhtml#en1">1</a>.</p>"##,
+            ),
+            (
+                "OEBPS/text/ch2.xhtml",
+                r#"<h1>Two</h1><p>Intro</p><h2 id="sec 2">Section two</h2><p>Body</p>"#,
+            ),
+            (
+                "OEBPS/text/notes.xhtml",
+                r#"<h1>Notes</h1><ol><li id="en1"><p>The endnote.</p></li></ol>
+                    <aside epub:type="footnote" id="fn"><p>Typed note.</p></aside>"#,
+            ),
+        ];
+
+        let mut chapters: Vec<Chapter> = chapters
+            .iter()
+            .enumerate()
+            .map(|(idx, (href, html))| Chapter {
+                title: format!("Chapter {}", idx + 1),
+                sections: Vec::new(),
+                content_lines: Vec::new(),
+                file_path: html.to_string(),
+                href: href.to_string(),
+                document: crate::epub::document::build_document(html),
+            })
+            .collect();
+        for chapter in &mut chapters {
+            render_chapter(chapter, Some(80), 100, false);
+        }
+
+        Book {
+            metadata: BookMetadata {
+                title: "Links".to_string(),
+                author: None,
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters,
+            images: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_other_chapter_fragment() {
+        let book = create_test_book();
+        match LinkNavigator::resolve(&book, 0, "ch2.xhtml#sec%202").unwrap() {
+            LinkTarget::Internal {
+                chapter_idx,
+                anchor: Some(anchor),
+            } => {
+                assert_eq!(chapter_idx, 1);
+                let line = book.chapters[1].line_for_anchor(&anchor);
+                assert_eq!(book.chapters[1].content_lines[line].text, "Section two");
+            }
+            other => panic!("Unexpected target: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_relative_paths_and_external() {
+        let book = create_test_book();
+        assert_eq!(
+            LinkNavigator::resolve(&book, 1, "../text/./ch1.xhtml").unwrap(),
+            LinkTarget::Internal {
+                chapter_idx: 0,
+                anchor: None
+            }
+        );
+        assert!(matches!(
+            LinkNavigator::resolve(&book, 0, "#note").unwrap(),
+            LinkTarget::Internal {
+                chapter_idx: 0,
+                anchor: Some(_)
+            }
+        ));
+        assert_eq!(
+            LinkNavigator::resolve(&book, 0, "mailto:a@b.c").unwrap(),
+            LinkTarget::External("mailto:a@b.c".to_string())
+        );
+        assert!(LinkNavigator::resolve(&book, 0, "missing.xhtml").is_err());
+        assert!(LinkNavigator::resolve(&book, 0, "#missing").is_err());
+    }
+
+    #[test]
+    fn test_visible_hints() {
+        let book = create_test_book();
+        let hints = LinkNavigator::visible_hints(&book.chapters[0], 0, 100);
+        let hrefs: Vec<_> = hints.iter().map(|h| h.href.as_str()).collect();
+        assert_eq!(
+            hrefs,
+            vec![
+                "ch2.xhtml#sec%202",
+                "#note",
+                "https://example.com",
+                "notes.xhtml#en1"
+            ]
+        );
+        assert_eq!(hints[0].label, "a");
+        assert!(!hints[1].note);
+        assert!(hints[3].note);
+        let line = &book.chapters[0].content_lines[hints[0].line];
+        assert!(line.text[hints[0].column..].starts_with("section two"));
+    }
+
+    #[test]
+    fn test_find_note() {
+        let book = create_test_book();
+        let note = LinkNavigator::find_note(&book, 0, "notes.xhtml#en1", true).unwrap();
+        assert_eq!(note.chapter_title, "Chapter 3");
+        assert_eq!(note.paragraphs, vec!["The endnote.".to_string()]);
+
+        // Untyped targets are only notes when referenced as one
+        assert!(LinkNavigator::find_note(&book, 0, "notes.xhtml#en1", false).is_none());
+        assert!(LinkNavigator::find_note(&book, 0, "notes.xhtml#fn", false).is_some());
+        assert!(LinkNavigator::find_note(&book, 0, "ch2.xhtml", true).is_none());
+        assert!(LinkNavigator::find_note(&book, 0, "https://example.com#a", true).is_none());
+    }
+
+    #[test]
+    fn test_hint_labels() {
+        assert_eq!(LinkNavigator::hint_labels(3), vec!["a", "s", "d"]);
+        let labels = LinkNavigator::hint_labels(10);
+        assert_eq!(labels.len(), 10);
+        assert_eq!(labels[0], "aa");
+        assert_eq!(labels[9], "sa");
+        assert!(LinkNavigator::hint_labels(0).is_empty());
+    }
+}
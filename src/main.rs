@@ -1,12 +1,27 @@
+mod annotations;
 mod app;
 mod async_tasks;
 mod bookmarks;
 mod cli;
+mod clipboard;
+mod collections;
 mod constants;
+mod dump;
 mod epub;
 mod error;
+mod export;
+mod grep;
+mod images;
+mod import;
+mod info;
+mod jump_list;
+mod library;
+mod links;
 mod persistence;
+#[cfg(unix)]
+mod remote;
 mod search;
+mod state_file;
 mod toc;
 mod types;
 mod ui;
@@ -14,18 +29,35 @@ mod ui;
 use app::AppState;
 use async_tasks::{AsyncTaskRunner, TaskMessage};
 use clap::Parser;
-use cli::Cli;
-use constants::{MIN_TERMINAL_HEIGHT, MIN_TERMINAL_WIDTH, RESIZE_DEBOUNCE_MS};
+#[cfg(unix)]
+use cli::RemoteCommand;
+use cli::{Cli, Command};
+use constants::{
+    DEFAULT_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT, MIN_TERMINAL_WIDTH, RESIZE_DEBOUNCE_MS,
+};
 use crossterm::{
     cursor::{Hide, Show},
-    event::{self, Event, KeyEventKind},
+    event::{
+        self, Event, KeyEventKind, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    terminal::{
+        EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+        supports_keyboard_enhancement,
+    },
 };
+use dump::{ChapterRange, DumpFormat};
 use error::{AppError, Result};
+use export::ExportFormat;
+use images::protocol::GraphicsProtocol;
+use info::BookInfo;
 use persistence::PersistenceManager;
 use ratatui::{Terminal, backend::CrosstermBackend};
-use std::io;
+#[cfg(unix)]
+use remote::{RemoteServer, Request};
+use search::SearchEngine;
+use std::io::{self, Write};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::mpsc;
@@ -63,6 +95,11 @@ async fn run() -> Result<()> {
         }
     }
 
+    // Subcommands print their result and exit without starting the reader
+    if let Some(command) = &cli.command {
+        return run_command(command);
+    }
+
     // Check terminal size
     let (width, height) = crossterm::terminal::size()?;
     if width < MIN_TERMINAL_WIDTH || height < MIN_TERMINAL_HEIGHT {
@@ -100,10 +137,328 @@ async fn run() -> Result<()> {
     result
 }
 
+/// Run a subcommand that works without the terminal UI
+fn run_command(command: &Command) -> Result<()> {
+    match command {
+        Command::Dump {
+            file,
+            format,
+            width,
+            chapters,
+        } => dump_book(file, format, *width, chapters.as_deref()),
+        Command::Export {
+            file,
+            format,
+            output,
+        } => export_bookmarks(file, format, output.as_deref()),
+        Command::Grep {
+            pattern,
+            paths,
+            context,
+            ignore_case,
+            fixed_strings,
+        } => {
+            // Like grep, the exit status tells matches, no matches and errors apart
+            let status = grep_books(pattern, paths, *context, *ignore_case, *fixed_strings)
+                .unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    GREP_ERROR
+                });
+            std::process::exit(status);
+        }
+        Command::Info { file, json } => print_info(file, *json),
+        Command::Toc { file, json } => print_toc(file, *json),
+        Command::Import { file, annotations } => import_annotations(file, annotations),
+        #[cfg(unix)]
+        Command::Remote { socket, command } => remote_command(socket.as_deref(), command),
+        #[cfg(not(unix))]
+        Command::Remote { .. } => Err(AppError::Other(REMOTE_UNSUPPORTED.to_string())),
+    }
+}
+
+/// Error for remote control on platforms without Unix domain sockets
+#[cfg(not(unix))]
+const REMOTE_UNSUPPORTED: &str = "Remote control is only supported on Unix";
+
+/// Send a command to a running reader and print what it answers
+#[cfg(unix)]
+fn remote_command(socket: Option<&std::path::Path>, command: &RemoteCommand) -> Result<()> {
+    let request = match command {
+        RemoteCommand::Open { file, position } => {
+            // The reader runs in another directory, so relative paths are resolved here
+            let canonical_path = persistence::canonicalize_path(file)
+                .map_err(|_| AppError::FileNotFound(file.to_string()))?;
+            Request::Open {
+                path: canonical_path,
+                at: position.position(),
+            }
+        }
+        RemoteCommand::Goto { position } => Request::Goto {
+            at: position
+                .position()
+                .ok_or_else(|| AppError::Other("No position given".to_string()))?,
+        },
+        RemoteCommand::Search { query } => Request::Search {
+            query: query.clone(),
+        },
+        RemoteCommand::NextChapter => Request::NextChapter,
+        RemoteCommand::PrevChapter => Request::PrevChapter,
+        RemoteCommand::GetPosition => Request::GetPosition,
+        RemoteCommand::GetSelection => Request::GetSelection,
+        RemoteCommand::Quit => Request::Quit,
+    };
+
+    let response = remote::send(socket, &request).map_err(AppError::Other)?;
+    if let Some(error) = response.error {
+        return Err(AppError::Other(error));
+    }
+    if let Some(position) = response.position {
+        let text =
+            serde_json::to_string_pretty(&position).map_err(|e| AppError::Other(e.to_string()))?;
+        write_stdout(&format!("{}\n", text))?;
+    }
+    if let Some(selection) = response.selection {
+        write_stdout(&format!("{}\n", selection))?;
+    }
+    if let Some(matches) = response.matches {
+        write_stdout(&format!("{} matches\n", matches))?;
+    }
+    Ok(())
+}
+
+/// Print the text of a book, or of a range of its chapters, to stdout
+fn dump_book(file: &str, format: &str, width: usize, chapters: Option<&str>) -> Result<()> {
+    let format = DumpFormat::parse(format).map_err(AppError::Other)?;
+    let canonical_path = persistence::canonicalize_path(file)
+        .map_err(|_| AppError::FileNotFound(file.to_string()))?;
+
+    let mut book = epub::parse_epub(&canonical_path)?;
+    let range = chapters
+        .map(ChapterRange::parse)
+        .transpose()
+        .and_then(|range| range.unwrap_or_default().indices(book.chapters.len()))
+        .map_err(AppError::Other)?;
+
+    write_stdout(&dump::dump_book(&mut book, range, format, width))
+}
+
+/// Exit status of `reef grep` when a book could not be searched
+const GREP_ERROR: i32 = 2;
+
+/// Search books for a pattern and print the matching lines
+///
+/// Books that cannot be read are reported on stderr and skipped.
+///
+/// # Returns
+/// The exit status: 0 when a book matched, 1 when none did, and
+/// `GREP_ERROR` when a book could not be read
+fn grep_books(
+    pattern: &str,
+    paths: &[String],
+    context: usize,
+    ignore_case: bool,
+    fixed_strings: bool,
+) -> Result<i32> {
+    let regex =
+        SearchEngine::compile(pattern, ignore_case, fixed_strings).map_err(AppError::Other)?;
+
+    let mut found = false;
+    let mut failed = false;
+    for path in grep::book_paths(paths) {
+        let mut book = match epub::parse_epub(&path) {
+            Ok(book) => book,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+                continue;
+            }
+        };
+        for chapter in &mut book.chapters {
+            epub::render_chapter(chapter, None, DEFAULT_TERMINAL_WIDTH, false);
+        }
+
+        let matches = grep::find_matches(&book, &regex);
+        if let Some(text) = grep::format_matches(&book, &path, &matches, context) {
+            // Books are separated by a blank line
+            if found {
+                write_stdout("\n")?;
+            }
+            write_stdout(&text)?;
+            found = true;
+        }
+    }
+
+    Ok(if failed {
+        GREP_ERROR
+    } else if found {
+        0
+    } else {
+        1
+    })
+}
+
+/// Print the metadata of a book, as text or JSON
+fn print_info(file: &str, json: bool) -> Result<()> {
+    let canonical_path = persistence::canonicalize_path(file)
+        .map_err(|_| AppError::FileNotFound(file.to_string()))?;
+    let book = epub::parse_epub(&canonical_path)?;
+    let info = BookInfo::new(&book, epub::read_details(&canonical_path)?);
+
+    if json {
+        let text = info.to_json().map_err(AppError::Other)?;
+        write_stdout(&format!("{}\n", text))
+    } else {
+        write_stdout(&info.to_text())
+    }
+}
+
+/// Print the chapters and sections of a book, as text or JSON
+fn print_toc(file: &str, json: bool) -> Result<()> {
+    let canonical_path = persistence::canonicalize_path(file)
+        .map_err(|_| AppError::FileNotFound(file.to_string()))?;
+    let book = epub::parse_epub(&canonical_path)?;
+
+    if json {
+        let text = info::toc_json(&book).map_err(AppError::Other)?;
+        write_stdout(&format!("{}\n", text))
+    } else {
+        write_stdout(&info::toc_text(&book))
+    }
+}
+
+/// Merge another reader's annotations into the saved state of a book
+fn import_annotations(file: &str, annotations: &str) -> Result<()> {
+    let data = import::read_file(std::path::Path::new(annotations)).map_err(AppError::Other)?;
+    let persistence = PersistenceManager::new()
+        .map_err(|e| AppError::Other(format!("Failed to initialize persistence: {}", e)))?;
+    let canonical_path = persistence::canonicalize_path(file)
+        .map_err(|_| AppError::FileNotFound(file.to_string()))?;
+
+    // Bookmarks store the line of their position, so every chapter is rendered
+    let mut book = epub::parse_epub(&canonical_path)?;
+    for chapter in &mut book.chapters {
+        epub::render_chapter(chapter, None, DEFAULT_TERMINAL_WIDTH, false);
+    }
+
+    let load_error = |e: anyhow::Error| AppError::Other(format!("Failed to load state: {}", e));
+    let identity = persistence
+        .book_identity(&canonical_path, book.metadata.identifier.as_deref())
+        .map_err(load_error)?;
+    let mut bookmarks = persistence
+        .load_bookmarks(&identity.id)
+        .map_err(load_error)?;
+    let mut highlights = persistence
+        .load_highlights(&identity.id)
+        .map_err(load_error)?;
+    let mut progress = persistence
+        .load_reading_progress()
+        .map_err(load_error)?
+        .remove(&identity.id);
+
+    let report = import::merge(&book, &data, &mut bookmarks, &mut highlights, &mut progress);
+
+    let save_error = |e: anyhow::Error| AppError::Other(format!("Failed to save state: {}", e));
+    persistence
+        .save_bookmarks(&identity.id, &bookmarks)
+        .map_err(save_error)?;
+    persistence
+        .save_highlights(&identity.id, &highlights)
+        .map_err(save_error)?;
+    if let Some(progress) = progress {
+        persistence
+            .update_reading_progress(|all_progress| {
+                all_progress.insert(identity.id, progress);
+            })
+            .map_err(save_error)?;
+    }
+
+    let mut summary = format!(
+        "Imported {} bookmarks and {} highlights ({} already present)\n",
+        report.bookmarks_added, report.highlights_added, report.duplicates
+    );
+    if let Some(chapter_idx) = report.position_chapter {
+        summary.push_str(&format!(
+            "Reading position moved to chapter {}\n",
+            chapter_idx + 1
+        ));
+    }
+    if !report.unplaced.is_empty() {
+        summary.push_str(&format!(
+            "Could not place {} entries:\n",
+            report.unplaced.len()
+        ));
+        for entry in &report.unplaced {
+            summary.push_str(&format!("  {}\n", entry));
+        }
+    }
+    write_stdout(&summary)
+}
+
+/// Write the saved bookmarks of a book to a file (or stdout for '-')
+fn export_bookmarks(file: &str, format: &str, output: Option<&str>) -> Result<()> {
+    let format = ExportFormat::parse(format).map_err(AppError::Other)?;
+    let persistence = PersistenceManager::new()
+        .map_err(|e| AppError::Other(format!("Failed to initialize persistence: {}", e)))?;
+    let canonical_path = persistence::canonicalize_path(file)
+        .map_err(|_| AppError::FileNotFound(file.to_string()))?;
+
+    let mut book = epub::parse_epub(&canonical_path)?;
+    let bookmarks = persistence
+        .book_identity(&canonical_path, book.metadata.identifier.as_deref())
+        .and_then(|identity| persistence.load_bookmarks(&identity.id))
+        .map_err(|e| AppError::Other(format!("Failed to load bookmarks: {}", e)))?;
+    if bookmarks.is_empty() {
+        return Err(AppError::Other(format!("No bookmarks saved for {}", file)));
+    }
+
+    // Context text comes from rendered lines; only bookmarked chapters need them
+    for bookmark in &bookmarks {
+        if let Some(chapter) = book.chapters.get_mut(bookmark.chapter_idx)
+            && chapter.content_lines.is_empty()
+        {
+            epub::render_chapter(chapter, None, DEFAULT_TERMINAL_WIDTH, false);
+        }
+    }
+
+    let text = export::export_bookmarks(&book, &bookmarks, format).map_err(AppError::Other)?;
+    match output {
+        Some("-") => write_stdout(&text),
+        _ => {
+            let path = output
+                .map(str::to_string)
+                .unwrap_or_else(|| export::default_file_name(&book, format));
+            std::fs::write(&path, text)?;
+            write_stdout(&format!(
+                "Exported {} bookmarks to {}\n",
+                bookmarks.len(),
+                path
+            ))
+        }
+    }
+}
+
+/// Write command output to stdout
+///
+/// A closed pipe (e.g. `reef export book.epub -o - | head`) is not an error.
+fn write_stdout(text: &str) -> Result<()> {
+    match io::stdout().lock().write_all(text.as_bytes()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
 fn setup_terminal() -> Result<()> {
     enable_raw_mode()?;
     execute!(io::stdout(), EnterAlternateScreen, Hide)?;
 
+    // Ask for unambiguous key codes so Ctrl-i and Tab can be told apart
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        execute!(
+            io::stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
+
     // Set panic hook to restore terminal
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
@@ -115,6 +470,9 @@ fn setup_terminal() -> Result<()> {
 }
 
 fn cleanup_terminal() -> Result<()> {
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        execute!(io::stdout(), PopKeyboardEnhancementFlags)?;
+    }
     execute!(io::stdout(), Show, LeaveAlternateScreen)?;
     disable_raw_mode()?;
     Ok(())
@@ -165,13 +523,16 @@ async fn run_app(cli: Cli, running: Arc<AtomicBool>) -> Result<()> {
     // Create task runner
     let task_runner = AsyncTaskRunner::new(task_tx.clone());
 
+    // Listen for `reef remote` commands; the socket is removed when dropped
+    let _remote_server = start_remote_server(&mut app, &task_tx);
+
     // Set task channel in app for async book loading
     app.set_task_channel(task_tx);
 
     // Create resize debouncer
     let resize_tx = task_runner.spawn_resize_debouncer(RESIZE_DEBOUNCE_MS);
 
-    // Load initial book or show picker
+    // Load initial book or show the library
     load_initial_book(&mut app, &cli, &task_runner)?;
 
     // Run main event loop
@@ -184,6 +545,32 @@ async fn run_app(cli: Cli, running: Arc<AtomicBool>) -> Result<()> {
     Ok(())
 }
 
+/// Start listening for `reef remote` commands if the config enables it
+#[cfg(unix)]
+fn start_remote_server(
+    app: &mut AppState,
+    task_tx: &mpsc::UnboundedSender<TaskMessage>,
+) -> Option<RemoteServer> {
+    if !app.config.remote_control {
+        return None;
+    }
+    RemoteServer::start(task_tx.clone())
+        .map_err(|e| log::warn!("Failed to start remote control: {}", e))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn start_remote_server(
+    app: &mut AppState,
+    _task_tx: &mpsc::UnboundedSender<TaskMessage>,
+) -> Option<()> {
+    if app.config.remote_control {
+        log::warn!("{}", REMOTE_UNSUPPORTED);
+        app.status_message = Some(REMOTE_UNSUPPORTED.to_string());
+    }
+    None
+}
+
 fn initialize_app_state(cli: &Cli) -> Result<AppState> {
     log::debug!("Initializing application state");
 
@@ -193,9 +580,10 @@ fn initialize_app_state(cli: &Cli) -> Result<AppState> {
         AppError::Other(format!("Failed to initialize persistence: {}", e))
     })?;
 
-    // Load config
+    // Load config; a damaged file is offered for recovery once the UI is up
+    let mut config_error = None;
     let config = persistence.load_config().unwrap_or_else(|e| {
-        log::warn!("Failed to load config: {}. Using defaults.", e);
+        config_error = Some(e);
         Config::default()
     });
     log::debug!(
@@ -206,6 +594,9 @@ fn initialize_app_state(cli: &Cli) -> Result<AppState> {
     );
 
     let mut app = AppState::new(config, persistence);
+    if let Some(e) = config_error {
+        app.report_state_error(e);
+    }
 
     // Set CLI max_width override (not persisted)
     if let Some(max_width) = cli.max_width {
@@ -213,6 +604,20 @@ fn initialize_app_state(cli: &Cli) -> Result<AppState> {
         log::debug!("CLI max width override applied: {}", max_width);
     }
 
+    // Force a graphics protocol instead of detecting one (not persisted)
+    if let Some(name) = &cli.images
+        && let Ok(Some(protocol)) = GraphicsProtocol::parse(name)
+    {
+        app.graphics.protocol = protocol;
+        log::debug!("CLI image protocol: {:?}", protocol);
+    }
+
+    // Open at a given position instead of the saved one (not persisted)
+    if let Some(position) = cli.position.position() {
+        log::debug!("CLI start position: {}", position);
+        app.start_position = Some(position);
+    }
+
     // Get terminal size and update viewport
     let (width, height) = crossterm::terminal::size()?;
     app.update_viewport_size(width, height);
@@ -233,29 +638,35 @@ fn load_initial_book(app: &mut AppState, cli: &Cli, task_runner: &AsyncTaskRunne
         let effective_width = app.effective_max_width();
         let viewport_width = app.viewport.width;
 
-        let (_handle, _join_handle) =
-            task_runner.spawn_load_epub(file_path.clone(), effective_width, viewport_width);
+        let (_handle, _join_handle) = task_runner.spawn_load_epub(
+            app.persistence.clone(),
+            file_path.clone(),
+            effective_width,
+            viewport_width,
+            app.config.wrap_code,
+        );
 
         app.loading_state = LoadingState::LoadingBook {
             file_path: file_path.clone(),
         };
     } else {
-        // No file provided - check if we have recent books
-        log::debug!("No file provided, checking recent books");
-        if app.recent_books.is_empty() {
-            log::error!("No recent books available");
+        // No file provided - show the library if there is one
+        log::debug!("No file provided, checking the library");
+        if !app.state_problems.is_empty() {
+            // The recovery popup opens the library once it is dealt with
+            log::debug!("Deferring library until saved data is recovered");
+            return Ok(());
+        }
+        if !app.has_library() {
+            log::error!("No books in the library");
             return Err(AppError::Other(
-                "No recent books. Usage: reef <file.epub>".to_string(),
+                "No recent books and no library_dirs configured. Usage: reef <file.epub>"
+                    .to_string(),
             ));
         }
 
-        log::debug!(
-            "Showing book picker with {} recent books",
-            app.recent_books.len()
-        );
-        // Show book picker
-        app.ui_mode = UiMode::BookPicker;
-        app.book_picker_selected_idx = Some(0);
+        log::debug!("Showing library with {} books", app.library.len());
+        app.open_library();
     }
 
     Ok(())
@@ -272,6 +683,7 @@ async fn run_event_loop(
     terminal.draw(|f| {
         ui::layout::render(f, app);
     })?;
+    present_images(terminal, app)?;
 
     // Flush any pending stdin to avoid picking up buffered input from shell
     // This prevents "cargo run" keypresses from being interpreted as app input
@@ -331,16 +743,52 @@ async fn run_event_loop(
                 handle_event(app, ev, resize_tx)?;
             }
         }
+        app.update_reading_status();
+        copy_to_clipboard(terminal, app);
 
         // Render UI after processing event
         terminal.draw(|f| {
             ui::layout::render(f, app);
         })?;
+        present_images(terminal, app)?;
     }
 
+    app.graphics.clear(terminal.backend_mut())?;
     Ok(())
 }
 
+/// Show the frame's images using the terminal's graphics protocol
+fn present_images(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut AppState,
+) -> Result<()> {
+    // Images that moved or disappeared can only be erased by a full redraw
+    if app.graphics.needs_clear() {
+        terminal.clear()?;
+        terminal.draw(|f| {
+            ui::layout::render(f, app);
+        })?;
+    }
+
+    app.graphics
+        .present(terminal.backend_mut(), &app.image_cache, app.book.as_ref())?;
+    Ok(())
+}
+
+/// Hand yanked text to the terminal clipboard (and the configured command)
+fn copy_to_clipboard(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut AppState) {
+    if let Some(text) = app.pending_clipboard.take()
+        && let Err(e) = clipboard::copy(
+            terminal.backend_mut(),
+            &text,
+            app.config.clipboard_command.as_deref(),
+        )
+    {
+        log::warn!("Failed to copy to clipboard: {}", e);
+        app.status_message = Some(format!("Copy failed: {}", e));
+    }
+}
+
 fn handle_task_message(app: &mut AppState, msg: TaskMessage) {
     match msg {
         TaskMessage::BookLoadingStarted { file_path } => {
@@ -353,15 +801,24 @@ fn handle_task_message(app: &mut AppState, msg: TaskMessage) {
             app.loading_state = LoadingState::RenderingChapters { rendered, total };
         }
 
-        TaskMessage::BookLoadingComplete { book, file_path } => {
+        TaskMessage::BookLoadingComplete {
+            book,
+            file_path,
+            identity,
+        } => {
             log::info!(
                 "Book loading complete: {} ({} chapters)",
                 book.metadata.title,
                 book.chapters.len()
             );
 
+            // Images of the previous book are no longer valid
+            if let Err(e) = app.graphics.clear(&mut io::stdout()) {
+                log::warn!("Failed to clear terminal images: {}", e);
+            }
+
             // Call finalize_book_load to set up book metadata
-            if let Err(e) = app.finalize_book_load(book, file_path) {
+            if let Err(e) = app.finalize_book_load(*book, file_path, identity) {
                 log::error!("Failed to finalize book load: {}", e);
                 app.ui_mode = UiMode::ErrorPopup(format!("Failed to load book: {}", e));
                 app.loading_state = LoadingState::Idle;
@@ -374,10 +831,22 @@ fn handle_task_message(app: &mut AppState, msg: TaskMessage) {
             app.loading_state = LoadingState::Idle;
         }
 
+        TaskMessage::LibraryScanComplete { entries } => {
+            log::info!("Library scan complete: {} books", entries.len());
+            app.finish_library_scan(entries);
+        }
+
         TaskMessage::ResizeComplete { width, height } => {
             log::info!("Resize complete: {}x{}", width, height);
             handle_resize_complete(app, width, height);
         }
+
+        #[cfg(unix)]
+        TaskMessage::RemoteRequest(call) => {
+            let response = remote::execute(app, call.request);
+            // The client may have given up waiting
+            let _ = call.reply.send(response);
+        }
     }
 }
 
@@ -389,6 +858,7 @@ fn handle_event(
     match ev {
         Event::Key(key) => {
             // Event is already filtered for Press in the reader task
+            app.status_message = None;
             ui::handle_key_event(app, key)?;
         }
         Event::Resize(width, height) => {
@@ -406,38 +876,9 @@ fn handle_event(
 fn handle_resize_complete(app: &mut AppState, width: u16, _height: u16) {
     log::info!("Handling resize complete: {}x{}", width, _height);
 
-    let effective_width = app.effective_max_width();
-    let viewport_width = width;
-    let has_search_results = !app.search_results.is_empty();
-    let search_query = app.search_query.clone();
-
-    if let Some(book) = &mut app.book {
-        log::debug!(
-            "Re-rendering {} chapters for new width",
-            book.chapters.len()
-        );
-
-        // Re-render all chapters
-        for chapter in &mut book.chapters {
-            epub::render_chapter(chapter, effective_width, viewport_width);
-        }
-
-        // Re-apply search highlights if there are active results
-        if has_search_results {
-            log::debug!("Re-applying search highlights after resize");
-
-            // Re-run search to recalculate match positions in new line structure
-            match search::SearchEngine::search(book, &search_query) {
-                Ok(new_results) => {
-                    app.search_results = new_results;
-                    search::SearchEngine::apply_highlights(book, &app.search_results);
-                }
-                Err(e) => {
-                    log::warn!("Failed to re-apply search after resize: {}", e);
-                }
-            }
-        }
-    }
+    // Viewport was already updated when the resize event arrived; re-render
+    // against it so positions are carried over via content anchors
+    app.rerender_chapters();
 
     log::debug!("Resize handling complete");
 }
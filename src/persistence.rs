@@ -1,23 +1,47 @@
 //! Persistent storage for reading progress, bookmarks, and configuration
 //!
 //! This module manages all file-based persistence using JSON files stored
-//! in the user's config directory. It handles:
-//! - Reading progress (chapter, line, scroll position) per book
+//! in the user's config directory. Per-book state is keyed by the book's
+//! identity (its unique identifier plus a hash of its contents) rather than
+//! its path, so moved, renamed or synced copies keep their state. It handles:
+//! - Reading progress (chapter, content anchor, scroll position) per book
 //! - User bookmarks with labels
+//! - Highlights and their notes, stored next to the bookmarks of each book
 //! - Application configuration (panel widths, max-width setting)
 //! - Recently opened books list
+//! - An index from file paths to book identities
+//! - The library index of books found in the configured directories
+//! - Reading status and tags per book
+//!
+//! Files are written atomically with a backup of the previous version and
+//! carry a `version` field; see [`crate::state_file`]. A damaged file is
+//! reported as a [`StateFileError`] rather than replaced with defaults.
+//!
+//! Several reef processes may share the config directory. Every write holds
+//! an advisory lock on `state.lock`, and the shared files (reading progress,
+//! recent books, collections) are updated read-merge-write under that lock,
+//! so each process only replaces the entries it owns.
 
+use crate::collections::BookCollections;
 use crate::constants::{
     MAX_BOOKMARKS_PANEL_WIDTH, MAX_TOC_PANEL_WIDTH, MIN_BOOKMARKS_PANEL_WIDTH, MIN_TOC_PANEL_WIDTH,
 };
-use crate::types::{Bookmark, Config};
+use crate::jump_list::JumpList;
+use crate::library::LibraryEntry;
+use crate::state_file::{self, Migration, UnreadableFile};
+use crate::types::{Bookmark, Config, ContentAnchor, Highlight};
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 /// Reading position and state for a specific book
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,9 +51,134 @@ pub struct ReadingProgress {
     pub scroll_offset: usize,
     pub last_read: DateTime<Utc>,
     pub toc_expansion_state: Vec<String>,
+    /// Width-independent cursor position; `line` is only a fallback for old files
+    #[serde(default)]
+    pub anchor: Option<ContentAnchor>,
+    /// Back/forward navigation history
+    #[serde(default)]
+    pub jump_list: JumpList,
+    /// Share of the book read, in percent
+    #[serde(default)]
+    pub percent: Option<u8>,
+}
+
+/// Identity under which the state of a book is stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookIdentity {
+    /// Key for the book's progress, bookmarks and highlights
+    pub id: String,
+    /// Reading progress key used before this identity (the file path of
+    /// path-keyed data, or the identity of an earlier version of the file)
+    pub previous_key: Option<String>,
+}
+
+/// Last known identity of a file, reused while its size and mtime match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    identity: String,
+    size: u64,
+    modified: Option<u64>,
+}
+
+/// A state document managed by [`PersistenceManager`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateFile {
+    Config,
+    ReadingProgress,
+    RecentBooks,
+    BookIndex,
+    Library,
+    Collections,
+    /// Bookmarks of the book with this identity
+    Bookmarks(String),
+    /// Highlights of the book with this identity
+    Highlights(String),
+}
+
+impl StateFile {
+    fn file_name(&self) -> String {
+        match self {
+            StateFile::Config => "config.json".to_string(),
+            StateFile::ReadingProgress => "reading_progress.json".to_string(),
+            StateFile::RecentBooks => "recent_books.json".to_string(),
+            StateFile::BookIndex => "book_index.json".to_string(),
+            StateFile::Library => "library.json".to_string(),
+            StateFile::Collections => "collections.json".to_string(),
+            StateFile::Bookmarks(book_id) => format!("bookmarks_{}.json", book_id),
+            StateFile::Highlights(book_id) => format!("highlights_{}.json", book_id),
+        }
+    }
+
+    /// Upgrades from each earlier format; the current format is the count
+    fn migrations(&self) -> &'static [Migration] {
+        match self {
+            StateFile::Config
+            | StateFile::Library
+            | StateFile::Collections
+            | StateFile::Bookmarks(_)
+            | StateFile::Highlights(_) => &[tag_version],
+            StateFile::ReadingProgress | StateFile::RecentBooks | StateFile::BookIndex => {
+                &[wrap_in_books]
+            }
+        }
+    }
+
+    /// Human-readable name for messages
+    pub fn description(&self) -> &'static str {
+        match self {
+            StateFile::Config => "settings",
+            StateFile::ReadingProgress => "reading progress",
+            StateFile::RecentBooks => "recent books list",
+            StateFile::BookIndex => "book index",
+            StateFile::Library => "library index",
+            StateFile::Collections => "reading status and tags",
+            StateFile::Bookmarks(_) => "bookmarks",
+            StateFile::Highlights(_) => "highlights",
+        }
+    }
+}
+
+/// A state file that could not be loaded
+///
+/// Returned inside the `anyhow::Error` of the loaders, so callers can
+/// downcast to it and offer to restore the backup.
+#[derive(Debug, thiserror::Error)]
+#[error("Your {} could not be loaded: {source}", file.description())]
+pub struct StateFileError {
+    pub file: StateFile,
+    #[source]
+    pub source: UnreadableFile,
+}
+
+/// Documents whose content is one collection of books
+#[derive(Serialize, Deserialize)]
+struct BooksFile<T> {
+    books: T,
+}
+
+// Migration 0 -> 1: objects only gained the `version` field
+fn tag_version(value: Value) -> std::result::Result<Value, String> {
+    match value {
+        Value::Object(_) => Ok(value),
+        _ => Err("expected a JSON object".to_string()),
+    }
+}
+
+// Migration 0 -> 1: bare maps and lists moved under a `books` field
+fn wrap_in_books(value: Value) -> std::result::Result<Value, String> {
+    match value {
+        Value::Object(_) | Value::Array(_) => Ok(serde_json::json!({ "books": value })),
+        _ => Err("expected a JSON object or list".to_string()),
+    }
+}
+
+/// Exclusive advisory lock on the config directory, released when dropped
+struct StateLock {
+    _file: fs::File,
 }
 
 /// Manages persistent storage of reading progress, bookmarks, and configuration
+#[derive(Clone)]
 pub struct PersistenceManager {
     config_dir: PathBuf,
 }
@@ -51,25 +200,88 @@ impl PersistenceManager {
         Ok(PersistenceManager { config_dir })
     }
 
+    /// Create a persistence manager storing its files in `config_dir`
+    #[cfg(test)]
+    pub(crate) fn in_dir(config_dir: &Path) -> Self {
+        PersistenceManager {
+            config_dir: config_dir.to_path_buf(),
+        }
+    }
+
+    // State file methods
+    /// Location of a state file in the config directory
+    pub fn path_of(&self, file: &StateFile) -> PathBuf {
+        self.config_dir.join(file.file_name())
+    }
+
+    /// Whether a backup of a state file exists to restore from
+    pub fn has_backup(&self, file: &StateFile) -> bool {
+        state_file::backup_path(&self.path_of(file)).exists()
+    }
+
+    /// Replace a damaged state file with its backup
+    pub fn restore_backup(&self, file: &StateFile) -> Result<()> {
+        let _lock = self.lock()?;
+        state_file::restore_backup(&self.path_of(file))
+            .with_context(|| format!("Failed to restore the {} backup", file.description()))
+    }
+
+    /// Move a damaged state file aside so the next save starts it afresh
+    ///
+    /// # Returns
+    /// Where the damaged file was kept
+    pub fn set_aside(&self, file: &StateFile) -> Result<PathBuf> {
+        let _lock = self.lock()?;
+        state_file::set_aside(&self.path_of(file))
+            .with_context(|| format!("Failed to move the damaged {} aside", file.description()))
+    }
+
+    fn read<T: DeserializeOwned>(&self, file: &StateFile) -> Result<Option<T>> {
+        state_file::read(&self.path_of(file), file.migrations()).map_err(|source| {
+            anyhow::Error::new(StateFileError {
+                file: file.clone(),
+                source,
+            })
+        })
+    }
+
+    /// Write a state file; the lock guarantees no other process writes at once
+    fn write<T: Serialize>(&self, _lock: &StateLock, file: &StateFile, document: &T) -> Result<()> {
+        state_file::write(&self.path_of(file), file.migrations().len(), document)
+            .with_context(|| format!("Failed to write {}", file.description()))
+    }
+
+    /// Wait for exclusive access to the state files
+    ///
+    /// The lock is advisory and shared by all reef processes using this
+    /// config directory; it is released when the returned guard is dropped.
+    fn lock(&self) -> Result<StateLock> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.config_dir.join("state.lock"))
+            .context("Failed to open state lock file")?;
+        file.lock().context("Failed to lock state directory")?;
+        Ok(StateLock { _file: file })
+    }
+
     // Config methods
     /// Load user configuration from disk
     /// Creates default config if file doesn't exist
+    ///
+    /// # Errors
+    /// A [`StateFileError`] if the file is damaged
     pub fn load_config(&self) -> Result<Config> {
-        let config_path = self.config_dir.join("config.json");
-
-        if !config_path.exists() {
-            // Create default config
-            let config = Config::default();
-            self.save_config(&config)?;
-            return Ok(config);
-        }
-
-        let content = fs::read_to_string(&config_path).context("Failed to read config file")?;
-
-        let config: Config = serde_json::from_str(&content).unwrap_or_else(|e| {
-            log::warn!("Failed to parse config file: {}. Using defaults.", e);
-            Config::default()
-        });
+        let config = match self.read::<Config>(&StateFile::Config)? {
+            Some(config) => config,
+            None => {
+                // Create default config
+                let config = Config::default();
+                self.save_config(&config)?;
+                return Ok(config);
+            }
+        };
 
         // Validate and clamp panel widths
         let mut validated_config = config;
@@ -85,67 +297,111 @@ impl PersistenceManager {
 
     /// Save user configuration to disk
     pub fn save_config(&self, config: &Config) -> Result<()> {
-        let config_path = self.config_dir.join("config.json");
-        let content = serde_json::to_string_pretty(config).context("Failed to serialize config")?;
-
-        fs::write(&config_path, content).context("Failed to write config file")?;
-
-        Ok(())
+        let lock = self.lock()?;
+        self.write(&lock, &StateFile::Config, config)
     }
 
     // Reading progress methods
-    /// Load reading progress for all books
-    /// Returns empty map if file doesn't exist or can't be parsed
+    /// Load reading progress for all books, keyed by book identity
+    /// Returns empty map if file doesn't exist
+    ///
+    /// # Errors
+    /// A [`StateFileError`] if the file is damaged
     pub fn load_reading_progress(&self) -> Result<HashMap<String, ReadingProgress>> {
-        let progress_path = self.config_dir.join("reading_progress.json");
-
-        if !progress_path.exists() {
-            return Ok(HashMap::new());
-        }
-
-        let content =
-            fs::read_to_string(&progress_path).context("Failed to read reading progress file")?;
-
-        let progress: HashMap<String, ReadingProgress> = serde_json::from_str(&content)
-            .unwrap_or_else(|e| {
-                log::warn!(
-                    "Failed to parse reading progress file: {}. Starting fresh.",
-                    e
-                );
-                HashMap::new()
-            });
+        Ok(self
+            .read::<BooksFile<HashMap<String, ReadingProgress>>>(&StateFile::ReadingProgress)?
+            .map(|file| file.books)
+            .unwrap_or_default())
+    }
 
+    /// Update the reading progress of some books without touching the others
+    ///
+    /// The file is re-read under the lock, so entries written by other reef
+    /// processes since this one loaded the file are kept.
+    ///
+    /// # Arguments
+    /// * `update` - Applies this process's changes to the current map
+    ///
+    /// # Returns
+    /// The merged map as written, including other processes' entries
+    pub fn update_reading_progress(
+        &self,
+        update: impl FnOnce(&mut HashMap<String, ReadingProgress>),
+    ) -> Result<HashMap<String, ReadingProgress>> {
+        let lock = self.lock()?;
+        let mut progress = self.load_reading_progress()?;
+        update(&mut progress);
+        self.save_reading_progress(&lock, &progress)?;
         Ok(progress)
     }
 
-    /// Save reading progress for all books
-    pub fn save_reading_progress(&self, progress: &HashMap<String, ReadingProgress>) -> Result<()> {
-        let progress_path = self.config_dir.join("reading_progress.json");
-        let content = serde_json::to_string_pretty(progress)
-            .context("Failed to serialize reading progress")?;
+    fn save_reading_progress(
+        &self,
+        lock: &StateLock,
+        progress: &HashMap<String, ReadingProgress>,
+    ) -> Result<()> {
+        self.write(
+            lock,
+            &StateFile::ReadingProgress,
+            &BooksFile { books: progress },
+        )
+    }
 
-        fs::write(&progress_path, content).context("Failed to write reading progress file")?;
+    // Collections methods
+    /// Load the reading status and tags of all books, keyed by book identity
+    ///
+    /// # Errors
+    /// A [`StateFileError`] if the file is damaged
+    pub fn load_collections(&self) -> Result<HashMap<String, BookCollections>> {
+        Ok(self
+            .read::<BooksFile<HashMap<String, BookCollections>>>(&StateFile::Collections)?
+            .map(|file| file.books)
+            .unwrap_or_default())
+    }
 
-        Ok(())
+    /// Update the reading status and tags of some books
+    ///
+    /// Like [`update_reading_progress`](Self::update_reading_progress), other
+    /// processes' changes are kept. Books left without status and tags are
+    /// dropped from the file.
+    ///
+    /// # Returns
+    /// The merged map as written
+    pub fn update_collections(
+        &self,
+        update: impl FnOnce(&mut HashMap<String, BookCollections>),
+    ) -> Result<HashMap<String, BookCollections>> {
+        let lock = self.lock()?;
+        let mut collections = self.load_collections()?;
+        update(&mut collections);
+        collections.retain(|_, book| !book.is_empty());
+        self.save_collections(&lock, &collections)?;
+        Ok(collections)
+    }
+
+    fn save_collections(
+        &self,
+        lock: &StateLock,
+        collections: &HashMap<String, BookCollections>,
+    ) -> Result<()> {
+        self.write(
+            lock,
+            &StateFile::Collections,
+            &BooksFile { books: collections },
+        )
     }
 
     // Recent books methods
     /// Load list of recently opened books
     /// Filters out books that no longer exist on disk
+    ///
+    /// # Errors
+    /// A [`StateFileError`] if the file is damaged
     pub fn load_recent_books(&self) -> Result<Vec<String>> {
-        let recent_path = self.config_dir.join("recent_books.json");
-
-        if !recent_path.exists() {
-            return Ok(Vec::new());
-        }
-
-        let content =
-            fs::read_to_string(&recent_path).context("Failed to read recent books file")?;
-
-        let books: Vec<String> = serde_json::from_str(&content).unwrap_or_else(|e| {
-            log::warn!("Failed to parse recent books file: {}. Starting fresh.", e);
-            Vec::new()
-        });
+        let books = self
+            .read::<BooksFile<Vec<String>>>(&StateFile::RecentBooks)?
+            .map(|file| file.books)
+            .unwrap_or_default();
 
         // Filter out books that no longer exist
         let existing_books: Vec<String> = books
@@ -156,67 +412,305 @@ impl PersistenceManager {
         Ok(existing_books)
     }
 
-    /// Save list of recently opened books
-    pub fn save_recent_books(&self, books: &[String]) -> Result<()> {
-        let recent_path = self.config_dir.join("recent_books.json");
-        let content =
-            serde_json::to_string_pretty(books).context("Failed to serialize recent books")?;
+    /// Move the books this process opened to the top of the recent books list
+    ///
+    /// Books opened by other processes in the meantime stay in the list.
+    ///
+    /// # Arguments
+    /// * `opened` - Books opened by this process, most recent first
+    ///
+    /// # Returns
+    /// The merged list as written
+    pub fn update_recent_books(&self, opened: &[String]) -> Result<Vec<String>> {
+        let lock = self.lock()?;
+        let mut books = self.load_recent_books()?;
+        books.retain(|path| !opened.contains(path));
+        books.splice(0..0, opened.iter().cloned());
+        self.save_recent_books(&lock, &books)?;
+        Ok(books)
+    }
 
-        fs::write(&recent_path, content).context("Failed to write recent books file")?;
+    /// Remove a book from the recent books list
+    ///
+    /// # Returns
+    /// The list as written
+    pub fn remove_recent_book(&self, path: &str) -> Result<Vec<String>> {
+        let lock = self.lock()?;
+        let mut books = self.load_recent_books()?;
+        books.retain(|book| book != path);
+        self.save_recent_books(&lock, &books)?;
+        Ok(books)
+    }
 
-        Ok(())
+    fn save_recent_books(&self, lock: &StateLock, books: &[String]) -> Result<()> {
+        self.write(lock, &StateFile::RecentBooks, &BooksFile { books })
     }
 
-    // Bookmark methods
-    /// Load bookmarks for a specific book
-    /// Returns empty list if no bookmarks exist
-    pub fn load_bookmarks(&self, book_path: &str) -> Result<Vec<Bookmark>> {
-        let hash = compute_path_hash(book_path);
-        let bookmarks_path = self.config_dir.join(format!("bookmarks_{}.json", hash));
+    // Book identity methods
+    /// Identify the book at a path, migrating state stored under an older key
+    ///
+    /// The content hash is cached in `book_index.json` and only recomputed
+    /// when the file's size or modification time changes. The first time a
+    /// path is identified, bookmarks and highlights saved under its path hash
+    /// are moved to the new identity; when the contents of a known path
+    /// change, the state of the previous identity is moved instead. Reading
+    /// progress is migrated on disk; callers holding a progress map loaded
+    /// earlier must apply [`adopt_progress`] to it as well.
+    ///
+    /// # Arguments
+    /// * `book_path` - Canonical path of the EPUB file
+    /// * `identifier` - The book's `unique-identifier` (`dc:identifier`), if any
+    ///
+    /// # Returns
+    /// * `Ok(BookIdentity)` - Identity to store the book's state under
+    /// * `Err` - The file could not be read
+    pub fn book_identity(&self, book_path: &str, identifier: Option<&str>) -> Result<BookIdentity> {
+        let metadata = fs::metadata(book_path).context("Failed to read book metadata")?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as u64);
+
+        let cached = self
+            .load_book_index()
+            .remove(book_path)
+            .filter(|entry| entry.size == size && entry.modified == modified);
+        if let Some(entry) = cached {
+            return Ok(BookIdentity {
+                id: entry.identity,
+                previous_key: None,
+            });
+        }
+
+        let id = compute_content_hash(Path::new(book_path), identifier)?;
+
+        // Another process may be identifying a book at the same time
+        let lock = self.lock()?;
+        let mut index = self.load_book_index();
+        let known = index.get(book_path).cloned();
+        let (file_key, previous_key) = match known {
+            Some(entry) if entry.identity != id => {
+                (Some(entry.identity.clone()), Some(entry.identity))
+            }
+            Some(_) => (None, None),
+            None => (
+                Some(compute_path_hash(book_path)),
+                Some(book_path.to_string()),
+            ),
+        };
+        let identity = BookIdentity { id, previous_key };
+        if let Some(file_key) = file_key {
+            for kind in ["bookmarks", "highlights"] {
+                self.migrate_book_file(kind, &file_key, &identity.id);
+            }
+
+            // A damaged progress file is left for the caller to report
+            match self.load_reading_progress() {
+                Ok(mut progress) => {
+                    if adopt_progress(&mut progress, &identity) {
+                        self.save_reading_progress(&lock, &progress)?;
+                    }
+                }
+                Err(e) => log::warn!("Reading progress not migrated: {}", e),
+            }
+            match self.load_collections() {
+                Ok(mut collections) => {
+                    if let Some(previous) = &identity.previous_key
+                        && !collections.contains_key(&identity.id)
+                        && let Some(book) = collections.remove(previous)
+                    {
+                        collections.insert(identity.id.clone(), book);
+                        self.save_collections(&lock, &collections)?;
+                    }
+                }
+                Err(e) => log::warn!("Reading status and tags not migrated: {}", e),
+            }
+        }
+
+        index.insert(
+            book_path.to_string(),
+            IndexEntry {
+                identity: identity.id.clone(),
+                size,
+                modified,
+            },
+        );
+        if let Err(e) = self.save_book_index(&lock, &index) {
+            log::warn!("Failed to save book index: {}", e);
+        }
+
+        Ok(identity)
+    }
 
-        if !bookmarks_path.exists() {
-            return Ok(Vec::new());
+    /// The index is only a cache of content hashes, so a damaged one is rebuilt
+    fn load_book_index(&self) -> HashMap<String, IndexEntry> {
+        match self.read::<BooksFile<HashMap<String, IndexEntry>>>(&StateFile::BookIndex) {
+            Ok(index) => index.map(|file| file.books).unwrap_or_default(),
+            Err(e) => {
+                log::warn!("{}. Rebuilding the book index.", e);
+                HashMap::new()
+            }
         }
+    }
 
-        let content =
-            fs::read_to_string(&bookmarks_path).context("Failed to read bookmarks file")?;
+    fn save_book_index(&self, lock: &StateLock, index: &HashMap<String, IndexEntry>) -> Result<()> {
+        self.write(lock, &StateFile::BookIndex, &BooksFile { books: index })
+    }
 
+    /// Move a per-book file to a new key unless the new key already has one
+    fn migrate_book_file(&self, kind: &str, from: &str, to: &str) {
+        let source = self.config_dir.join(format!("{}_{}.json", kind, from));
+        let target = self.config_dir.join(format!("{}_{}.json", kind, to));
+        if !source.exists() || target.exists() {
+            return;
+        }
+
+        match fs::rename(&source, &target) {
+            Ok(()) => log::info!("Migrated {} from {} to {}", kind, from, to),
+            Err(e) => log::warn!("Failed to migrate {} from {}: {}", kind, from, e),
+        }
+    }
+
+    // Library methods
+    /// Load the library index
+    ///
+    /// The index can be rebuilt by scanning, so a damaged one is discarded.
+    pub fn load_library(&self) -> Vec<LibraryEntry> {
+        match self.read::<BooksFile<Vec<LibraryEntry>>>(&StateFile::Library) {
+            Ok(library) => library.map(|file| file.books).unwrap_or_default(),
+            Err(e) => {
+                log::warn!("{}. Rebuilding the library index.", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Save the library index
+    pub fn save_library(&self, entries: &[LibraryEntry]) -> Result<()> {
+        let lock = self.lock()?;
+        self.write(&lock, &StateFile::Library, &BooksFile { books: entries })
+    }
+
+    // Bookmark methods
+    /// Load bookmarks for a specific book
+    /// Returns empty list if no bookmarks exist
+    ///
+    /// # Errors
+    /// A [`StateFileError`] if the file is damaged
+    pub fn load_bookmarks(&self, book_id: &str) -> Result<Vec<Bookmark>> {
         #[derive(Deserialize)]
         struct BookmarksFile {
             bookmarks: Vec<Bookmark>,
         }
 
-        let file: BookmarksFile = serde_json::from_str(&content).unwrap_or_else(|e| {
-            log::warn!("Failed to parse bookmarks file: {}. Starting fresh.", e);
-            BookmarksFile {
-                bookmarks: Vec::new(),
-            }
-        });
-
-        Ok(file.bookmarks)
+        Ok(self
+            .read::<BookmarksFile>(&StateFile::Bookmarks(book_id.to_string()))?
+            .map(|file| file.bookmarks)
+            .unwrap_or_default())
     }
 
     /// Save bookmarks for a specific book
-    pub fn save_bookmarks(&self, book_path: &str, bookmarks: &[Bookmark]) -> Result<()> {
-        let hash = compute_path_hash(book_path);
-        let bookmarks_path = self.config_dir.join(format!("bookmarks_{}.json", hash));
-
+    pub fn save_bookmarks(&self, book_id: &str, bookmarks: &[Bookmark]) -> Result<()> {
         #[derive(Serialize)]
         struct BookmarksFile<'a> {
             bookmarks: &'a [Bookmark],
         }
 
-        let file = BookmarksFile { bookmarks };
-        let content =
-            serde_json::to_string_pretty(&file).context("Failed to serialize bookmarks")?;
+        let lock = self.lock()?;
+        self.write(
+            &lock,
+            &StateFile::Bookmarks(book_id.to_string()),
+            &BookmarksFile { bookmarks },
+        )
+    }
 
-        fs::write(&bookmarks_path, content).context("Failed to write bookmarks file")?;
+    // Highlight methods
+    /// Load highlights for a specific book
+    /// Returns empty list if no highlights exist
+    ///
+    /// # Errors
+    /// A [`StateFileError`] if the file is damaged
+    pub fn load_highlights(&self, book_id: &str) -> Result<Vec<Highlight>> {
+        #[derive(Deserialize)]
+        struct HighlightsFile {
+            highlights: Vec<Highlight>,
+        }
 
-        Ok(())
+        Ok(self
+            .read::<HighlightsFile>(&StateFile::Highlights(book_id.to_string()))?
+            .map(|file| file.highlights)
+            .unwrap_or_default())
+    }
+
+    /// Save highlights for a specific book
+    pub fn save_highlights(&self, book_id: &str, highlights: &[Highlight]) -> Result<()> {
+        #[derive(Serialize)]
+        struct HighlightsFile<'a> {
+            highlights: &'a [Highlight],
+        }
+
+        let lock = self.lock()?;
+        self.write(
+            &lock,
+            &StateFile::Highlights(book_id.to_string()),
+            &HighlightsFile { highlights },
+        )
     }
 }
 
-// Compute hash of file path for creating unique bookmark files
+/// Move reading progress stored under a book's previous key to its identity
+///
+/// Progress already stored under the identity wins over the previous entry.
+///
+/// # Returns
+/// `true` if an entry was moved
+pub fn adopt_progress(
+    progress: &mut HashMap<String, ReadingProgress>,
+    identity: &BookIdentity,
+) -> bool {
+    let Some(previous_key) = &identity.previous_key else {
+        return false;
+    };
+    if progress.contains_key(&identity.id) {
+        return false;
+    }
+
+    match progress.remove(previous_key) {
+        Some(entry) => {
+            log::info!("Migrated reading progress from {}", previous_key);
+            progress.insert(identity.id.clone(), entry);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Hash a book's unique identifier together with its contents
+///
+/// SHA-256 is used (rather than `DefaultHasher`) because the result must be
+/// the same on every machine and Rust version.
+fn compute_content_hash(path: &Path, identifier: Option<&str>) -> Result<String> {
+    let mut file = fs::File::open(path).context("Failed to open book for hashing")?;
+    let mut hasher = Sha256::new();
+    hasher.update(identifier.unwrap_or_default().trim().as_bytes());
+    hasher.update([0]);
+
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).context("Failed to read book")?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    let digest = hasher.finalize();
+    Ok(digest[..8].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Compute hash of file path, the key of per-book files before book identities
 fn compute_path_hash(path: &str) -> String {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -244,13 +738,12 @@ pub fn canonicalize_path(path: &str) -> Result<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::HighlightColor;
     use tempfile::TempDir;
 
     fn create_test_manager() -> (PersistenceManager, TempDir) {
         let temp_dir = TempDir::new().unwrap();
-        let manager = PersistenceManager {
-            config_dir: temp_dir.path().to_path_buf(),
-        };
+        let manager = PersistenceManager::in_dir(temp_dir.path());
         (manager, temp_dir)
     }
 
@@ -262,20 +755,37 @@ mod tests {
             max_width: Some(100),
             toc_panel_width: 35,
             bookmarks_panel_width: 40,
+            wrap_code: true,
+            clipboard_command: Some("wl-copy".to_string()),
+            library_dirs: vec!["~/Books".to_string()],
+            library_sort: crate::types::LibrarySort::Author,
+            remote_control: true,
         };
 
         manager.save_config(&config).unwrap();
         let loaded = manager.load_config().unwrap();
 
         assert_eq!(loaded.max_width, Some(100));
+        assert_eq!(loaded.library_dirs, vec!["~/Books"]);
+        assert_eq!(loaded.library_sort, crate::types::LibrarySort::Author);
         assert_eq!(loaded.toc_panel_width, 35);
         assert_eq!(loaded.bookmarks_panel_width, 40);
+        assert!(loaded.wrap_code);
+        assert_eq!(loaded.clipboard_command.as_deref(), Some("wl-copy"));
+        assert!(loaded.remote_control);
     }
 
     #[test]
     fn test_save_and_load_reading_progress() {
         let (manager, _temp) = create_test_manager();
 
+        let mut jump_list = JumpList::default();
+        jump_list.push(crate::types::Location {
+            chapter_idx: 1,
+            line: 7,
+            anchor: None,
+        });
+
         let mut progress = HashMap::new();
         progress.insert(
             "/path/to/book.epub".to_string(),
@@ -285,10 +795,18 @@ mod tests {
                 scroll_offset: 30,
                 last_read: chrono::Utc::now(),
                 toc_expansion_state: vec!["chapter_0".to_string()],
+                anchor: Some(ContentAnchor {
+                    element_path: vec![3, 1],
+                    char_offset: 120,
+                }),
+                jump_list,
+                percent: Some(40),
             },
         );
 
-        manager.save_reading_progress(&progress).unwrap();
+        manager
+            .update_reading_progress(|all| all.extend(progress))
+            .unwrap();
         let loaded = manager.load_reading_progress().unwrap();
 
         assert_eq!(loaded.len(), 1);
@@ -296,6 +814,33 @@ mod tests {
         assert_eq!(book_progress.chapter_idx, 5);
         assert_eq!(book_progress.line, 42);
         assert_eq!(book_progress.scroll_offset, 30);
+        let anchor = book_progress.anchor.as_ref().unwrap();
+        assert_eq!(anchor.element_path, vec![3, 1]);
+        assert_eq!(anchor.char_offset, 120);
+        assert_eq!(book_progress.jump_list.len(), 1);
+    }
+
+    #[test]
+    fn test_load_reading_progress_without_anchor() {
+        let (manager, temp) = create_test_manager();
+
+        // Progress files written before anchors existed only have line numbers
+        let legacy = r#"{
+            "/path/to/book.epub": {
+                "chapter_idx": 2,
+                "line": 17,
+                "scroll_offset": 5,
+                "last_read": "2025-01-01T00:00:00Z",
+                "toc_expansion_state": []
+            }
+        }"#;
+        fs::write(temp.path().join("reading_progress.json"), legacy).unwrap();
+
+        let loaded = manager.load_reading_progress().unwrap();
+        let book_progress = loaded.get("/path/to/book.epub").unwrap();
+        assert_eq!(book_progress.line, 17);
+        assert!(book_progress.anchor.is_none());
+        assert!(book_progress.jump_list.is_empty());
     }
 
     #[test]
@@ -307,22 +852,68 @@ mod tests {
                 chapter_idx: 0,
                 line: 10,
                 label: "Important point".to_string(),
+                anchor: Some(ContentAnchor {
+                    element_path: vec![0],
+                    char_offset: 0,
+                }),
+                cfi: Some("epubcfi(/6/2!/4/2/1:0)".to_string()),
             },
             Bookmark {
                 chapter_idx: 2,
                 line: 50,
                 label: "Remember this".to_string(),
+                anchor: None,
+                cfi: None,
             },
         ];
 
         manager
-            .save_bookmarks("/path/to/book.epub", &bookmarks)
+            .save_bookmarks("0123456789abcdef", &bookmarks)
             .unwrap();
-        let loaded = manager.load_bookmarks("/path/to/book.epub").unwrap();
+        let loaded = manager.load_bookmarks("0123456789abcdef").unwrap();
 
         assert_eq!(loaded.len(), 2);
         assert_eq!(loaded[0].label, "Important point");
         assert_eq!(loaded[1].label, "Remember this");
+        assert_eq!(loaded[0].anchor.as_ref().unwrap().element_path, vec![0]);
+        assert_eq!(loaded[0].cfi.as_deref(), Some("epubcfi(/6/2!/4/2/1:0)"));
+        assert!(loaded[1].anchor.is_none());
+    }
+
+    #[test]
+    fn test_save_and_load_highlights() {
+        let (manager, _temp) = create_test_manager();
+
+        let anchor = |char_offset| ContentAnchor {
+            element_path: vec![1, 3],
+            char_offset,
+        };
+        let highlights = vec![Highlight {
+            chapter_idx: 1,
+            start: anchor(4),
+            end: anchor(20),
+            text: "a highlighted passage".to_string(),
+            color: HighlightColor::Green,
+            note: Some("Worth rereading".to_string()),
+            created: Utc::now(),
+        }];
+
+        manager
+            .save_highlights("0123456789abcdef", &highlights)
+            .unwrap();
+        let loaded = manager.load_highlights("0123456789abcdef").unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].start, anchor(4));
+        assert_eq!(loaded[0].end, anchor(20));
+        assert_eq!(loaded[0].color, HighlightColor::Green);
+        assert_eq!(loaded[0].note.as_deref(), Some("Worth rereading"));
+        assert!(
+            manager
+                .load_highlights("/path/to/other.epub")
+                .unwrap()
+                .is_empty()
+        );
     }
 
     #[test]
@@ -345,12 +936,16 @@ mod tests {
             "/nonexistent/book.epub".to_string(), // This should be filtered out
         ];
 
-        manager.save_recent_books(&books).unwrap();
+        manager.update_recent_books(&books).unwrap();
         let loaded = manager.load_recent_books().unwrap();
 
         // Only the real file should be loaded
         assert_eq!(loaded.len(), 1);
         assert!(loaded[0].contains("real_book.epub"));
+
+        let remaining = manager.remove_recent_book(&loaded[0]).unwrap();
+        assert!(remaining.is_empty());
+        assert!(manager.load_recent_books().unwrap().is_empty());
     }
 
     #[test]
@@ -362,6 +957,11 @@ mod tests {
             max_width: None,
             toc_panel_width: 5,         // Too small
             bookmarks_panel_width: 100, // Too large
+            wrap_code: false,
+            clipboard_command: None,
+            library_dirs: Vec::new(),
+            library_sort: Default::default(),
+            remote_control: false,
         };
 
         manager.save_config(&config).unwrap();
@@ -372,6 +972,246 @@ mod tests {
         assert!(loaded.bookmarks_panel_width >= 20 && loaded.bookmarks_panel_width <= 80);
     }
 
+    #[test]
+    fn test_damaged_state_is_reported_not_replaced() {
+        let (manager, temp) = create_test_manager();
+
+        // Unversioned files from earlier releases are upgraded
+        fs::write(temp.path().join("recent_books.json"), "[]").unwrap();
+        assert!(manager.load_recent_books().unwrap().is_empty());
+
+        let bookmark = Bookmark {
+            chapter_idx: 0,
+            line: 1,
+            label: "Saved".to_string(),
+            anchor: None,
+            cfi: None,
+        };
+        let bookmarks = vec![bookmark.clone(), bookmark];
+        manager
+            .save_bookmarks("0123456789abcdef", &bookmarks[..1])
+            .unwrap();
+        manager
+            .save_bookmarks("0123456789abcdef", &bookmarks)
+            .unwrap();
+
+        let file = StateFile::Bookmarks("0123456789abcdef".to_string());
+        fs::write(manager.path_of(&file), "{\"version\": 1, \"bookm").unwrap();
+        let error = manager.load_bookmarks("0123456789abcdef").unwrap_err();
+        let problem = error.downcast_ref::<StateFileError>().unwrap();
+        assert_eq!(problem.file, file);
+
+        assert!(manager.has_backup(&file));
+        manager.restore_backup(&file).unwrap();
+        assert_eq!(manager.load_bookmarks("0123456789abcdef").unwrap().len(), 1);
+    }
+
+    fn sample_progress(chapter_idx: usize) -> ReadingProgress {
+        ReadingProgress {
+            chapter_idx,
+            line: 0,
+            scroll_offset: 0,
+            last_read: chrono::Utc::now(),
+            toc_expansion_state: Vec::new(),
+            anchor: None,
+            jump_list: JumpList::default(),
+            percent: None,
+        }
+    }
+
+    #[test]
+    fn test_book_identity_migrates_path_keyed_state() {
+        let (manager, temp) = create_test_manager();
+        let book = temp.path().join("book.epub");
+        fs::write(&book, b"epub contents").unwrap();
+        let book_path = book.to_str().unwrap();
+
+        let bookmark = Bookmark {
+            chapter_idx: 1,
+            line: 3,
+            label: "Old".to_string(),
+            anchor: None,
+            cfi: None,
+        };
+        manager
+            .save_bookmarks(
+                &compute_path_hash(book_path),
+                std::slice::from_ref(&bookmark),
+            )
+            .unwrap();
+        manager
+            .update_reading_progress(|progress| {
+                progress.insert(book_path.to_string(), sample_progress(4));
+            })
+            .unwrap();
+
+        let identity = manager
+            .book_identity(book_path, Some("urn:isbn:1"))
+            .unwrap();
+        assert_eq!(identity.id.len(), 16);
+        assert_eq!(identity.previous_key.as_deref(), Some(book_path));
+        assert_eq!(manager.load_bookmarks(&identity.id).unwrap().len(), 1);
+        let progress = manager.load_reading_progress().unwrap();
+        assert_eq!(progress[&identity.id].chapter_idx, 4);
+        assert!(!progress.contains_key(book_path));
+
+        // A cached identity needs no migration
+        let again = manager
+            .book_identity(book_path, Some("urn:isbn:1"))
+            .unwrap();
+        assert_eq!(again.id, identity.id);
+        assert!(again.previous_key.is_none());
+
+        // The same file elsewhere is the same book; another identifier is not
+        let copy = temp.path().join("renamed.epub");
+        fs::copy(&book, &copy).unwrap();
+        let copy_path = copy.to_str().unwrap();
+        assert_eq!(
+            manager
+                .book_identity(copy_path, Some("urn:isbn:1"))
+                .unwrap()
+                .id,
+            identity.id
+        );
+        assert_ne!(
+            compute_content_hash(&copy, Some("urn:isbn:2")).unwrap(),
+            identity.id
+        );
+    }
+
+    #[test]
+    fn test_book_identity_follows_changed_contents() {
+        let (manager, temp) = create_test_manager();
+        let book = temp.path().join("book.epub");
+        fs::write(&book, b"first edition").unwrap();
+        let book_path = book.to_str().unwrap();
+
+        let first = manager.book_identity(book_path, None).unwrap();
+        manager
+            .save_highlights(
+                &first.id,
+                &[Highlight {
+                    chapter_idx: 0,
+                    start: ContentAnchor {
+                        element_path: vec![0],
+                        char_offset: 0,
+                    },
+                    end: ContentAnchor {
+                        element_path: vec![0],
+                        char_offset: 4,
+                    },
+                    text: "text".to_string(),
+                    note: None,
+                    color: HighlightColor::Yellow,
+                    created: chrono::Utc::now(),
+                }],
+            )
+            .unwrap();
+        let mut progress = HashMap::new();
+        progress.insert(first.id.clone(), sample_progress(2));
+        manager
+            .update_collections(|all| {
+                all.insert(
+                    first.id.clone(),
+                    BookCollections {
+                        status: Some(crate::collections::ReadingStatus::Reading),
+                        tags: vec!["fiction".to_string()],
+                    },
+                );
+                // Books without status or tags are not kept
+                all.insert("0123456789abcdef".to_string(), BookCollections::default());
+            })
+            .unwrap();
+
+        fs::write(&book, b"second edition, edited").unwrap();
+        let second = manager.book_identity(book_path, None).unwrap();
+        assert_ne!(second.id, first.id);
+        assert_eq!(second.previous_key.as_deref(), Some(first.id.as_str()));
+        assert_eq!(manager.load_highlights(&second.id).unwrap().len(), 1);
+        assert!(manager.load_highlights(&first.id).unwrap().is_empty());
+        let collections = manager.load_collections().unwrap();
+        assert_eq!(collections.len(), 1);
+        assert_eq!(collections[&second.id].tags, vec!["fiction"]);
+
+        // An in-memory map loaded before the migration is adopted the same way
+        assert!(adopt_progress(&mut progress, &second));
+        assert_eq!(progress[&second.id].chapter_idx, 2);
+        assert!(!adopt_progress(&mut progress, &second));
+    }
+
+    #[test]
+    fn test_concurrent_managers_keep_each_others_entries() {
+        let (first, temp) = create_test_manager();
+        let second = PersistenceManager {
+            config_dir: temp.path().to_path_buf(),
+        };
+
+        // Both processes start from the same empty state...
+        assert!(first.load_reading_progress().unwrap().is_empty());
+        assert!(second.load_reading_progress().unwrap().is_empty());
+
+        // ...and save their own book in turns
+        first
+            .update_reading_progress(|progress| {
+                progress.insert("book-a".to_string(), sample_progress(1));
+            })
+            .unwrap();
+        let merged = second
+            .update_reading_progress(|progress| {
+                progress.insert("book-b".to_string(), sample_progress(2));
+            })
+            .unwrap();
+        assert_eq!(merged.len(), 2);
+        first
+            .update_reading_progress(|progress| {
+                progress.insert("book-a".to_string(), sample_progress(3));
+            })
+            .unwrap();
+
+        let loaded = second.load_reading_progress().unwrap();
+        assert_eq!(loaded["book-a"].chapter_idx, 3);
+        assert_eq!(loaded["book-b"].chapter_idx, 2);
+
+        // Recent books keep the other process's entries below its own
+        let books: Vec<String> = ["one", "two"]
+            .iter()
+            .map(|name| {
+                let path = temp.path().join(name);
+                fs::write(&path, b"epub").unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        first.update_recent_books(&books[..1]).unwrap();
+        let recent = second.update_recent_books(&books[1..]).unwrap();
+        assert_eq!(recent, vec![books[1].clone(), books[0].clone()]);
+
+        // Writers in parallel threads are serialized by the lock
+        let dir = temp.path().to_path_buf();
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let manager = PersistenceManager {
+                    config_dir: dir.clone(),
+                };
+                std::thread::spawn(move || {
+                    for round in 0..10 {
+                        manager
+                            .update_reading_progress(|progress| {
+                                progress.insert(
+                                    format!("writer-{}-{}", writer, round),
+                                    sample_progress(round),
+                                );
+                            })
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(first.load_reading_progress().unwrap().len(), 42);
+    }
+
     #[test]
     fn test_path_hash_consistency() {
         let path = "/some/path/to/book.epub";
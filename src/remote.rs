@@ -0,0 +1,666 @@
+//! Remote control over a Unix domain socket
+//!
+//! With `remote_control` enabled in the config, the reader listens on a
+//! socket in the user's runtime directory (`$XDG_RUNTIME_DIR/reef/<pid>.sock`)
+//! so editors and window managers can drive it. Requests and responses are
+//! JSON objects, one per line, and a connection may carry any number of them:
+//!
+//! ```text
+//! {"command": "goto", "at": {"section": "Lexical Analysis"}}
+//! {"ok": true}
+//! {"command": "get-position"}
+//! {"ok": true, "position": {"title": "...", "chapter": 3, ...}}
+//! ```
+//!
+//! The listener runs on its own thread and hands every request to the event
+//! loop, which executes it with [`execute`] and sends the response back.
+//! `reef remote <command>` is the matching client.
+
+use crate::app::AppState;
+use crate::async_tasks::TaskMessage;
+use crate::types::StartPosition;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Environment variable naming the socket `reef remote` talks to
+pub const SOCKET_ENV: &str = "REEF_SOCKET";
+
+/// How long the client waits for the reader to answer
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A command sent to a running reader
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Open a book, optionally at a position instead of the saved one
+    Open {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        at: Option<StartPosition>,
+    },
+    /// Move to a position in the open book
+    Goto {
+        at: StartPosition,
+    },
+    /// Search the open book and jump to the first match
+    Search {
+        query: String,
+    },
+    NextChapter,
+    PrevChapter,
+    /// Where the cursor is, with enough detail to cite it
+    GetPosition,
+    /// Text of the lines selected with `v`
+    GetSelection,
+    Quit,
+}
+
+/// Answer to a [`Request`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<String>,
+    /// Number of matches of a search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<usize>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Response {
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Response {
+            error: Some(message.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The cursor position in the open book
+///
+/// Chapters and lines are numbered from 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub path: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub chapter: usize,
+    pub chapter_title: String,
+    pub section: Option<String>,
+    pub line: usize,
+    pub percent: u8,
+    pub cfi: Option<String>,
+}
+
+/// A request waiting for the event loop, with the channel for its response
+#[derive(Debug)]
+pub struct RemoteCall {
+    pub request: Request,
+    pub reply: oneshot::Sender<Response>,
+}
+
+/// Run a request against the reader
+///
+/// Requests go through the same methods as the keys that do the same thing,
+/// so the result is what the user would see after pressing them.
+pub fn execute(app: &mut AppState, request: Request) -> Response {
+    log::info!("Remote request: {:?}", request);
+    if !matches!(request, Request::Open { .. } | Request::Quit) && app.book.is_none() {
+        return Response::error("No book loaded");
+    }
+
+    match request {
+        Request::Open { path, at } => {
+            let Ok(path) = crate::persistence::canonicalize_path(&path) else {
+                return Response::error(format!("EPUB file not found: {}", path));
+            };
+            app.start_position = at;
+            app.load_book_async(path);
+            Response::ok()
+        }
+        Request::Goto { at } => match app.go_to_start_position(&at) {
+            Ok(()) => Response::ok(),
+            Err(e) => Response::error(format!("Cannot go to {}: {}", at, e)),
+        },
+        Request::Search { query } => match app.search(&query) {
+            Ok(matches) => Response {
+                matches: Some(matches),
+                ..Response::ok()
+            },
+            Err(e) => Response::error(e),
+        },
+        Request::NextChapter => {
+            app.next_chapter();
+            Response::ok()
+        }
+        Request::PrevChapter => {
+            app.previous_chapter();
+            Response::ok()
+        }
+        Request::GetPosition => Response {
+            position: position(app),
+            ..Response::ok()
+        },
+        Request::GetSelection => {
+            let selection = app.visual_selection().and_then(|range| {
+                app.get_current_chapter()
+                    .map(|chapter| crate::clipboard::selection_text(chapter, range))
+            });
+            match selection {
+                Some(text) => Response {
+                    selection: Some(text),
+                    ..Response::ok()
+                },
+                None => Response::error("Nothing selected"),
+            }
+        }
+        Request::Quit => {
+            app.should_quit = true;
+            Response::ok()
+        }
+    }
+}
+
+fn position(app: &AppState) -> Option<Position> {
+    let book = app.book.as_ref()?;
+    let chapter = book.chapters.get(app.current_chapter)?;
+    let section = crate::toc::TocManager::find_section_at_line(chapter, app.cursor_line)
+        .map(|idx| chapter.sections[idx].title.clone())
+        .filter(|title| !title.is_empty());
+    Some(Position {
+        path: app.current_book_path.clone().unwrap_or_default(),
+        title: book.metadata.title.trim().to_string(),
+        author: book.metadata.author.clone(),
+        chapter: app.current_chapter + 1,
+        chapter_title: chapter.title.clone(),
+        section,
+        line: app.cursor_line + 1,
+        percent: book.percent_read(app.current_chapter, app.cursor_line),
+        cfi: app.current_cfi(),
+    })
+}
+
+/// Directory the sockets of running readers are created in
+fn socket_dir() -> PathBuf {
+    directories::ProjectDirs::from("", "", "reef")
+        .and_then(|dirs| dirs.runtime_dir().map(Path::to_path_buf))
+        .unwrap_or_else(|| {
+            let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
+            std::env::temp_dir().join(format!("reef-{}", user))
+        })
+}
+
+/// User id the reader runs as
+fn current_uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+/// Check that `dir` can hold sockets without other users reaching them
+///
+/// The directory may be a predictable path in the shared temp directory,
+/// so like tmux it must be a real directory (not a symlink) owned by us
+/// that nobody else can read or write.
+///
+/// # Errors
+/// Returns an error if the directory is missing or not private
+fn check_private_dir(dir: &Path) -> std::io::Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o777 != 0o700 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "{} must be a directory owned by the current user with mode 700",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Listener for remote control requests; removes its socket when dropped
+pub struct RemoteServer {
+    path: PathBuf,
+}
+
+impl RemoteServer {
+    /// Listen on a socket for this process and send requests to the event loop
+    ///
+    /// # Errors
+    /// Returns an error if the socket cannot be created
+    pub fn start(tx: mpsc::UnboundedSender<TaskMessage>) -> std::io::Result<Self> {
+        Self::start_in(&socket_dir(), tx)
+    }
+
+    /// Listen on a socket for this process in `dir`
+    fn start_in(dir: &Path, tx: mpsc::UnboundedSender<TaskMessage>) -> std::io::Result<Self> {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        // An existing directory keeps its owner and mode
+        check_private_dir(dir)?;
+        let path = dir.join(format!("{}.sock", std::process::id()));
+        // A socket left behind by a crashed reader that had our pid
+        let _ = fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        log::info!("Remote control listening on {}", path.display());
+
+        // Accepting blocks, so it gets a thread that ends with the process
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let tx = tx.clone();
+                        std::thread::spawn(move || serve_connection(stream, tx));
+                    }
+                    Err(e) => log::warn!("Remote control connection failed: {}", e),
+                }
+            }
+        });
+        Ok(RemoteServer { path })
+    }
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answer the requests of one client until it disconnects
+fn serve_connection(stream: UnixStream, tx: mpsc::UnboundedSender<TaskMessage>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (reply, response) = oneshot::channel();
+                let call = RemoteCall { request, reply };
+                if tx.send(TaskMessage::RemoteRequest(call)).is_err() {
+                    // The reader is shutting down
+                    break;
+                }
+                response
+                    .blocking_recv()
+                    .unwrap_or_else(|_| Response::error("The reader closed"))
+            }
+            Err(e) => Response::error(format!("Invalid request: {}", e)),
+        };
+
+        let Ok(json) = serde_json::to_string(&response) else {
+            break;
+        };
+        if writeln!(writer, "{}", json).is_err() {
+            break;
+        }
+    }
+}
+
+/// Sockets of running readers, newest first
+///
+/// # Errors
+/// Returns an error if the socket directory is not private, since any
+/// socket in it could belong to another user
+fn running_sockets() -> Result<Vec<PathBuf>, String> {
+    let dir = socket_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    check_private_dir(&dir).map_err(|e| format!("Refusing to look for readers: {}", e))?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut sockets: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sock"))
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .collect();
+    sockets.sort();
+    Ok(sockets.into_iter().rev().map(|(_, path)| path).collect())
+}
+
+/// Check whether a socket that failed to connect was left by a dead reader
+///
+/// Only a refused connection means nobody listens; a full backlog or a
+/// permission error can come from a live reader.
+fn is_stale(path: &Path, error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::ConnectionRefused
+        && fs::symlink_metadata(path).is_ok_and(|metadata| metadata.uid() == current_uid())
+}
+
+/// Connect to a reader: the given socket, `$REEF_SOCKET`, or the newest running one
+fn connect(socket: Option<&Path>) -> Result<UnixStream, String> {
+    let explicit = socket
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os(SOCKET_ENV).map(PathBuf::from));
+    if let Some(path) = explicit {
+        return UnixStream::connect(&path)
+            .map_err(|e| format!("Cannot connect to {}: {}", path.display(), e));
+    }
+
+    for path in running_sockets()? {
+        match UnixStream::connect(&path) {
+            Ok(stream) => return Ok(stream),
+            Err(e) if is_stale(&path, &e) => {
+                // Nobody listens: the reader was killed before cleaning up
+                log::debug!("Removing stale socket {}", path.display());
+                let _ = fs::remove_file(&path);
+            }
+            Err(e) => log::debug!("Skipping socket {}: {}", path.display(), e),
+        }
+    }
+    Err(
+        "No running reader with remote control enabled (set remote_control in the config)"
+            .to_string(),
+    )
+}
+
+/// Send a request to a running reader and wait for its response
+///
+/// # Arguments
+/// * `socket` - Socket of the reader; found automatically when `None`
+/// * `request` - The request to send
+///
+/// # Errors
+/// Returns an error if no reader can be reached or it doesn't answer
+pub fn send(socket: Option<&Path>, request: &Request) -> Result<Response, String> {
+    let stream = connect(socket)?;
+    stream
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+
+    let mut writer = stream.try_clone().map_err(|e| e.to_string())?;
+    let json = serde_json::to_string(request).map_err(|e| e.to_string())?;
+    writeln!(writer, "{}", json).map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|e| format!("No response from the reader: {}", e))?;
+    serde_json::from_str(&line).map_err(|e| format!("Invalid response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::render_chapter;
+    use crate::persistence::PersistenceManager;
+    use crate::types::{Book, BookMetadata, Chapter, Config};
+    use tempfile::TempDir;
+
+    fn book() -> Book {
+        let chapter = |title: &str, html: &str| {
+            let mut chapter = Chapter {
+                title: title.to_string(),
+                sections: Vec::new(),
+                content_lines: Vec::new(),
+                file_path: html.to_string(),
+                href: "test.xhtml".to_string(),
+                document: crate::epub::document::build_document(html),
+            };
+            render_chapter(&mut chapter, None, 80, false);
+            chapter
+        };
+        Book {
+            metadata: BookMetadata {
+                title: "Remote Test".to_string(),
+                author: Some("Ann Author".to_string()),
+                publisher: None,
+                publication_date: None,
+                language: None,
+                identifier: None,
+            },
+            chapters: vec![
+                chapter("Preface", "<p>Why this book exists.</p>"),
+                chapter(
+                    "Basics",
+                    "<p>Opening words.</p><h2>Lexical Analysis</h2><p>Tokens first.</p>\
+                     <p>Then comments.</p>",
+                ),
+            ],
+            images: Default::default(),
+        }
+    }
+
+    fn app(config_dir: &TempDir) -> AppState {
+        let persistence = PersistenceManager::in_dir(config_dir.path());
+        let mut app = AppState::new(Config::default(), persistence);
+        app.book = Some(book());
+        app.current_book_path = Some("/books/remote.epub".to_string());
+        app
+    }
+
+    #[test]
+    fn test_execute_goto_and_get_position() {
+        let dir = TempDir::new().unwrap();
+        let mut app = app(&dir);
+
+        let goto = Request::Goto {
+            at: StartPosition::Section("Lexical Analysis".to_string()),
+        };
+        assert_eq!(execute(&mut app, goto), Response::ok());
+
+        let response = execute(&mut app, Request::GetPosition);
+        assert!(response.ok);
+        let position = response.position.unwrap();
+        assert_eq!(position.path, "/books/remote.epub");
+        assert_eq!(position.title, "Remote Test");
+        assert_eq!(position.author.as_deref(), Some("Ann Author"));
+        assert_eq!(
+            (position.chapter, position.chapter_title.as_str()),
+            (2, "Basics")
+        );
+        assert_eq!(position.section.as_deref(), Some("Lexical Analysis"));
+        let line = &app.book.as_ref().unwrap().chapters[1].content_lines[position.line - 1];
+        assert_eq!(line.text.trim(), "Lexical Analysis");
+        assert!(position.cfi.unwrap().starts_with("epubcfi("));
+
+        let response = execute(
+            &mut app,
+            Request::Goto {
+                at: StartPosition::Chapter(9),
+            },
+        );
+        assert_eq!(
+            response.error.as_deref(),
+            Some("Cannot go to chapter 9: the book has 2 chapters")
+        );
+        assert_eq!(app.current_chapter, 1);
+    }
+
+    #[test]
+    fn test_execute_get_selection() {
+        let dir = TempDir::new().unwrap();
+        let mut app = app(&dir);
+
+        let response = execute(&mut app, Request::GetSelection);
+        assert_eq!(response.error.as_deref(), Some("Nothing selected"));
+
+        app.current_chapter = 1;
+        let lines = &app.book.as_ref().unwrap().chapters[1].content_lines;
+        let first = lines
+            .iter()
+            .position(|l| l.text.contains("Tokens"))
+            .unwrap();
+        let last = lines
+            .iter()
+            .position(|l| l.text.contains("comments"))
+            .unwrap();
+        app.cursor_line = first;
+        app.toggle_visual_selection();
+        app.cursor_line = last;
+
+        let response = execute(&mut app, Request::GetSelection);
+        assert!(response.ok);
+        assert_eq!(
+            response.selection.as_deref(),
+            Some("Tokens first.\n\nThen comments.")
+        );
+    }
+
+    #[test]
+    fn test_execute_without_book() {
+        let dir = TempDir::new().unwrap();
+        let mut app = app(&dir);
+        app.book = None;
+        let response = execute(&mut app, Request::GetPosition);
+        assert_eq!(response, Response::error("No book loaded"));
+    }
+
+    #[test]
+    fn test_socket_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = RemoteServer::start_in(&dir.path().join("reef"), tx).unwrap();
+        let socket = server.path.clone();
+        assert_eq!(
+            fs::metadata(&socket).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        // Stand in for the event loop
+        let config_dir = TempDir::new().unwrap();
+        let mut app = app(&config_dir);
+        let client = std::thread::spawn(move || {
+            let goto = Request::Goto {
+                at: StartPosition::Chapter(2),
+            };
+            let moved = send(Some(&socket), &goto).unwrap();
+            let position = send(Some(&socket), &Request::GetPosition).unwrap();
+            (moved, position)
+        });
+        for _ in 0..2 {
+            let Some(TaskMessage::RemoteRequest(call)) = rx.blocking_recv() else {
+                panic!("expected a remote request");
+            };
+            call.reply.send(execute(&mut app, call.request)).unwrap();
+        }
+
+        let (moved, position) = client.join().unwrap();
+        assert_eq!(moved, Response::ok());
+        assert_eq!(position.position.unwrap().chapter, 2);
+
+        // Malformed requests are answered without reaching the event loop
+        let mut stream = UnixStream::connect(&server.path).unwrap();
+        writeln!(stream, "{{\"command\": \"explode\"}}").unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert!(response.error.unwrap().starts_with("Invalid request"));
+
+        let path = server.path.clone();
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_shared_socket_dir_is_rejected() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().join("reef-user");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let error = RemoteServer::start_in(&dir, tx).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        // A symlink to a private directory is not accepted either
+        let private = temp.path().join("private");
+        fs::DirBuilder::new().mode(0o700).create(&private).unwrap();
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&private, &link).unwrap();
+        assert!(check_private_dir(&link).is_err());
+        assert!(check_private_dir(&private).is_ok());
+    }
+
+    #[test]
+    fn test_only_refused_sockets_are_stale() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("1.sock");
+        drop(UnixListener::bind(&path).unwrap());
+
+        let refused = UnixStream::connect(&path).unwrap_err();
+        assert_eq!(refused.kind(), std::io::ErrorKind::ConnectionRefused);
+        assert!(is_stale(&path, &refused));
+
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(!is_stale(&path, &denied));
+        let busy = std::io::Error::from(std::io::ErrorKind::WouldBlock);
+        assert!(!is_stale(&path, &busy));
+    }
+
+    #[test]
+    fn test_request_json() {
+        let request: Request =
+            serde_json::from_str(r#"{"command": "goto", "at": {"section": "Intro"}}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Goto {
+                at: StartPosition::Section("Intro".to_string())
+            }
+        );
+
+        let request: Request =
+            serde_json::from_str(r#"{"command": "open", "path": "/b.epub"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Open {
+                path: "/b.epub".to_string(),
+                at: None
+            }
+        );
+
+        let json = serde_json::to_string(&Request::PrevChapter).unwrap();
+        assert_eq!(json, r#"{"command":"prev-chapter"}"#);
+        let json = serde_json::to_string(&Request::Goto {
+            at: StartPosition::Percent(42),
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"command":"goto","at":{"percent":42}}"#);
+
+        assert!(serde_json::from_str::<Request>(r#"{"command": "explode"}"#).is_err());
+    }
+
+    #[test]
+    fn test_response_json() {
+        let json = serde_json::to_string(&Response::error("No book loaded")).unwrap();
+        assert_eq!(json, r#"{"ok":false,"error":"No book loaded"}"#);
+
+        let response = Response {
+            matches: Some(3),
+            ..Response::ok()
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(json, r#"{"ok":true,"matches":3}"#);
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response);
+    }
+}
@@ -3,8 +3,8 @@
 //! This module provides regex-based search across all chapters and lines,
 //! with highlighting support and result navigation.
 
-use crate::types::{Book, SearchMatch, Viewport};
-use regex::Regex;
+use crate::types::{Book, ContentAnchor, SearchMatch, Viewport};
+use regex::{Regex, RegexBuilder};
 use std::time::{Duration, Instant};
 
 const MAX_SEARCH_RESULTS: usize = 1000;
@@ -28,13 +28,39 @@ impl SearchEngine {
     /// * `Err(String)` - Invalid regex or search timeout
     pub fn search(book: &Book, query: &str) -> Result<Vec<SearchMatch>, String> {
         log::info!("Starting search: query='{}'", query);
+        let regex = Self::compile(query, false, false)?;
+        Self::search_regex(book, &regex)
+    }
 
-        // Validate and compile regex
-        let regex = Regex::new(query).map_err(|e| {
-            log::warn!("Invalid regex pattern '{}': {}", query, e);
-            format!("Invalid regex pattern: {}", e)
-        })?;
+    /// Compile a search pattern
+    ///
+    /// # Arguments
+    /// * `query` - Regex pattern, or literal text when `fixed_strings` is set
+    /// * `ignore_case` - Match regardless of case
+    /// * `fixed_strings` - Match the query literally instead of as a regex
+    ///
+    /// # Errors
+    /// Returns an error for an invalid regex
+    pub fn compile(query: &str, ignore_case: bool, fixed_strings: bool) -> Result<Regex, String> {
+        let pattern = if fixed_strings {
+            regex::escape(query)
+        } else {
+            query.to_string()
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| {
+                log::warn!("Invalid regex pattern '{}': {}", query, e);
+                format!("Invalid regex pattern: {}", e)
+            })
+    }
 
+    /// Perform full-book search with a compiled pattern
+    ///
+    /// Same as [`search`](Self::search), for patterns built by
+    /// [`compile`](Self::compile).
+    pub fn search_regex(book: &Book, regex: &Regex) -> Result<Vec<SearchMatch>, String> {
         let mut results = Vec::new();
         let start_time = Instant::now();
 
@@ -54,11 +80,16 @@ impl SearchEngine {
             for (line_idx, rendered_line) in chapter.content_lines.iter().enumerate() {
                 // Find all matches in this line
                 for mat in regex.find_iter(&rendered_line.text) {
+                    let anchor = rendered_line.anchor.as_ref().map(|a| ContentAnchor {
+                        element_path: a.element_path.clone(),
+                        char_offset: a.char_offset + mat.start(),
+                    });
                     results.push(SearchMatch {
                         chapter_idx,
                         line: line_idx,
                         column: mat.start(),
                         match_length: mat.end() - mat.start(),
+                        anchor,
                     });
 
                     // Stop if we've hit the limit
@@ -184,6 +215,7 @@ mod tests {
                 publisher: None,
                 publication_date: None,
                 language: None,
+                identifier: None,
             },
             chapters: vec![Chapter {
                 title: "Chapter 1".to_string(),
@@ -195,6 +227,8 @@ mod tests {
                         search_matches: vec![],
                         inline_styles: vec![],
                         syntax_colors: vec![],
+                        links: vec![],
+                        anchor: None,
                     },
                     RenderedLine {
                         text: "Another test line here".to_string(),
@@ -202,10 +236,15 @@ mod tests {
                         search_matches: vec![],
                         inline_styles: vec![],
                         syntax_colors: vec![],
+                        links: vec![],
+                        anchor: None,
                     },
                 ],
                 file_path: "ch1.xhtml".to_string(),
+                href: "ch1.xhtml".to_string(),
+                document: Default::default(),
             }],
+            images: Default::default(),
         }
     }
 
@@ -225,6 +264,23 @@ mod tests {
         assert_eq!(results.len(), 2);
     }
 
+    #[test]
+    fn test_compile_options() {
+        let book = create_test_book();
+        let regex = SearchEngine::compile("TEST", true, false).unwrap();
+        assert_eq!(SearchEngine::search_regex(&book, &regex).unwrap().len(), 2);
+
+        // Fixed strings match regex syntax literally
+        let regex = SearchEngine::compile("a.test", false, true).unwrap();
+        assert!(
+            SearchEngine::search_regex(&book, &regex)
+                .unwrap()
+                .is_empty()
+        );
+        let regex = SearchEngine::compile("[invalid", false, true).unwrap();
+        assert!(regex.is_match("an [invalid pattern"));
+    }
+
     #[test]
     fn test_invalid_regex() {
         let book = create_test_book();
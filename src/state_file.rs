@@ -0,0 +1,276 @@
+//! Crash-safe, versioned JSON state files
+//!
+//! Every document written by [`write`] is a JSON object carrying a `version`
+//! field. Writes go to a temporary file that is synced and then renamed over
+//! the old file, so an interrupted write never leaves a half-written document
+//! behind; the previous contents are kept as a `.bak` backup. [`read`]
+//! upgrades documents written by older versions through a chain of
+//! migrations and reports damaged files instead of replacing them.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Upgrade of a document from one version to the next
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+/// A state file that exists but could not be turned into a document
+#[derive(Debug, Error)]
+#[error("{} could not be read: {reason}", path.display())]
+pub struct UnreadableFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Read a versioned document, upgrading it to the current version
+///
+/// Files without a `version` field are version 0. `migrations[n]` turns a
+/// version `n` document into version `n + 1`, so the current version is
+/// `migrations.len()`.
+///
+/// # Returns
+/// * `Ok(Some(T))` - The upgraded document
+/// * `Ok(None)` - The file does not exist
+/// * `Err(UnreadableFile)` - The file is damaged or from a newer version of reef
+pub fn read<T: DeserializeOwned>(
+    path: &Path,
+    migrations: &[Migration],
+) -> Result<Option<T>, UnreadableFile> {
+    let unreadable = |reason: String| UnreadableFile {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(unreadable(e.to_string())),
+    };
+    let mut value: Value =
+        serde_json::from_str(&content).map_err(|e| unreadable(format!("invalid JSON ({})", e)))?;
+
+    let version = match value.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| unreadable(format!("invalid version {}", version)))?
+            as usize,
+    };
+    if version > migrations.len() {
+        return Err(unreadable(format!(
+            "it was written by a newer version of reef (format {}, this version reads up to {})",
+            version,
+            migrations.len()
+        )));
+    }
+
+    for (from, migration) in migrations.iter().enumerate().skip(version) {
+        value = migration(value)
+            .map_err(|e| unreadable(format!("cannot upgrade from format {}: {}", from, e)))?;
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.remove("version");
+    }
+
+    serde_json::from_value(value)
+        .map(Some)
+        .map_err(|e| unreadable(e.to_string()))
+}
+
+/// Write a document atomically, tagged with its version
+///
+/// # Arguments
+/// * `path` - File to replace
+/// * `version` - Current version of the document's format
+/// * `document` - Must serialize to a JSON object
+pub fn write<T: Serialize>(path: &Path, version: usize, document: &T) -> io::Result<()> {
+    let mut value = serde_json::to_value(document)?;
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "document is not an object"))?;
+    object.insert("version".to_string(), Value::from(version));
+
+    let content = serde_json::to_string_pretty(&value)?;
+    write_atomic(path, content.as_bytes())
+}
+
+/// Replace a file without ever exposing a partially written version
+///
+/// The contents are written and synced to `<file>.tmp`, the current file is
+/// kept as `<file>.bak`, and the temporary file is renamed into place.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = sibling(path, "tmp");
+    {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        // A hard link keeps the old contents without copying them; the
+        // rename below then only replaces the original name
+        let backup = backup_path(path);
+        let _ = fs::remove_file(&backup);
+        if fs::hard_link(path, &backup).is_err() {
+            fs::copy(path, &backup)?;
+        }
+    }
+
+    fs::rename(&temp_path, path)?;
+    sync_parent(path);
+    Ok(())
+}
+
+/// Location of the backup of a state file
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, "bak")
+}
+
+/// Move a damaged file out of the way so it is kept but no longer read
+///
+/// Earlier damaged copies are never overwritten: the file becomes
+/// `<file>.damaged`, or `<file>.damaged.N` if that name is taken.
+///
+/// # Returns
+/// Where the damaged file now lives
+pub fn set_aside(path: &Path) -> io::Result<PathBuf> {
+    let mut damaged = sibling(path, "damaged");
+    let mut n = 1;
+    while damaged.exists() {
+        damaged = sibling(path, &format!("damaged.{}", n));
+        n += 1;
+    }
+    fs::rename(path, &damaged)?;
+    Ok(damaged)
+}
+
+/// Replace a (damaged) file with its backup
+///
+/// The damaged file is set aside first. The backup is moved rather than
+/// copied, so a backup that turns out to be damaged too is not offered again;
+/// the next write creates a new one.
+pub fn restore_backup(path: &Path) -> io::Result<()> {
+    let backup = backup_path(path);
+    if !backup.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no backup is available",
+        ));
+    }
+    if path.exists() {
+        set_aside(path)?;
+    }
+    fs::rename(&backup, path)?;
+    sync_parent(path);
+    Ok(())
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Make the rename durable; directories cannot be synced on every platform
+fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent()
+        && let Ok(dir) = fs::File::open(parent)
+    {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Document {
+        items: Vec<String>,
+    }
+
+    fn wrap_items(value: Value) -> Result<Value, String> {
+        match value {
+            Value::Array(_) => Ok(serde_json::json!({ "items": value })),
+            _ => Err("expected a list".to_string()),
+        }
+    }
+
+    const MIGRATIONS: &[Migration] = &[wrap_items];
+
+    #[test]
+    fn test_write_read_and_backup() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("state.json");
+        assert!(read::<Document>(&path, MIGRATIONS).unwrap().is_none());
+
+        let first = Document {
+            items: vec!["a".to_string()],
+        };
+        write(&path, 1, &first).unwrap();
+        let second = Document {
+            items: vec!["b".to_string()],
+        };
+        write(&path, 1, &second).unwrap();
+
+        assert!(
+            fs::read_to_string(&path)
+                .unwrap()
+                .contains("\"version\": 1")
+        );
+        assert_eq!(read(&path, MIGRATIONS).unwrap(), Some(second));
+        assert_eq!(read(&backup_path(&path), MIGRATIONS).unwrap(), Some(first));
+        assert!(!sibling(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn test_migrates_unversioned_documents() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("state.json");
+        fs::write(&path, r#"["x", "y"]"#).unwrap();
+
+        let document: Document = read(&path, MIGRATIONS).unwrap().unwrap();
+        assert_eq!(document.items, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn test_damaged_files_are_reported_and_recoverable() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("state.json");
+        let good = Document {
+            items: vec!["kept".to_string()],
+        };
+        write(&path, 1, &good).unwrap();
+        write(&path, 1, &good).unwrap();
+
+        // Truncated by a crash in an older version of reef
+        fs::write(&path, r#"{"version": 1, "items": ["ke"#).unwrap();
+        let error = read::<Document>(&path, MIGRATIONS).unwrap_err();
+        assert!(error.reason.contains("invalid JSON"));
+        // Reading never modifies the damaged file
+        assert!(path.exists());
+
+        restore_backup(&path).unwrap();
+        assert_eq!(read(&path, MIGRATIONS).unwrap(), Some(good));
+        assert!(sibling(&path, "damaged").exists());
+        assert!(restore_backup(&path).is_err());
+
+        // A second damaged copy does not replace the first
+        fs::write(&path, "{").unwrap();
+        assert_eq!(set_aside(&path).unwrap(), sibling(&path, "damaged.1"));
+
+        fs::write(&path, r#"{"version": 7, "items": []}"#).unwrap();
+        let error = read::<Document>(&path, MIGRATIONS).unwrap_err();
+        assert!(error.reason.contains("newer version"));
+    }
+}
@@ -79,7 +79,7 @@ impl TocManager {
     }
 
     /// Find the section index that contains the given line
-    fn find_section_at_line(chapter: &Chapter, cursor_line: usize) -> Option<usize> {
+    pub fn find_section_at_line(chapter: &Chapter, cursor_line: usize) -> Option<usize> {
         for (idx, section) in chapter.sections.iter().enumerate() {
             let next_start = chapter
                 .sections
@@ -125,6 +125,42 @@ impl TocManager {
         format!("chapter_{}_section_{}", chapter_idx, section_idx)
     }
 
+    /// Find a chapter or section by its title
+    ///
+    /// Titles are compared ignoring case. A title that equals `title` wins
+    /// over the first one that merely contains it.
+    ///
+    /// # Returns
+    /// The chapter index and, for sections, the section index
+    pub fn find_by_title(book: &Book, title: &str) -> Option<(usize, Option<usize>)> {
+        let title = title.trim().to_lowercase();
+        let entries: Vec<((usize, Option<usize>), String)> = book
+            .chapters
+            .iter()
+            .enumerate()
+            .flat_map(|(chapter_idx, chapter)| {
+                let sections =
+                    chapter
+                        .sections
+                        .iter()
+                        .enumerate()
+                        .map(move |(section_idx, section)| {
+                            (
+                                (chapter_idx, Some(section_idx)),
+                                section.title.to_lowercase(),
+                            )
+                        });
+                std::iter::once(((chapter_idx, None), chapter.title.to_lowercase())).chain(sections)
+            })
+            .collect();
+
+        entries
+            .iter()
+            .find(|(_, entry)| entry.trim() == title)
+            .or_else(|| entries.iter().find(|(_, entry)| entry.contains(&title)))
+            .map(|(position, _)| *position)
+    }
+
     /// Parse a TOC item ID to extract chapter and optional section indices
     pub fn parse_item_id(item_id: &str) -> Option<(usize, Option<usize>)> {
         if !item_id.starts_with("chapter_") {
@@ -160,6 +196,7 @@ mod tests {
                 publisher: None,
                 publication_date: None,
                 language: None,
+                identifier: None,
             },
             chapters: vec![
                 Chapter {
@@ -178,14 +215,19 @@ mod tests {
                     ],
                     content_lines: vec![],
                     file_path: String::new(),
+                    href: String::new(),
+                    document: Default::default(),
                 },
                 Chapter {
                     title: "Chapter 2".to_string(),
                     sections: vec![],
                     content_lines: vec![],
                     file_path: String::new(),
+                    href: String::new(),
+                    document: Default::default(),
                 },
             ],
+            images: Default::default(),
         }
     }
 
@@ -221,6 +263,23 @@ mod tests {
         assert_eq!(path[0], "chapter_1");
     }
 
+    #[test]
+    fn test_find_by_title() {
+        let book = create_test_book();
+        assert_eq!(
+            TocManager::find_by_title(&book, "chapter 2"),
+            Some((1, None))
+        );
+        assert_eq!(
+            TocManager::find_by_title(&book, "Section 1.2"),
+            Some((0, Some(1)))
+        );
+        // Contained titles are found in book order, after exact matches
+        assert_eq!(TocManager::find_by_title(&book, "1.1"), Some((0, Some(0))));
+        assert_eq!(TocManager::find_by_title(&book, "Chapter"), Some((0, None)));
+        assert_eq!(TocManager::find_by_title(&book, "Appendix"), None);
+    }
+
     #[test]
     fn test_parse_chapter_id() {
         let result = TocManager::parse_item_id("chapter_5");
@@ -12,6 +12,56 @@
 pub struct Book {
     pub metadata: BookMetadata,
     pub chapters: Vec<Chapter>,
+    /// Image resources referenced by the chapters
+    pub images: crate::images::ImageStore,
+}
+
+impl Book {
+    /// Share of the book before a rendered line, in percent
+    ///
+    /// Chapters are weighted by their number of rendered lines.
+    pub fn percent_read(&self, chapter_idx: usize, line: usize) -> u8 {
+        let total: usize = self.chapters.iter().map(|c| c.content_lines.len()).sum();
+        if total == 0 {
+            return 0;
+        }
+        let before: usize = self
+            .chapters
+            .iter()
+            .take(chapter_idx)
+            .map(|c| c.content_lines.len())
+            .sum();
+        let within = self
+            .chapters
+            .get(chapter_idx)
+            .map_or(0, |c| (line + 1).min(c.content_lines.len()));
+        ((before + within) * 100 / total) as u8
+    }
+
+    /// Rendered line at a share of the book, the inverse of [`percent_read`](Self::percent_read)
+    ///
+    /// # Returns
+    /// The chapter index and line, or `None` when nothing has been rendered
+    pub fn line_at_percent(&self, percent: u8) -> Option<(usize, usize)> {
+        let total: usize = self.chapters.iter().map(|c| c.content_lines.len()).sum();
+        let mut remaining = total * percent.min(100) as usize / 100;
+        for (chapter_idx, chapter) in self.chapters.iter().enumerate() {
+            if remaining < chapter.content_lines.len() {
+                return Some((chapter_idx, remaining));
+            }
+            remaining -= chapter.content_lines.len();
+        }
+
+        // All of the book: its last line
+        let chapter_idx = self
+            .chapters
+            .iter()
+            .rposition(|c| !c.content_lines.is_empty())?;
+        Some((
+            chapter_idx,
+            self.chapters[chapter_idx].content_lines.len() - 1,
+        ))
+    }
 }
 
 /// Represents a single chapter in an EPUB book
@@ -23,8 +73,86 @@ pub struct Chapter {
     pub sections: Vec<Section>,
     /// Rendered lines of text ready for display
     pub content_lines: Vec<RenderedLine>,
-    /// Original HTML file path or content (used for re-rendering)
+    /// Original HTML content (used to resolve CFIs)
     pub file_path: String,
+    /// Path of the chapter file inside the EPUB archive (used to resolve links)
+    pub href: String,
+    /// Width-independent document model laid out by the renderer
+    pub document: crate::epub::document::Document,
+}
+
+impl Chapter {
+    /// Get the content anchor for a rendered line
+    ///
+    /// Lines without an anchor of their own (blank separators) use the anchor
+    /// of the nearest preceding anchored line, falling back to the next one.
+    pub fn anchor_at_line(&self, line: usize) -> Option<ContentAnchor> {
+        let line = line.min(self.content_lines.len().saturating_sub(1));
+        self.content_lines
+            .get(..=line)
+            .and_then(|lines| lines.iter().rev().find_map(|l| l.anchor.clone()))
+            .or_else(|| {
+                self.content_lines
+                    .get(line..)
+                    .and_then(|lines| lines.iter().find_map(|l| l.anchor.clone()))
+            })
+    }
+
+    /// Find the rendered line that contains a content anchor
+    ///
+    /// Returns the last line whose anchor is at or before the target, so an
+    /// anchor recorded at any wrap width lands on the line holding that text.
+    pub fn line_for_anchor(&self, anchor: &ContentAnchor) -> usize {
+        self.content_lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.anchor.as_ref().is_some_and(|a| a <= anchor))
+            .map(|(idx, _)| idx)
+            .next_back()
+            .unwrap_or(0)
+    }
+
+    /// Find the range of consecutive code block lines around a line
+    ///
+    /// # Returns
+    /// * `Some(range)` - Lines of the code block containing `line`
+    /// * `None` - The line is not part of a code block
+    pub fn code_block_at(&self, line: usize) -> Option<std::ops::Range<usize>> {
+        let is_code = |idx: usize| {
+            matches!(
+                self.content_lines.get(idx).map(|l| &l.style),
+                Some(LineStyle::CodeBlock { .. })
+            )
+        };
+        if !is_code(line) {
+            return None;
+        }
+        let start = (0..line)
+            .rev()
+            .take_while(|&idx| is_code(idx))
+            .last()
+            .unwrap_or(line);
+        let end = (line..self.content_lines.len())
+            .take_while(|&idx| is_code(idx))
+            .last()
+            .map_or(line, |idx| idx + 1);
+        Some(start..end)
+    }
+}
+
+/// Width-independent position within a chapter's source document
+///
+/// Rendered line numbers are only valid for the width they were wrapped at,
+/// so positions that must survive a re-render are stored as an anchor instead.
+/// Anchors order by document position, which makes them cheap to map back.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct ContentAnchor {
+    /// Element-child indices from `<body>` down to the block element
+    pub element_path: Vec<usize>,
+    /// Byte offset into the block's text
+    pub char_offset: usize,
 }
 
 /// Represents a section within a chapter (e.g., h2/h3 headings)
@@ -90,6 +218,10 @@ pub struct RenderedLine {
     /// Syntax highlighting color spans (start, end, color) for code blocks
     /// Each span defines a range of characters and their foreground color
     pub syntax_colors: Vec<(usize, usize, ratatui::style::Color)>,
+    /// Hyperlink spans (start, end, href) with the raw target from the source
+    pub links: Vec<(usize, usize, String)>,
+    /// Source position of the first character on this line (None for blank separators)
+    pub anchor: Option<ContentAnchor>,
 }
 
 /// Visual style options for rendering text lines
@@ -99,9 +231,18 @@ pub enum LineStyle {
     Heading1,
     Heading2,
     Heading3,
-    CodeBlock { language: Option<String> },
+    CodeBlock {
+        language: Option<String>,
+    },
     Quote,
     Link,
+    /// One row of an inline image (`row` of `rows`, `cols` cells wide)
+    Image {
+        path: String,
+        row: u16,
+        rows: u16,
+        cols: u16,
+    },
 }
 
 /// Inline text styling options (bold, italic, code, etc.)
@@ -113,16 +254,33 @@ pub enum InlineStyle {
     Underline,
     Strikethrough,
     Highlight,
+    /// Reference to a footnote or endnote
+    NoteRef,
 }
 
 /// EPUB metadata extracted from the book
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BookMetadata {
     pub title: String,
     pub author: Option<String>,
     pub publisher: Option<String>,
     pub publication_date: Option<String>,
     pub language: Option<String>,
+    /// The package's `unique-identifier` (an ISBN, UUID or URI)
+    pub identifier: Option<String>,
+}
+
+/// Package metadata only shown by `reef info`
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct BookDetails {
+    pub subjects: Vec<String>,
+    /// Description as plain text, markup removed
+    pub description: Option<String>,
+    pub series: Option<String>,
+    /// Position in the series as written in the book (e.g. "2" or "2.5")
+    pub series_index: Option<String>,
+    /// Whether the package declares a cover image
+    pub cover: bool,
 }
 
 /// Viewport configuration for rendering content
@@ -142,10 +300,11 @@ pub enum FocusTarget {
     Content,
     Toc,
     Bookmarks,
+    Annotations,
 }
 
 /// User-configurable settings persisted across sessions
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Config {
     /// Maximum line width for text wrapping (None = use full terminal width)
     pub max_width: Option<usize>,
@@ -153,6 +312,21 @@ pub struct Config {
     pub toc_panel_width: u16,
     /// Width of the bookmarks panel in columns
     pub bookmarks_panel_width: u16,
+    /// Soft-wrap long code block lines instead of scrolling them horizontally
+    #[serde(default)]
+    pub wrap_code: bool,
+    /// External command that receives yanked text on stdin (e.g. `wl-copy`)
+    #[serde(default)]
+    pub clipboard_command: Option<String>,
+    /// Directories scanned for EPUB files to show in the library (`~` expands)
+    #[serde(default)]
+    pub library_dirs: Vec<String>,
+    /// Order of the books in the library view
+    #[serde(default)]
+    pub library_sort: LibrarySort,
+    /// Accept commands from `reef remote` over a Unix socket
+    #[serde(default)]
+    pub remote_control: bool,
 }
 
 impl Default for Config {
@@ -161,6 +335,11 @@ impl Default for Config {
             max_width: None,
             toc_panel_width: 34,
             bookmarks_panel_width: 34,
+            wrap_code: false,
+            clipboard_command: None,
+            library_dirs: Vec::new(),
+            library_sort: LibrarySort::default(),
+            remote_control: false,
         }
     }
 }
@@ -176,6 +355,8 @@ pub struct SearchMatch {
     pub column: usize,
     /// Length of the matched text in characters
     pub match_length: usize,
+    /// Source position of the match start, used to follow it across re-renders
+    pub anchor: Option<ContentAnchor>,
 }
 
 /// User-created bookmark for quick navigation
@@ -187,6 +368,217 @@ pub struct Bookmark {
     pub line: usize,
     /// User-provided label for this bookmark
     pub label: String,
+    /// Width-independent position, used to recompute `line` after re-rendering
+    #[serde(default)]
+    pub anchor: Option<ContentAnchor>,
+    /// EPUB CFI of the position, for exchanging locations with other readers
+    #[serde(default)]
+    pub cfi: Option<String>,
+}
+
+/// Highlighted passage of text with an optional note
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Highlight {
+    /// Chapter index containing the passage
+    pub chapter_idx: usize,
+    /// Width-independent start of the passage
+    pub start: ContentAnchor,
+    /// Width-independent end of the passage (exclusive)
+    pub end: ContentAnchor,
+    /// Text of the passage when it was highlighted
+    pub text: String,
+    pub color: HighlightColor,
+    /// Margin note attached to the passage
+    #[serde(default)]
+    pub note: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Orders the library view can be sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LibrarySort {
+    /// Most recently read first
+    #[default]
+    Recent,
+    Title,
+    Author,
+    /// Furthest read first
+    Progress,
+}
+
+impl LibrarySort {
+    pub const ALL: [LibrarySort; 4] = [
+        LibrarySort::Recent,
+        LibrarySort::Title,
+        LibrarySort::Author,
+        LibrarySort::Progress,
+    ];
+
+    /// The order after this one, wrapping around
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    /// Display name of the order
+    pub fn name(self) -> &'static str {
+        match self {
+            LibrarySort::Recent => "recent",
+            LibrarySort::Title => "title",
+            LibrarySort::Author => "author",
+            LibrarySort::Progress => "progress",
+        }
+    }
+}
+
+/// Background colors a highlight can be drawn with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightColor {
+    #[default]
+    Yellow,
+    Green,
+    Blue,
+    Pink,
+}
+
+impl HighlightColor {
+    pub const ALL: [HighlightColor; 4] = [
+        HighlightColor::Yellow,
+        HighlightColor::Green,
+        HighlightColor::Blue,
+        HighlightColor::Pink,
+    ];
+
+    /// The color after this one, wrapping around
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&c| c == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    /// Display name of the color
+    pub fn name(self) -> &'static str {
+        match self {
+            HighlightColor::Yellow => "yellow",
+            HighlightColor::Green => "green",
+            HighlightColor::Blue => "blue",
+            HighlightColor::Pink => "pink",
+        }
+    }
+
+    /// Background color used to paint highlighted text
+    pub fn background(self) -> ratatui::style::Color {
+        use ratatui::style::Color;
+        match self {
+            HighlightColor::Yellow => Color::Rgb(110, 95, 20),
+            HighlightColor::Green => Color::Rgb(40, 90, 45),
+            HighlightColor::Blue => Color::Rgb(35, 65, 120),
+            HighlightColor::Pink => Color::Rgb(115, 45, 85),
+        }
+    }
+}
+
+/// Where a hyperlink leads
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkTarget {
+    /// A position inside the book (anchor is None for the start of a chapter)
+    Internal {
+        chapter_idx: usize,
+        anchor: Option<ContentAnchor>,
+    },
+    /// A link leaving the book (web page, email address, ...)
+    External(String),
+}
+
+/// A visible link labelled for selection in link-hint mode
+#[derive(Debug, Clone)]
+pub struct LinkHint {
+    /// Label the user types to follow the link
+    pub label: String,
+    /// Line number within the current chapter
+    pub line: usize,
+    /// Byte offset of the link start within the line
+    pub column: usize,
+    /// Raw link target
+    pub href: String,
+    /// True when the link references a footnote or endnote
+    pub note: bool,
+}
+
+/// Content of a footnote or endnote shown in a popup
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    /// Title of the chapter the note lives in
+    pub chapter_title: String,
+    /// Paragraphs of the note text
+    pub paragraphs: Vec<String>,
+}
+
+/// A reading position that can be returned to
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Location {
+    /// Chapter index of the position
+    pub chapter_idx: usize,
+    /// Line number within the chapter when the location was recorded
+    pub line: usize,
+    /// Width-independent position, preferred over `line` when available
+    #[serde(default)]
+    pub anchor: Option<ContentAnchor>,
+}
+
+impl Location {
+    /// Check whether two locations point at the same place in the book
+    pub fn same_place(&self, other: &Location) -> bool {
+        self.chapter_idx == other.chapter_idx
+            && match (&self.anchor, &other.anchor) {
+                (Some(a), Some(b)) => a == b,
+                _ => self.line == other.line,
+            }
+    }
+}
+
+/// Where to open a book instead of its saved position (given on the command line
+/// or by a remote control request)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartPosition {
+    /// An EPUB CFI
+    Cfi(String),
+    /// Chapter number, counted from 1
+    Chapter(usize),
+    /// Title of a chapter or section
+    Section(String),
+    Percent(u8),
+    /// First match of a search regex
+    Search(String),
+    /// Label of a bookmark
+    Bookmark(String),
+}
+
+impl std::fmt::Display for StartPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartPosition::Cfi(cfi) => write!(f, "{}", cfi),
+            StartPosition::Chapter(number) => write!(f, "chapter {}", number),
+            StartPosition::Section(title) => write!(f, "section '{}'", title),
+            StartPosition::Percent(percent) => write!(f, "{}%", percent),
+            StartPosition::Search(query) => write!(f, "search '{}'", query),
+            StartPosition::Bookmark(label) => write!(f, "bookmark '{}'", label),
+        }
+    }
+}
+
+/// A book whose reading status and tags are being edited
+///
+/// The tags being typed are kept in the input buffer.
+#[derive(Debug, Clone)]
+pub struct CollectionsEdit {
+    pub book_id: String,
+    pub title: String,
+    pub status: Option<crate::collections::ReadingStatus>,
+    /// Library filter to restore when the prompt was opened from the library
+    pub library_filter: Option<String>,
 }
 
 /// Current UI mode determining which controls are active
@@ -198,14 +590,39 @@ pub enum UiMode {
     SearchPopup,
     /// Bookmark creation prompt is open
     BookmarkPrompt,
-    /// Book selection dialog is open
-    BookPicker,
+    /// Library of known books is open
+    Library,
+    /// Reading status and tags of a book are being edited
+    CollectionsPrompt,
     /// Help screen is displayed
     Help,
     /// Metadata information popup is displayed
     MetadataPopup,
+    /// Footnote or endnote content popup is displayed
+    FootnotePopup,
     /// Error message popup with error text
     ErrorPopup(String),
+    /// Labels are shown over visible links, typing one follows the link
+    LinkHints,
+    /// Full-screen image viewer is displayed
+    ImageViewer,
+    /// Highlight color and note prompt is open
+    HighlightPrompt,
+    /// Bookmark export format choice is open
+    ExportPrompt,
+    /// A saved state file is damaged and the user chooses how to recover
+    StateFilePopup,
+}
+
+/// How to continue after a saved state file failed to load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateRecovery {
+    /// Replace the damaged file with its backup and load that
+    RestoreBackup,
+    /// Move the damaged file aside and start with empty state
+    StartFresh,
+    /// Leave the file alone and do not save over it this session
+    KeepFile,
 }
 
 /// Saved UI state for restoring after exiting zen mode
@@ -213,6 +630,7 @@ pub enum UiMode {
 pub struct ZenModeState {
     pub toc_visible: bool,
     pub bookmarks_visible: bool,
+    pub annotations_visible: bool,
     pub statusbar_visible: bool,
     pub titlebar_visible: bool,
 }
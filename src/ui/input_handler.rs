@@ -4,9 +4,12 @@
 //! handlers based on current UI mode and focus state.
 
 use crate::app::AppState;
-use crate::constants::{MAX_BOOKMARK_INPUT_LENGTH, MAX_SEARCH_INPUT_LENGTH};
+use crate::constants::{
+    CODE_SCROLL_STEP, MAX_BOOKMARK_INPUT_LENGTH, MAX_NOTE_INPUT_LENGTH, MAX_SEARCH_INPUT_LENGTH,
+};
 use crate::error::Result;
-use crate::types::{FocusTarget, UiMode};
+use crate::export::ExportFormat;
+use crate::types::{FocusTarget, StateRecovery, UiMode};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 pub struct InputHandler;
@@ -42,6 +45,10 @@ impl InputHandler {
                 app.toggle_bookmarks();
                 true
             }
+            KeyCode::Char('A') => {
+                app.toggle_annotations();
+                true
+            }
             KeyCode::Char('z') => {
                 app.toggle_zen_mode();
                 true
@@ -63,6 +70,10 @@ impl InputHandler {
                 app.focus_bookmarks();
                 true
             }
+            KeyCode::Char('4') => {
+                app.focus_annotations();
+                true
+            }
             _ => false,
         }
     }
@@ -72,10 +83,17 @@ impl InputHandler {
         match &app.ui_mode {
             UiMode::SearchPopup => Self::handle_search_popup(app, key),
             UiMode::BookmarkPrompt => Self::handle_bookmark_prompt(app, key),
-            UiMode::BookPicker => Self::handle_book_picker(app, key),
+            UiMode::HighlightPrompt => Self::handle_highlight_prompt(app, key),
+            UiMode::ExportPrompt => Self::handle_export_prompt(app, key),
+            UiMode::StateFilePopup => Self::handle_state_file_popup(app, key),
+            UiMode::Library => Self::handle_library(app, key),
+            UiMode::CollectionsPrompt => Self::handle_collections_prompt(app, key),
             UiMode::Help => Self::handle_help(app, key),
             UiMode::MetadataPopup => Self::handle_metadata_popup(app, key),
+            UiMode::FootnotePopup => Self::handle_footnote_popup(app, key),
             UiMode::ErrorPopup(_) => Self::handle_error_popup(app, key),
+            UiMode::LinkHints => Self::handle_link_hints(app, key),
+            UiMode::ImageViewer => Self::handle_image_viewer(app, key),
             UiMode::Normal => {
                 // Route based on focus
                 match app.focus {
@@ -83,6 +101,9 @@ impl InputHandler {
                     FocusTarget::Bookmarks if app.bookmarks_panel_visible => {
                         Self::handle_bookmarks(app, key)
                     }
+                    FocusTarget::Annotations if app.annotations_panel_visible => {
+                        Self::handle_annotations(app, key)
+                    }
                     _ => Self::handle_content(app, key),
                 }
             }
@@ -96,48 +117,24 @@ impl InputHandler {
                 app.ui_mode = UiMode::Normal;
                 app.input_buffer.clear();
             }
-            KeyCode::Enter => {
-                if !app.input_buffer.is_empty() {
-                    log::info!("Executing search: query='{}'", app.input_buffer);
-                    // Perform search
-                    if let Some(book) = &mut app.book {
-                        match crate::search::SearchEngine::search(book, &app.input_buffer) {
-                            Ok(results) => {
-                                log::info!("Search completed: {} results found", results.len());
-                                app.search_query = app.input_buffer.clone();
-                                app.search_results = results;
-                                app.current_search_idx = 0;
-
-                                // Apply highlights
-                                crate::search::SearchEngine::apply_highlights(
-                                    book,
-                                    &app.search_results,
-                                );
-
-                                // Jump to first result if any
-                                if !app.search_results.is_empty() {
-                                    log::debug!("Jumping to first search result");
-                                    app.next_search_result();
-                                }
-
-                                app.ui_mode = UiMode::Normal;
-                                app.input_buffer.clear();
-                            }
-                            Err(e) => {
-                                log::warn!("Search failed: {}", e);
-                                // Keep popup open on error
-                            }
-                        }
+            KeyCode::Enter if !app.input_buffer.is_empty() => {
+                let query = app.input_buffer.clone();
+                match app.search(&query) {
+                    Ok(_) => {
+                        app.ui_mode = UiMode::Normal;
+                        app.input_buffer.clear();
+                    }
+                    Err(e) => {
+                        log::warn!("Search failed: {}", e);
+                        // Keep popup open on error
                     }
                 }
             }
             KeyCode::Backspace => {
                 app.input_buffer.pop();
             }
-            KeyCode::Char(c) => {
-                if app.input_buffer.len() < MAX_SEARCH_INPUT_LENGTH {
-                    app.input_buffer.push(c);
-                }
+            KeyCode::Char(c) if app.input_buffer.len() < MAX_SEARCH_INPUT_LENGTH => {
+                app.input_buffer.push(c);
             }
             _ => {}
         }
@@ -151,73 +148,145 @@ impl InputHandler {
                 app.ui_mode = UiMode::Normal;
                 app.input_buffer.clear();
             }
-            KeyCode::Enter => {
-                if !app.input_buffer.is_empty() {
-                    log::debug!("Creating bookmark: label='{}'", app.input_buffer);
-                    // Add bookmark
-                    let result = crate::bookmarks::BookmarkManager::add_bookmark(
-                        &mut app.bookmarks,
-                        app.current_chapter,
-                        app.cursor_line,
-                        app.input_buffer.clone(),
-                    );
-
-                    if result.is_ok() {
-                        app.ui_mode = UiMode::Normal;
-                        app.input_buffer.clear();
-                    } else if let Err(e) = result {
-                        log::warn!("Bookmark creation failed: {}", e);
-                    }
-                    // If error, keep popup open
+            KeyCode::Enter if !app.input_buffer.is_empty() => {
+                log::debug!("Creating bookmark: label='{}'", app.input_buffer);
+                // Add bookmark
+                let result = app.add_bookmark(app.input_buffer.clone());
+
+                if result.is_ok() {
+                    app.ui_mode = UiMode::Normal;
+                    app.input_buffer.clear();
+                } else if let Err(e) = result {
+                    log::warn!("Bookmark creation failed: {}", e);
                 }
+                // If error, keep popup open
             }
             KeyCode::Backspace => {
                 app.input_buffer.pop();
             }
-            KeyCode::Char(c) => {
-                if app.input_buffer.len() < MAX_BOOKMARK_INPUT_LENGTH {
-                    app.input_buffer.push(c);
+            KeyCode::Char(c) if app.input_buffer.len() < MAX_BOOKMARK_INPUT_LENGTH => {
+                app.input_buffer.push(c);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_highlight_prompt(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Esc => {
+                log::debug!("Highlight cancelled by user");
+                app.cancel_highlight_prompt();
+            }
+            KeyCode::Enter => {
+                // The note is optional, so an empty buffer still saves
+                if let Err(e) = app.save_pending_highlight() {
+                    log::warn!("Highlight creation failed: {}", e);
                 }
             }
+            KeyCode::Tab => app.cycle_highlight_color(),
+            KeyCode::Backspace => {
+                app.input_buffer.pop();
+            }
+            KeyCode::Char(c) if app.input_buffer.len() < MAX_NOTE_INPUT_LENGTH => {
+                app.input_buffer.push(c);
+            }
             _ => {}
         }
         Ok(())
     }
 
-    fn handle_book_picker(app: &mut AppState, key: KeyEvent) -> Result<()> {
+    fn handle_export_prompt(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        let format = match key.code {
+            KeyCode::Char('m') => Some(ExportFormat::Markdown),
+            KeyCode::Char('j') => Some(ExportFormat::Json),
+            KeyCode::Char('o') => Some(ExportFormat::Org),
+            KeyCode::Esc => None,
+            _ => return Ok(()),
+        };
+
+        app.ui_mode = UiMode::Normal;
+        if let Some(format) = format {
+            app.export_bookmarks(format);
+        }
+        Ok(())
+    }
+
+    fn handle_state_file_popup(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        let recovery = match key.code {
+            KeyCode::Char('r')
+                if app
+                    .state_problems
+                    .first()
+                    .is_some_and(|problem| app.persistence.has_backup(&problem.file)) =>
+            {
+                StateRecovery::RestoreBackup
+            }
+            KeyCode::Char('d') => StateRecovery::StartFresh,
+            KeyCode::Esc => StateRecovery::KeepFile,
+            _ => return Ok(()),
+        };
+
+        app.recover_state_file(recovery);
+        Ok(())
+    }
+
+    fn handle_library(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
         match key.code {
+            KeyCode::Esc if !app.input_buffer.is_empty() => {
+                app.input_buffer.clear();
+                app.library_filter_changed();
+            }
             KeyCode::Esc => {
-                log::debug!("Book picker closed");
+                log::debug!("Library closed");
                 if app.book.is_none() {
                     // No book loaded, exit app
                     app.should_quit = true;
                 } else {
-                    // Book loaded, close picker
+                    // Book loaded, close library
                     app.ui_mode = UiMode::Normal;
+                    if let Some(prev_focus) = app.previous_focus.take() {
+                        app.focus = prev_focus;
+                    }
                 }
             }
-            KeyCode::Char('q') => {
+            KeyCode::Char('c') if ctrl => {
                 app.should_quit = true;
             }
-            KeyCode::Char('j') | KeyCode::Down => {
-                if let Some(idx) = app.book_picker_selected_idx {
-                    let next_idx = (idx + 1).min(app.recent_books.len().saturating_sub(1));
-                    app.book_picker_selected_idx = Some(next_idx);
-                }
+            KeyCode::Down | KeyCode::Tab => app.library_next(),
+            KeyCode::Char('n') | KeyCode::Char('j') if ctrl => app.library_next(),
+            KeyCode::Up | KeyCode::BackTab => app.library_previous(),
+            KeyCode::Char('p') | KeyCode::Char('k') if ctrl => app.library_previous(),
+            KeyCode::Enter => app.open_selected_library_entry(),
+            KeyCode::Char('s') if ctrl => app.cycle_library_sort(),
+            KeyCode::Char('r') if ctrl => app.scan_library(),
+            KeyCode::Char('d') if ctrl => app.remove_selected_from_recent(),
+            KeyCode::Char('t') if ctrl => app.edit_selected_library_entry(),
+            KeyCode::Delete => app.remove_selected_from_recent(),
+            KeyCode::Backspace => {
+                app.input_buffer.pop();
+                app.library_filter_changed();
             }
-            KeyCode::Char('k') | KeyCode::Up => {
-                if let Some(idx) = app.book_picker_selected_idx {
-                    app.book_picker_selected_idx = Some(idx.saturating_sub(1));
-                }
+            KeyCode::Char(c) if !ctrl && app.input_buffer.len() < MAX_SEARCH_INPUT_LENGTH => {
+                app.input_buffer.push(c);
+                app.library_filter_changed();
             }
-            KeyCode::Enter => {
-                if let Some(idx) = app.book_picker_selected_idx
-                    && let Some(book_path) = app.recent_books.get(idx).cloned()
-                {
-                    log::info!("Loading book from picker: {}", book_path);
-                    // Load the selected book asynchronously
-                    app.load_book_async(book_path);
-                }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_collections_prompt(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Esc => app.close_collections_prompt(),
+            KeyCode::Enter => app.save_collections_edit(),
+            KeyCode::Tab => app.cycle_edited_status(),
+            KeyCode::Backspace => {
+                app.input_buffer.pop();
+            }
+            KeyCode::Char(c) if app.input_buffer.len() < MAX_NOTE_INPUT_LENGTH => {
+                app.input_buffer.push(c);
             }
             _ => {}
         }
@@ -250,6 +319,50 @@ impl InputHandler {
         Ok(())
     }
 
+    fn handle_footnote_popup(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => app.close_footnote(),
+            KeyCode::Char('j') | KeyCode::Down => {
+                app.footnote_scroll = app.footnote_scroll.saturating_add(1);
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                app.footnote_scroll = app.footnote_scroll.saturating_sub(1);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_image_viewer(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.close_image_viewer(),
+            KeyCode::Enter => app.goto_viewer_image(),
+            _ => {
+                let Some(viewer) = &mut app.image_viewer else {
+                    return Ok(());
+                };
+                match key.code {
+                    // Image navigation
+                    KeyCode::Char('n') | KeyCode::Char(' ') | KeyCode::PageDown => viewer.next(),
+                    KeyCode::Char('N') | KeyCode::Char('p') | KeyCode::PageUp => viewer.previous(),
+
+                    // Zoom
+                    KeyCode::Char('+') | KeyCode::Char('=') => viewer.zoom_in(),
+                    KeyCode::Char('-') => viewer.zoom_out(),
+                    KeyCode::Char('0') => viewer.reset(),
+
+                    // Pan
+                    KeyCode::Char('h') | KeyCode::Left => viewer.pan(-1.0, 0.0),
+                    KeyCode::Char('l') | KeyCode::Right => viewer.pan(1.0, 0.0),
+                    KeyCode::Char('k') | KeyCode::Up => viewer.pan(0.0, -1.0),
+                    KeyCode::Char('j') | KeyCode::Down => viewer.pan(0.0, 1.0),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn handle_error_popup(app: &mut AppState, key: KeyEvent) -> Result<()> {
         match key.code {
             KeyCode::Esc | KeyCode::Enter => {
@@ -260,6 +373,15 @@ impl InputHandler {
         Ok(())
     }
 
+    fn handle_link_hints(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Esc => app.cancel_link_hints(),
+            KeyCode::Char(c) if c.is_ascii_alphabetic() => app.link_hint_input(c),
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn handle_bookmarks(app: &mut AppState, key: KeyEvent) -> Result<()> {
         // Try common controls first
         if Self::handle_common_controls(app, key) {
@@ -285,6 +407,34 @@ impl InputHandler {
         Ok(())
     }
 
+    fn handle_annotations(app: &mut AppState, key: KeyEvent) -> Result<()> {
+        // Try common controls first
+        if Self::handle_common_controls(app, key) {
+            return Ok(());
+        }
+
+        // Highlight-specific controls
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => {
+                app.highlight_next();
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                app.highlight_previous();
+            }
+            KeyCode::Enter => {
+                app.jump_to_selected_highlight();
+            }
+            KeyCode::Char('e') => {
+                app.edit_selected_highlight();
+            }
+            KeyCode::Char('d') => {
+                app.delete_selected_highlight();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn handle_toc(app: &mut AppState, key: KeyEvent) -> Result<()> {
         // Try common controls first
         if Self::handle_common_controls(app, key) {
@@ -377,18 +527,30 @@ impl InputHandler {
                 Self::open_bookmark_prompt(app)
             }
 
+            KeyCode::Char('E') if app.book.is_some() => Self::open_popup(app, UiMode::ExportPrompt),
+
+            // Links
+            KeyCode::Char('f') => app.start_link_hints(),
+            KeyCode::Enter => app.open_at_cursor(),
+            KeyCode::Backspace => app.jump_back(),
+
+            // Jump list (Ctrl-i is only distinct from Tab with keyboard enhancement)
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => app.jump_back(),
+            KeyCode::Char('i') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.jump_forward()
+            }
+
             // Help
             KeyCode::Char('?') | KeyCode::F(1) => Self::open_help(app),
 
             // Metadata popup
             KeyCode::Char('I') => Self::open_metadata_popup(app),
 
-            // Book picker
-            KeyCode::Char('o') | KeyCode::Char('O')
-                if key.modifiers.contains(KeyModifiers::CONTROL) =>
-            {
-                Self::open_book_picker(app)
-            }
+            // Reading status and tags
+            KeyCode::Char('T') => app.open_collections_prompt(),
+
+            // Library
+            KeyCode::Char('o') | KeyCode::Char('O') => Self::open_library(app),
 
             // Cursor movement
             KeyCode::Char('H') => app.move_cursor_to_top(),
@@ -407,13 +569,30 @@ impl InputHandler {
             KeyCode::Left if key.modifiers.contains(KeyModifiers::ALT) => app.previous_section(),
             KeyCode::Right if key.modifiers.contains(KeyModifiers::ALT) => app.next_section(),
 
+            // Code blocks (horizontal scrolling only applies with the cursor in one)
+            KeyCode::Char('h') | KeyCode::Left => app.scroll_code(-CODE_SCROLL_STEP),
+            KeyCode::Char('l') | KeyCode::Right => app.scroll_code(CODE_SCROLL_STEP),
+            KeyCode::Char('0') => app.reset_code_scroll(),
+            KeyCode::Char('W') => app.toggle_code_wrap(),
+
+            // Selection and clipboard
+            KeyCode::Char('v') | KeyCode::Char('V') => app.toggle_visual_selection(),
+            KeyCode::Char('y') => app.yank_selection(),
+            KeyCode::Char('Y') => app.yank_code_block(),
+
+            // Highlights
+            KeyCode::Char('a') => app.start_highlight(),
+
             _ => {}
         }
         Ok(())
     }
 
     fn handle_escape(app: &mut AppState) {
-        if !app.search_results.is_empty() {
+        // A selection is cancelled before search highlights are cleared
+        if app.visual_selection().is_some() {
+            app.visual_anchor = None;
+        } else if !app.search_results.is_empty() {
             // Clear highlights from book
             if let Some(book) = &mut app.book {
                 crate::search::SearchEngine::clear_highlights(book);
@@ -462,18 +641,8 @@ impl InputHandler {
         Self::open_popup(app, UiMode::MetadataPopup);
     }
 
-    fn open_book_picker(app: &mut AppState) {
-        Self::open_popup(app, UiMode::BookPicker);
-
-        // Set selection to current book if available
-        if let Some(current_path) = &app.current_book_path {
-            app.book_picker_selected_idx = app
-                .recent_books
-                .iter()
-                .position(|p| p == current_path)
-                .or(Some(0));
-        } else {
-            app.book_picker_selected_idx = Some(0);
-        }
+    fn open_library(app: &mut AppState) {
+        Self::open_popup(app, UiMode::Library);
+        app.open_library();
     }
 }
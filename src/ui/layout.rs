@@ -3,8 +3,10 @@
 //! This module handles the overall layout of the application UI,
 //! including title bar, status bar, content area, and side panels.
 
+use crate::annotations::AnnotationManager;
 use crate::app::AppState;
-use crate::types::{FocusTarget, InlineStyle, LineStyle, LoadingState, UiMode};
+use crate::images::protocol::{ImagePlacement, cell_size};
+use crate::types::{FocusTarget, HighlightColor, InlineStyle, LineStyle, LoadingState, UiMode};
 use crate::ui::widgets;
 use ratatui::{
     Frame,
@@ -13,6 +15,7 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph, Wrap},
 };
+use textwrap::core::display_width;
 use tui_tree_widget::Tree;
 
 pub fn render(f: &mut Frame, app: &mut AppState) {
@@ -40,7 +43,7 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
         chunk_idx += 1;
     }
 
-    // Render content area (may include TOC and Bookmarks panels)
+    // Render content area (may include TOC, Bookmarks and Highlights panels)
     let content_area = main_chunks[chunk_idx];
     chunk_idx += 1;
 
@@ -50,7 +53,7 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
         toc_bookmarks_constraints.push(Constraint::Length(1)); // Left margin
     }
     toc_bookmarks_constraints.push(Constraint::Min(0)); // Main content
-    if app.bookmarks_panel_visible {
+    if app.right_panel_visible() {
         toc_bookmarks_constraints.push(Constraint::Length(1)); // Right margin
         toc_bookmarks_constraints.push(Constraint::Length(app.config.bookmarks_panel_width));
     }
@@ -67,12 +70,26 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
         // Skip the left margin chunk
         chunk_index += 1;
     }
-    render_content(f, app, content_chunks[chunk_index]);
+    let mut placements = render_content(f, app, content_chunks[chunk_index]);
     chunk_index += 1;
-    if app.bookmarks_panel_visible {
+    if app.right_panel_visible() {
         // Skip the right margin chunk
         chunk_index += 1;
-        render_bookmarks(f, app, content_chunks[chunk_index]);
+        let right_area = content_chunks[chunk_index];
+
+        // Bookmarks and highlights share the column, stacked when both are shown
+        match (app.bookmarks_panel_visible, app.annotations_panel_visible) {
+            (true, true) => {
+                let halves = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(right_area);
+                render_bookmarks(f, app, halves[0]);
+                render_annotations(f, app, halves[1]);
+            }
+            (true, false) => render_bookmarks(f, app, right_area),
+            _ => render_annotations(f, app, right_area),
+        }
     }
 
     // Render statusbar if visible
@@ -122,27 +139,78 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
                 error,
             );
         }
-        UiMode::BookPicker => {
-            widgets::popups::book_picker::render_book_picker(
-                f,
-                &app.recent_books,
-                app.book_picker_selected_idx,
-            );
+        UiMode::HighlightPrompt => {
+            if let Some(highlight) = &app.pending_highlight {
+                widgets::popups::highlight_prompt::render_highlight_prompt(
+                    f,
+                    highlight,
+                    &app.input_buffer,
+                    app.editing_highlight_idx.is_some(),
+                );
+            }
+        }
+        UiMode::ExportPrompt => {
+            widgets::popups::export::render_export_popup(f, app.bookmarks.len());
+        }
+        UiMode::StateFilePopup => {
+            if let Some(problem) = app.state_problems.first() {
+                widgets::popups::state_file::render_state_file_popup(
+                    f,
+                    problem,
+                    app.persistence.has_backup(&problem.file),
+                    app.state_problems.len() - 1,
+                );
+            }
+        }
+        UiMode::Library => render_library(f, app, &app.input_buffer),
+        UiMode::CollectionsPrompt => {
+            if let Some(edit) = &app.collections_edit {
+                // Opened from the library, which stays in view
+                if let Some(filter) = &edit.library_filter {
+                    render_library(f, app, filter);
+                }
+                widgets::popups::collections_prompt::render_collections_prompt(
+                    f,
+                    edit,
+                    &app.input_buffer,
+                );
+            }
         }
         UiMode::Help => {
             widgets::popups::help::render_help_popup(f, f.area());
         }
         UiMode::MetadataPopup => {
             if let Some(book) = &app.book {
-                widgets::popups::metadata::render_metadata_popup(f, &book.metadata);
+                let position_cfi = app.current_cfi();
+                widgets::popups::metadata::render_metadata_popup(
+                    f,
+                    &book.metadata,
+                    position_cfi.as_deref(),
+                );
+            }
+        }
+        UiMode::FootnotePopup => {
+            if let Some(note) = &app.footnote {
+                widgets::popups::footnote::render_footnote_popup(f, note, app.footnote_scroll);
             }
         }
         UiMode::ErrorPopup(message) => {
             widgets::popups::error::render_error_popup(f, message, f.area());
         }
-        UiMode::Normal => {}
+        UiMode::ImageViewer => placements = render_image_viewer(f, app),
+        UiMode::Normal | UiMode::LinkHints => {}
     }
 
+    // Protocol images would be drawn on top of popups and overlays
+    let overlay = !matches!(
+        app.ui_mode,
+        UiMode::Normal | UiMode::LinkHints | UiMode::ImageViewer
+    ) || !matches!(app.loading_state, LoadingState::Idle);
+    if overlay {
+        placements.clear();
+    }
+    app.graphics.set_placements(placements);
+
     // Render loading overlay if active
     match &app.loading_state {
         LoadingState::LoadingBook { file_path } => {
@@ -163,6 +231,28 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
     }
 }
 
+/// Render the library narrowed by a filter
+fn render_library(f: &mut Frame, app: &AppState, filter: &str) {
+    let view = crate::library::LibraryManager::view(
+        &app.library,
+        &app.collections,
+        filter,
+        app.config.library_sort,
+    );
+    widgets::popups::library::render_library(
+        f,
+        &widgets::popups::library::LibraryView {
+            entries: &app.library,
+            view: &view,
+            collections: &app.collections,
+            filter,
+            sort: app.config.library_sort,
+            selected_idx: app.library_selected_idx,
+            scanning: app.library_scanning,
+        },
+    );
+}
+
 fn render_titlebar(f: &mut Frame, app: &AppState, area: Rect) {
     let title_text = if let Some(book) = &app.book {
         let chapter_title = app
@@ -182,27 +272,32 @@ fn render_titlebar(f: &mut Frame, app: &AppState, area: Rect) {
     f.render_widget(title, area);
 }
 
-/// Build a styled line with inline styles and search highlighting
+/// Build a styled line with inline styles, user highlights and search highlighting
 /// Handles the complex logic of merging inline styles with search highlighting
+#[allow(clippy::too_many_arguments)]
 fn build_styled_line(
     line: &crate::types::RenderedLine,
     line_idx: usize,
     cursor_line: usize,
+    selected: bool,
+    highlights: &[(usize, usize, HighlightColor)],
     search_results: &[crate::types::SearchMatch],
     current_search_idx: usize,
     current_chapter: usize,
 ) -> Line<'static> {
-    // If no inline styles, no search matches, and no syntax colors, use simple rendering
+    // If no inline styles, highlights, search matches, syntax colors or links, use simple rendering
     if line.inline_styles.is_empty()
+        && highlights.is_empty()
         && line.search_matches.is_empty()
         && line.syntax_colors.is_empty()
+        && line.links.is_empty()
     {
-        let base_style = get_line_style(&line.style, line_idx, cursor_line);
+        let base_style = get_line_style(&line.style, line_idx, cursor_line, selected);
         return Line::from(Span::styled(line.text.clone(), base_style));
     }
 
     let mut spans = Vec::new();
-    let base_style = get_line_style(&line.style, line_idx, cursor_line);
+    let base_style = get_line_style(&line.style, line_idx, cursor_line, selected);
 
     // Split text into regions based on all style boundaries
     let mut boundaries: Vec<usize> = vec![0, line.text.len()];
@@ -213,6 +308,12 @@ fn build_styled_line(
         boundaries.push(*end);
     }
 
+    // Add boundaries from user highlights
+    for (start, end, _) in highlights {
+        boundaries.push(*start);
+        boundaries.push(*end);
+    }
+
     // Add boundaries from inline styles
     for (start, end, _) in &line.inline_styles {
         boundaries.push(*start);
@@ -225,6 +326,12 @@ fn build_styled_line(
         boundaries.push(*end);
     }
 
+    // Add boundaries from links
+    for (start, end, _) in &line.links {
+        boundaries.push(*start);
+        boundaries.push(*end);
+    }
+
     // Remove duplicates and sort
     boundaries.sort_unstable();
     boundaries.dedup();
@@ -282,11 +389,31 @@ fn build_styled_line(
             };
             span_style = span_style.bg(highlight_color).fg(Color::Black);
         } else {
+            // User highlights paint the background, the cursor line keeps showing through
+            // the rest of the line
+            if let Some((_, _, color)) = highlights
+                .iter()
+                .find(|(h_start, h_end, _)| start >= *h_start && end <= *h_end)
+            {
+                span_style = span_style.bg(color.background());
+            }
+
             // Apply syntax highlighting color if present (takes precedence over base style)
             if let Some(color) = syntax_color {
                 span_style = span_style.fg(color);
             }
 
+            // Mark link text
+            let in_link = line
+                .links
+                .iter()
+                .any(|(l_start, l_end, _)| start >= *l_start && end <= *l_end);
+            if in_link {
+                span_style = span_style
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::UNDERLINED);
+            }
+
             // Apply inline styles (bold, italic, etc.) on top
             for inline_style in applicable_inline_styles {
                 match inline_style {
@@ -311,6 +438,12 @@ fn build_styled_line(
                     InlineStyle::Highlight => {
                         span_style = span_style.bg(Color::Yellow).fg(Color::Black);
                     }
+                    InlineStyle::NoteRef => {
+                        span_style = span_style
+                            .fg(Color::Magenta)
+                            .add_modifier(Modifier::BOLD)
+                            .remove_modifier(Modifier::UNDERLINED);
+                    }
                 }
             }
         }
@@ -364,7 +497,58 @@ fn build_styled_line(
     Line::from(spans)
 }
 
-fn render_content(f: &mut Frame, app: &AppState, area: Rect) {
+/// Cut the columns `offset..offset + width` out of a styled line
+///
+/// Columns hidden on either side are marked with an arrow in the first or
+/// last visible column. Wide characters that straddle the left edge leave
+/// blank cells so the remaining text keeps its alignment.
+fn clip_line(line: Line<'static>, offset: usize, width: usize) -> Line<'static> {
+    let total = line.width();
+    if width == 0 || (offset == 0 && total <= width) {
+        return line;
+    }
+
+    let clipped_left = offset > 0;
+    let clipped_right = total > offset + width;
+    let from = offset + clipped_left as usize;
+    let to = offset + width - clipped_right as usize;
+    let marker_style = line
+        .spans
+        .first()
+        .map_or(Style::default(), |span| span.style)
+        .fg(Color::DarkGray);
+
+    let mut spans = Vec::new();
+    if clipped_left {
+        spans.push(Span::styled("←", marker_style));
+    }
+    let mut column = 0;
+    for span in &line.spans {
+        let mut text = String::new();
+        for (idx, ch) in span.content.char_indices() {
+            let ch_width = display_width(&span.content[idx..idx + ch.len_utf8()]);
+            if column >= from && column + ch_width <= to {
+                text.push(ch);
+            } else if column < from && column + ch_width > from {
+                text.push_str(&" ".repeat(column + ch_width - from));
+            }
+            column += ch_width;
+        }
+        if !text.is_empty() {
+            spans.push(Span::styled(text, span.style));
+        }
+    }
+    if clipped_right {
+        spans.push(Span::styled("→", marker_style));
+    }
+
+    Line::from(spans)
+}
+
+/// Render the chapter text, returning the images a graphics protocol should show
+fn render_content(f: &mut Frame, app: &AppState, area: Rect) -> Vec<ImagePlacement> {
+    let mut placements = Vec::new();
+
     // Calculate the width that was used for text wrapping during chapter rendering
     let available_width = area.width as usize;
 
@@ -407,6 +591,8 @@ fn render_content(f: &mut Frame, app: &AppState, area: Rect) {
     if let Some(chapter) = app.get_current_chapter() {
         let visible_start = app.viewport.scroll_offset;
         let visible_end = (visible_start + area.height as usize).min(chapter.content_lines.len());
+        let cursor_code_block = chapter.code_block_at(app.cursor_line);
+        let selection = app.visual_selection();
 
         let mut lines = Vec::new();
 
@@ -417,15 +603,32 @@ fn render_content(f: &mut Frame, app: &AppState, area: Rect) {
             let global_line_idx = visible_start + idx;
 
             // Build styled line with inline styles and search highlighting
+            let highlights =
+                AnnotationManager::spans_for_line(&app.highlights, app.current_chapter, line);
             let styled_line = build_styled_line(
                 line,
                 global_line_idx,
                 app.cursor_line,
+                selection
+                    .as_ref()
+                    .is_some_and(|range| range.contains(&global_line_idx)),
+                &highlights,
                 &app.search_results,
                 app.current_search_idx,
                 app.current_chapter,
             );
 
+            // Code is never re-wrapped by the paragraph, it is clipped to the view instead
+            let styled_line = if matches!(line.style, LineStyle::CodeBlock { .. }) {
+                let scroll = cursor_code_block
+                    .as_ref()
+                    .filter(|block| block.contains(&global_line_idx))
+                    .map_or(0, |block| app.code_scroll_for(block.start));
+                clip_line(styled_line, scroll, content_area.width as usize)
+            } else {
+                styled_line
+            };
+
             lines.push(styled_line);
         }
 
@@ -434,15 +637,163 @@ fn render_content(f: &mut Frame, app: &AppState, area: Rect) {
             .wrap(Wrap { trim: false });
 
         f.render_widget(paragraph, content_area);
+        render_images(f, app, content_area, &mut placements);
+
+        if app.ui_mode == UiMode::LinkHints {
+            render_link_hints(f, app, content_area);
+        }
     } else {
         let text = Paragraph::new("No book loaded")
             .style(Style::default().fg(Color::Gray))
             .alignment(ratatui::layout::Alignment::Center);
         f.render_widget(text, content_area);
     }
+
+    placements
+}
+
+/// Draw the images on visible lines, or record where protocol images go
+fn render_images(f: &mut Frame, app: &AppState, area: Rect, placements: &mut Vec<ImagePlacement>) {
+    let (Some(book), Some(chapter)) = (&app.book, app.get_current_chapter()) else {
+        return;
+    };
+    let visible_start = app.viewport.scroll_offset;
+    let visible_end = (visible_start + area.height as usize).min(chapter.content_lines.len());
+
+    for line_idx in visible_start..visible_end {
+        let LineStyle::Image {
+            path,
+            row,
+            rows,
+            cols,
+        } = &chapter.content_lines[line_idx].style
+        else {
+            continue;
+        };
+        // Handle each image once, at its first visible row
+        if *row > 0 && line_idx > visible_start {
+            continue;
+        }
+
+        let cols = (*cols).min(area.width);
+        let visible = (*rows - row).min((visible_end - line_idx) as u16);
+        let image_area = Rect {
+            x: area.x + (area.width - cols) / 2,
+            y: area.y + (line_idx - visible_start) as u16,
+            width: cols,
+            height: visible,
+        };
+
+        if app.graphics.uses_cells() {
+            if let Some(image) =
+                app.image_cache
+                    .scaled(book, path, None, cols as u32, *rows as u32 * 2)
+            {
+                widgets::image::render_half_blocks(f.buffer_mut(), image_area, &image, *row);
+            }
+        } else {
+            placements.push(ImagePlacement {
+                path: path.clone(),
+                x: image_area.x,
+                y: image_area.y,
+                cols,
+                rows: *rows,
+                skip: *row,
+                visible,
+                crop: None,
+            });
+        }
+    }
 }
 
-fn get_line_style(line_style: &LineStyle, line_idx: usize, cursor_line: usize) -> Style {
+/// Draw the full-screen image viewer, returning the placement of its image
+fn render_image_viewer(f: &mut Frame, app: &AppState) -> Vec<ImagePlacement> {
+    let (Some(book), Some(viewer)) = (&app.book, &app.image_viewer) else {
+        return Vec::new();
+    };
+    let image = viewer.current();
+    let chapter_title = book
+        .chapters
+        .get(image.chapter_idx)
+        .map(|ch| ch.title.as_str())
+        .unwrap_or("");
+    let area = widgets::image_viewer::render_image_viewer(f, viewer, chapter_title);
+
+    // Center the image at the largest size that fits
+    let crop = viewer.crop();
+    let (cols, rows) = viewer.fit(area.width, area.height, cell_size());
+    let image_area = Rect {
+        x: area.x + area.width.saturating_sub(cols) / 2,
+        y: area.y + area.height.saturating_sub(rows) / 2,
+        width: cols,
+        height: rows,
+    };
+
+    if app.graphics.uses_cells() {
+        if let Some(pixels) =
+            app.image_cache
+                .scaled(book, &image.path, Some(crop), cols as u32, rows as u32 * 2)
+        {
+            widgets::image::render_half_blocks(f.buffer_mut(), image_area, &pixels, 0);
+        }
+        return Vec::new();
+    }
+
+    vec![ImagePlacement {
+        path: image.path.clone(),
+        x: image_area.x,
+        y: image_area.y,
+        cols,
+        rows,
+        skip: 0,
+        visible: rows,
+        crop: Some(crop),
+    }]
+}
+
+/// Draw link-hint labels over the start of each visible link
+fn render_link_hints(f: &mut Frame, app: &AppState, area: Rect) {
+    let chapter = match app.get_current_chapter() {
+        Some(ch) => ch,
+        None => return,
+    };
+    let label_style = Style::default()
+        .fg(Color::Black)
+        .bg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+
+    for hint in &app.link_hints {
+        // Only show labels that still match what has been typed
+        let Some(remaining) = hint.label.strip_prefix(app.input_buffer.as_str()) else {
+            continue;
+        };
+        let Some(row) = hint.line.checked_sub(app.viewport.scroll_offset) else {
+            continue;
+        };
+        let Some(line) = chapter.content_lines.get(hint.line) else {
+            continue;
+        };
+        if row >= area.height as usize {
+            continue;
+        }
+
+        let prefix = line.text.get(..hint.column).unwrap_or("");
+        let x = area.x as usize + Span::raw(prefix).width();
+        if x + remaining.len() > (area.x + area.width) as usize {
+            continue;
+        }
+
+        f.buffer_mut()
+            .set_string(x as u16, area.y + row as u16, remaining, label_style);
+    }
+}
+
+fn get_line_style(
+    line_style: &LineStyle,
+    line_idx: usize,
+    cursor_line: usize,
+    selected: bool,
+) -> Style {
     let mut base_style = match line_style {
         LineStyle::Heading1 => Style::default()
             .fg(Color::Cyan)
@@ -460,11 +811,13 @@ fn get_line_style(line_style: &LineStyle, line_idx: usize, cursor_line: usize) -
         LineStyle::Link => Style::default()
             .fg(Color::Blue)
             .add_modifier(Modifier::UNDERLINED),
-        LineStyle::Normal => Style::default(),
+        LineStyle::Normal | LineStyle::Image { .. } => Style::default(),
     };
 
-    // Add cursor background highlight
-    if line_idx == cursor_line {
+    // Add selection or cursor background highlight
+    if selected {
+        base_style = base_style.bg(Color::Rgb(50, 60, 100));
+    } else if line_idx == cursor_line {
         base_style = base_style.bg(Color::Rgb(40, 40, 50));
     }
 
@@ -478,11 +831,9 @@ fn render_statusbar(f: &mut Frame, app: &AppState, area: Rect) {
         let current_line = app.cursor_line + 1;
         let total_lines = app.current_chapter_lines();
 
-        let percentage = if total_lines > 0 {
-            (app.cursor_line * 100) / total_lines
-        } else {
-            0
-        };
+        let percentage = (app.cursor_line * 100)
+            .checked_div(total_lines)
+            .unwrap_or(0);
 
         // Determine current section
         let section_info = if let Some(chapter) = app.get_current_chapter() {
@@ -529,7 +880,7 @@ fn render_statusbar(f: &mut Frame, app: &AppState, area: Rect) {
             available_width =
                 available_width.saturating_sub(app.config.toc_panel_width as usize + 1);
         }
-        if app.bookmarks_panel_visible {
+        if app.right_panel_visible() {
             available_width =
                 available_width.saturating_sub(app.config.bookmarks_panel_width as usize + 1);
         }
@@ -568,6 +919,16 @@ fn render_statusbar(f: &mut Frame, app: &AppState, area: Rect) {
         status_with_width
     };
 
+    // Selection size and one-off messages go in front of everything else
+    let full_status = match app.visual_selection() {
+        Some(range) => format!("-- VISUAL {} lines -- | {}", range.count(), full_status),
+        None => full_status,
+    };
+    let full_status = match &app.status_message {
+        Some(message) => format!("{} | {}", message, full_status),
+        None => full_status,
+    };
+
     let status =
         Paragraph::new(full_status).style(Style::default().fg(Color::White).bg(Color::DarkGray));
 
@@ -612,3 +973,15 @@ fn render_bookmarks(f: &mut Frame, app: &AppState, area: Rect) {
 
     panel.render(f, area);
 }
+
+fn render_annotations(f: &mut Frame, app: &AppState, area: Rect) {
+    let is_focused = app.focus == FocusTarget::Annotations;
+
+    let panel = widgets::annotations::AnnotationsPanel::new(
+        &app.highlights,
+        app.selected_highlight_idx,
+        is_focused,
+    );
+
+    panel.render(f, area);
+}
@@ -0,0 +1,112 @@
+use crate::types::Highlight;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState},
+};
+
+pub struct AnnotationsPanel<'a> {
+    highlights: &'a [Highlight],
+    selected_idx: Option<usize>,
+    focused: bool,
+}
+
+impl<'a> AnnotationsPanel<'a> {
+    pub fn new(highlights: &'a [Highlight], selected_idx: Option<usize>, focused: bool) -> Self {
+        Self {
+            highlights,
+            selected_idx,
+            focused,
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let border_style = if self.focused {
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::White)
+        };
+
+        let block = Block::default()
+            .title("Highlights")
+            .borders(Borders::ALL)
+            .border_style(border_style);
+
+        if self.highlights.is_empty() {
+            // Show empty state
+            let empty_text = vec![
+                Line::from(""),
+                Line::from(Span::styled(
+                    "[No highlights]",
+                    Style::default().fg(Color::DarkGray),
+                )),
+                Line::from(""),
+                Line::from(Span::styled(
+                    "Press a to add",
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ];
+            let paragraph = ratatui::widgets::Paragraph::new(empty_text)
+                .block(block)
+                .alignment(ratatui::layout::Alignment::Center);
+            frame.render_widget(paragraph, area);
+        } else {
+            // Borders and the highlight symbol take up 5 columns
+            let text_width = (area.width as usize).saturating_sub(5);
+
+            // Show the excerpt of each highlight, with its note underneath
+            let items: Vec<ListItem> = self
+                .highlights
+                .iter()
+                .map(|highlight| {
+                    let chapter = format!(" Ch {} | ", highlight.chapter_idx + 1);
+                    let excerpt_width = text_width.saturating_sub(2 + chapter.len());
+                    let mut lines = vec![Line::from(vec![
+                        Span::styled("  ", Style::default().bg(highlight.color.background())),
+                        Span::raw(chapter),
+                        Span::raw(excerpt(&highlight.text, excerpt_width)),
+                    ])];
+                    if let Some(note) = &highlight.note {
+                        lines.push(Line::from(Span::styled(
+                            excerpt(note, text_width),
+                            Style::default()
+                                .fg(Color::Gray)
+                                .add_modifier(Modifier::ITALIC),
+                        )));
+                    }
+                    ListItem::new(lines)
+                })
+                .collect();
+
+            let list = List::new(items)
+                .block(block)
+                .highlight_style(
+                    Style::default()
+                        .bg(Color::DarkGray)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .highlight_symbol(">> ");
+
+            // Create list state
+            let mut list_state = ListState::default();
+            list_state.select(self.selected_idx);
+
+            frame.render_stateful_widget(list, area, &mut list_state);
+        }
+    }
+}
+
+/// First line of `text`, shortened with an ellipsis to about `width` characters
+fn excerpt(text: &str, width: usize) -> String {
+    let first_line = text.lines().next().unwrap_or("");
+    if first_line.chars().count() > width && width > 3 {
+        let short: String = first_line.chars().take(width - 3).collect();
+        format!("{}...", short)
+    } else {
+        first_line.to_string()
+    }
+}
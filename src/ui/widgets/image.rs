@@ -0,0 +1,39 @@
+//! Half-block image rendering
+//!
+//! Draws pictures with the upper half block character, using the foreground
+//! color for the top pixel and the background color for the bottom pixel of
+//! each cell. Works in any true-color terminal without a graphics protocol.
+
+use crate::images::RgbImage;
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+/// Draw part of an image into the buffer
+///
+/// # Arguments
+/// * `buf` - Frame buffer to draw into
+/// * `area` - Cells to fill, one cell per image column and two image rows
+/// * `image` - Image scaled to `cols` x `2 * rows` pixels
+/// * `skip_rows` - Cell rows of the image scrolled out above `area`
+pub fn render_half_blocks(buf: &mut Buffer, area: Rect, image: &RgbImage, skip_rows: u16) {
+    let area = area.intersection(buf.area);
+    let color = |x: u32, y: u32| {
+        if x < image.width && y < image.height {
+            let [r, g, b] = image.pixel(x, y);
+            Color::Rgb(r, g, b)
+        } else {
+            Color::Reset
+        }
+    };
+
+    for row in 0..area.height {
+        let top = (skip_rows + row) as u32 * 2;
+        for col in 0..area.width {
+            let x = col as u32;
+            if let Some(cell) = buf.cell_mut((area.x + col, area.y + row)) {
+                cell.set_char('▀')
+                    .set_fg(color(x, top))
+                    .set_bg(color(x, top + 1));
+            }
+        }
+    }
+}
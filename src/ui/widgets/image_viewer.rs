@@ -0,0 +1,79 @@
+//! Full-screen image viewer frame
+//!
+//! Draws everything around the image: the title with the image's position
+//! in the book, the zoom level, the figure caption and the key hints. The
+//! image itself is drawn by the layout into the area this leaves free.
+
+use crate::images::viewer::ImageViewer;
+use ratatui::{
+    Frame,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// Maximum number of lines shown for a caption
+const MAX_CAPTION_LINES: u16 = 3;
+
+/// Draw the viewer frame over the whole screen
+///
+/// # Arguments
+/// * `f` - Frame to draw into
+/// * `viewer` - Viewer state
+/// * `chapter_title` - Title of the chapter containing the image
+///
+/// # Returns
+/// The area left for the image
+pub fn render_image_viewer(f: &mut Frame, viewer: &ImageViewer, chapter_title: &str) -> Rect {
+    let area = f.area();
+    f.render_widget(Clear, area);
+
+    let image = viewer.current();
+    let block = Block::default()
+        .title(format!(
+            " Image {}/{} ({}) ",
+            viewer.index + 1,
+            viewer.images.len(),
+            chapter_title
+        ))
+        .title_bottom(Line::from(format!(" {}% ", (viewer.zoom * 100.0).round())).right_aligned())
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    // Figures show their caption, other images their alt text
+    let caption = image
+        .caption
+        .as_deref()
+        .or(Some(image.alt.as_str()).filter(|alt| !alt.is_empty()));
+    let caption_lines = caption.map_or(0, |text| {
+        let width = inner_area.width.max(1) as usize;
+        (text.chars().count().div_ceil(width) as u16).clamp(1, MAX_CAPTION_LINES)
+    });
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(caption_lines),
+            Constraint::Length(1),
+        ])
+        .split(inner_area);
+
+    if let Some(text) = caption {
+        let caption = Paragraph::new(text)
+            .style(Style::default().add_modifier(Modifier::ITALIC))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        f.render_widget(caption, chunks[1]);
+    }
+
+    let hint =
+        Line::from("n/N next/previous, +/- zoom, h/j/k/l pan, 0 fit, Enter go to image, Esc close")
+            .style(Style::default().fg(Color::Gray));
+    f.render_widget(Paragraph::new(hint).alignment(Alignment::Center), chunks[2]);
+
+    chunks[0]
+}
@@ -33,11 +33,7 @@ impl LoadingWidget {
     pub fn render(&self, f: &mut Frame, area: Rect) {
         let lines = if let Some((current, total)) = self.progress {
             // With progress bar
-            let percentage = if total > 0 {
-                (current * 100) / total
-            } else {
-                0
-            };
+            let percentage = (current * 100).checked_div(total).unwrap_or(0);
 
             let bar_width = 40;
             let filled = (bar_width * current) / total.max(1);
@@ -1,8 +1,11 @@
 //! UI widget components
 //!
 //! This module contains reusable UI components including popups,
-//! loading indicators, and specialized panel widgets.
+//! loading indicators, images, the image viewer, and specialized panel widgets.
 
+pub mod annotations;
 pub mod bookmarks;
+pub mod image;
+pub mod image_viewer;
 pub mod loading;
 pub mod popups;
@@ -0,0 +1,108 @@
+use crate::collections::ReadingStatus;
+use crate::types::CollectionsEdit;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// Render the reading status and tags prompt
+///
+/// # Arguments
+/// * `edit` - Book being edited and the status chosen so far
+/// * `tags` - Tags typed so far
+pub fn render_collections_prompt(frame: &mut Frame, edit: &CollectionsEdit, tags: &str) {
+    // 1 (title) + 1 (spacing) + 1 (status) + 1 (spacing) + 1 (prompt) + 1 (tags)
+    // + 1 (spacing) + 1 (hint) + 2 (borders) = 10 lines
+    let content_height = 10;
+    let area = centered_rect_fixed(60, content_height, frame.area());
+
+    // Clear the area
+    frame.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title("Reading Status and Tags")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Book title
+            Constraint::Length(1), // Spacing
+            Constraint::Length(1), // Status
+            Constraint::Length(1), // Spacing
+            Constraint::Length(1), // Prompt
+            Constraint::Length(1), // Tags
+            Constraint::Length(1), // Spacing
+            Constraint::Min(1),    // Hint
+        ])
+        .split(inner);
+
+    let title = Paragraph::new(edit.title.as_str()).style(Style::default().fg(Color::Gray));
+    frame.render_widget(title, chunks[0]);
+
+    // Render the status choices, marking the current one
+    let mut statuses = vec![Span::styled("Status: ", Style::default().fg(Color::White))];
+    let choices = std::iter::once(None).chain(ReadingStatus::ALL.into_iter().map(Some));
+    for status in choices {
+        let name = status.map_or("none", ReadingStatus::name);
+        if status == edit.status {
+            statuses.push(Span::styled(
+                format!("[{}]", name),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        } else {
+            statuses.push(Span::styled(
+                format!(" {} ", name),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+    }
+    frame.render_widget(Paragraph::new(Line::from(statuses)), chunks[2]);
+
+    // Render prompt
+    let prompt =
+        Paragraph::new("Tags (comma separated): ").style(Style::default().fg(Color::White));
+    frame.render_widget(prompt, chunks[4]);
+
+    // Render input
+    let input_text = Paragraph::new(tags).style(
+        Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+    );
+    frame.render_widget(input_text, chunks[5]);
+
+    let hint = Paragraph::new("Tab: change status  Enter: save  Esc: cancel")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(hint, chunks[7]);
+}
+
+/// Create a centered rect with fixed height and percentage width
+fn centered_rect_fixed(percent_x: u16, height: u16, area: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(height),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
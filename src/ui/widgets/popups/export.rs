@@ -0,0 +1,53 @@
+use ratatui::{
+    Frame,
+    layout::{Alignment, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// Render the bookmark export popup
+///
+/// # Arguments
+/// * `bookmark_count` - Number of bookmarks that will be exported
+pub fn render_export_popup(f: &mut Frame, bookmark_count: usize) {
+    let popup_width = 44.min(f.area().width);
+    let popup_height = 9;
+
+    let popup_area = Rect {
+        x: (f.area().width.saturating_sub(popup_width)) / 2,
+        y: (f.area().height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height.min(f.area().height),
+    };
+
+    // Clear the area behind the popup
+    f.render_widget(Clear, popup_area);
+
+    let key_style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let text = vec![
+        Line::from(format!("Export {} bookmarks as:", bookmark_count)),
+        Line::from(""),
+        Line::from(vec![Span::styled("m", key_style), Span::raw("  Markdown")]),
+        Line::from(vec![Span::styled("j", key_style), Span::raw("  JSON    ")]),
+        Line::from(vec![Span::styled("o", key_style), Span::raw("  Org-mode")]),
+        Line::from(""),
+        Line::from(Span::styled(
+            "Esc to cancel",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .title(" Export Bookmarks ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .alignment(Alignment::Center);
+
+    f.render_widget(paragraph, popup_area);
+}
@@ -0,0 +1,69 @@
+use crate::types::Footnote;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+pub fn render_footnote_popup(f: &mut Frame, note: &Footnote, scroll: u16) {
+    // Create a centered popup (60% width, 40% height)
+    let area = centered_rect(60, 40, f.area());
+
+    // Clear the area behind the popup
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(format!(" Note ({}) ", note.chapter_title))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Magenta));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    // Split inner area into note text and key hint
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(inner_area);
+
+    // Separate paragraphs with blank lines
+    let mut lines = Vec::new();
+    for (idx, paragraph) in note.paragraphs.iter().enumerate() {
+        if idx > 0 {
+            lines.push(Line::from(""));
+        }
+        lines.push(Line::from(paragraph.as_str()));
+    }
+
+    let text = Paragraph::new(lines)
+        .wrap(Wrap { trim: true })
+        .scroll((scroll, 0));
+    f.render_widget(text, chunks[0]);
+
+    let hint =
+        Line::from("j/k to scroll, Esc or Enter to close").style(Style::default().fg(Color::Gray));
+    f.render_widget(Paragraph::new(hint), chunks[1]);
+}
+
+// Helper function to create a centered rectangle
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
@@ -27,6 +27,15 @@ pub fn render_help_popup(f: &mut Frame, _area: Rect) {
         Line::from("  G / End            Move cursor to bottom of chapter"),
         Line::from("  { / }              Previous/next chapter"),
         Line::from("  [ / ]              Previous/next section"),
+        Line::from("  f                  Show link hints, type a label to follow"),
+        Line::from("  Enter              View the image or note on the cursor line"),
+        Line::from("  Ctrl-o / Backspace Jump back to previous position"),
+        Line::from("  Ctrl-i             Jump forward again"),
+        Line::from("  h / l              Scroll code block under cursor left/right"),
+        Line::from("  0                  Scroll code block back to the start"),
+        Line::from("  v / V              Start/cancel a line selection"),
+        Line::from("  y                  Copy the selection to the clipboard"),
+        Line::from("  Y                  Copy the code block under the cursor"),
         Line::from(""),
         Line::from(vec![Span::styled(
             "PANELS & VIEWS",
@@ -37,12 +46,28 @@ pub fn render_help_popup(f: &mut Frame, _area: Rect) {
         Line::from(""),
         Line::from("  t                  Toggle TOC panel"),
         Line::from("  b                  Toggle bookmarks panel"),
+        Line::from("  A                  Toggle highlights panel"),
         Line::from("  Ctrl-s             Toggle statusbar"),
         Line::from("  Ctrl-t             Toggle titlebar"),
         Line::from("  z                  Zen mode (hide all UI)"),
         Line::from("  w                  Cycle text width (None/80/100/120)"),
+        Line::from("  W                  Toggle soft-wrap of long code lines"),
         Line::from("  Shift-I            Show book metadata"),
-        Line::from("  o / Ctrl-o         Open book picker"),
+        Line::from("  o                  Open library (type to filter)"),
+        Line::from("  T                  Set reading status and tags"),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "IMAGE VIEWER",
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(Color::Yellow),
+        )]),
+        Line::from(""),
+        Line::from("  n / N              Next/previous image in the book"),
+        Line::from("  + / -              Zoom in/out"),
+        Line::from("  h / j / k / l      Pan left/down/up/right"),
+        Line::from("  0                  Fit the whole image"),
+        Line::from("  Enter              Go to the image in the text"),
         Line::from(""),
         Line::from(vec![Span::styled(
             "SEARCH & BOOKMARKS",
@@ -55,6 +80,10 @@ pub fn render_help_popup(f: &mut Frame, _area: Rect) {
         Line::from("  n / N              Next/previous search result"),
         Line::from("  Ctrl-m             Add bookmark at cursor"),
         Line::from("  d                  Delete bookmark (in bookmarks panel)"),
+        Line::from("  E                  Export bookmarks (Markdown/JSON/Org)"),
+        Line::from("  a                  Highlight selection or cursor line"),
+        Line::from("  Tab                Cycle highlight color (in highlight prompt)"),
+        Line::from("  e / d              Edit/delete highlight (in highlights panel)"),
         Line::from(""),
         Line::from(vec![Span::styled(
             "FOCUS MANAGEMENT",
@@ -64,7 +93,7 @@ pub fn render_help_popup(f: &mut Frame, _area: Rect) {
         )]),
         Line::from(""),
         Line::from("  Tab                Cycle focus between panels"),
-        Line::from("  1 / 2 / 3          Focus TOC/Content/Bookmarks"),
+        Line::from("  1 / 2 / 3 / 4      Focus TOC/Content/Bookmarks/Highlights"),
         Line::from(""),
         Line::from(vec![Span::styled(
             "APPLICATION",
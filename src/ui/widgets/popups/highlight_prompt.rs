@@ -0,0 +1,116 @@
+use crate::types::{Highlight, HighlightColor};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// Render the highlight prompt popup
+///
+/// # Arguments
+/// * `highlight` - Highlight being created or edited
+/// * `note` - Note typed so far
+/// * `editing` - Whether an existing highlight is being edited
+pub fn render_highlight_prompt(
+    frame: &mut Frame,
+    highlight: &Highlight,
+    note: &str,
+    editing: bool,
+) {
+    // 3 (excerpt) + 1 (spacing) + 1 (colors) + 1 (spacing) + 1 (prompt) + 2 (note)
+    // + 1 (hint) + 2 (borders) = 12 lines
+    let content_height = 12;
+    let area = centered_rect_fixed(60, content_height, frame.area());
+
+    // Clear the area
+    frame.render_widget(Clear, area);
+
+    let title = if editing {
+        "Edit Highlight"
+    } else {
+        "Add Highlight"
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Excerpt
+            Constraint::Length(1), // Spacing
+            Constraint::Length(1), // Colors
+            Constraint::Length(1), // Spacing
+            Constraint::Length(1), // Prompt
+            Constraint::Length(2), // Note
+            Constraint::Min(1),    // Hint
+        ])
+        .split(inner);
+
+    // Render the highlighted text in its color
+    let excerpt = Paragraph::new(highlight.text.replace('\n', " "))
+        .style(Style::default().bg(highlight.color.background()))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(excerpt, chunks[0]);
+
+    // Render the color choices, marking the current one
+    let mut colors = vec![Span::styled("Color: ", Style::default().fg(Color::White))];
+    for color in HighlightColor::ALL {
+        let style = Style::default().bg(color.background());
+        if color == highlight.color {
+            colors.push(Span::styled(
+                format!("[{}]", color.name()),
+                style.add_modifier(Modifier::BOLD),
+            ));
+        } else {
+            colors.push(Span::styled(format!(" {} ", color.name()), style));
+        }
+        colors.push(Span::raw(" "));
+    }
+    frame.render_widget(Paragraph::new(Line::from(colors)), chunks[2]);
+
+    // Render prompt
+    let prompt = Paragraph::new("Note (optional): ").style(Style::default().fg(Color::White));
+    frame.render_widget(prompt, chunks[4]);
+
+    // Render input
+    let input_text = Paragraph::new(note)
+        .style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(input_text, chunks[5]);
+
+    let hint = Paragraph::new("Tab: color | Enter: save | Esc: cancel")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(hint, chunks[6]);
+}
+
+/// Create a centered rect with fixed height and percentage width
+fn centered_rect_fixed(percent_x: u16, height: u16, area: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(height),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
@@ -0,0 +1,221 @@
+use crate::collections::BookCollections;
+use crate::library::LibraryEntry;
+use crate::types::LibrarySort;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState},
+};
+use std::collections::HashMap;
+
+/// What the library view shows
+pub struct LibraryView<'a> {
+    /// All library entries
+    pub entries: &'a [LibraryEntry],
+    /// Indices of the entries to show, in display order
+    pub view: &'a [usize],
+    /// Reading status and tags by book identity
+    pub collections: &'a HashMap<String, BookCollections>,
+    /// Text typed to filter the entries
+    pub filter: &'a str,
+    /// Order the entries are sorted by
+    pub sort: LibrarySort,
+    /// Selected position in `view`
+    pub selected_idx: Option<usize>,
+    /// Whether a rescan is running
+    pub scanning: bool,
+}
+
+/// Render the library view
+pub fn render_library(f: &mut Frame, library: &LibraryView) {
+    let LibraryView {
+        entries,
+        view,
+        collections,
+        filter,
+        sort,
+        selected_idx,
+        scanning,
+    } = *library;
+
+    // Create a centered popup (80% width, 70% height)
+    let area = centered_rect(80, 70, f.area());
+
+    // Clear the area behind the popup
+    f.render_widget(Clear, area);
+
+    // Create the block
+    let block = Block::default()
+        .title(if view.len() == entries.len() {
+            format!("Library ({}) - by {}", entries.len(), sort.name())
+        } else {
+            format!(
+                "Library ({}/{}) - by {}",
+                view.len(),
+                entries.len(),
+                sort.name()
+            )
+        })
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(1),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .split(inner_area);
+
+    let filter_line = Line::from(vec![
+        Span::styled("Filter: ", Style::default().fg(Color::Cyan)),
+        Span::raw(filter),
+        Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+    ]);
+    f.render_widget(Paragraph::new(filter_line), chunks[0]);
+
+    let footer = if scanning {
+        "Scanning library…"
+    } else {
+        "Enter: open  ^T: status/tags  ^S: sort  ^D: remove from recent  ^R: rescan  Esc: close"
+    };
+    f.render_widget(
+        Paragraph::new(footer).style(Style::default().fg(Color::Gray)),
+        chunks[3],
+    );
+
+    if view.is_empty() {
+        let message = if !entries.is_empty() {
+            "No books match the filter."
+        } else if scanning {
+            "Looking for books…"
+        } else {
+            "No books found.\n\nAdd directories to library_dirs in config.json\nor open a book with: reef <file.epub>"
+        };
+        let message = Paragraph::new(message)
+            .style(Style::default().fg(Color::Gray))
+            .alignment(ratatui::layout::Alignment::Center);
+        f.render_widget(message, chunks[1]);
+        return;
+    }
+
+    let now = chrono::Utc::now();
+    let rows: Vec<Row> = view
+        .iter()
+        .filter_map(|&idx| entries.get(idx))
+        .map(|entry| {
+            let status = collections
+                .get(&entry.book_id)
+                .and_then(|book| book.status)
+                .map(|status| status.name())
+                .unwrap_or_default();
+            Row::new(vec![
+                Cell::from(entry.metadata.title.trim().to_string()),
+                Cell::from(entry.metadata.author.clone().unwrap_or_default()),
+                Cell::from(status),
+                Cell::from(
+                    entry
+                        .percent_read()
+                        .map(|percent| format!("{:>3}%", percent))
+                        .unwrap_or_default(),
+                ),
+                Cell::from(entry.last_read_label(now).unwrap_or_default()),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(45),
+            Constraint::Percentage(25),
+            Constraint::Length(9),
+            Constraint::Length(5),
+            Constraint::Length(10),
+        ],
+    )
+    .header(
+        Row::new(vec!["Title", "Author", "Status", "Read", "Last read"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .row_highlight_style(
+        Style::default()
+            .fg(Color::Black)
+            .bg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let mut state = TableState::default().with_selected(selected_idx);
+    f.render_stateful_widget(table, chunks[1], &mut state);
+
+    if let Some(entry) = selected_idx
+        .and_then(|idx| view.get(idx))
+        .and_then(|&idx| entries.get(idx))
+    {
+        f.render_widget(
+            Paragraph::new(details(entry, collections.get(&entry.book_id)))
+                .style(Style::default().fg(Color::Gray)),
+            chunks[2],
+        );
+    }
+}
+
+/// Publication details, tags and location of the selected book
+fn details(entry: &LibraryEntry, collections: Option<&BookCollections>) -> Vec<Line<'static>> {
+    let metadata = &entry.metadata;
+    let published: Vec<String> = [
+        metadata.publisher.clone(),
+        metadata.publication_date.clone(),
+        metadata
+            .language
+            .as_ref()
+            .map(|language| format!("Language: {}", language)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let tags = collections
+        .map(|book| {
+            book.tags
+                .iter()
+                .map(|tag| format!("#{}", tag))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+
+    vec![
+        Line::from(tags).style(Style::default().fg(Color::Green)),
+        Line::from(published.join(" · ")),
+        Line::from(entry.path.clone()),
+    ]
+}
+
+// Helper function to create a centered rectangle
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
@@ -4,10 +4,10 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     text::Line,
-    widgets::{Block, Borders, Clear, Paragraph},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
 
-pub fn render_metadata_popup(f: &mut Frame, metadata: &BookMetadata) {
+pub fn render_metadata_popup(f: &mut Frame, metadata: &BookMetadata, position_cfi: Option<&str>) {
     // Create a centered popup (50% width, 50% height)
     let area = centered_rect(50, 50, f.area());
 
@@ -44,10 +44,15 @@ pub fn render_metadata_popup(f: &mut Frame, metadata: &BookMetadata) {
         lines.push(Line::from(format!("Language: {}", language)));
     }
 
+    if let Some(cfi) = position_cfi {
+        lines.push(Line::from(""));
+        lines.push(Line::from(format!("Position: {}", cfi)));
+    }
+
     lines.push(Line::from(""));
     lines.push(Line::from("Press Esc or Shift-I to close").style(Style::default().fg(Color::Gray)));
 
-    let paragraph = Paragraph::new(lines);
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
     f.render_widget(paragraph, inner_area);
 }
 
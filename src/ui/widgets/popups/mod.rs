@@ -1,11 +1,18 @@
 //! Modal popup components
 //!
-//! This module contains all popup/dialog widgets including search,
-//! bookmark creation, help screen, metadata display, and error messages.
+//! This module contains all popup/dialog widgets including the library and
+//! its reading status and tags prompt, search, bookmark and highlight
+//! creation, bookmark export, help screen, metadata display, footnotes,
+//! error messages, and recovery of damaged saved data.
 
-pub mod book_picker;
 pub mod bookmark_prompt;
+pub mod collections_prompt;
 pub mod error;
+pub mod export;
+pub mod footnote;
 pub mod help;
+pub mod highlight_prompt;
+pub mod library;
 pub mod metadata;
 pub mod search;
+pub mod state_file;
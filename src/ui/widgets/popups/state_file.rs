@@ -0,0 +1,85 @@
+use crate::persistence::StateFileError;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// Render the recovery popup for a saved state file that failed to load
+///
+/// # Arguments
+/// * `problem` - The file and why it could not be read
+/// * `has_backup` - Whether a backup exists to restore from
+/// * `remaining` - Number of further damaged files waiting after this one
+pub fn render_state_file_popup(
+    f: &mut Frame,
+    problem: &StateFileError,
+    has_backup: bool,
+    remaining: usize,
+) {
+    let popup_width = 64.min(f.area().width);
+    let popup_height = 16.min(f.area().height);
+
+    let popup_area = Rect {
+        x: (f.area().width.saturating_sub(popup_width)) / 2,
+        y: (f.area().height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    // Clear the area behind the popup
+    f.render_widget(Clear, popup_area);
+
+    let key_style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let dim = Style::default().fg(Color::DarkGray);
+
+    let mut text = vec![
+        Line::from(Span::styled(
+            format!(
+                "Your saved {} could not be loaded.",
+                problem.file.description()
+            ),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(problem.source.reason.clone()),
+        Line::from(Span::styled(problem.source.path.display().to_string(), dim)),
+        Line::from(""),
+    ];
+    if has_backup {
+        text.push(Line::from(vec![
+            Span::styled("r    ", key_style),
+            Span::raw("Restore the previous version from the backup"),
+        ]));
+    }
+    text.push(Line::from(vec![
+        Span::styled("d    ", key_style),
+        Span::raw("Start afresh (the damaged file is kept aside)"),
+    ]));
+    text.push(Line::from(vec![
+        Span::styled("Esc  ", key_style),
+        Span::raw("Leave the file alone and don't save over it"),
+    ]));
+    if remaining > 0 {
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled(
+            format!("{} more damaged file(s) after this one", remaining),
+            dim,
+        )));
+    }
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .title(" Saved Data Damaged ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Red)),
+        )
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(paragraph, popup_area);
+}